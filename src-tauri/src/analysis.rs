@@ -0,0 +1,259 @@
+use crate::images::{validate_image_references, ImageRef};
+use crate::paths::{expand_path, path_key};
+use crate::versions::{count_words_ex, WordCountOptions};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+/// Average adult silent-reading speed used for the reading-time estimate
+const WORDS_PER_MINUTE: u32 = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutlineItem {
+    pub level: usize,
+    pub title: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentStats {
+    pub words: u32,
+    pub characters: usize,
+    pub lines: usize,
+    pub reading_minutes: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentAnalysis {
+    pub content_hash: String,
+    pub outline: Vec<OutlineItem>,
+    pub stats: DocumentStats,
+    pub images: Vec<ImageRef>,
+}
+
+/// ATX headings (`#` to `######`), skipping fenced code blocks
+fn build_outline(content: &str) -> Vec<OutlineItem> {
+    let mut outline = vec![];
+    let mut in_code = false;
+    for (i, line) in content.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        let Some(rest) = trimmed[level..].strip_prefix(' ') else {
+            continue;
+        };
+        if (1..=6).contains(&level) {
+            outline.push(OutlineItem {
+                level,
+                title: rest.trim().trim_end_matches('#').trim_end().to_string(),
+                line: i + 1,
+            });
+        }
+    }
+    outline
+}
+
+/// Outline, statistics, and image-reference checks for one document in a single pass
+pub fn analyze_content(
+    content: &str,
+    base_dir: &str,
+    options: &WordCountOptions,
+    content_hash: String,
+) -> DocumentAnalysis {
+    let words = count_words_ex(content, options);
+    DocumentAnalysis {
+        content_hash,
+        outline: build_outline(content),
+        stats: DocumentStats {
+            words,
+            characters: content.chars().count(),
+            lines: content.lines().count(),
+            reading_minutes: words.div_ceil(WORDS_PER_MINUTE),
+        },
+        images: validate_image_references(content.to_string(), base_dir.to_string()),
+    }
+}
+
+/// Cache key for an analysis: the content plus the settings that shape the word count
+fn analysis_hash(content: &str, options: &WordCountOptions) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(options).unwrap_or_default());
+    hasher.update(content.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// The cached analysis for `key`, if it was computed from content with this hash
+fn cached_analysis(
+    cache: &Mutex<HashMap<String, DocumentAnalysis>>,
+    key: &str,
+    hash: &str,
+) -> Option<DocumentAnalysis> {
+    cache
+        .lock()
+        .unwrap()
+        .get(key)
+        .filter(|cached| cached.content_hash == hash)
+        .cloned()
+}
+
+/// Analyze a document off the main thread. Results are cached per document and
+/// reused while the content (and word-count settings) are unchanged.
+#[tauri::command]
+pub async fn analyze_document(path: String, app: AppHandle) -> Result<DocumentAnalysis, String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let options = state
+            .settings
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?
+            .get_settings()
+            .word_count_options;
+        analyze_file(&state.analysis_cache, &path, &options)
+    })
+    .await
+    .map_err(|e| format!("Analysis failed: {}", e))?
+}
+
+/// Analyze the file at `path`, reusing the cached outline and statistics while
+/// its content hash matches. Image references are checked again on every call,
+/// since images can appear or disappear without the document changing.
+fn analyze_file(
+    cache: &Mutex<HashMap<String, DocumentAnalysis>>,
+    path: &str,
+    options: &WordCountOptions,
+) -> Result<DocumentAnalysis, String> {
+    let file_path = expand_path(path);
+    let content =
+        fs::read_to_string(&file_path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let base_dir = file_path
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let hash = analysis_hash(&content, options);
+    let key = path_key(path);
+    if let Some(mut cached) = cached_analysis(cache, &key, &hash) {
+        cached.images = validate_image_references(content, base_dir);
+        return Ok(cached);
+    }
+
+    let analysis = analyze_content(&content, &base_dir, options, hash);
+    cache.lock().unwrap().insert(key, analysis.clone());
+    Ok(analysis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::images::ImageStatus;
+
+    const DOCUMENT: &str = "# Introduction\n\
+        Some words here.\n\
+        ![figure](fig.png)\n\
+        ```\n\
+        # not a heading\n\
+        ```\n\
+        ## Method ##\n\
+        ![missing](gone.png)\n";
+
+    #[test]
+    fn combines_outline_stats_and_images() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("fig.png"), "png").unwrap();
+        let base_dir = dir.path().to_string_lossy().to_string();
+        let options = WordCountOptions::default();
+
+        let analysis = analyze_content(DOCUMENT, &base_dir, &options, "hash".into());
+
+        let outline: Vec<_> = analysis
+            .outline
+            .iter()
+            .map(|item| (item.level, item.title.as_str(), item.line))
+            .collect();
+        assert_eq!(outline, [(1, "Introduction", 1), (2, "Method", 7)]);
+
+        assert_eq!(analysis.stats.words, count_words_ex(DOCUMENT, &options));
+        assert_eq!(analysis.stats.lines, 8);
+        assert_eq!(analysis.stats.characters, DOCUMENT.chars().count());
+        assert_eq!(analysis.stats.reading_minutes, 1);
+
+        let images: Vec<_> = analysis
+            .images
+            .iter()
+            .map(|image| (image.source.as_str(), image.status))
+            .collect();
+        assert_eq!(
+            images,
+            [
+                ("fig.png", ImageStatus::Exists),
+                ("gone.png", ImageStatus::Missing)
+            ]
+        );
+    }
+
+    #[test]
+    fn identical_content_is_a_cache_hit() {
+        let options = WordCountOptions::default();
+        let hash = analysis_hash(DOCUMENT, &options);
+        assert_eq!(hash, analysis_hash(DOCUMENT, &options));
+
+        let cache = Mutex::new(HashMap::new());
+        assert!(cached_analysis(&cache, "doc", &hash).is_none());
+        let analysis = analyze_content(DOCUMENT, "", &options, hash.clone());
+        cache.lock().unwrap().insert("doc".to_string(), analysis);
+
+        let hit = cached_analysis(&cache, "doc", &hash).unwrap();
+        assert_eq!(hit.content_hash, hash);
+        assert_eq!(hit.outline.len(), 2);
+    }
+
+    #[test]
+    fn changed_content_or_settings_invalidate_the_cache() {
+        let options = WordCountOptions::default();
+        let hash = analysis_hash(DOCUMENT, &options);
+        let cache = Mutex::new(HashMap::new());
+        let analysis = analyze_content(DOCUMENT, "", &options, hash);
+        cache.lock().unwrap().insert("doc".to_string(), analysis);
+
+        let edited = format!("{}More text.\n", DOCUMENT);
+        assert!(cached_analysis(&cache, "doc", &analysis_hash(&edited, &options)).is_none());
+
+        let strict = WordCountOptions {
+            exclude_code_blocks: true,
+            ..WordCountOptions::default()
+        };
+        assert!(cached_analysis(&cache, "doc", &analysis_hash(DOCUMENT, &strict)).is_none());
+    }
+
+    #[test]
+    fn cache_hits_recheck_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("doc.lml");
+        fs::write(&document, DOCUMENT).unwrap();
+        let path = document.to_string_lossy().to_string();
+        let options = WordCountOptions::default();
+        let cache = Mutex::new(HashMap::new());
+        let status = |analysis: &DocumentAnalysis| analysis.images[0].status;
+
+        let first = analyze_file(&cache, &path, &options).unwrap();
+        assert_eq!(status(&first), ImageStatus::Missing);
+
+        // The document is unchanged, but the figure now exists
+        fs::write(dir.path().join("fig.png"), "png").unwrap();
+        let second = analyze_file(&cache, &path, &options).unwrap();
+        assert_eq!(second.content_hash, first.content_hash);
+        assert_eq!(status(&second), ImageStatus::Exists);
+        // Served from the cache, which isn't rewritten on a hit
+        assert_eq!(status(&cache.lock().unwrap()[&path_key(&path)]), ImageStatus::Missing);
+    }
+}
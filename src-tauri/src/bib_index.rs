@@ -0,0 +1,357 @@
+use crate::errors::AppError;
+use crate::paths::expand_path;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tauri::State;
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CitationSuggestion {
+    pub key: String,
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+/// Parsed keys of one .bib file, valid while its mtime and size are unchanged
+pub struct BibIndex {
+    modified: SystemTime,
+    len: u64,
+    entries: Vec<CitationSuggestion>,
+}
+
+pub type BibIndexCache = Mutex<HashMap<PathBuf, BibIndex>>;
+
+/// Entry types that hold no citable entry
+const NON_ENTRY_TYPES: &[&str] = &["comment", "preamble", "string"];
+
+struct Scanner {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Contents of a `{...}` group, with the cursor on the opening brace
+    fn braced(&mut self) -> String {
+        let mut depth = 0;
+        let start = self.pos + 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.chars[start..self.pos - 1].iter().collect();
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.chars[start.min(self.pos)..].iter().collect()
+    }
+
+    /// Contents of a `"..."` string; quotes inside braces don't end it
+    fn quoted(&mut self) -> String {
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                '"' if depth == 0 => {
+                    let value = self.chars[start..self.pos].iter().collect();
+                    self.pos += 1;
+                    return value;
+                }
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        self.chars[start..].iter().collect()
+    }
+
+    /// A field value: braced, quoted or bare parts joined with `#`
+    fn value(&mut self, close: char) -> String {
+        let mut value = String::new();
+        loop {
+            self.skip_whitespace();
+            let part = match self.peek() {
+                Some('{') => self.braced(),
+                Some('"') => self.quoted(),
+                Some(_) => {
+                    self.take_while(|c| c != ',' && c != '#' && c != close && !c.is_whitespace())
+                }
+                None => break,
+            };
+            value.push_str(&part);
+            self.skip_whitespace();
+            if self.peek() != Some('#') {
+                break;
+            }
+            self.pos += 1;
+        }
+        value
+    }
+}
+
+/// Strip grouping braces and collapse whitespace for display
+fn display_value(value: &str) -> Option<String> {
+    let cleaned = value.replace(['{', '}'], "").replace("\\&", "&");
+    let cleaned = cleaned.split_whitespace().collect::<Vec<_>>().join(" ");
+    Some(cleaned).filter(|v| !v.is_empty())
+}
+
+/// Keys with title and author of every entry in BibTeX source, in file order.
+/// `@comment`, `@preamble` and `@string` blocks are skipped.
+pub fn parse_bib_keys(content: &str) -> Vec<CitationSuggestion> {
+    let mut scanner = Scanner {
+        chars: content.chars().collect(),
+        pos: 0,
+    };
+    let mut entries = vec![];
+
+    while let Some(offset) = scanner.chars[scanner.pos..].iter().position(|&c| c == '@') {
+        scanner.pos += offset + 1;
+        let entry_type = scanner
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .to_lowercase();
+        scanner.skip_whitespace();
+        let close = match scanner.peek() {
+            Some('{') => '}',
+            Some('(') => ')',
+            _ => continue,
+        };
+        if NON_ENTRY_TYPES.contains(&entry_type.as_str()) {
+            if close == '}' {
+                scanner.braced();
+            }
+            continue;
+        }
+        scanner.pos += 1;
+        scanner.skip_whitespace();
+        let key = scanner
+            .take_while(|c| c != ',' && c != close && !c.is_whitespace())
+            .to_string();
+
+        let mut fields: HashMap<String, String> = HashMap::new();
+        loop {
+            scanner.skip_whitespace();
+            match scanner.peek() {
+                Some(',') => scanner.pos += 1,
+                Some(c) if c == close => {
+                    scanner.pos += 1;
+                    break;
+                }
+                // A new entry before this one closed; let the outer loop take it
+                Some('@') | None => break,
+                Some(_) => {
+                    let name = scanner
+                        .take_while(|c| c != '=' && c != ',' && c != close)
+                        .trim()
+                        .to_lowercase();
+                    if scanner.peek() != Some('=') {
+                        continue;
+                    }
+                    scanner.pos += 1;
+                    let value = scanner.value(close);
+                    fields.insert(name, value);
+                }
+            }
+        }
+
+        if !key.is_empty() {
+            entries.push(CitationSuggestion {
+                key,
+                title: fields.get("title").and_then(|t| display_value(t)),
+                author: fields.get("author").and_then(|a| display_value(a)),
+            });
+        }
+    }
+    entries
+}
+
+/// Entries whose key starts with `prefix`, ignoring case, sorted by key
+pub fn filter_by_prefix(
+    entries: &[CitationSuggestion],
+    prefix: &str,
+    limit: usize,
+) -> Vec<CitationSuggestion> {
+    let prefix = prefix.trim().to_lowercase();
+    let mut matches: Vec<&CitationSuggestion> = entries
+        .iter()
+        .filter(|e| e.key.to_lowercase().starts_with(&prefix))
+        .collect();
+    matches.sort_by_key(|e| e.key.to_lowercase());
+    matches.into_iter().take(limit).cloned().collect()
+}
+
+/// Citation keys for `\cite{` autocomplete. The .bib file is parsed once and
+/// re-read only after it changes on disk.
+#[tauri::command]
+pub fn suggest_citation_keys(
+    bib_path: String,
+    prefix: String,
+    limit: usize,
+    state: State<AppState>,
+) -> Result<Vec<CitationSuggestion>, AppError> {
+    suggest_keys(
+        &state.bib_index_cache,
+        &expand_path(&bib_path),
+        &prefix,
+        limit,
+    )
+}
+
+fn suggest_keys(
+    cache: &BibIndexCache,
+    path: &Path,
+    prefix: &str,
+    limit: usize,
+) -> Result<Vec<CitationSuggestion>, AppError> {
+    let metadata = fs::metadata(path).map_err(|e| AppError::io("Failed to read .bib file", e))?;
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut cache = cache.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let stale = cache
+        .get(path)
+        .is_none_or(|index| index.modified != modified || index.len != metadata.len());
+    if stale {
+        let content =
+            fs::read_to_string(path).map_err(|e| AppError::io("Failed to read .bib file", e))?;
+        let index = BibIndex {
+            modified,
+            len: metadata.len(),
+            entries: parse_bib_keys(&content),
+        };
+        cache.insert(path.to_path_buf(), index);
+    }
+    Ok(filter_by_prefix(&cache[path].entries, prefix, limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = r#"
+@string{mit = "MIT Press"}
+@comment{@article{commented, title = {Not an entry}}}
+
+@book{Knuth1984,
+  author    = {Donald E. Knuth},
+  title     = {The {\TeX}book},
+  publisher = mit,
+}
+
+@article{knuth1974structured,
+  title  = "Structured Programming with {"}go to{"} Statements",
+  author = "Knuth, " # "Donald E.",
+}
+
+@inproceedings(lamport1986latex,
+  title = {LaTeX:   A Document
+           Preparation System}
+)
+
+@misc{KnuthArt, title = {Art \& Science}}
+"#;
+
+    fn keys(suggestions: &[CitationSuggestion]) -> Vec<&str> {
+        suggestions.iter().map(|s| s.key.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_keys_with_title_and_author() {
+        let entries = parse_bib_keys(FIXTURE);
+        assert_eq!(
+            keys(&entries),
+            [
+                "Knuth1984",
+                "knuth1974structured",
+                "lamport1986latex",
+                "KnuthArt"
+            ]
+        );
+        assert_eq!(entries[0].title.as_deref(), Some("The \\TeXbook"));
+        assert_eq!(entries[0].author.as_deref(), Some("Donald E. Knuth"));
+        assert_eq!(entries[1].author.as_deref(), Some("Knuth, Donald E."));
+        assert_eq!(
+            entries[2].title.as_deref(),
+            Some("LaTeX: A Document Preparation System")
+        );
+        assert_eq!(entries[2].author, None);
+        assert_eq!(entries[3].title.as_deref(), Some("Art & Science"));
+    }
+
+    #[test]
+    fn suggestions_match_the_prefix_ignoring_case_up_to_the_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let bib = dir.path().join("refs.bib");
+        fs::write(&bib, FIXTURE).unwrap();
+        let cache = BibIndexCache::default();
+
+        let all_knuth = suggest_keys(&cache, &bib, "KNUTH", 10).unwrap();
+        assert_eq!(
+            keys(&all_knuth),
+            ["knuth1974structured", "Knuth1984", "KnuthArt"]
+        );
+        assert_eq!(
+            keys(&suggest_keys(&cache, &bib, "knuth", 2).unwrap()).len(),
+            2
+        );
+        assert_eq!(
+            keys(&suggest_keys(&cache, &bib, " lam", 10).unwrap()),
+            ["lamport1986latex"]
+        );
+        assert!(suggest_keys(&cache, &bib, "smith", 10).unwrap().is_empty());
+        assert!(suggest_keys(&cache, &bib, "", 0).unwrap().is_empty());
+        assert_eq!(suggest_keys(&cache, &bib, "", 10).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn the_index_is_rebuilt_when_the_file_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let bib = dir.path().join("refs.bib");
+        fs::write(&bib, "@book{first, title = {One}}").unwrap();
+        let cache = BibIndexCache::default();
+        assert_eq!(
+            keys(&suggest_keys(&cache, &bib, "", 10).unwrap()),
+            ["first"]
+        );
+
+        fs::write(
+            &bib,
+            "@book{first, title = {One}}\n@book{second, title = {Two}}",
+        )
+        .unwrap();
+        assert_eq!(
+            keys(&suggest_keys(&cache, &bib, "", 10).unwrap()),
+            ["first", "second"]
+        );
+
+        let missing = dir.path().join("missing.bib");
+        assert!(suggest_keys(&cache, &missing, "", 10).is_err());
+    }
+}
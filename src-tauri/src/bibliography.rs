@@ -1,5 +1,66 @@
+use crate::errors::AppError;
+use crate::metadata::{LazyReqwestClient, MetadataProvider, ProviderRegistry};
+use crate::operations::Operation;
+use crate::paths::expand_path;
+use crate::AppState;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, Manager, State};
+
+const DEFAULT_CONTACT_EMAIL: &str = "contact@lilia.dev";
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct BibCacheData {
+    pub entries: HashMap<String, BibEntry>,
+}
+
+/// Persisted results of DOI/ISBN lookups, keyed by `doi:<doi>` / `isbn:<isbn>`
+pub struct BibCache {
+    path: PathBuf,
+    data: BibCacheData,
+}
+
+impl BibCache {
+    pub fn new(path: PathBuf) -> Self {
+        let data = Self::load_from_path(&path).unwrap_or_default();
+        Self { path, data }
+    }
+
+    fn load_from_path(path: &PathBuf) -> io::Result<BibCacheData> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn get(&self, key: &str) -> Option<BibEntry> {
+        self.data.entries.get(key).cloned()
+    }
+
+    pub fn insert(&mut self, key: String, entry: BibEntry) {
+        self.data.entries.insert(key, entry);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(&self.data)?;
+        fs::write(&self.path, content)
+    }
+}
+
+fn cached_entry(cache: &Mutex<BibCache>, key: &str) -> Option<BibEntry> {
+    cache.lock().ok()?.get(key)
+}
+
+fn cache_entry(cache: &Mutex<BibCache>, key: String, entry: &BibEntry) {
+    if let Ok(mut cache) = cache.lock() {
+        cache.insert(key, entry.clone());
+        cache.save().ok();
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BibEntry {
@@ -18,235 +79,1723 @@ pub struct BibEntry {
     pub booktitle: Option<String>,
 }
 
-// CrossRef API response types
-#[derive(Deserialize)]
-struct CrossRefResponse {
-    message: CrossRefMessage,
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BibValidation {
+    pub key: String,
+    pub entry_type: String,
+    pub missing_required: Vec<String>,
+    pub missing_recommended: Vec<String>,
+    /// Percentage of required and recommended fields that are filled in
+    pub completeness: f64,
 }
 
-#[derive(Deserialize)]
-struct CrossRefMessage {
-    title: Option<Vec<String>>,
-    author: Option<Vec<CrossRefAuthor>>,
-    #[serde(rename = "container-title")]
-    container_title: Option<Vec<String>>,
-    #[serde(rename = "published-print")]
-    published_print: Option<CrossRefDate>,
-    #[serde(rename = "published-online")]
-    published_online: Option<CrossRefDate>,
-    volume: Option<String>,
-    page: Option<String>,
-    publisher: Option<String>,
-    #[serde(rename = "DOI")]
-    doi: Option<String>,
-    #[serde(rename = "URL")]
-    url: Option<String>,
-    #[serde(rename = "type")]
-    work_type: Option<String>,
+#[tauri::command]
+pub fn read_bib_file(path: String) -> Result<String, AppError> {
+    fs::read_to_string(expand_path(&path)).map_err(|e| AppError::io("Failed to read .bib file", e))
 }
 
-#[derive(Deserialize)]
-struct CrossRefAuthor {
-    given: Option<String>,
-    family: Option<String>,
+#[tauri::command]
+pub fn write_bib_file(path: String, content: String) -> Result<(), AppError> {
+    let path = expand_path(&path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| AppError::io("Failed to create directory", e))?;
+    }
+    fs::write(&path, content).map_err(|e| AppError::io("Failed to write .bib file", e))
 }
 
-#[derive(Deserialize)]
-struct CrossRefDate {
-    #[serde(rename = "date-parts")]
-    date_parts: Option<Vec<Vec<u32>>>,
+/// Required and recommended fields per BibTeX entry type
+fn expected_fields(entry_type: &str) -> (&'static [&'static str], &'static [&'static str]) {
+    match entry_type.to_lowercase().as_str() {
+        "article" => (
+            &["author", "title", "journal", "year"],
+            &["volume", "pages", "doi"],
+        ),
+        "book" => (&["author", "title", "publisher", "year"], &["isbn"]),
+        "inproceedings" | "conference" => (
+            &["author", "title", "booktitle", "year"],
+            &["pages", "publisher", "doi"],
+        ),
+        _ => (&["author", "title", "year"], &["url"]),
+    }
 }
 
-// OpenLibrary response types
-#[derive(Deserialize)]
-struct OpenLibraryBook {
-    title: Option<String>,
-    authors: Option<Vec<OpenLibraryAuthor>>,
-    publishers: Option<Vec<OpenLibraryPublisher>>,
-    publish_date: Option<String>,
-    url: Option<String>,
+fn field_present(entry: &BibEntry, field: &str) -> bool {
+    let value = match field {
+        "author" => Some(entry.author.as_str()),
+        "title" => Some(entry.title.as_str()),
+        // Lookups record an unknown year as 0
+        "year" => return entry.year > 0,
+        "journal" => entry.journal.as_deref(),
+        "publisher" => entry.publisher.as_deref(),
+        "volume" => entry.volume.as_deref(),
+        "pages" => entry.pages.as_deref(),
+        "doi" => entry.doi.as_deref(),
+        "url" => entry.url.as_deref(),
+        "isbn" => entry.isbn.as_deref(),
+        "booktitle" => entry.booktitle.as_deref(),
+        _ => None,
+    };
+    value.is_some_and(|v| !v.trim().is_empty())
 }
 
-#[derive(Deserialize)]
-struct OpenLibraryAuthor {
-    name: Option<String>,
-}
+pub fn validate_bib_entry(entry: &BibEntry) -> BibValidation {
+    let (required, recommended) = expected_fields(&entry.entry_type);
+    let missing = |fields: &[&str]| -> Vec<String> {
+        fields
+            .iter()
+            .filter(|f| !field_present(entry, f))
+            .map(|f| f.to_string())
+            .collect()
+    };
+    let missing_required = missing(required);
+    let missing_recommended = missing(recommended);
+
+    let total = required.len() + recommended.len();
+    let present = total - missing_required.len() - missing_recommended.len();
 
-#[derive(Deserialize)]
-struct OpenLibraryPublisher {
-    name: Option<String>,
+    BibValidation {
+        key: entry.key.clone(),
+        entry_type: entry.entry_type.clone(),
+        missing_required,
+        missing_recommended,
+        completeness: present as f64 / total as f64 * 100.0,
+    }
 }
 
 #[tauri::command]
-pub fn read_bib_file(path: String) -> Result<String, String> {
-    fs::read_to_string(&path).map_err(|e| format!("Failed to read .bib file: {}", e))
+pub fn validate_bib_entries(entries: Vec<BibEntry>) -> Vec<BibValidation> {
+    entries.iter().map(validate_bib_entry).collect()
 }
 
-#[tauri::command]
-pub fn write_bib_file(path: String, content: String) -> Result<(), String> {
-    if let Some(parent) = std::path::PathBuf::from(&path).parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+/// Which entry wins when both have a field filled in
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergePreference {
+    A,
+    B,
+}
+
+/// Combine two entries for the same work. Fields empty in the preferred entry are
+/// taken from the other; populated fields, including the key, keep the preferred value.
+pub fn merge_entries(a: BibEntry, b: BibEntry, prefer: MergePreference) -> BibEntry {
+    let (mut merged, other) = match prefer {
+        MergePreference::A => (a, b),
+        MergePreference::B => (b, a),
+    };
+
+    fn fill(field: &mut String, other: String) {
+        if field.trim().is_empty() {
+            *field = other;
+        }
     }
-    fs::write(&path, content).map_err(|e| format!("Failed to write .bib file: {}", e))
+    fn fill_option(field: &mut Option<String>, other: Option<String>) {
+        if field.as_deref().is_none_or(|v| v.trim().is_empty()) {
+            *field = other;
+        }
+    }
+
+    fill(&mut merged.key, other.key);
+    fill(&mut merged.entry_type, other.entry_type);
+    fill(&mut merged.author, other.author);
+    fill(&mut merged.title, other.title);
+    if merged.year == 0 {
+        merged.year = other.year;
+    }
+    fill_option(&mut merged.journal, other.journal);
+    fill_option(&mut merged.publisher, other.publisher);
+    fill_option(&mut merged.volume, other.volume);
+    fill_option(&mut merged.pages, other.pages);
+    fill_option(&mut merged.doi, other.doi);
+    fill_option(&mut merged.url, other.url);
+    fill_option(&mut merged.isbn, other.isbn);
+    fill_option(&mut merged.booktitle, other.booktitle);
+    merged
 }
 
 #[tauri::command]
-pub fn lookup_doi(doi: String) -> Result<BibEntry, String> {
-    let url = format!("https://api.crossref.org/works/{}", doi);
-
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("Lilia-Desktop/0.1.0 (mailto:contact@lilia.dev)")
-        .build()
-        .map_err(|e| format!("HTTP client error: {}", e))?;
-
-    let response: CrossRefResponse = client
-        .get(&url)
-        .send()
-        .map_err(|e| format!("CrossRef request failed: {}", e))?
-        .json()
-        .map_err(|e| format!("Failed to parse CrossRef response: {}", e))?;
-
-    let msg = response.message;
-
-    let title = msg
-        .title
-        .and_then(|t| t.into_iter().next())
-        .unwrap_or_default();
-
-    let author = msg
-        .author
-        .map(|authors| {
-            authors
-                .iter()
-                .map(|a| {
-                    let family = a.family.as_deref().unwrap_or("");
-                    let given = a.given.as_deref().unwrap_or("");
-                    if given.is_empty() {
-                        family.to_string()
-                    } else {
-                        format!("{}, {}", family, given)
-                    }
-                })
-                .collect::<Vec<_>>()
-                .join(" and ")
+pub fn merge_bib_entries(a: BibEntry, b: BibEntry, prefer: MergePreference) -> BibEntry {
+    merge_entries(a, b, prefer)
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CitationStyle {
+    Apa,
+    Mla,
+    Ieee,
+}
+
+/// An author name split into family and given parts
+struct AuthorName {
+    family: String,
+    given: String,
+}
+
+impl AuthorName {
+    /// Given names reduced to initials: "Jane Ann" -> "J. A."
+    fn initials(&self) -> String {
+        self.given
+            .split([' ', '-'])
+            .filter_map(|part| part.chars().next())
+            .map(|c| format!("{}.", c))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Split a BibTeX author list ("Family, Given and Given Family") into names
+fn parse_authors(author: &str) -> Vec<AuthorName> {
+    author
+        .split(" and ")
+        .map(str::trim)
+        .filter(|a| !a.is_empty())
+        .map(|a| match a.split_once(',') {
+            Some((family, given)) => AuthorName {
+                family: family.trim().to_string(),
+                given: given.trim().to_string(),
+            },
+            None => match a.rsplit_once(' ') {
+                Some((given, family)) => AuthorName {
+                    family: family.trim().to_string(),
+                    given: given.trim().to_string(),
+                },
+                None => AuthorName {
+                    family: a.to_string(),
+                    given: String::new(),
+                },
+            },
+        })
+        .collect()
+}
+
+/// Join names as "A, B, and C" with the given final conjunction
+fn join_names(names: Vec<String>, conjunction: &str) -> String {
+    match names.len() {
+        0 => String::new(),
+        1 => names[0].clone(),
+        2 => format!("{} {} {}", names[0], conjunction, names[1]),
+        n => format!("{}, {} {}", names[..n - 1].join(", "), conjunction, names[n - 1]),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+/// Append `part` to a citation, ending the previous part with `separator`
+fn push_part(out: &mut String, separator: &str, part: &str) {
+    if !out.is_empty() {
+        out.push_str(separator);
+    }
+    out.push_str(part);
+}
+
+fn format_apa(entry: &BibEntry) -> String {
+    let names: Vec<String> = parse_authors(&entry.author)
+        .iter()
+        .map(|a| {
+            let initials = a.initials();
+            if initials.is_empty() {
+                a.family.clone()
+            } else {
+                format!("{}, {}", a.family, initials)
+            }
         })
-        .unwrap_or_default();
-
-    let year = msg
-        .published_print
-        .or(msg.published_online)
-        .and_then(|d| d.date_parts)
-        .and_then(|parts| parts.into_iter().next())
-        .and_then(|parts| parts.into_iter().next())
-        .unwrap_or(0);
-
-    let entry_type = match msg.work_type.as_deref() {
-        Some("journal-article") => "article",
-        Some("book") | Some("monograph") => "book",
-        Some("proceedings-article") => "inproceedings",
-        _ => "misc",
+        .collect();
+    let authors = match names.len() {
+        0 | 1 => names.join(""),
+        n => format!("{}, & {}", names[..n - 1].join(", "), names[n - 1]),
     };
 
-    // Generate a citation key from first author's family name + year
-    let key = {
-        let family = author.split(',').next().unwrap_or("unknown").trim().to_lowercase();
-        let clean: String = family.chars().filter(|c| c.is_alphanumeric()).collect();
-        format!("{}{}", clean, year)
+    let mut out = authors;
+    let year = if entry.year > 0 {
+        entry.year.to_string()
+    } else {
+        "n.d.".to_string()
     };
+    push_part(&mut out, " ", &format!("({}).", year));
+    if !entry.title.trim().is_empty() {
+        push_part(&mut out, " ", &format!("{}.", entry.title.trim().trim_end_matches('.')));
+    }
 
-    Ok(BibEntry {
-        key,
-        entry_type: entry_type.to_string(),
-        author,
-        title,
-        year,
-        journal: msg.container_title.and_then(|t| t.into_iter().next()),
-        publisher: msg.publisher,
-        volume: msg.volume,
-        pages: msg.page,
-        doi: msg.doi,
-        url: msg.url,
-        isbn: None,
-        booktitle: None,
-    })
+    if let Some(container) = non_empty(&entry.journal).or(non_empty(&entry.booktitle)) {
+        let mut source = container.to_string();
+        if let Some(volume) = non_empty(&entry.volume) {
+            source.push_str(&format!(", {}", volume));
+        }
+        if let Some(pages) = non_empty(&entry.pages) {
+            source.push_str(&format!(", {}", pages));
+        }
+        push_part(&mut out, " ", &format!("{}.", source));
+    } else if let Some(publisher) = non_empty(&entry.publisher) {
+        push_part(&mut out, " ", &format!("{}.", publisher));
+    }
+
+    if let Some(doi) = non_empty(&entry.doi) {
+        push_part(&mut out, " ", &format!("https://doi.org/{}", doi));
+    } else if let Some(url) = non_empty(&entry.url) {
+        push_part(&mut out, " ", url);
+    }
+    out
 }
 
-#[tauri::command]
-pub fn lookup_isbn(isbn: String) -> Result<BibEntry, String> {
-    let clean_isbn: String = isbn.chars().filter(|c| c.is_alphanumeric()).collect();
-    let url = format!(
-        "https://openlibrary.org/api/books?bibkeys=ISBN:{}&format=json&jscmd=data",
-        clean_isbn
-    );
+fn format_mla(entry: &BibEntry) -> String {
+    let authors = parse_authors(&entry.author);
+    let full = |a: &AuthorName| {
+        if a.given.is_empty() {
+            a.family.clone()
+        } else {
+            format!("{} {}", a.given, a.family)
+        }
+    };
+    let inverted = |a: &AuthorName| {
+        if a.given.is_empty() {
+            a.family.clone()
+        } else {
+            format!("{}, {}", a.family, a.given)
+        }
+    };
+    let author_text = match authors.len() {
+        0 => String::new(),
+        1 => inverted(&authors[0]),
+        2 => format!("{}, and {}", inverted(&authors[0]), full(&authors[1])),
+        _ => format!("{}, et al", inverted(&authors[0])),
+    };
+
+    let mut out = String::new();
+    if !author_text.is_empty() {
+        out.push_str(&format!("{}.", author_text.trim_end_matches('.')));
+    }
 
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("Lilia-Desktop/0.1.0")
-        .build()
-        .map_err(|e| format!("HTTP client error: {}", e))?;
-
-    let response: serde_json::Value = client
-        .get(&url)
-        .send()
-        .map_err(|e| format!("OpenLibrary request failed: {}", e))?
-        .json()
-        .map_err(|e| format!("Failed to parse OpenLibrary response: {}", e))?;
-
-    let bib_key = format!("ISBN:{}", clean_isbn);
-    let book_data = response
-        .get(&bib_key)
-        .ok_or_else(|| format!("ISBN {} not found", clean_isbn))?;
-
-    let book: OpenLibraryBook = serde_json::from_value(book_data.clone())
-        .map_err(|e| format!("Failed to parse book data: {}", e))?;
-
-    let title = book.title.unwrap_or_default();
-    let author = book
-        .authors
+    let title = entry.title.trim().trim_end_matches('.');
+    let container = non_empty(&entry.journal).or(non_empty(&entry.booktitle));
+    if !title.is_empty() {
+        if container.is_some() {
+            push_part(&mut out, " ", &format!("\"{}.\"", title));
+        } else {
+            push_part(&mut out, " ", &format!("{}.", title));
+        }
+    }
+
+    let mut details: Vec<String> = vec![];
+    match container {
+        Some(container) => {
+            details.push(container.to_string());
+            if let Some(volume) = non_empty(&entry.volume) {
+                details.push(format!("vol. {}", volume));
+            }
+            if entry.year > 0 {
+                details.push(entry.year.to_string());
+            }
+            if let Some(pages) = non_empty(&entry.pages) {
+                details.push(format!("pp. {}", pages));
+            }
+        }
+        None => {
+            if let Some(publisher) = non_empty(&entry.publisher) {
+                details.push(publisher.to_string());
+            }
+            if entry.year > 0 {
+                details.push(entry.year.to_string());
+            }
+        }
+    }
+    if !details.is_empty() {
+        push_part(&mut out, " ", &format!("{}.", details.join(", ")));
+    }
+    out
+}
+
+fn format_ieee(entry: &BibEntry, number: usize) -> String {
+    let names: Vec<String> = parse_authors(&entry.author)
+        .iter()
         .map(|a| {
-            a.iter()
-                .filter_map(|a| a.name.clone())
-                .collect::<Vec<_>>()
-                .join(" and ")
+            let initials = a.initials();
+            if initials.is_empty() {
+                a.family.clone()
+            } else {
+                format!("{} {}", initials, a.family)
+            }
         })
-        .unwrap_or_default();
-
-    let publisher = book.publishers.and_then(|p| p.into_iter().next()).and_then(|p| p.name);
-
-    // Try to extract year from publish_date
-    let year = book
-        .publish_date
-        .and_then(|d| {
-            d.chars()
-                .filter(|c| c.is_ascii_digit())
-                .collect::<String>()
-                .get(..4)
-                .and_then(|y| y.parse::<u32>().ok())
+        .collect();
+
+    let mut parts: Vec<String> = vec![];
+    let authors = join_names(names, "and");
+    if !authors.is_empty() {
+        parts.push(authors);
+    }
+
+    let title = entry.title.trim().trim_end_matches('.');
+    match non_empty(&entry.journal).or(non_empty(&entry.booktitle)) {
+        Some(container) => {
+            if !title.is_empty() {
+                parts.push(format!("\"{},\"", title));
+            }
+            let mut source = vec![container.to_string()];
+            if let Some(volume) = non_empty(&entry.volume) {
+                source.push(format!("vol. {}", volume));
+            }
+            if let Some(pages) = non_empty(&entry.pages) {
+                source.push(format!("pp. {}", pages));
+            }
+            if entry.year > 0 {
+                source.push(entry.year.to_string());
+            }
+            parts.push(format!("{}.", source.join(", ")));
+        }
+        None => {
+            if !title.is_empty() {
+                parts.push(format!("{}.", title));
+            }
+            let source: Vec<String> = non_empty(&entry.publisher)
+                .map(str::to_string)
+                .into_iter()
+                .chain((entry.year > 0).then(|| entry.year.to_string()))
+                .collect();
+            if !source.is_empty() {
+                parts.push(format!("{}.", source.join(", ")));
+            }
+        }
+    }
+
+    // The author list ends in a comma before the title
+    let mut out = format!("[{}]", number);
+    for (i, part) in parts.iter().enumerate() {
+        let is_author = i == 0 && !entry.author.trim().is_empty();
+        out.push(' ');
+        out.push_str(part);
+        if is_author && parts.len() > 1 {
+            out.push(',');
+        }
+    }
+    out
+}
+
+/// Render one entry as a reference-list citation in the given style
+pub fn format_reference(entry: &BibEntry, style: CitationStyle, number: usize) -> String {
+    match style {
+        CitationStyle::Apa => format_apa(entry),
+        CitationStyle::Mla => format_mla(entry),
+        CitationStyle::Ieee => format_ieee(entry, number),
+    }
+}
+
+#[tauri::command]
+pub fn format_references(entries: Vec<BibEntry>, style: CitationStyle) -> Vec<String> {
+    entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| format_reference(entry, style, i + 1))
+        .collect()
+}
+
+/// Escape LaTeX special characters (`&`, `%`, `_`, `#`, `$`) in a BibTeX
+/// field value. Backslash sequences are copied as written, so already-escaped
+/// text is not escaped twice, and `$...$` / `$$...$$` math is left intact.
+pub fn escape_bibtex_value(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut out = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                out.extend(&chars[i..(i + 2).min(chars.len())]);
+                i += 2;
+            }
+            '$' => {
+                let delim = if chars.get(i + 1) == Some(&'$') { 2 } else { 1 };
+                match closing_math_delimiter(&chars, i + delim, delim) {
+                    Some(end) => {
+                        out.extend(&chars[i..end + delim]);
+                        i = end + delim;
+                    }
+                    None => {
+                        out.push_str("\\$");
+                        i += 1;
+                    }
+                }
+            }
+            c => {
+                if matches!(c, '&' | '%' | '_' | '#') {
+                    out.push('\\');
+                }
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+/// Position of the `$` (or `$$`) closing a math span opened just before `from`.
+/// Like TeX-aware Markdown, inline math can't start with whitespace or end
+/// before a digit, so prices like "$5 and $10" aren't taken for math.
+fn closing_math_delimiter(chars: &[char], from: usize, delim: usize) -> Option<usize> {
+    if delim == 1 && chars.get(from).is_none_or(|c| c.is_whitespace()) {
+        return None;
+    }
+    let mut i = from;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '$' if delim == 2 => {
+                if chars.get(i + 1) == Some(&'$') {
+                    return Some(i);
+                }
+                i += 1;
+            }
+            '$' => {
+                let closes = !chars[i - 1].is_whitespace()
+                    && !chars.get(i + 1).is_some_and(|c| c.is_ascii_digit());
+                if closes {
+                    return Some(i);
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+/// Render entries as BibTeX. Field values are escaped with `escape_bibtex_value`,
+/// except `doi` and `url`, which are identifiers rather than text.
+pub fn entries_to_bibtex(entries: &[BibEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| {
+            let year = (entry.year > 0).then(|| entry.year.to_string());
+            let text_fields = [
+                ("author", Some(&entry.author).filter(|a| !a.is_empty())),
+                ("title", Some(&entry.title).filter(|t| !t.is_empty())),
+                ("year", year.as_ref()),
+                ("journal", entry.journal.as_ref()),
+                ("booktitle", entry.booktitle.as_ref()),
+                ("publisher", entry.publisher.as_ref()),
+                ("volume", entry.volume.as_ref()),
+                ("pages", entry.pages.as_ref()),
+                ("isbn", entry.isbn.as_ref()),
+            ];
+            let mut out = format!("@{}{{{},\n", entry.entry_type, entry.key);
+            for (name, value) in text_fields {
+                if let Some(value) = value {
+                    out.push_str(&format!("  {} = {{{}}},\n", name, escape_bibtex_value(value)));
+                }
+            }
+            for (name, value) in [("doi", &entry.doi), ("url", &entry.url)] {
+                if let Some(value) = value {
+                    out.push_str(&format!("  {} = {{{}}},\n", name, value));
+                }
+            }
+            out.push_str("}\n");
+            out
         })
-        .unwrap_or(0);
-
-    let key = {
-        let family = author
-            .split_whitespace()
-            .last()
-            .unwrap_or("unknown")
-            .to_lowercase();
-        let clean: String = family.chars().filter(|c| c.is_alphanumeric()).collect();
-        format!("{}{}", clean, year)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// BibTeX source for the given entries, ready for `write_bib_file`
+#[tauri::command]
+pub fn serialize_bib_entries(entries: Vec<BibEntry>) -> String {
+    entries_to_bibtex(&entries)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DoiLookupFailure {
+    pub doi: String,
+    pub error: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DoiImportResult {
+    pub entries: Vec<BibEntry>,
+    pub failures: Vec<DoiLookupFailure>,
+}
+
+/// DOIs from a one-per-line list, skipping blanks and `#` comments. Duplicates
+/// (compared after normalization) are kept once, in first-seen order.
+fn parse_doi_list(content: &str) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .filter(|l| {
+            let key = normalize_doi(l).unwrap_or_else(|_| l.to_string()).to_lowercase();
+            seen.insert(key)
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+/// Resolve every DOI listed in a text file through the cached batch lookup
+#[tauri::command]
+pub async fn import_bib_from_doi_file(
+    path: String,
+    offline: Option<bool>,
+    operation_id: Option<String>,
+    app: AppHandle,
+) -> Result<DoiImportResult, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let content = fs::read_to_string(expand_path(&path))
+            .map_err(|e| AppError::io("Failed to read file", e))?;
+        let dois = parse_doi_list(&content);
+        let lookups = lookup_dois_blocking(&dois, offline, operation_id, &app)?;
+        Ok(collect_doi_import(lookups))
+    })
+    .await
+    .map_err(|e| AppError::Other(format!("DOI import failed: {}", e)))?
+}
+
+/// Split batch lookup results into resolved entries and failures
+fn collect_doi_import(lookups: Vec<DoiLookupResult>) -> DoiImportResult {
+    let mut result = DoiImportResult {
+        entries: vec![],
+        failures: vec![],
+    };
+    for lookup in lookups {
+        match (lookup.entry, lookup.error) {
+            (Some(entry), _) => result.entries.push(entry),
+            (None, error) => result.failures.push(DoiLookupFailure {
+                doi: lookup.doi,
+                error: error.unwrap_or_else(|| "Lookup failed".to_string()),
+            }),
+        }
+    }
+    result
+}
+
+/// Family name + year, the scheme used before keys became configurable
+pub const DEFAULT_CITATION_KEY_PATTERN: &str = "{author}{year}";
+
+/// Short words skipped by `{title}` tokens
+const TITLE_STOPWORDS: &[&str] = &["a", "an", "and", "for", "in", "of", "on", "the", "to", "with"];
+
+fn key_pattern(settings: &crate::settings::Settings) -> &str {
+    settings
+        .citation_key_pattern
+        .as_deref()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or(DEFAULT_CITATION_KEY_PATTERN)
+}
+
+/// Family name of the first author. Handles both "Family, Given" and
+/// "Given Family" spellings.
+fn first_author_family(author: &str) -> String {
+    let first = author.split(" and ").next().unwrap_or("").trim();
+    match first.split_once(',') {
+        Some((family, _)) => family.trim().to_string(),
+        None => first.split_whitespace().last().unwrap_or("").to_string(),
+    }
+}
+
+/// Expand a single `{token}` for an entry; unknown tokens expand to nothing
+fn expand_key_token(entry: &BibEntry, token: &str) -> String {
+    let (name, arg) = match token.split_once(':') {
+        Some((name, arg)) => (name.trim(), Some(arg.trim())),
+        None => (token.trim(), None),
+    };
+    match name {
+        "author" => first_author_family(&entry.author).to_lowercase(),
+        "Author" => {
+            let family = first_author_family(&entry.author);
+            let mut chars = family.chars();
+            match chars.next() {
+                Some(c) => c.to_uppercase().chain(chars.flat_map(char::to_lowercase)).collect(),
+                None => String::new(),
+            }
+        }
+        "year" if entry.year > 0 => entry.year.to_string(),
+        "title" => {
+            let count = arg.and_then(|n| n.parse::<usize>().ok()).unwrap_or(1);
+            entry
+                .title
+                .split(|c: char| !c.is_alphanumeric())
+                .filter(|w| !w.is_empty() && !TITLE_STOPWORDS.contains(&w.to_lowercase().as_str()))
+                .take(count)
+                .map(str::to_lowercase)
+                .collect()
+        }
+        _ => String::new(),
+    }
+}
+
+/// Keep only characters that are safe in a BibTeX key, folding common accents
+fn sanitize_citation_key(raw: &str) -> String {
+    raw.chars()
+        .filter_map(|c| match c {
+            'à' | 'á' | 'â' | 'ä' | 'ã' | 'å' => Some('a'),
+            'ç' => Some('c'),
+            'è' | 'é' | 'ê' | 'ë' => Some('e'),
+            'ì' | 'í' | 'î' | 'ï' => Some('i'),
+            'ñ' => Some('n'),
+            'ò' | 'ó' | 'ô' | 'ö' | 'õ' | 'ø' => Some('o'),
+            'ù' | 'ú' | 'û' | 'ü' => Some('u'),
+            c if c.is_ascii_alphanumeric() || "-_:.".contains(c) => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Build a citation key from a pattern of literal text and `{author}`,
+/// `{Author}`, `{year}`, and `{title}` / `{title:N}` tokens
+pub fn citation_key(entry: &BibEntry, pattern: &str) -> String {
+    let mut raw = String::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        raw.push_str(&rest[..start]);
+        match rest[start..].find('}') {
+            Some(end) => {
+                raw.push_str(&expand_key_token(entry, &rest[start + 1..start + end]));
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    raw.push_str(rest);
+
+    let key = sanitize_citation_key(&raw);
+    if key.is_empty() {
+        "unknown".to_string()
+    } else {
+        key
+    }
+}
+
+#[tauri::command]
+pub fn generate_citation_key(entry: BibEntry, pattern: String) -> String {
+    citation_key(&entry, &pattern)
+}
+
+/// Loose shape check for a contact address: `local@domain.tld`, no whitespace
+fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.split_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !email.chars().any(|c| c.is_whitespace() || c == '(' || c == ')')
+        && !domain.contains('@')
+        && domain
+            .split_once('.')
+            .is_some_and(|(host, tld)| !host.is_empty() && !tld.is_empty() && !tld.ends_with('.'))
+}
+
+/// CrossRef's polite pool identifies clients by the `mailto:` in the user agent
+fn crossref_user_agent(contact_email: Option<&str>) -> String {
+    let email = contact_email
+        .map(str::trim)
+        .filter(|e| is_valid_email(e))
+        .unwrap_or(DEFAULT_CONTACT_EMAIL);
+    format!("Lilia-Desktop/0.1.0 (mailto:{})", email)
+}
+
+/// The lookup providers configured by the current settings. The HTTP client is
+/// only built once a provider actually fetches.
+fn provider_registry(settings: &crate::settings::Settings) -> ProviderRegistry {
+    ProviderRegistry::with_defaults(
+        Arc::new(LazyReqwestClient::from_settings(settings)),
+        crossref_user_agent(settings.crossref_contact_email.as_deref()),
+    )
+}
+
+#[tauri::command]
+pub fn lookup_doi(
+    doi: String,
+    offline: Option<bool>,
+    state: State<AppState>,
+) -> Result<BibEntry, AppError> {
+    let settings = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings();
+
+    let registry = provider_registry(&settings);
+    let mut entry = resolve_identifier(
+        &state.bib_cache,
+        registry.get("doi")?,
+        &doi,
+        offline.unwrap_or(settings.offline_mode),
+    )?;
+    entry.key = citation_key(&entry, key_pattern(&settings));
+    Ok(entry)
+}
+
+/// Strip resolver URLs and `doi:` prefixes, then check the `10.<registrant>/<suffix>` shape
+pub fn normalize_doi(input: &str) -> Result<String, AppError> {
+    let trimmed = input.trim();
+    let lower = trimmed.to_lowercase();
+    let prefixes = [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi.org/",
+        "doi:",
+    ];
+    let doi = prefixes
+        .iter()
+        .find(|p| lower.starts_with(*p))
+        .map(|p| &trimmed[p.len()..])
+        .unwrap_or(trimmed)
+        .trim();
+
+    let valid = doi.starts_with("10.")
+        && doi
+            .split_once('/')
+            .is_some_and(|(prefix, suffix)| prefix.len() > 3 && !suffix.is_empty());
+    if !valid || doi.chars().any(char::is_whitespace) {
+        return Err(AppError::Validation(format!(
+            "Invalid DOI '{}': expected a form like 10.1234/abc",
+            input
+        )));
+    }
+    Ok(doi.to_string())
+}
+
+/// Strip separators and an `ISBN` prefix, then verify the ISBN-10 or ISBN-13 check digit
+pub fn normalize_isbn(input: &str) -> Result<String, AppError> {
+    let trimmed = input.trim();
+    let without_label = if trimmed.to_uppercase().starts_with("ISBN") {
+        &trimmed[4..]
+    } else {
+        trimmed
     };
+    let isbn: String = without_label
+        .chars()
+        .filter(|c| !matches!(c, '-' | ' ' | ':'))
+        .collect::<String>()
+        .to_uppercase();
+
+    let digits: Vec<u32> = isbn.chars().filter_map(|c| c.to_digit(10)).collect();
+    let valid = match isbn.len() {
+        10 if isbn.chars().take(9).all(|c| c.is_ascii_digit()) => {
+            let check = match isbn.chars().last() {
+                Some('X') => Some(10),
+                Some(c) => c.to_digit(10),
+                None => None,
+            };
+            check.is_some_and(|check| {
+                let sum: u32 = digits[..9]
+                    .iter()
+                    .enumerate()
+                    .map(|(i, d)| (10 - i as u32) * d)
+                    .sum();
+                (sum + check).is_multiple_of(11)
+            })
+        }
+        13 if digits.len() == 13 => {
+            let sum: u32 = digits
+                .iter()
+                .enumerate()
+                .map(|(i, d)| if i % 2 == 0 { *d } else { d * 3 })
+                .sum();
+            sum.is_multiple_of(10)
+        }
+        _ => false,
+    };
+
+    if !valid {
+        return Err(AppError::Validation(format!(
+            "Invalid ISBN '{}': checksum does not match",
+            input.trim()
+        )));
+    }
+    Ok(isbn)
+}
+
+/// Cache-first resolution through a provider, shared by single and batch lookups
+fn resolve_identifier(
+    cache: &Mutex<BibCache>,
+    provider: &dyn MetadataProvider,
+    identifier: &str,
+    offline: bool,
+) -> Result<BibEntry, AppError> {
+    let identifier = &provider.normalize(identifier)?;
+    let cache_key = provider.cache_key(identifier);
+    if let Some(entry) = cached_entry(cache, &cache_key) {
+        return Ok(entry);
+    }
+    if offline {
+        return Err(AppError::Offline(format!(
+            "Cannot look up {} {} without a network connection",
+            provider.kind().to_uppercase(),
+            identifier
+        )));
+    }
+
+    let entry = provider.fetch(identifier)?;
+    cache_entry(cache, cache_key, &entry);
+    Ok(entry)
+}
+
+/// Number of DOI requests in flight at once during a batch lookup
+const BATCH_LOOKUP_WORKERS: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DoiLookupResult {
+    pub doi: String,
+    pub entry: Option<BibEntry>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BibLookupProgress {
+    pub completed: usize,
+    pub total: usize,
+    pub current_doi: String,
+}
 
-    Ok(BibEntry {
-        key,
-        entry_type: "book".to_string(),
-        author,
-        title,
-        year,
-        journal: None,
-        publisher,
-        volume: None,
-        pages: None,
-        doi: None,
-        url: book.url,
-        isbn: Some(clean_isbn),
-        booktitle: None,
+/// Resolve many DOIs concurrently, emitting `bib-lookup-progress` as each one
+/// finishes. Results are returned in input order. Runs off the main thread so
+/// progress reaches the UI and `cancel_operation` can stop the batch.
+#[tauri::command]
+pub async fn lookup_dois(
+    dois: Vec<String>,
+    offline: Option<bool>,
+    operation_id: Option<String>,
+    app: AppHandle,
+) -> Result<Vec<DoiLookupResult>, AppError> {
+    tauri::async_runtime::spawn_blocking(move || {
+        lookup_dois_blocking(&dois, offline, operation_id, &app)
     })
+    .await
+    .map_err(|e| AppError::Other(format!("DOI lookup failed: {}", e)))?
+}
+
+fn lookup_dois_blocking(
+    dois: &[String],
+    offline: Option<bool>,
+    operation_id: Option<String>,
+    app: &AppHandle,
+) -> Result<Vec<DoiLookupResult>, AppError> {
+    let state = app.state::<AppState>();
+    let settings = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings();
+    let offline = offline.unwrap_or(settings.offline_mode);
+    let registry = provider_registry(&settings);
+    let provider = registry.get("doi")?;
+    let pattern = key_pattern(&settings);
+
+    let operation = Operation::begin(&state.operations, operation_id);
+    let results = resolve_dois(
+        &state.bib_cache,
+        provider,
+        dois,
+        offline,
+        pattern,
+        &operation,
+        |progress| {
+            app.emit("bib-lookup-progress", progress).ok();
+        },
+    );
+
+    operation.check()?;
+    Ok(results)
+}
+
+/// Resolve `dois` on up to `BATCH_LOOKUP_WORKERS` threads, passing progress for
+/// each one to `on_progress` as it finishes. Results keep input order; DOIs not
+/// started before `operation` was cancelled are left out.
+fn resolve_dois(
+    cache: &Mutex<BibCache>,
+    provider: &dyn MetadataProvider,
+    dois: &[String],
+    offline: bool,
+    key_pattern: &str,
+    operation: &Operation,
+    mut on_progress: impl FnMut(BibLookupProgress),
+) -> Vec<DoiLookupResult> {
+    let total = dois.len();
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<DoiLookupResult>> = vec![None; total];
+
+    std::thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..BATCH_LOOKUP_WORKERS.min(total) {
+            let tx = tx.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                if operation.is_cancelled() {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(doi) = dois.get(index) else { break };
+                let outcome = resolve_identifier(cache, provider, doi, offline);
+                if tx.send((index, outcome)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        // Progress is reported from this thread only, so it arrives in completion order
+        for (completed, (index, outcome)) in rx.iter().enumerate() {
+            let doi = dois[index].clone();
+            on_progress(BibLookupProgress {
+                completed: completed + 1,
+                total,
+                current_doi: doi.clone(),
+            });
+            let (entry, error) = match outcome {
+                Ok(mut entry) => {
+                    entry.key = citation_key(&entry, key_pattern);
+                    (Some(entry), None)
+                }
+                Err(e) => (None, Some(e.to_string())),
+            };
+            results[index] = Some(DoiLookupResult { doi, entry, error });
+        }
+    });
+
+    results.into_iter().flatten().collect()
+}
+
+#[tauri::command]
+pub fn lookup_isbn(
+    isbn: String,
+    offline: Option<bool>,
+    state: State<AppState>,
+) -> Result<BibEntry, AppError> {
+    let settings = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings();
+
+    let registry = provider_registry(&settings);
+    let mut entry = resolve_identifier(
+        &state.bib_cache,
+        registry.get("isbn")?,
+        &isbn,
+        offline.unwrap_or(settings.offline_mode),
+    )?;
+    entry.key = citation_key(&entry, key_pattern(&settings));
+    Ok(entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::HttpClient;
+
+    fn entry(entry_type: &str) -> BibEntry {
+        BibEntry {
+            key: String::new(),
+            entry_type: entry_type.to_string(),
+            author: String::new(),
+            title: String::new(),
+            year: 0,
+            journal: None,
+            publisher: None,
+            volume: None,
+            pages: None,
+            doi: None,
+            url: None,
+            isbn: None,
+            booktitle: None,
+        }
+    }
+
+    /// Answers every request with `response`, recording the URL and user agent
+    struct RecordingHttp {
+        response: serde_json::Value,
+        requests: Mutex<Vec<(String, String)>>,
+    }
+
+    impl RecordingHttp {
+        fn new(response: serde_json::Value) -> Arc<Self> {
+            Arc::new(Self {
+                response,
+                requests: Mutex::new(vec![]),
+            })
+        }
+    }
+
+    impl HttpClient for RecordingHttp {
+        fn get_json(&self, url: &str, user_agent: &str) -> Result<serde_json::Value, AppError> {
+            self.requests
+                .lock()
+                .unwrap()
+                .push((url.to_string(), user_agent.to_string()));
+            Ok(self.response.clone())
+        }
+    }
+
+    #[test]
+    fn crossref_user_agent_uses_configured_email() {
+        assert_eq!(
+            crossref_user_agent(Some(" me@uni.edu ")),
+            "Lilia-Desktop/0.1.0 (mailto:me@uni.edu)"
+        );
+        for fallback in [
+            None,
+            Some(""),
+            Some("not-an-email"),
+            Some("me@uni"),
+            Some("a b@c.d"),
+        ] {
+            assert_eq!(
+                crossref_user_agent(fallback),
+                "Lilia-Desktop/0.1.0 (mailto:contact@lilia.dev)"
+            );
+        }
+    }
+
+    #[test]
+    fn doi_lookups_send_the_configured_user_agent() {
+        let http = RecordingHttp::new(serde_json::json!({ "message": { "title": ["On Things"] } }));
+        let registry =
+            ProviderRegistry::with_defaults(http.clone(), crossref_user_agent(Some("me@uni.edu")));
+
+        let entry = registry.get("doi").unwrap().fetch("10.1000/182").unwrap();
+        assert_eq!(entry.title, "On Things");
+        assert_eq!(
+            *http.requests.lock().unwrap(),
+            [(
+                "https://api.crossref.org/works/10.1000/182".to_string(),
+                "Lilia-Desktop/0.1.0 (mailto:me@uni.edu)".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn email_validation() {
+        assert!(is_valid_email("someone@example.com"));
+        assert!(is_valid_email("first.last+tag@sub.example.org"));
+        assert!(!is_valid_email("someone@"));
+        assert!(!is_valid_email("@example.com"));
+        assert!(!is_valid_email("someone@example."));
+        assert!(!is_valid_email("a@b@example.com"));
+        assert!(!is_valid_email("some one@example.com"));
+        assert!(!is_valid_email("x@example.com) (evil"));
+    }
+
+    /// A DOI provider that counts fetches instead of touching the network
+    struct CountingProvider {
+        fetches: AtomicUsize,
+    }
+
+    impl MetadataProvider for CountingProvider {
+        fn kind(&self) -> &'static str {
+            "doi"
+        }
+
+        fn normalize(&self, identifier: &str) -> Result<String, AppError> {
+            normalize_doi(identifier)
+        }
+
+        fn fetch(&self, doi: &str) -> Result<BibEntry, AppError> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok(BibEntry {
+                doi: Some(doi.to_string()),
+                ..entry("misc")
+            })
+        }
+    }
+
+    fn empty_cache(dir: &tempfile::TempDir) -> Mutex<BibCache> {
+        Mutex::new(BibCache::new(dir.path().join("bib_cache.json")))
+    }
+
+    #[test]
+    fn offline_lookup_fails_fast_without_fetching() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = CountingProvider {
+            fetches: AtomicUsize::new(0),
+        };
+
+        let err =
+            resolve_identifier(&empty_cache(&dir), &provider, "10.1000/182", true).unwrap_err();
+        assert!(matches!(err, AppError::Offline(_)), "{:?}", err);
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn offline_lookup_returns_cached_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = empty_cache(&dir);
+        let provider = CountingProvider {
+            fetches: AtomicUsize::new(0),
+        };
+
+        // The first, online lookup fills the cache; identifiers are normalized first
+        resolve_identifier(&cache, &provider, "10.1000/182", false).unwrap();
+        let entry = resolve_identifier(&cache, &provider, "doi:10.1000/182", true).unwrap();
+        assert_eq!(entry.doi.as_deref(), Some("10.1000/182"));
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn offline_lookup_never_builds_the_http_client() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = empty_cache(&dir);
+        cache.lock().unwrap().insert(
+            "doi:10.1000/182".to_string(),
+            BibEntry {
+                title: "Cached".to_string(),
+                ..entry("misc")
+            },
+        );
+        // Building a client from this proxy would fail
+        let settings = crate::settings::Settings {
+            http_proxy: Some("ftp://proxy.example.com".to_string()),
+            ..Default::default()
+        };
+        let registry = provider_registry(&settings);
+        let provider = registry.get("doi").unwrap();
+
+        let entry = resolve_identifier(&cache, provider, "10.1000/182", true).unwrap();
+        assert_eq!(entry.title, "Cached");
+        let err = resolve_identifier(&cache, provider, "10.1000/999", true).unwrap_err();
+        assert!(matches!(err, AppError::Offline(_)), "{:?}", err);
+    }
+
+    fn filled(entry_type: &str) -> BibEntry {
+        let some = |v: &str| Some(v.to_string());
+        BibEntry {
+            key: "doe2020".to_string(),
+            author: "Doe, Jane".to_string(),
+            title: "A Study".to_string(),
+            year: 2020,
+            journal: some("Journal of Studies"),
+            publisher: some("Academic Press"),
+            volume: some("12"),
+            pages: some("1--10"),
+            doi: some("10.1000/182"),
+            url: some("https://example.com"),
+            isbn: some("9780306406157"),
+            booktitle: some("Proceedings of Things"),
+            ..entry(entry_type)
+        }
+    }
+
+    #[test]
+    fn complete_entries_score_full_marks() {
+        for entry_type in ["article", "book", "inproceedings", "misc"] {
+            let validation = validate_bib_entry(&filled(entry_type));
+            assert!(validation.missing_required.is_empty(), "{}", entry_type);
+            assert!(validation.missing_recommended.is_empty(), "{}", entry_type);
+            assert_eq!(validation.completeness, 100.0, "{}", entry_type);
+        }
+    }
+
+    #[test]
+    fn incomplete_article_needs_journal_and_year() {
+        let article = BibEntry {
+            journal: None,
+            year: 0,
+            doi: Some("  ".to_string()),
+            ..filled("article")
+        };
+        let validation = validate_bib_entry(&article);
+        assert_eq!(validation.missing_required, ["journal", "year"]);
+        assert_eq!(validation.missing_recommended, ["doi"]);
+        // 4 of 7 fields present
+        assert!((validation.completeness - 400.0 / 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn incomplete_book_needs_publisher() {
+        let book = BibEntry {
+            publisher: None,
+            ..filled("Book")
+        };
+        let validation = validate_bib_entry(&book);
+        assert_eq!(validation.missing_required, ["publisher"]);
+        assert_eq!(validation.completeness, 80.0);
+    }
+
+    #[test]
+    fn incomplete_inproceedings_needs_booktitle() {
+        let paper = BibEntry {
+            booktitle: None,
+            pages: None,
+            ..filled("inproceedings")
+        };
+        let validations = validate_bib_entries(vec![paper, filled("article")]);
+        assert_eq!(validations[0].missing_required, ["booktitle"]);
+        assert_eq!(validations[0].missing_recommended, ["pages"]);
+        assert_eq!(validations[0].key, "doe2020");
+        assert_eq!(validations[1].completeness, 100.0);
+    }
+
+    /// Resolves `10.1000/<ms>` after sleeping that many milliseconds
+    struct SlowProvider;
+
+    impl MetadataProvider for SlowProvider {
+        fn kind(&self) -> &'static str {
+            "doi"
+        }
+
+        fn normalize(&self, identifier: &str) -> Result<String, AppError> {
+            normalize_doi(identifier)
+        }
+
+        fn fetch(&self, doi: &str) -> Result<BibEntry, AppError> {
+            let ms = doi.rsplit('/').next().unwrap().parse().unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(ms));
+            Ok(BibEntry {
+                author: "Doe, Jane".to_string(),
+                year: 2020,
+                doi: Some(doi.to_string()),
+                ..entry("article")
+            })
+        }
+    }
+
+    #[test]
+    fn batch_lookup_reports_progress_in_completion_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let flags = crate::operations::CancelFlags::default();
+        let operation = Operation::begin(&flags, None);
+        let dois: Vec<String> = [
+            "10.1000/120",
+            "not a doi",
+            "10.1000/5",
+            "10.1000/60",
+            "10.1000/1",
+        ]
+        .map(String::from)
+        .to_vec();
+
+        let mut events = vec![];
+        let results = resolve_dois(
+            &empty_cache(&dir),
+            &SlowProvider,
+            &dois,
+            false,
+            "{author}{year}",
+            &operation,
+            |progress| events.push(progress),
+        );
+
+        let completed: Vec<usize> = events.iter().map(|e| e.completed).collect();
+        assert_eq!(completed, [1, 2, 3, 4, 5]);
+        assert!(events.iter().all(|e| e.total == 5));
+        // The slowest lookup finishes last
+        assert_eq!(events.last().unwrap().current_doi, "10.1000/120");
+        let mut reported: Vec<&str> = events.iter().map(|e| e.current_doi.as_str()).collect();
+        reported.sort();
+        let mut expected: Vec<&str> = dois.iter().map(String::as_str).collect();
+        expected.sort();
+        assert_eq!(reported, expected);
+
+        // Results keep input order, with keys from the configured pattern
+        let result_dois: Vec<&str> = results.iter().map(|r| r.doi.as_str()).collect();
+        assert_eq!(result_dois, dois);
+        assert_eq!(results[0].entry.as_ref().unwrap().key, "doe2020");
+        assert!(results[1].entry.is_none());
+        assert!(results[1].error.as_deref().unwrap().contains("Invalid DOI"));
+    }
+
+    #[test]
+    fn cancelled_batch_lookup_stops_early() {
+        let dir = tempfile::tempdir().unwrap();
+        let flags = crate::operations::CancelFlags::default();
+        let dois: Vec<String> = (0..20).map(|i| format!("10.{}/50", 1000 + i)).collect();
+
+        let (results, finished) = std::thread::scope(|scope| {
+            let batch = scope.spawn(|| {
+                let operation = Operation::begin(&flags, Some("batch".to_string()));
+                let mut completed = 0;
+                let results = resolve_dois(
+                    &empty_cache(&dir),
+                    &SlowProvider,
+                    &dois,
+                    false,
+                    "{author}{year}",
+                    &operation,
+                    |_| completed += 1,
+                );
+                (results, operation.check(), completed)
+            });
+            // Cancel the way `cancel_operation` does, while the batch is running
+            while !crate::operations::cancel(&flags, "batch") {
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            let (results, check, completed) = batch.join().unwrap();
+            assert!(check.is_err());
+            (results, completed)
+        });
+        assert!(results.len() < dois.len());
+        assert_eq!(results.len(), finished);
+    }
+
+    fn paper() -> BibEntry {
+        BibEntry {
+            author: "Müller, Jürgen and Smith, Anna".to_string(),
+            title: "The Theory of Everything: A Review".to_string(),
+            year: 2021,
+            ..entry("article")
+        }
+    }
+
+    #[test]
+    fn citation_keys_follow_the_pattern() {
+        assert_eq!(
+            citation_key(&paper(), DEFAULT_CITATION_KEY_PATTERN),
+            "muller2021"
+        );
+        assert_eq!(citation_key(&paper(), "{Author}{year}"), "Muller2021");
+        assert_eq!(citation_key(&paper(), "{author}_{year}"), "muller_2021");
+        // Leading stopwords are skipped
+        assert_eq!(
+            citation_key(&paper(), "{author}{year}{title:1}"),
+            "muller2021theory"
+        );
+        assert_eq!(
+            citation_key(&paper(), "{author}:{title:2}"),
+            "muller:theoryeverything"
+        );
+    }
+
+    #[test]
+    fn citation_keys_accept_given_family_names() {
+        let entry = BibEntry {
+            author: "Ada Lovelace".to_string(),
+            ..paper()
+        };
+        assert_eq!(
+            generate_citation_key(entry, "{author}{year}".to_string()),
+            "lovelace2021"
+        );
+    }
+
+    #[test]
+    fn citation_keys_are_sanitized() {
+        // Spaces, braces and unknown tokens vanish; a missing year expands to nothing
+        let entry = BibEntry { year: 0, ..paper() };
+        assert_eq!(citation_key(&entry, "{author} {year}{bogus}#"), "muller");
+        // Nothing usable left
+        let empty = BibEntry {
+            author: String::new(),
+            ..entry
+        };
+        assert_eq!(citation_key(&empty, "{author}"), "unknown");
+        // An unclosed token is kept as literal text, minus the brace
+        assert_eq!(citation_key(&paper(), "{year}{author"), "2021author");
+    }
+
+    #[test]
+    fn url_form_dois_are_normalized() {
+        for input in [
+            "10.1000/182",
+            "  https://doi.org/10.1000/182 ",
+            "http://dx.doi.org/10.1000/182",
+            "HTTPS://DOI.ORG/10.1000/182",
+            "doi:10.1000/182",
+        ] {
+            assert_eq!(normalize_doi(input).unwrap(), "10.1000/182", "{}", input);
+        }
+        for input in ["", "11.1000/182", "10.1000", "10./182", "10.1000/ 182"] {
+            assert!(
+                matches!(normalize_doi(input), Err(AppError::Validation(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn url_form_doi_lookups_request_the_bare_doi() {
+        let dir = tempfile::tempdir().unwrap();
+        let http = RecordingHttp::new(serde_json::json!({ "message": { "title": ["On Things"] } }));
+        let registry = ProviderRegistry::with_defaults(http.clone(), crossref_user_agent(None));
+
+        let entry = resolve_identifier(
+            &empty_cache(&dir),
+            registry.get("doi").unwrap(),
+            "https://doi.org/10.1000/182",
+            false,
+        )
+        .unwrap();
+        assert_eq!(entry.title, "On Things");
+        let requests = http.requests.lock().unwrap();
+        assert_eq!(requests[0].0, "https://api.crossref.org/works/10.1000/182");
+    }
+
+    #[test]
+    fn isbn_checksums_are_verified() {
+        assert_eq!(
+            normalize_isbn("978-0-306-40615-7").unwrap(),
+            "9780306406157"
+        );
+        assert_eq!(normalize_isbn("ISBN 0-306-40615-2").unwrap(), "0306406152");
+        assert_eq!(normalize_isbn("0-8044-2957-x").unwrap(), "080442957X");
+
+        for input in [
+            "978-0-306-40615-8",
+            "0-306-40615-3",
+            "12345",
+            "978030640615X",
+        ] {
+            assert!(
+                matches!(normalize_isbn(input), Err(AppError::Validation(_))),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn bad_checksum_isbns_are_rejected_before_any_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let http = RecordingHttp::new(serde_json::json!({}));
+        let registry = ProviderRegistry::with_defaults(http.clone(), crossref_user_agent(None));
+
+        let result = resolve_identifier(
+            &empty_cache(&dir),
+            registry.get("isbn").unwrap(),
+            "978-0-306-40615-8",
+            false,
+        );
+        assert!(matches!(result, Err(AppError::Validation(_))));
+        assert!(http.requests.lock().unwrap().is_empty());
+    }
+
+    fn article() -> BibEntry {
+        BibEntry {
+            author: "Doe, Jane Ann and Smith, John".to_string(),
+            title: "Deep Results".to_string(),
+            year: 2020,
+            journal: Some("Journal of Things".to_string()),
+            volume: Some("12".to_string()),
+            pages: Some("1--10".to_string()),
+            doi: Some("10.1000/182".to_string()),
+            ..entry("article")
+        }
+    }
+
+    fn book() -> BibEntry {
+        BibEntry {
+            author: "Knuth, Donald E.".to_string(),
+            title: "The Art of Computer Programming".to_string(),
+            year: 1968,
+            publisher: Some("Addison-Wesley".to_string()),
+            ..entry("book")
+        }
+    }
+
+    #[test]
+    fn formats_apa_references() {
+        assert_eq!(
+            format_references(vec![article(), book()], CitationStyle::Apa),
+            [
+                "Doe, J. A., & Smith, J. (2020). Deep Results. Journal of Things, 12, 1--10. \
+                 https://doi.org/10.1000/182",
+                "Knuth, D. E. (1968). The Art of Computer Programming. Addison-Wesley.",
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_mla_references() {
+        assert_eq!(
+            format_references(vec![article(), book()], CitationStyle::Mla),
+            [
+                "Doe, Jane Ann, and John Smith. \"Deep Results.\" Journal of Things, vol. 12, \
+                 2020, pp. 1--10.",
+                "Knuth, Donald E. The Art of Computer Programming. Addison-Wesley, 1968.",
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_ieee_references_numbered_in_order() {
+        assert_eq!(
+            format_references(vec![article(), book()], CitationStyle::Ieee),
+            [
+                "[1] J. A. Doe and J. Smith, \"Deep Results,\" Journal of Things, vol. 12, \
+                 pp. 1--10, 2020.",
+                "[2] D. E. Knuth, The Art of Computer Programming. Addison-Wesley, 1968.",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_fields_are_omitted() {
+        let notes = BibEntry {
+            title: "Untitled Notes".to_string(),
+            ..entry("misc")
+        };
+        let render = |style| format_reference(&notes, style, 1);
+        assert_eq!(render(CitationStyle::Apa), "(n.d.). Untitled Notes.");
+        assert_eq!(render(CitationStyle::Mla), "Untitled Notes.");
+        assert_eq!(render(CitationStyle::Ieee), "[1] Untitled Notes.");
+    }
+
+    #[test]
+    fn doi_list_files_are_deduplicated_and_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        let list = dir.path().join("reading.txt");
+        fs::write(
+            &list,
+            "# reading list\n\
+             10.1000/182\n\
+             \n\
+             https://doi.org/10.1000/182\n\
+             10.1000/ABC\n\
+             \t10.1000/abc  \n\
+             not-a-doi\n",
+        )
+        .unwrap();
+        let dois = parse_doi_list(&fs::read_to_string(&list).unwrap());
+        assert_eq!(dois, ["10.1000/182", "10.1000/ABC", "not-a-doi"]);
+
+        let cache = empty_cache(&dir);
+        let provider = CountingProvider {
+            fetches: AtomicUsize::new(0),
+        };
+        let flags = crate::operations::CancelFlags::default();
+        let operation = Operation::begin(&flags, None);
+        let import = |offline: bool| {
+            collect_doi_import(resolve_dois(
+                &cache,
+                &provider,
+                &dois,
+                offline,
+                DEFAULT_CITATION_KEY_PATTERN,
+                &operation,
+                |_| {},
+            ))
+        };
+
+        let result = import(false);
+        let resolved: Vec<Option<&str>> = result.entries.iter().map(|e| e.doi.as_deref()).collect();
+        assert_eq!(resolved, [Some("10.1000/182"), Some("10.1000/ABC")]);
+        assert_eq!(result.failures.len(), 1);
+        assert_eq!(result.failures[0].doi, "not-a-doi");
+        assert!(result.failures[0].error.contains("Invalid DOI"));
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 2);
+
+        // Offline, the cached entries still resolve without another fetch
+        let result = import(true);
+        assert_eq!(result.entries.len(), 2);
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn offline_doi_imports_report_uncached_entries_as_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = CountingProvider {
+            fetches: AtomicUsize::new(0),
+        };
+        let flags = crate::operations::CancelFlags::default();
+        let dois = parse_doi_list("10.1000/1\n10.1000/2\n");
+        let result = collect_doi_import(resolve_dois(
+            &empty_cache(&dir),
+            &provider,
+            &dois,
+            true,
+            DEFAULT_CITATION_KEY_PATTERN,
+            &Operation::begin(&flags, None),
+            |_| {},
+        ));
+        assert!(result.entries.is_empty());
+        assert_eq!(result.failures.len(), 2);
+        assert!(result
+            .failures
+            .iter()
+            .all(|f| f.error.contains("without a network")));
+        assert_eq!(provider.fetches.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn bibtex_special_characters_are_escaped() {
+        let cases = [
+            ("Research & Development", r"Research \& Development"),
+            ("A 95% Confidence Bound", r"A 95\% Confidence Bound"),
+            ("The snake_case Convention", r"The snake\_case Convention"),
+            ("C# in Practice", r"C\# in Practice"),
+            ("Costs of $5 and $10", r"Costs of \$5 and \$10"),
+        ];
+        for (title, escaped) in cases {
+            assert_eq!(escape_bibtex_value(title), escaped, "{}", title);
+        }
+    }
+
+    #[test]
+    fn escaped_sequences_and_math_are_left_alone() {
+        assert_eq!(escape_bibtex_value(r"R\&D at 50\%"), r"R\&D at 50\%");
+        assert_eq!(
+            escape_bibtex_value("Bounds on $x_i^2$ for $\\alpha$ & more"),
+            r"Bounds on $x_i^2$ for $\alpha$ \& more"
+        );
+        assert_eq!(
+            escape_bibtex_value("Display $$a_1 + a_2$$ math"),
+            "Display $$a_1 + a_2$$ math"
+        );
+        // An unclosed delimiter is a literal dollar sign
+        assert_eq!(escape_bibtex_value("Only $x_1"), r"Only \$x\_1");
+    }
+
+    #[test]
+    fn bibtex_output_escapes_text_fields_but_not_identifiers() {
+        let entry = BibEntry {
+            key: "smith2020".to_string(),
+            title: "Q&A on 100% of $n$ cases".to_string(),
+            year: 2020,
+            doi: Some("10.1000/a_b%c".to_string()),
+            ..entry("article")
+        };
+        let bibtex = entries_to_bibtex(&[entry]);
+        assert!(bibtex.contains(r"title = {Q\&A on 100\% of $n$ cases},"));
+        assert!(bibtex.contains("doi = {10.1000/a_b%c},"));
+    }
+
+    fn merge_sources() -> (BibEntry, BibEntry) {
+        let from_crossref = BibEntry {
+            key: "lecun2015".to_string(),
+            title: "Deep learning".to_string(),
+            author: "LeCun, Yann".to_string(),
+            year: 2015,
+            journal: Some("Nature".to_string()),
+            doi: Some("10.1038/nature14539".to_string()),
+            ..entry("article")
+        };
+        let from_library = BibEntry {
+            key: "lecun_deep".to_string(),
+            title: "Deep Learning (Review)".to_string(),
+            volume: Some("521".to_string()),
+            pages: Some("436--444".to_string()),
+            url: Some("https://example.org/deep".to_string()),
+            ..entry("")
+        };
+        (from_crossref, from_library)
+    }
+
+    #[test]
+    fn merging_fills_each_side_from_the_other() {
+        let (a, b) = merge_sources();
+        let merged = merge_entries(a, b, MergePreference::A);
+        assert_eq!(merged.key, "lecun2015");
+        assert_eq!(merged.entry_type, "article");
+        assert_eq!(merged.author, "LeCun, Yann");
+        assert_eq!(merged.year, 2015);
+        assert_eq!(merged.journal.as_deref(), Some("Nature"));
+        assert_eq!(merged.volume.as_deref(), Some("521"));
+        assert_eq!(merged.pages.as_deref(), Some("436--444"));
+        assert_eq!(merged.url.as_deref(), Some("https://example.org/deep"));
+        // The one conflict goes to the preferred entry
+        assert_eq!(merged.title, "Deep learning");
+    }
+
+    #[test]
+    fn the_preferred_entry_wins_conflicts_and_keeps_its_key() {
+        let (a, b) = merge_sources();
+        let merged = merge_entries(a, b, MergePreference::B);
+        assert_eq!(merged.key, "lecun_deep");
+        assert_eq!(merged.title, "Deep Learning (Review)");
+        // Empty fields of the preferred entry are still filled
+        assert_eq!(merged.entry_type, "article");
+        assert_eq!(merged.doi.as_deref(), Some("10.1038/nature14539"));
+        assert_eq!(merged.volume.as_deref(), Some("521"));
+    }
 }
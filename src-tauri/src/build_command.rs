@@ -0,0 +1,274 @@
+use crate::operations::{Operation, CANCEL_POLL_INTERVAL};
+use crate::paths::expand_path;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+use tauri_plugin_shell::process::CommandEvent;
+use tauri_plugin_shell::ShellExt;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BuildResult {
+    pub command: Vec<String>,
+    pub stdout: String,
+    pub stderr: String,
+    /// `None` when the process was killed by a signal or the timeout
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+}
+
+/// Characters that only make sense to a shell. Templates run without one, so
+/// these are rejected rather than silently passed through as literal arguments.
+const SHELL_METACHARACTERS: &[char] = &[';', '|', '&', '<', '>', '`', '$', '\n'];
+
+const BUILD_PLACEHOLDERS: &[&str] = &["{file}", "{dir}", "{basename}"];
+
+/// Split a command template into arguments (honouring single and double quotes)
+/// and fill in `{file}`, `{dir}`, and `{basename}` (file name without extension).
+/// Substituted values are never re-split, so paths with spaces stay one argument.
+pub fn expand_build_template(template: &str, document: &Path) -> Result<Vec<String>, String> {
+    if let Some(c) = template.chars().find(|c| SHELL_METACHARACTERS.contains(c)) {
+        return Err(format!(
+            "Build command may not contain '{}'; it is run directly, not through a shell",
+            c.escape_default()
+        ));
+    }
+
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .ok_or("Build command has an unclosed '{'")?;
+        let placeholder = &rest[open..open + close + 1];
+        if !BUILD_PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!("Unknown placeholder in build command: {}", placeholder));
+        }
+        rest = &rest[open + close + 1..];
+    }
+
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+    for c in template.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => current.push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (None, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err("Build command has an unterminated quote".to_string());
+    }
+    if in_arg {
+        args.push(current);
+    }
+    if args.is_empty() {
+        return Err("Build command is empty".to_string());
+    }
+
+    let file = document.to_string_lossy().to_string();
+    let dir = document
+        .parent()
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let basename = document
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    Ok(args
+        .into_iter()
+        .map(|arg| {
+            arg.replace("{file}", &file)
+                .replace("{dir}", &dir)
+                .replace("{basename}", &basename)
+        })
+        .collect())
+}
+
+/// Run the `build_command` template from settings for a document, capturing
+/// its output. The process is killed if it outlives `build_timeout_secs` or
+/// its operation is cancelled.
+#[tauri::command]
+pub async fn run_build_command(
+    document_path: String,
+    operation_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<BuildResult, String> {
+    let settings = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings();
+    let template = settings
+        .build_command
+        .filter(|t| !t.trim().is_empty())
+        .ok_or("No build command configured; set one in settings")?;
+
+    let document = expand_path(&document_path);
+    let args = expand_build_template(&template, &document)?;
+    let working_dir = document.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let (mut events, child) = app
+        .shell()
+        .command(&args[0])
+        .args(&args[1..])
+        .current_dir(working_dir)
+        .set_raw_out(true)
+        .spawn()
+        .map_err(|e| format!("Failed to start build command: {}", e))?;
+
+    // Watchdog: kill the child if the build is cancelled or doesn't finish in time
+    let operation = Operation::begin(&state.operations, operation_id);
+    let child = Arc::new(Mutex::new(Some(child)));
+    let timed_out = Arc::new(Mutex::new(false));
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    {
+        let child = child.clone();
+        let timed_out = timed_out.clone();
+        let cancelled = operation.flag();
+        let deadline = Instant::now() + Duration::from_secs(settings.build_timeout_secs.max(1));
+        std::thread::spawn(move || loop {
+            if done_rx.recv_timeout(CANCEL_POLL_INTERVAL) != Err(mpsc::RecvTimeoutError::Timeout) {
+                break;
+            }
+            let expired = Instant::now() >= deadline;
+            if expired || cancelled.load(Ordering::SeqCst) {
+                if let Some(child) = child.lock().unwrap().take() {
+                    child.kill().ok();
+                    *timed_out.lock().unwrap() = expired;
+                }
+                break;
+            }
+        });
+    }
+
+    let mut stdout = vec![];
+    let mut stderr = vec![];
+    let mut exit_code = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            CommandEvent::Stdout(bytes) => stdout.extend(bytes),
+            CommandEvent::Stderr(bytes) => stderr.extend(bytes),
+            CommandEvent::Terminated(payload) => exit_code = payload.code,
+            _ => {}
+        }
+    }
+    child.lock().unwrap().take();
+    done_tx.send(()).ok();
+    operation.check()?;
+
+    let timed_out = *timed_out.lock().unwrap();
+    Ok(BuildResult {
+        command: args,
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        exit_code: if timed_out { None } else { exit_code },
+        timed_out,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn placeholders_are_substituted_as_whole_arguments() {
+        let document = Path::new("/home/me/My Thesis/thesis.final.lml");
+        let args = expand_build_template(
+            "latexmk -pdf -outdir='{dir}/build' \"{file}\" --jobname {basename}",
+            document,
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            [
+                "latexmk",
+                "-pdf",
+                "-outdir=/home/me/My Thesis/build",
+                "/home/me/My Thesis/thesis.final.lml",
+                "--jobname",
+                "thesis.final",
+            ]
+        );
+
+        // Unquoted placeholders still expand to one argument each
+        let args = expand_build_template("make {basename}.pdf", document).unwrap();
+        assert_eq!(args, ["make", "thesis.final.pdf"]);
+    }
+
+    #[test]
+    fn templates_that_need_a_shell_are_rejected() {
+        let document = Path::new("/docs/a.lml");
+        for template in [
+            "make; rm -rf ~",
+            "cat {file} | lpr",
+            "build && open",
+            "echo $HOME",
+            "echo `id`",
+            "build > log.txt",
+        ] {
+            let err = expand_build_template(template, document).unwrap_err();
+            assert!(err.contains("not through a shell"), "{}: {}", template, err);
+        }
+        let error = |template: &str| expand_build_template(template, document).unwrap_err();
+        assert_eq!(
+            error("make {target}"),
+            "Unknown placeholder in build command: {target}"
+        );
+        assert_eq!(error("make {file"), "Build command has an unclosed '{'");
+        assert_eq!(
+            error("make 'all"),
+            "Build command has an unterminated quote"
+        );
+        assert_eq!(error("   "), "Build command is empty");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn expanded_arguments_reach_the_build_script() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("build.sh");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nfor arg; do echo \"[$arg]\"; done\necho oops >&2\nexit 3\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let document = dir.path().join("my notes.lml");
+
+        let template = format!("'{}' {{file}} {{basename}}", script.display());
+        let args = expand_build_template(&template, &document).unwrap();
+        let output = std::process::Command::new(&args[0])
+            .args(&args[1..])
+            .current_dir(dir.path())
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("[{}]\n[my notes]\n", document.display())
+        );
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "oops\n");
+        assert_eq!(output.status.code(), Some(3));
+    }
+}
@@ -1,9 +1,27 @@
+use crate::diff::{diff_hunks, DiffHunk, DIFF_CONTEXT_LINES};
+use crate::errors::AppError;
 use crate::AppState;
-use crate::formulas::{Formula, FormulaUpdate};
+use crate::formulas::{
+    build_preview, read_import_file, render_formula_sheet, validate_latex, ConflictResolution,
+    Formula, FormulaBackup, FormulaManager, FormulaStats, FormulaUpdate, ImportPreview,
+    ImportResult, PreviewMode, PreviewResult, SheetFormat,
+};
+use crate::paths::{
+    expand_path, parse_quarantine_name, path_key, probe_writable, quarantine_dir,
+    quarantine_file,
+};
+use crate::recent_files::{
+    RecentFilesExport, RecentFilesImport, RecentFilesManager, RecentFilesRepair,
+};
+use crate::settings::SettingsManager;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use tauri::State;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_shell::ShellExt;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileInfo {
@@ -12,6 +30,32 @@ pub struct FileInfo {
     pub size: u64,
     pub modified: Option<String>,
     pub created: Option<String>,
+    pub modified_ms: Option<i64>,
+    pub created_ms: Option<i64>,
+    pub read_only: bool,
+    #[serde(default)]
+    pub is_symlink: bool,
+    /// Fully resolved path the symlink points to
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncodingReport {
+    pub encoding: String,
+    pub has_bom: bool,
+    pub valid_utf8: bool,
+    pub non_ascii_bytes: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AppInfo {
+    pub app_version: String,
+    pub platform: String,
+    pub arch: String,
+    pub settings_schema_version: u32,
+    pub formula_count: usize,
+    pub recent_file_count: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,30 +71,100 @@ pub struct WindowState {
 // File Operations
 // ============================================================================
 
+/// Read a text file. With `require_text`, files that look binary are refused
+/// with `AppError::NotTextFile` instead of being decoded.
+#[tauri::command]
+pub fn read_file(path: String, require_text: Option<bool>) -> Result<String, AppError> {
+    if require_text.unwrap_or(false) && !is_text_file(path.clone())? {
+        return Err(AppError::NotTextFile(format!(
+            "{} appears to be a binary file",
+            path
+        )));
+    }
+    fs::read_to_string(expand_path(&path)).map_err(|e| AppError::io("Failed to read file", e))
+}
+
+/// Number of leading bytes checked for NUL bytes when sniffing for binary content
+const BINARY_SNIFF_BYTES: u64 = 8 * 1024;
+
+/// Guess whether a file is text by looking for NUL bytes near its start.
+/// UTF-16 text (which is full of NULs) is recognized by its byte-order mark.
 #[tauri::command]
-pub fn read_file(path: String) -> Result<String, String> {
-    fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))
+pub fn is_text_file(path: String) -> Result<bool, AppError> {
+    use std::io::Read;
+
+    let file =
+        fs::File::open(expand_path(&path)).map_err(|e| AppError::io("Failed to open file", e))?;
+    let mut sample = Vec::with_capacity(BINARY_SNIFF_BYTES as usize);
+    file.take(BINARY_SNIFF_BYTES)
+        .read_to_end(&mut sample)
+        .map_err(|e| AppError::io("Failed to read file", e))?;
+
+    if sample.starts_with(&[0xFF, 0xFE]) || sample.starts_with(&[0xFE, 0xFF]) {
+        return Ok(true);
+    }
+    Ok(!sample.contains(&0))
+}
+
+/// What to carry over from the file being replaced by `write_file`
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PreserveMetadata {
+    /// Reapply the original mode bits (or read-only flag on Windows)
+    #[serde(default)]
+    pub permissions: bool,
+    /// Keep the original modification time
+    #[serde(default)]
+    pub mtime: bool,
 }
 
+/// Write a file, creating parent directories. With `preserve`, the existing
+/// file's permissions and/or mtime are read first and reapplied after writing.
 #[tauri::command]
-pub fn write_file(path: String, content: String) -> Result<(), String> {
+pub fn write_file(
+    path: String,
+    content: String,
+    preserve: Option<PreserveMetadata>,
+) -> Result<(), AppError> {
+    let path = expand_path(&path);
+
     // Ensure parent directory exists
-    if let Some(parent) = PathBuf::from(&path).parent() {
-        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| AppError::io("Failed to create directory", e))?;
     }
 
-    fs::write(&path, content).map_err(|e| format!("Failed to write file: {}", e))
+    let preserve = preserve.unwrap_or_default();
+    let original = fs::metadata(&path).ok();
+
+    fs::write(&path, content).map_err(|e| AppError::io("Failed to write file", e))?;
+
+    let Some(original) = original else {
+        return Ok(());
+    };
+    if preserve.permissions {
+        fs::set_permissions(&path, original.permissions())
+            .map_err(|e| AppError::io("Failed to restore permissions", e))?;
+    }
+    if preserve.mtime {
+        if let Ok(modified) = original.modified() {
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|f| f.set_modified(modified))
+                .map_err(|e| AppError::io("Failed to restore modification time", e))?;
+        }
+    }
+    Ok(())
 }
 
 #[tauri::command]
 pub fn file_exists(path: String) -> bool {
-    PathBuf::from(&path).exists()
+    expand_path(&path).exists()
 }
 
 #[tauri::command]
-pub fn get_file_info(path: String) -> Result<FileInfo, String> {
-    let path_buf = PathBuf::from(&path);
-    let metadata = fs::metadata(&path_buf).map_err(|e| format!("Failed to get metadata: {}", e))?;
+pub fn get_file_info(path: String) -> Result<FileInfo, AppError> {
+    let path_buf = expand_path(&path);
+    let metadata = fs::metadata(&path_buf).map_err(|e| AppError::io("Failed to get metadata", e))?;
 
     let name = path_buf
         .file_name()
@@ -78,32 +192,424 @@ pub fn get_file_info(path: String) -> Result<FileInfo, String> {
                 .into()
         });
 
+    // Raw epoch milliseconds so the frontend can compare timestamps exactly
+    let modified_ms = metadata.modified().ok().map(epoch_millis);
+    let created_ms = metadata.created().ok().map(epoch_millis);
+
+    let is_symlink = fs::symlink_metadata(&path_buf).is_ok_and(|m| m.file_type().is_symlink());
+    let target = is_symlink
+        .then(|| fs::canonicalize(&path_buf).ok())
+        .flatten()
+        .map(|t| t.to_string_lossy().to_string());
+
     Ok(FileInfo {
-        path,
+        path: path_buf.to_string_lossy().to_string(),
         name,
         size: metadata.len(),
         modified,
         created,
+        modified_ms,
+        created_ms,
+        read_only: metadata.permissions().readonly(),
+        is_symlink,
+        target,
+    })
+}
+
+/// Line diff of two separate documents, grouped into hunks
+#[tauri::command]
+pub fn diff_files(
+    path_a: String,
+    path_b: String,
+    ignore_whitespace: Option<bool>,
+) -> Result<Vec<DiffHunk>, AppError> {
+    let read = |path: &str| {
+        let path_buf = expand_path(path);
+        if !path_buf.is_file() {
+            return Err(AppError::NotFound(format!("File not found: {}", path)));
+        }
+        fs::read_to_string(&path_buf)
+            .map_err(|e| AppError::io(&format!("Failed to read {}", path), e))
+    };
+    let a = read(&path_a)?;
+    let b = read(&path_b)?;
+    Ok(diff_hunks(&a, &b, DIFF_CONTEXT_LINES, ignore_whitespace.unwrap_or(false)))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpenedDocument {
+    pub content: String,
+    pub file_info: FileInfo,
+    /// Opened for viewing only; nothing was recorded
+    pub read_only: bool,
+}
+
+/// Read a document and record it in recent files and `last_directory` in one step.
+/// Nothing is recorded if the read fails, or when `read_only` is set.
+#[tauri::command]
+pub fn open_document(
+    path: String,
+    read_only: Option<bool>,
+    state: State<AppState>,
+) -> Result<OpenedDocument, AppError> {
+    open_and_record(
+        path,
+        read_only.unwrap_or(false),
+        &state.settings,
+        &state.recent_files,
+    )
+}
+
+fn open_and_record(
+    path: String,
+    read_only: bool,
+    settings: &Mutex<SettingsManager>,
+    recent_files: &Mutex<RecentFilesManager>,
+) -> Result<OpenedDocument, AppError> {
+    let content = read_file(path.clone(), None)?;
+    let file_info = get_file_info(path)?;
+    if read_only {
+        return Ok(OpenedDocument {
+            content,
+            file_info,
+            read_only,
+        });
+    }
+
+    {
+        let resolve_symlinks = settings.lock().unwrap().get_settings().resolve_symlinks;
+        let mut recent = recent_files.lock().unwrap();
+        recent.add_file(&file_info.path, resolve_symlinks);
+        recent
+            .save()
+            .map_err(|e| AppError::io("Failed to save recent files", e))?;
+    }
+
+    if let Some(parent) = Path::new(&file_info.path).parent() {
+        let mut manager = settings.lock().unwrap();
+        let mut settings = manager.get_settings();
+        settings.last_directory = Some(parent.to_string_lossy().to_string());
+        manager.update_settings(settings);
+        manager
+            .save()
+            .map_err(|e| AppError::io("Failed to save settings", e))?;
+    }
+
+    Ok(OpenedDocument {
+        content,
+        file_info,
+        read_only,
     })
 }
 
+/// Write a document to a new path, record it in recent files, and carry over
+/// the version history of `from_path` when given. An existing file at
+/// `to_path` is only replaced when `overwrite` is set.
+#[tauri::command]
+pub fn save_as(
+    from_path: Option<String>,
+    to_path: String,
+    content: String,
+    overwrite: Option<bool>,
+    state: State<AppState>,
+) -> Result<FileInfo, AppError> {
+    check_save_target(&to_path, overwrite.unwrap_or(false))?;
+    write_file(to_path.clone(), content, None)?;
+
+    // `~/a.lml` and `/home/me/a.lml` (or case variants on Windows/macOS) are one file
+    let same_file = |path: &str| {
+        path_key(&expand_path(path).to_string_lossy())
+            == path_key(&expand_path(&to_path).to_string_lossy())
+    };
+    if let Some(from_path) = from_path.filter(|f| !same_file(f)) {
+        let (_store, root) = crate::versions::open_version_store(&state)?;
+        crate::versions::copy_version_history(
+            &root,
+            &state.manifest_locks,
+            &crate::versions::history_path(&state, &from_path),
+            &crate::versions::history_path(&state, &to_path),
+        )?;
+    }
+
+    {
+        let resolve_symlinks = state.settings.lock().unwrap().get_settings().resolve_symlinks;
+        let mut recent = state.recent_files.lock().unwrap();
+        recent.add_file(&to_path, resolve_symlinks);
+        recent
+            .save()
+            .map_err(|e| AppError::io("Failed to save recent files", e))?;
+    }
+
+    get_file_info(to_path)
+}
+
+/// Refuse to replace an existing file unless `overwrite` is set
+fn check_save_target(to_path: &str, overwrite: bool) -> Result<(), AppError> {
+    if expand_path(to_path).exists() && !overwrite {
+        return Err(AppError::Validation(format!("File already exists: {}", to_path)));
+    }
+    Ok(())
+}
+
+fn epoch_millis(time: std::time::SystemTime) -> i64 {
+    chrono::DateTime::<chrono::Utc>::from(time).timestamp_millis()
+}
+
+/// Number of leading bytes sampled when guessing a file's encoding
+const ENCODING_SAMPLE_BYTES: usize = 64 * 1024;
+
+#[tauri::command]
+pub fn detect_encoding(path: String) -> Result<EncodingReport, AppError> {
+    use std::io::Read;
+
+    let file =
+        fs::File::open(expand_path(&path)).map_err(|e| AppError::io("Failed to open file", e))?;
+    let mut sample = Vec::with_capacity(ENCODING_SAMPLE_BYTES);
+    file.take(ENCODING_SAMPLE_BYTES as u64)
+        .read_to_end(&mut sample)
+        .map_err(|e| AppError::io("Failed to read file", e))?;
+
+    Ok(sniff_encoding(&sample))
+}
+
+/// Guess the encoding of a byte sample from its BOM, UTF-8 validity, and byte distribution
+fn sniff_encoding(sample: &[u8]) -> EncodingReport {
+    let non_ascii_bytes = sample.iter().filter(|b| !b.is_ascii()).count();
+
+    let bom = [
+        (&[0xEF, 0xBB, 0xBF][..], "utf-8"),
+        (&[0xFF, 0xFE, 0x00, 0x00][..], "utf-32le"),
+        (&[0x00, 0x00, 0xFE, 0xFF][..], "utf-32be"),
+        (&[0xFF, 0xFE][..], "utf-16le"),
+        (&[0xFE, 0xFF][..], "utf-16be"),
+    ]
+    .into_iter()
+    .find(|(bom, _)| sample.starts_with(bom));
+
+    // A sample may end mid-character; only an invalid sequence counts as an error
+    let valid_utf8 = match std::str::from_utf8(sample) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+
+    let encoding = if let Some((_, name)) = bom {
+        name
+    } else if valid_utf8 {
+        if non_ascii_bytes == 0 {
+            "ascii"
+        } else {
+            "utf-8"
+        }
+    } else if looks_like_utf16(sample) {
+        "utf-16"
+    } else if sample.iter().any(|b| (0x80..=0x9F).contains(b)) {
+        // C1 range is unused in Latin-1 text but holds punctuation in Windows-1252
+        "windows-1252"
+    } else {
+        "iso-8859-1"
+    };
+
+    EncodingReport {
+        encoding: encoding.to_string(),
+        has_bom: bom.is_some(),
+        valid_utf8: valid_utf8 && bom.is_none_or(|(_, name)| name == "utf-8"),
+        non_ascii_bytes,
+    }
+}
+
+/// BOM-less UTF-16 text has a NUL in roughly every other byte
+fn looks_like_utf16(sample: &[u8]) -> bool {
+    if sample.len() < 4 {
+        return false;
+    }
+    let nulls = sample.iter().filter(|b| **b == 0).count();
+    nulls * 3 >= sample.len()
+}
+
 // ============================================================================
 // Recent Files
 // ============================================================================
 
 #[tauri::command]
 pub fn get_recent_files(state: State<AppState>) -> Vec<String> {
-    let manager = state.recent_files.lock().unwrap();
+    let mut manager = state.recent_files.lock().unwrap();
+    manager.get_files()
+}
+
+#[tauri::command]
+pub fn get_recent_files_grouped(
+    state: State<AppState>,
+) -> Vec<crate::recent_files::RecentFileGroup> {
+    let mut manager = state.recent_files.lock().unwrap();
+    manager.get_files_grouped()
+}
+
+/// Re-check every recent file now instead of trusting cached existence results
+#[tauri::command]
+pub fn refresh_recent_files(state: State<AppState>) -> Vec<String> {
+    let mut manager = state.recent_files.lock().unwrap();
+    manager.invalidate_exists_cache();
     manager.get_files()
 }
 
 #[tauri::command]
 pub fn add_recent_file(path: String, state: State<AppState>) -> Result<(), String> {
+    let resolve_symlinks = state.settings.lock().unwrap().get_settings().resolve_symlinks;
     let mut manager = state.recent_files.lock().unwrap();
-    manager.add_file(&path);
+    manager.add_file(&path, resolve_symlinks);
     manager.save().map_err(|e| e.to_string())
 }
 
+/// Recover what can be read from a damaged recent files list, quarantining the original
+#[tauri::command]
+pub fn repair_recent_files(state: State<AppState>) -> Result<RecentFilesRepair, String> {
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .clone();
+    let mut manager = state.recent_files.lock().unwrap();
+    manager
+        .repair(&app_data_dir)
+        .map_err(|e| format!("Failed to repair recent files: {}", e))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuarantinedFile {
+    /// Name of the data file it came from, e.g. `recent_files.json`
+    pub kind: String,
+    pub path: String,
+    pub quarantined_at: String,
+}
+
+/// Data files moved aside as corrupt, newest first
+#[tauri::command]
+pub fn list_quarantined_files(state: State<AppState>) -> Result<Vec<QuarantinedFile>, String> {
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .clone();
+    Ok(quarantined_files(&app_data_dir))
+}
+
+fn quarantined_files(app_data_dir: &Path) -> Vec<QuarantinedFile> {
+    let Ok(entries) = fs::read_dir(quarantine_dir(app_data_dir)) else {
+        return vec![];
+    };
+
+    let mut files: Vec<QuarantinedFile> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let (kind, quarantined_at) =
+                parse_quarantine_name(&entry.file_name().to_string_lossy())?;
+            Some(QuarantinedFile {
+                kind,
+                path: entry.path().to_string_lossy().to_string(),
+                quarantined_at: quarantined_at.to_rfc3339(),
+            })
+        })
+        .collect();
+    files.sort_by(|a, b| b.quarantined_at.cmp(&a.quarantined_at));
+    files
+}
+
+/// Put a quarantined file back in place of its data file and reload it. The file it
+/// replaces is quarantined in turn, so nothing is lost. Returns the restored path.
+#[tauri::command]
+pub fn restore_quarantined_file(path: String, state: State<AppState>) -> Result<String, String> {
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .clone();
+    let (original, target) = restore_from_quarantine(&app_data_dir, &path)?;
+
+    // Reload so the restored data is used and not overwritten by the next save
+    match original.as_str() {
+        "recent_files.json" => {
+            *state.recent_files.lock().unwrap() =
+                crate::recent_files::RecentFilesManager::new(target.clone())
+        }
+        "settings.json" => {
+            *state.settings.lock().unwrap() = crate::settings::SettingsManager::new(target.clone())
+        }
+        "formulas.json" => {
+            *state.formulas.lock().unwrap() = crate::formulas::FormulaManager::new(target.clone())
+        }
+        "bib_cache.json" => {
+            *state.bib_cache.lock().unwrap() = crate::bibliography::BibCache::new(target.clone())
+        }
+        "document_settings.json" => {
+            *state.document_settings.lock().unwrap() =
+                crate::document_settings::DocumentSettingsManager::new(target.clone())
+        }
+        "session.json" => {
+            *state.session.lock().unwrap() = crate::session::SessionManager::new(target.clone())
+        }
+        _ => {}
+    }
+    Ok(target.to_string_lossy().to_string())
+}
+
+/// Move the quarantined file at `path` back to its data file in `app_data_dir`,
+/// quarantining whatever is there now. Returns the data file name and path.
+fn restore_from_quarantine(app_data_dir: &Path, path: &str) -> Result<(String, PathBuf), String> {
+    let source = Path::new(path);
+    let quarantine = quarantine_dir(app_data_dir).canonicalize().ok();
+    let in_quarantine = source
+        .parent()
+        .and_then(|p| p.canonicalize().ok())
+        .is_some_and(|p| Some(p) == quarantine);
+    let original = source
+        .file_name()
+        .and_then(|name| parse_quarantine_name(&name.to_string_lossy()))
+        .map(|(original, _)| original);
+    let Some(original) = original.filter(|_| in_quarantine && source.is_file()) else {
+        return Err(format!("Not a quarantined file: {}", path));
+    };
+
+    let target = app_data_dir.join(&original);
+    if target.exists() {
+        quarantine_file(app_data_dir, &target)
+            .map_err(|e| format!("Failed to quarantine current {}: {}", original, e))?;
+    }
+    if fs::rename(source, &target).is_err() {
+        fs::copy(source, &target).map_err(|e| format!("Failed to restore file: {}", e))?;
+        fs::remove_file(source).ok();
+    }
+    Ok((original, target))
+}
+
+/// Write the recents list to `path` as JSON for backup or syncing to another machine
+#[tauri::command]
+pub fn export_recent_files(path: String, state: State<AppState>) -> Result<usize, String> {
+    let export = state.recent_files.lock().unwrap().export();
+    let content = serde_json::to_string_pretty(&export)
+        .map_err(|e| format!("Failed to serialize recent files: {}", e))?;
+    fs::write(expand_path(&path), content)
+        .map_err(|e| format!("Failed to export recent files: {}", e))?;
+    Ok(export.files.len())
+}
+
+/// Load a list written by `export_recent_files`, merging with or replacing the current one
+#[tauri::command]
+pub fn import_recent_files(
+    path: String,
+    merge: bool,
+    state: State<AppState>,
+) -> Result<RecentFilesImport, String> {
+    let content = fs::read_to_string(expand_path(&path))
+        .map_err(|e| format!("Failed to read recent files: {}", e))?;
+    let export: RecentFilesExport = serde_json::from_str(&content)
+        .map_err(|e| format!("Invalid recent files export: {}", e))?;
+
+    let mut manager = state.recent_files.lock().unwrap();
+    let result = manager.import(export.files, merge);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
 #[tauri::command]
 pub fn clear_recent_files(state: State<AppState>) -> Result<(), String> {
     let mut manager = state.recent_files.lock().unwrap();
@@ -121,33 +627,76 @@ pub fn get_settings(state: State<AppState>) -> crate::settings::Settings {
     manager.get_settings()
 }
 
+/// Save the fields present in `settings`; fields it leaves out keep their value
 #[tauri::command]
 pub fn update_settings(
-    settings: crate::settings::Settings,
+    settings: serde_json::Value,
+    app: AppHandle,
     state: State<AppState>,
 ) -> Result<(), String> {
+    let settings = apply_settings_update(&mut state.settings.lock().unwrap(), settings)?;
+    app.emit("settings-changed", settings).ok();
+    Ok(())
+}
+
+pub(crate) fn apply_settings_update(
+    manager: &mut SettingsManager,
+    patch: serde_json::Value,
+) -> Result<crate::settings::Settings, String> {
+    let settings = manager.merged_settings(patch)?;
+    if let Some(dir) = settings.recovery_dir.as_deref().filter(|d| !d.trim().is_empty()) {
+        probe_writable(&expand_path(dir))
+            .map_err(|e| format!("Recovery directory is not usable: {}", e))?;
+    }
+    if let Some(proxy) = settings.http_proxy.as_deref().filter(|p| !p.trim().is_empty()) {
+        crate::metadata::validate_proxy_url(proxy.trim()).map_err(|e| e.to_string())?;
+    }
+
+    manager.update_settings(settings.clone());
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(settings)
+}
+
+/// Flip one boolean setting, save, and notify listeners; returns the new value
+fn toggle_setting(
+    app: &AppHandle,
+    state: &AppState,
+    field: fn(&mut crate::settings::Settings) -> &mut bool,
+) -> Result<bool, String> {
     let mut manager = state.settings.lock().unwrap();
-    manager.update_settings(settings);
-    manager.save().map_err(|e| e.to_string())
+    let (new_value, settings) = flip_setting(&mut manager, field)?;
+    app.emit("settings-changed", settings).ok();
+    Ok(new_value)
 }
 
-// ============================================================================
-// Export
-// ============================================================================
+/// Flip and save one boolean setting, returning its new value and the full settings
+fn flip_setting(
+    manager: &mut crate::settings::SettingsManager,
+    field: fn(&mut crate::settings::Settings) -> &mut bool,
+) -> Result<(bool, crate::settings::Settings), String> {
+    let mut settings = manager.get_settings();
+    let value = field(&mut settings);
+    *value = !*value;
+    let new_value = *value;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ExportOptions {
-    pub format: String,
-    pub content: String,
-    pub output_path: String,
+    manager.update_settings(settings.clone());
+    manager.save().map_err(|e| e.to_string())?;
+    Ok((new_value, settings))
+}
+
+#[tauri::command]
+pub fn toggle_line_numbers(app: AppHandle, state: State<AppState>) -> Result<bool, String> {
+    toggle_setting(&app, &state, |s| &mut s.line_numbers)
+}
+
+#[tauri::command]
+pub fn toggle_minimap(app: AppHandle, state: State<AppState>) -> Result<bool, String> {
+    toggle_setting(&app, &state, |s| &mut s.minimap)
 }
 
 #[tauri::command]
-pub fn export_to_format(options: ExportOptions) -> Result<String, String> {
-    // For now, we just write the content directly
-    // The actual format conversion happens in the frontend
-    write_file(options.output_path.clone(), options.content)?;
-    Ok(options.output_path)
+pub fn toggle_word_wrap(app: AppHandle, state: State<AppState>) -> Result<bool, String> {
+    toggle_setting(&app, &state, |s| &mut s.word_wrap)
 }
 
 // ============================================================================
@@ -155,68 +704,572 @@ pub fn export_to_format(options: ExportOptions) -> Result<String, String> {
 // ============================================================================
 
 #[tauri::command]
-pub fn save_image(source: String, destination: String) -> Result<String, String> {
-    let dest_path = PathBuf::from(&destination);
+pub fn save_image(source: String, destination: String) -> Result<String, AppError> {
+    let source_path = expand_path(&source);
+    let dest_path = expand_path(&destination);
 
     // Ensure parent directory exists
     if let Some(parent) = dest_path.parent() {
         fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create assets directory: {}", e))?;
+            .map_err(|e| AppError::io("Failed to create assets directory", e))?;
     }
 
-    fs::copy(&source, &destination)
-        .map_err(|e| format!("Failed to copy image: {}", e))?;
+    fs::copy(&source_path, &dest_path)
+        .map_err(|e| AppError::io("Failed to copy image", e))?;
 
-    Ok(destination)
+    Ok(dest_path.to_string_lossy().to_string())
 }
 
 #[tauri::command]
-pub fn save_image_bytes(bytes: Vec<u8>, destination: String) -> Result<String, String> {
-    let dest_path = PathBuf::from(&destination);
+pub fn save_image_bytes(bytes: Vec<u8>, destination: String) -> Result<String, AppError> {
+    let dest_path = expand_path(&destination);
 
     // Ensure parent directory exists
     if let Some(parent) = dest_path.parent() {
         fs::create_dir_all(parent)
-            .map_err(|e| format!("Failed to create assets directory: {}", e))?;
+            .map_err(|e| AppError::io("Failed to create assets directory", e))?;
+    }
+
+    fs::write(&dest_path, bytes)
+        .map_err(|e| AppError::io("Failed to save image", e))?;
+
+    Ok(dest_path.to_string_lossy().to_string())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetWrite {
+    pub bytes: Vec<u8>,
+    pub dest: String,
+}
+
+/// A write staged in a temp file beside its destination
+struct StagedWrite {
+    temp: PathBuf,
+    dest: PathBuf,
+    /// Where the file previously at `dest` was moved while committing
+    backup: Option<PathBuf>,
+}
+
+/// Hidden sibling of `dest` used while a transaction is in flight
+fn transaction_path(dest: &Path, kind: &str) -> PathBuf {
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    dest.with_file_name(format!(".{}.{}-{}", name, kind, uuid::Uuid::new_v4()))
+}
+
+/// Remove directories created for the transaction, deepest first, if still empty
+fn remove_created_dirs(created: &[PathBuf]) {
+    for dir in created.iter().rev() {
+        fs::remove_dir(dir).ok();
+    }
+}
+
+/// Undo committed writes, newest first, putting back any files they replaced
+fn roll_back(committed: &[StagedWrite]) {
+    for write in committed.iter().rev() {
+        fs::remove_file(&write.dest).ok();
+        if let Some(backup) = &write.backup {
+            fs::rename(backup, &write.dest).ok();
+        }
+    }
+}
+
+/// Write `bytes` to a temp file beside `dest`, recording any directories created
+fn stage_write(
+    dest: PathBuf,
+    bytes: &[u8],
+    created: &mut Vec<PathBuf>,
+) -> Result<StagedWrite, AppError> {
+    if dest.is_dir() {
+        return Err(AppError::Validation(format!(
+            "Cannot write {}: it is a directory",
+            dest.display()
+        )));
+    }
+    if let Some(parent) = dest.parent() {
+        let missing: Vec<PathBuf> = parent
+            .ancestors()
+            .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        let result = fs::create_dir_all(parent);
+        created.extend(missing.into_iter().rev());
+        result.map_err(|e| AppError::io(&format!("Failed to create {}", parent.display()), e))?;
+    }
+    let temp = transaction_path(&dest, "staged");
+    fs::write(&temp, bytes).map_err(|e| {
+        fs::remove_file(&temp).ok();
+        AppError::io(&format!("Failed to write {}", dest.display()), e)
+    })?;
+    Ok(StagedWrite {
+        temp,
+        dest,
+        backup: None,
+    })
+}
+
+/// Move a staged file into place, keeping whatever it replaces until the end
+fn commit_write(write: &mut StagedWrite) -> Result<(), AppError> {
+    if write.dest.exists() {
+        let backup = transaction_path(&write.dest, "backup");
+        fs::rename(&write.dest, &backup)
+            .map_err(|e| AppError::io(&format!("Failed to replace {}", write.dest.display()), e))?;
+        write.backup = Some(backup);
+    }
+    fs::rename(&write.temp, &write.dest)
+        .map_err(|e| AppError::io(&format!("Failed to write {}", write.dest.display()), e))
+}
+
+/// Save a document together with the images it references, all or nothing.
+/// Everything is staged to temp files first and then moved into place with the
+/// document last; on any failure, files already moved are put back as they were.
+#[tauri::command]
+pub fn save_document_with_assets(
+    document_path: String,
+    content: String,
+    assets: Vec<AssetWrite>,
+) -> Result<(), AppError> {
+    let writes = assets
+        .iter()
+        .map(|a| (expand_path(&a.dest), a.bytes.as_slice()))
+        .chain([(expand_path(&document_path), content.as_bytes())]);
+
+    let mut created = vec![];
+    let mut staged: Vec<StagedWrite> = vec![];
+    for (dest, bytes) in writes {
+        match stage_write(dest, bytes, &mut created) {
+            Ok(write) => staged.push(write),
+            Err(e) => {
+                for write in &staged {
+                    fs::remove_file(&write.temp).ok();
+                }
+                remove_created_dirs(&created);
+                return Err(e);
+            }
+        }
+    }
+
+    let mut committed: Vec<StagedWrite> = vec![];
+    let mut pending = staged.into_iter();
+    while let Some(mut write) = pending.next() {
+        if let Err(e) = commit_write(&mut write) {
+            if let Some(backup) = &write.backup {
+                fs::rename(backup, &write.dest).ok();
+            }
+            fs::remove_file(&write.temp).ok();
+            for write in pending {
+                fs::remove_file(&write.temp).ok();
+            }
+            roll_back(&committed);
+            remove_created_dirs(&created);
+            return Err(e);
+        }
+        committed.push(write);
+    }
+
+    for backup in committed.iter().filter_map(|w| w.backup.as_ref()) {
+        fs::remove_file(backup).ok();
+    }
+    Ok(())
+}
+
+// ============================================================================
+// External Links
+// ============================================================================
+
+/// Only plain web links may be handed to the system opener
+pub fn validate_external_url(url: &str) -> Result<reqwest::Url, String> {
+    let parsed =
+        reqwest::Url::parse(url.trim()).map_err(|e| format!("Invalid URL '{}': {}", url, e))?;
+
+    match parsed.scheme() {
+        "http" | "https" => {}
+        scheme => {
+            return Err(format!(
+                "Refusing to open '{}' URL: only http and https links are allowed",
+                scheme
+            ))
+        }
+    }
+
+    if parsed.host_str().is_none_or(|h| h.is_empty()) {
+        return Err(format!("Invalid URL '{}': missing host", url));
+    }
+
+    Ok(parsed)
+}
+
+#[tauri::command]
+#[allow(deprecated)]
+pub fn open_url(url: String, app: AppHandle) -> Result<(), String> {
+    let parsed = validate_external_url(&url)?;
+    app.shell()
+        .open(parsed.as_str(), None)
+        .map_err(|e| format!("Failed to open URL: {}", e))
+}
+
+// ============================================================================
+// App Info
+// ============================================================================
+
+#[tauri::command]
+pub fn get_app_info(state: State<AppState>) -> AppInfo {
+    app_info(
+        &state.formulas.lock().unwrap(),
+        &mut state.recent_files.lock().unwrap(),
+    )
+}
+
+fn app_info(formulas: &FormulaManager, recent_files: &mut RecentFilesManager) -> AppInfo {
+    let formula_count = formulas.get_all().len();
+    let recent_file_count = recent_files.get_files().len();
+
+    AppInfo {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        platform: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        settings_schema_version: crate::settings::SETTINGS_SCHEMA_VERSION,
+        formula_count,
+        recent_file_count,
     }
+}
 
-    fs::write(&destination, bytes)
-        .map_err(|e| format!("Failed to save image: {}", e))?;
+#[tauri::command]
+pub fn get_storage_status(state: State<AppState>) -> crate::paths::StorageStatus {
+    state.storage_status.clone()
+}
 
-    Ok(destination)
+/// Data files that failed to parse at startup, with the line and column of the problem
+#[tauri::command]
+pub fn get_data_file_errors(state: State<AppState>) -> Vec<crate::errors::JsonParseError> {
+    let settings = state.settings.lock().unwrap().load_error().cloned();
+    let formulas = state.formulas.lock().unwrap().load_error().cloned();
+    settings.into_iter().chain(formulas).collect()
 }
 
 // ============================================================================
 // Window State
 // ============================================================================
 
+/// Label of the window whose state is kept in `window_state`
+const MAIN_WINDOW_LABEL: &str = "main";
+
 #[tauri::command]
-pub fn get_window_state(state: State<AppState>) -> Option<WindowState> {
-    let settings = state.settings.lock().unwrap();
-    settings.get_settings().window_state
+pub fn get_window_state(label: Option<String>, state: State<AppState>) -> Option<WindowState> {
+    let settings = state.settings.lock().unwrap().get_settings();
+    match label.as_deref() {
+        None | Some(MAIN_WINDOW_LABEL) => settings.window_state,
+        Some(label) => settings.window_states.get(label).cloned(),
+    }
 }
 
 #[tauri::command]
-pub fn save_window_state(window_state: WindowState, state: State<AppState>) -> Result<(), String> {
+pub fn save_window_state(
+    window_state: WindowState,
+    label: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
     let mut manager = state.settings.lock().unwrap();
     let mut settings = manager.get_settings();
-    settings.window_state = Some(window_state);
+    match label {
+        Some(label) if label != MAIN_WINDOW_LABEL => {
+            settings.window_states.insert(label, window_state);
+        }
+        _ => settings.window_state = Some(window_state),
+    }
     manager.update_settings(settings);
     manager.save().map_err(|e| e.to_string())
 }
 
+/// Drop saved state for windows that are no longer created. Returns how many were dropped.
+#[tauri::command]
+pub fn prune_window_states(
+    active_labels: Vec<String>,
+    state: State<AppState>,
+) -> Result<usize, String> {
+    let mut manager = state.settings.lock().unwrap();
+    let pruned = manager.prune_window_states(&active_labels);
+    if pruned > 0 {
+        manager.save().map_err(|e| e.to_string())?;
+    }
+    Ok(pruned)
+}
+
 // ============================================================================
 // Formula Library
 // ============================================================================
 
+/// Formulas in quick-insert order, weighted by the `formula_rank_weights` setting
+#[tauri::command]
+pub fn get_formulas_smart(state: State<AppState>) -> Vec<Formula> {
+    let weights = state.settings.lock().unwrap().get_settings().formula_rank_weights;
+    let manager = state.formulas.lock().unwrap();
+    manager.smart_rank(&weights, chrono::Utc::now())
+}
+
+/// Whether a formula's LaTeX passes validation, memoized by a hash of the source.
+/// System formulas ship known-good and are not checked.
+pub fn is_renderable(cache: &Mutex<HashMap<String, bool>>, formula: &Formula) -> bool {
+    if formula.is_system {
+        return true;
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(formula.latex_content.as_bytes());
+    let key = hex::encode(hasher.finalize());
+
+    let mut cache = cache.lock().unwrap();
+    if let Some(renderable) = cache.get(&key) {
+        return *renderable;
+    }
+    let renderable = validate_latex(&formula.latex_content).is_ok();
+    if cache.len() >= MAX_PREVIEW_CACHE_ENTRIES {
+        cache.clear();
+    }
+    cache.insert(key, renderable);
+    renderable
+}
+
+/// All formulas; with `filter_renderable`, only those whose LaTeX will render
+#[tauri::command]
+pub fn get_formulas(filter_renderable: Option<bool>, state: State<AppState>) -> Vec<Formula> {
+    let formulas = state.formulas.lock().unwrap().get_all();
+    if !filter_renderable.unwrap_or(false) {
+        return formulas;
+    }
+    formulas
+        .into_iter()
+        .filter(|f| is_renderable(&state.renderable_cache, f))
+        .collect()
+}
+
+#[tauri::command]
+pub fn search_formulas(
+    query: String,
+    fuzzy: Option<bool>,
+    state: State<AppState>,
+) -> Vec<Formula> {
+    let manager = state.formulas.lock().unwrap();
+    manager.search(&query, fuzzy.unwrap_or(false))
+}
+
+/// Search restricted to the selected categories, tags, and favorites
+#[tauri::command]
+pub fn search_formulas_filtered(
+    query: String,
+    categories: Vec<String>,
+    tags: Vec<String>,
+    favorites_only: bool,
+    fuzzy: Option<bool>,
+    state: State<AppState>,
+) -> Vec<Formula> {
+    let manager = state.formulas.lock().unwrap();
+    manager.search_filtered(
+        &query,
+        fuzzy.unwrap_or(false),
+        &categories,
+        &tags,
+        favorites_only,
+    )
+}
+
+/// Distinct tags across the library, for tag pickers and autocomplete
+#[tauri::command]
+pub fn get_all_formula_tags(state: State<AppState>) -> Vec<String> {
+    state.formulas.lock().unwrap().all_tags()
+}
+
+/// Fill an empty category/subcategory from the `default_formula_*` settings
+fn apply_formula_defaults(formula: &mut Formula, settings: &crate::settings::Settings) {
+    let non_empty = |value: &Option<String>| value.clone().filter(|v| !v.trim().is_empty());
+    if formula.category.trim().is_empty() {
+        if let Some(category) = non_empty(&settings.default_formula_category) {
+            formula.category = category;
+        }
+    }
+    if formula.subcategory.as_deref().is_none_or(|s| s.trim().is_empty()) {
+        if let Some(subcategory) = non_empty(&settings.default_formula_subcategory) {
+            formula.subcategory = Some(subcategory);
+        }
+    }
+}
+
+#[tauri::command]
+pub fn create_formula(mut formula: Formula, state: State<AppState>) -> Result<Formula, String> {
+    apply_formula_defaults(&mut formula, &state.settings.lock().unwrap().get_settings());
+    let mut manager = state.formulas.lock().unwrap();
+    let result = manager.add(formula);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
 #[tauri::command]
-pub fn get_formulas(state: State<AppState>) -> Vec<Formula> {
+pub fn import_formulas_from_latex(
+    path: String,
+    default_category: String,
+    state: State<AppState>,
+) -> Result<Vec<Formula>, String> {
+    let path = expand_path(&path);
+    let source = fs::read_to_string(&path).map_err(|e| format!("Failed to read file: {}", e))?;
+    let prefix = path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or("Imported")
+        .to_string();
+
+    let mut manager = state.formulas.lock().unwrap();
+    let imported = manager.import_latex_equations(&source, &prefix, &default_category);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(imported)
+}
+
+/// Convert MathML to LaTeX and store it as a new user formula
+#[tauri::command]
+pub fn import_formula_mathml(
+    mathml: String,
+    name: String,
+    category: String,
+    state: State<AppState>,
+) -> Result<Formula, String> {
+    let latex = crate::mathml::convert_mathml(&mathml)?;
+    let mut formula = Formula::new_user(&name, &latex, &category);
+    apply_formula_defaults(&mut formula, &state.settings.lock().unwrap().get_settings());
+
+    let mut manager = state.formulas.lock().unwrap();
+    let result = manager.add(formula);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn formula_stats(state: State<AppState>) -> FormulaStats {
+    let manager = state.formulas.lock().unwrap();
+    manager.stats()
+}
+
+#[tauri::command]
+pub fn rename_formula_category(
+    old: String,
+    new: String,
+    subcategory: Option<(String, String)>,
+    state: State<AppState>,
+) -> Result<usize, String> {
+    let new = new.trim();
+    if new.is_empty() {
+        return Err("Category name cannot be empty".to_string());
+    }
+
+    let mut manager = state.formulas.lock().unwrap();
+    let changed = manager.rename_category(
+        &old,
+        new,
+        subcategory.as_ref().map(|(from, to)| (from.as_str(), to.as_str())),
+    );
+    if changed > 0 {
+        manager.save().map_err(|e| e.to_string())?;
+    }
+    Ok(changed)
+}
+
+/// Upper bound on cached formula previews; the cache is reset when it fills
+const MAX_PREVIEW_CACHE_ENTRIES: usize = 512;
+
+/// Validate a formula and return its LML wrapping and rendered SVG without
+/// persisting anything
+#[tauri::command]
+pub fn preview_formula(
+    latex: String,
+    mode: PreviewMode,
+    state: State<AppState>,
+) -> Result<PreviewResult, String> {
+    let settings = state.settings.lock().unwrap().get_settings();
+    let cache_dir = crate::render_cache::render_cache_dir(&state.app_data_dir.lock().unwrap());
+    let render_cache = RenderCache {
+        dir: &cache_dir,
+        max_bytes: crate::render_cache::render_cache_max_bytes(&settings),
+    };
+    cached_preview(
+        &latex,
+        mode,
+        &state.preview_cache,
+        &render_cache,
+        settings.latex_bin_dir.as_deref(),
+    )
+}
+
+/// Where preview renders are cached on disk, and the cache's budget
+struct RenderCache<'a> {
+    dir: &'a Path,
+    max_bytes: u64,
+}
+
+/// `preview_formula`, served from `cache` when the same input was previewed before
+fn cached_preview(
+    latex: &str,
+    mode: PreviewMode,
+    cache: &Mutex<HashMap<String, PreviewResult>>,
+    render_cache: &RenderCache,
+    bin_dir: Option<&str>,
+) -> Result<PreviewResult, String> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{:?}\0{}", mode, latex).as_bytes());
+    let key = hex::encode(hasher.finalize());
+
+    if let Some(cached) = cache.lock().unwrap().get(&key) {
+        return Ok(cached.clone());
+    }
+
+    let mut preview = build_preview(latex, mode)?;
+    match crate::render_cache::render_preview_svg(render_cache.dir, latex, mode, bin_dir) {
+        Ok(svg) => preview.svg = Some(svg),
+        Err(e) => preview.render_error = Some(e),
+    }
+    crate::render_cache::evict_render_cache(render_cache.dir, render_cache.max_bytes);
+
+    let mut cache = cache.lock().unwrap();
+    if cache.len() >= MAX_PREVIEW_CACHE_ENTRIES {
+        cache.clear();
+    }
+    cache.insert(key, preview.clone());
+    Ok(preview)
+}
+
+#[tauri::command]
+pub fn preview_formula_import(
+    path: String,
+    state: State<AppState>,
+) -> Result<ImportPreview, String> {
+    let incoming = read_import_file(&expand_path(&path))?;
+    let manager = state.formulas.lock().unwrap();
+    Ok(manager.preview_import(&incoming))
+}
+
+#[tauri::command]
+pub fn apply_formula_import(
+    path: String,
+    resolutions: HashMap<String, ConflictResolution>,
+    state: State<AppState>,
+) -> Result<ImportResult, String> {
+    let incoming = read_import_file(&expand_path(&path))?;
+    let mut manager = state.formulas.lock().unwrap();
+    if resolutions.values().any(|r| *r == ConflictResolution::Overwrite) {
+        manager.backup()?;
+    }
+    let result = manager.apply_import(incoming, &resolutions);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn encode_formula_share(id: String, state: State<AppState>) -> Result<String, String> {
     let manager = state.formulas.lock().unwrap();
-    manager.get_all()
+    let formula = manager
+        .get(&id)
+        .ok_or_else(|| format!("Formula not found: {}", id))?;
+    formula.to_share_blob()
 }
 
 #[tauri::command]
-pub fn create_formula(formula: Formula, state: State<AppState>) -> Result<Formula, String> {
+pub fn decode_formula_share(blob: String, state: State<AppState>) -> Result<Formula, String> {
+    let formula = Formula::from_share_blob(&blob)?;
     let mut manager = state.formulas.lock().unwrap();
     let result = manager.add(formula);
     manager.save().map_err(|e| e.to_string())?;
@@ -238,11 +1291,61 @@ pub fn update_formula(
 #[tauri::command]
 pub fn delete_formula(id: String, state: State<AppState>) -> Result<bool, String> {
     let mut manager = state.formulas.lock().unwrap();
+    manager.backup()?;
     let result = manager.remove(&id);
     manager.save().map_err(|e| e.to_string())?;
     Ok(result)
 }
 
+#[tauri::command]
+pub fn trash_formula(id: String, state: State<AppState>) -> Result<Option<Formula>, String> {
+    let mut manager = state.formulas.lock().unwrap();
+    let result = manager.trash(&id);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn restore_formula(id: String, state: State<AppState>) -> Result<Option<Formula>, String> {
+    let mut manager = state.formulas.lock().unwrap();
+    let result = manager.restore(&id);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn get_trashed_formulas(state: State<AppState>) -> Vec<Formula> {
+    let manager = state.formulas.lock().unwrap();
+    manager.get_trashed()
+}
+
+#[tauri::command]
+pub fn purge_trashed_formulas(
+    older_than_days: u32,
+    state: State<AppState>,
+) -> Result<usize, String> {
+    let mut manager = state.formulas.lock().unwrap();
+    manager.backup()?;
+    let result = manager.purge_trashed(older_than_days);
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(result)
+}
+
+#[tauri::command]
+pub fn list_formula_backups(state: State<AppState>) -> Vec<FormulaBackup> {
+    let manager = state.formulas.lock().unwrap();
+    manager.list_backups()
+}
+
+/// Replace the formula library with a backup; returns the restored formula count
+#[tauri::command]
+pub fn restore_formula_backup(name: String, state: State<AppState>) -> Result<usize, String> {
+    let mut manager = state.formulas.lock().unwrap();
+    let count = manager.restore_backup(&name)?;
+    manager.save().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
 #[tauri::command]
 pub fn toggle_formula_favorite(id: String, state: State<AppState>) -> Result<Option<Formula>, String> {
     let mut manager = state.formulas.lock().unwrap();
@@ -253,8 +1356,907 @@ pub fn toggle_formula_favorite(id: String, state: State<AppState>) -> Result<Opt
 
 #[tauri::command]
 pub fn increment_formula_usage(id: String, state: State<AppState>) -> Result<Option<Formula>, String> {
-    let mut manager = state.formulas.lock().unwrap();
-    let result = manager.increment_usage(&id);
+    let app_data_dir = state.app_data_dir.lock().unwrap().clone();
+    count_formula_use(&mut state.formulas.lock().unwrap(), &app_data_dir, &id)
+}
+
+/// Count a use of a formula and append it to the usage log. The log only feeds
+/// usage reports, so once the count is saved a failed log write is ignored.
+fn count_formula_use(
+    manager: &mut FormulaManager,
+    app_data_dir: &Path,
+    id: &str,
+) -> Result<Option<Formula>, String> {
+    let result = manager.increment_usage(id);
     manager.save().map_err(|e| e.to_string())?;
+
+    if result.is_some() {
+        crate::usage_log::record_usage(app_data_dir, id).ok();
+    }
     Ok(result)
 }
+
+#[tauri::command]
+pub fn export_formulas_flashcards(
+    path: String,
+    category: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let manager = state.formulas.lock().unwrap();
+    let csv = manager.to_flashcards_csv(category.as_deref());
+    Ok(write_file(path, csv, None)?)
+}
+
+/// Write the built-in formulas as a printable HTML or Markdown reference sheet
+#[tauri::command]
+pub fn export_formula_sheet(
+    output_path: String,
+    format: SheetFormat,
+    categories: Option<Vec<String>>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let manager = state.formulas.lock().unwrap();
+    let groups = manager.grouped_by_category(true, categories.as_deref());
+    if groups.is_empty() {
+        return Err("No formulas match the requested categories".to_string());
+    }
+    Ok(write_file(output_path, render_formula_sheet(&groups, format), None)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path_string(path: &Path) -> String {
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn file_info_reports_epoch_millis() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.lml");
+        fs::write(&path, "hello").unwrap();
+
+        let info = get_file_info(path_string(&path)).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        assert_eq!(info.size, 5);
+        assert_eq!(info.modified_ms, Some(epoch_millis(modified)));
+        assert!(info.modified.is_some());
+        assert!(!info.read_only);
+    }
+
+    #[test]
+    fn file_info_flags_read_only_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("locked.lml");
+        fs::write(&path, "hello").unwrap();
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions.clone()).unwrap();
+
+        let info = get_file_info(path_string(&path)).unwrap();
+
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        fs::set_permissions(&path, permissions).unwrap();
+        assert!(info.read_only);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_info_reports_symlink_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("real.lml");
+        fs::write(&target, "hello").unwrap();
+        let link = dir.path().join("link.lml");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        let info = get_file_info(path_string(&link)).unwrap();
+        assert!(info.is_symlink);
+        assert_eq!(
+            info.target,
+            Some(path_string(&fs::canonicalize(&target).unwrap()))
+        );
+        // The link's own path is reported, with the target's metadata
+        assert_eq!(info.path, path_string(&link));
+        assert_eq!(info.size, 5);
+
+        let info = get_file_info(path_string(&target)).unwrap();
+        assert!(!info.is_symlink);
+        assert_eq!(info.target, None);
+    }
+
+    #[test]
+    fn file_info_for_missing_file_is_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let err = get_file_info(path_string(&dir.path().join("missing.lml"))).unwrap_err();
+        assert!(matches!(err, AppError::NotFound(_)));
+    }
+
+    /// Text of the lines of `kind` across all hunks
+    fn diff_lines(hunks: &[DiffHunk], kind: crate::diff::DiffLineKind) -> Vec<&str> {
+        hunks
+            .iter()
+            .flat_map(|h| &h.lines)
+            .filter(|l| l.kind == kind)
+            .map(|l| l.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn diffing_two_files_reports_line_hunks() {
+        use crate::diff::DiffLineKind::{Delete, Insert};
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.lml");
+        let b = dir.path().join("b.lml");
+        fs::write(&a, "title\nfirst line\nsecond  line\nend\n").unwrap();
+        fs::write(&b, "title\nfirst line changed\nsecond line\nend\n").unwrap();
+
+        let hunks = diff_files(path_string(&a), path_string(&b), None).unwrap();
+        assert_eq!(hunks.len(), 1);
+        assert_eq!((hunks[0].old_start, hunks[0].old_lines), (1, 4));
+        assert_eq!(diff_lines(&hunks, Delete), ["first line", "second  line"]);
+        assert_eq!(
+            diff_lines(&hunks, Insert),
+            ["first line changed", "second line"]
+        );
+
+        // Whitespace-only changes drop out when ignored
+        let hunks = diff_files(path_string(&a), path_string(&b), Some(true)).unwrap();
+        assert_eq!(diff_lines(&hunks, Delete), ["first line"]);
+        assert_eq!(diff_lines(&hunks, Insert), ["first line changed"]);
+
+        fs::write(&b, "title\nfirst   line\nsecond line \nend\n").unwrap();
+        assert!(diff_files(path_string(&a), path_string(&b), Some(true))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            diff_files(path_string(&a), path_string(&b), Some(false))
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(diff_files(path_string(&a), path_string(&a), None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn diffing_a_missing_file_names_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a.lml");
+        fs::write(&a, "text\n").unwrap();
+        let missing = path_string(&dir.path().join("gone.lml"));
+
+        for (left, right) in [
+            (path_string(&a), missing.clone()),
+            (missing.clone(), path_string(&a)),
+        ] {
+            match diff_files(left, right, None) {
+                Err(AppError::NotFound(message)) => assert!(message.contains("gone.lml")),
+                other => panic!("expected NotFound, got {:?}", other),
+            }
+        }
+    }
+
+    fn encoding_of(bytes: &[u8]) -> EncodingReport {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("doc.lml");
+        fs::write(&path, bytes).unwrap();
+        detect_encoding(path_string(&path)).unwrap()
+    }
+
+    #[test]
+    fn detects_utf8_without_bom() {
+        let report = encoding_of("Café déjà vu".as_bytes());
+        assert_eq!(report.encoding, "utf-8");
+        assert!(!report.has_bom);
+        assert!(report.valid_utf8);
+        assert_eq!(report.non_ascii_bytes, 6);
+
+        assert_eq!(encoding_of(b"plain text").encoding, "ascii");
+    }
+
+    #[test]
+    fn detects_utf8_with_bom() {
+        let report = encoding_of(b"\xEF\xBB\xBFCaf\xC3\xA9");
+        assert_eq!(report.encoding, "utf-8");
+        assert!(report.has_bom);
+        assert!(report.valid_utf8);
+    }
+
+    #[test]
+    fn detects_latin1() {
+        // "Café déjà" in ISO-8859-1
+        let report = encoding_of(b"Caf\xE9 d\xE9j\xE0");
+        assert_eq!(report.encoding, "iso-8859-1");
+        assert!(!report.has_bom);
+        assert!(!report.valid_utf8);
+        assert_eq!(report.non_ascii_bytes, 3);
+
+        // Curly quotes live in the C1 range only in Windows-1252
+        assert_eq!(encoding_of(b"\x93quoted\x94").encoding, "windows-1252");
+    }
+
+    #[test]
+    fn sample_cut_mid_character_is_still_utf8() {
+        let report = sniff_encoding(&"é".as_bytes()[..1]);
+        assert!(report.valid_utf8);
+        assert_eq!(report.encoding, "utf-8");
+    }
+
+    #[test]
+    fn app_info_counts_active_formulas_and_existing_recent_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut trashed = Formula::new_user("Old", "a", "math");
+        trashed.deleted_at = Some(chrono::Utc::now().to_rfc3339());
+        let data = crate::formulas::FormulaData {
+            formulas: vec![
+                Formula::new_user("Ohm's Law", "V = IR", "physics"),
+                Formula::new_user("Newton", "F = ma", "physics"),
+                trashed,
+            ],
+            system_formula_version: crate::formulas::SYSTEM_FORMULA_VERSION,
+        };
+        let formulas_path = dir.path().join("formulas.json");
+        fs::write(&formulas_path, serde_json::to_string(&data).unwrap()).unwrap();
+        let formulas = FormulaManager::new(formulas_path);
+
+        let mut recent_files = RecentFilesManager::new(dir.path().join("recent.json"));
+        for name in ["a.lml", "b.lml"] {
+            let path = dir.path().join(name);
+            fs::write(&path, "").unwrap();
+            recent_files.add_file(&path_string(&path), false);
+        }
+        recent_files.add_file(&path_string(&dir.path().join("gone.lml")), false);
+
+        let info = app_info(&formulas, &mut recent_files);
+        assert_eq!(info.formula_count, 2);
+        assert_eq!(info.recent_file_count, 2);
+        assert_eq!(info.app_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.platform, std::env::consts::OS);
+        assert_eq!(
+            info.settings_schema_version,
+            crate::settings::SETTINGS_SCHEMA_VERSION
+        );
+    }
+
+    #[test]
+    fn formula_use_is_counted_even_if_the_usage_log_cannot_be_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let formulas_path = dir.path().join("formulas.json");
+        let mut formulas = FormulaManager::new(formulas_path.clone());
+        let id = formulas.add(Formula::new_user("Ohm's Law", "V = IR", "physics")).id;
+        // A file where the app data dir should be makes every log write fail
+        let blocked = dir.path().join("not-a-dir");
+        fs::write(&blocked, "").unwrap();
+
+        let counted = count_formula_use(&mut formulas, &blocked, &id).unwrap();
+        assert_eq!(counted.unwrap().usage_count, 1);
+        let reloaded = FormulaManager::new(formulas_path);
+        let saved = reloaded.get_all().into_iter().find(|f| f.id == id).unwrap();
+        assert_eq!(saved.usage_count, 1);
+
+        let app_data = dir.path().join("app");
+        fs::create_dir(&app_data).unwrap();
+        count_formula_use(&mut formulas, &app_data, &id).unwrap();
+        let logged: Vec<String> = crate::usage_log::read_usage_log(&app_data)
+            .into_iter()
+            .map(|(_, id)| id)
+            .collect();
+        assert_eq!(logged, vec![id]);
+    }
+
+    #[test]
+    fn external_urls_allow_only_web_links() {
+        for url in [
+            "https://doi.org/10.1000/182",
+            "http://example.com/paper.pdf",
+            "  HTTPS://Example.com  ",
+        ] {
+            assert!(
+                validate_external_url(url).is_ok(),
+                "{} should be allowed",
+                url
+            );
+        }
+        for url in [
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+            "ftp://example.com/paper.pdf",
+            "mailto:someone@example.com",
+            "smb://server/share",
+            "http://",
+            "example.com",
+            "",
+        ] {
+            assert!(
+                validate_external_url(url).is_err(),
+                "{} should be blocked",
+                url
+            );
+        }
+        let err = validate_external_url("file:///etc/passwd").unwrap_err();
+        assert!(err.contains("only http and https"), "{}", err);
+    }
+
+    #[test]
+    fn previews_are_cached_by_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Mutex::new(HashMap::new());
+        // No LaTeX tools in this directory, so rendering fails without an SVG
+        let bin_dir = path_string(&dir.path().join("no-tools"));
+        let render_cache = RenderCache {
+            dir: dir.path(),
+            max_bytes: u64::MAX,
+        };
+        let preview =
+            |latex: &str, mode| cached_preview(latex, mode, &cache, &render_cache, Some(&bin_dir));
+
+        let first = preview("x^2", PreviewMode::Inline).unwrap();
+        assert_eq!(first.lml_content, "@equation(mode: inline) x^2");
+        assert!(first.svg.is_none());
+        assert!(first.render_error.is_some());
+        assert_eq!(cache.lock().unwrap().len(), 1);
+
+        // A repeat is a cache hit; a different mode is a new entry
+        preview("x^2", PreviewMode::Inline).unwrap();
+        assert_eq!(cache.lock().unwrap().len(), 1);
+        let display = preview("x^2", PreviewMode::Display).unwrap();
+        assert_eq!(display.lml_content, "\n@equation(mode: display)\nx^2\n");
+        assert_eq!(cache.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn previews_keep_the_render_cache_within_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_render = dir.path().join("old.svg");
+        fs::write(&old_render, "<svg/>").unwrap();
+        let render_cache = RenderCache {
+            dir: dir.path(),
+            max_bytes: 0,
+        };
+        let bin_dir = path_string(&dir.path().join("no-tools"));
+
+        let cache = Mutex::new(HashMap::new());
+        cached_preview("x^2", PreviewMode::Display, &cache, &render_cache, Some(&bin_dir))
+            .unwrap();
+        assert!(!old_render.exists());
+    }
+
+    #[test]
+    fn invalid_previews_are_errors_and_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Mutex::new(HashMap::new());
+        let render_cache = RenderCache {
+            dir: dir.path(),
+            max_bytes: u64::MAX,
+        };
+        let err = cached_preview(
+            "\\frac{a}{b",
+            PreviewMode::Display,
+            &cache,
+            &render_cache,
+            None,
+        )
+        .unwrap_err();
+        assert_eq!(err, "1 unclosed brace(s)");
+        assert!(cache.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn toggles_flip_and_persist_only_their_field() {
+        use crate::settings::{Settings, SettingsManager};
+
+        type Field = fn(&mut Settings) -> &mut bool;
+        let fields: [Field; 3] = [
+            |s| &mut s.line_numbers,
+            |s| &mut s.minimap,
+            |s| &mut s.word_wrap,
+        ];
+
+        for (i, field) in fields.into_iter().enumerate() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("settings.json");
+            let mut manager = SettingsManager::new(path.clone());
+            let before = manager.get_settings();
+
+            let (value, _) = flip_setting(&mut manager, field).unwrap();
+            assert_eq!(value, !*field(&mut before.clone()));
+
+            // Reload from disk: only the toggled field differs
+            let mut saved = SettingsManager::new(path.clone()).get_settings();
+            assert_eq!(*field(&mut saved), value);
+            *field(&mut saved) = !value;
+            assert_eq!(
+                serde_json::to_value(&saved).unwrap(),
+                serde_json::to_value(&before).unwrap(),
+                "toggle {}",
+                i
+            );
+
+            // Toggling again flips it back
+            let (value, _) = flip_setting(&mut manager, field).unwrap();
+            assert_eq!(value, *field(&mut before.clone()));
+        }
+    }
+
+    /// Settings and recent files managers backed by files in `dir`
+    fn managers(dir: &Path) -> (Mutex<SettingsManager>, Mutex<RecentFilesManager>) {
+        (
+            Mutex::new(SettingsManager::new(dir.join("settings.json"))),
+            Mutex::new(RecentFilesManager::new(dir.join("recent_files.json"))),
+        )
+    }
+
+    #[test]
+    fn opening_a_document_records_it() {
+        let app_data = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        let path = docs.path().join("paper.lml");
+        fs::write(&path, "# Title").unwrap();
+        let (settings, recent_files) = managers(app_data.path());
+
+        let opened = open_and_record(path_string(&path), false, &settings, &recent_files).unwrap();
+        assert_eq!(opened.content, "# Title");
+        assert_eq!(opened.file_info.name, "paper.lml");
+        assert!(!opened.read_only);
+
+        // Both records are saved, not just held in memory
+        let (settings, recent_files) = managers(app_data.path());
+        assert_eq!(
+            recent_files.lock().unwrap().get_files(),
+            [path_string(&path)]
+        );
+        assert_eq!(
+            settings.lock().unwrap().get_settings().last_directory,
+            Some(path_string(docs.path()))
+        );
+    }
+
+    #[test]
+    fn failed_or_read_only_opens_record_nothing() {
+        let app_data = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        let (settings, recent_files) = managers(app_data.path());
+
+        let missing = path_string(&docs.path().join("missing.lml"));
+        assert!(open_and_record(missing, false, &settings, &recent_files).is_err());
+
+        let path = docs.path().join("shared.lml");
+        fs::write(&path, "text").unwrap();
+        let opened = open_and_record(path_string(&path), true, &settings, &recent_files).unwrap();
+        assert!(opened.read_only);
+
+        assert!(recent_files.lock().unwrap().get_files().is_empty());
+        assert_eq!(settings.lock().unwrap().get_settings().last_directory, None);
+        assert!(!app_data.path().join("recent_files.json").exists());
+    }
+
+    #[test]
+    fn read_only_opens_leave_existing_recents_untouched() {
+        let app_data = tempfile::tempdir().unwrap();
+        let docs = tempfile::tempdir().unwrap();
+        let references = tempfile::tempdir().unwrap();
+        let (settings, recent_files) = managers(app_data.path());
+        let file = |dir: &Path, name: &str| {
+            let path = dir.join(name);
+            fs::write(&path, name).unwrap();
+            path_string(&path)
+        };
+        let (first, second) = (file(docs.path(), "a.lml"), file(docs.path(), "b.lml"));
+        let reference = file(references.path(), "spec.lml");
+        open_and_record(first.clone(), false, &settings, &recent_files).unwrap();
+        open_and_record(second.clone(), false, &settings, &recent_files).unwrap();
+        let saved = fs::read_to_string(app_data.path().join("recent_files.json")).unwrap();
+
+        // Peeking at a new file, or at one already in recents, changes nothing
+        for path in [reference, first.clone()] {
+            let opened = open_and_record(path, true, &settings, &recent_files).unwrap();
+            assert!(opened.read_only);
+        }
+
+        assert_eq!(recent_files.lock().unwrap().get_files(), [second, first]);
+        assert_eq!(
+            fs::read_to_string(app_data.path().join("recent_files.json")).unwrap(),
+            saved
+        );
+        assert_eq!(
+            settings.lock().unwrap().get_settings().last_directory,
+            Some(path_string(docs.path()))
+        );
+    }
+
+    #[test]
+    fn save_as_only_replaces_existing_files_with_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("existing.lml");
+        fs::write(&existing, "keep me").unwrap();
+        let existing = path_string(&existing);
+
+        let err = check_save_target(&existing, false).unwrap_err();
+        assert!(matches!(err, AppError::Validation(ref m) if m.contains("already exists")));
+        assert!(check_save_target(&existing, true).is_ok());
+        assert!(check_save_target(&path_string(&dir.path().join("new.lml")), false).is_ok());
+    }
+
+    #[test]
+    fn text_and_binary_files_are_told_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = dir.path().join("notes.lml");
+        fs::write(&text, "# Notes\nCafé ∑ résumé\n").unwrap();
+        let binary = dir.path().join("figure.png");
+        fs::write(&binary, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+        let utf16 = dir.path().join("utf16.txt");
+        fs::write(&utf16, b"\xFF\xFEh\0i\0").unwrap();
+
+        assert!(is_text_file(path_string(&text)).unwrap());
+        assert!(!is_text_file(path_string(&binary)).unwrap());
+        assert!(is_text_file(path_string(&utf16)).unwrap());
+        let missing = is_text_file(path_string(&dir.path().join("missing")));
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn read_file_refuses_binary_only_when_asked() {
+        let dir = tempfile::tempdir().unwrap();
+        let text = path_string(&dir.path().join("notes.lml"));
+        fs::write(&text, "plain text").unwrap();
+        let binary = path_string(&dir.path().join("data.bin"));
+        fs::write(&binary, b"ab\0cd").unwrap();
+
+        assert_eq!(read_file(text.clone(), Some(true)).unwrap(), "plain text");
+        let err = read_file(binary.clone(), Some(true)).unwrap_err();
+        assert!(matches!(err, AppError::NotTextFile(_)), "{:?}", err);
+        // Without the guard, valid UTF-8 with NULs still reads as before
+        assert_eq!(read_file(binary, None).unwrap(), "ab\0cd");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_file_keeps_the_mode_when_preserving_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("build.sh");
+        fs::write(&file, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o754)).unwrap();
+
+        let preserve = PreserveMetadata {
+            permissions: true,
+            mtime: false,
+        };
+        write_file(
+            path_string(&file),
+            "#!/bin/sh\necho hi\n".into(),
+            Some(preserve),
+        )
+        .unwrap();
+
+        let mode = fs::metadata(&file).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o754);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "#!/bin/sh\necho hi\n");
+    }
+
+    #[test]
+    fn write_file_keeps_the_mtime_only_when_asked() {
+        use std::time::{Duration, SystemTime};
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("notes.lml");
+        let earlier = SystemTime::now() - Duration::from_secs(3600);
+        let age = |file: &Path| fs::metadata(file).unwrap().modified().unwrap();
+        let backdate = |file: &Path| {
+            fs::write(file, "v1").unwrap();
+            fs::File::options()
+                .write(true)
+                .open(file)
+                .unwrap()
+                .set_modified(earlier)
+                .unwrap();
+        };
+
+        backdate(&file);
+        let preserve = PreserveMetadata {
+            permissions: false,
+            mtime: true,
+        };
+        write_file(path_string(&file), "v2".into(), Some(preserve)).unwrap();
+        assert_eq!(age(&file), earlier);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "v2");
+
+        // Off by default: the save bumps the modification time
+        backdate(&file);
+        write_file(path_string(&file), "v3".into(), None).unwrap();
+        assert!(age(&file) > earlier);
+    }
+
+    #[test]
+    fn write_file_creates_new_files_with_preserve_set() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("nested/new.lml");
+        let preserve = PreserveMetadata {
+            permissions: true,
+            mtime: true,
+        };
+        write_file(path_string(&file), "fresh".into(), Some(preserve)).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "fresh");
+    }
+
+    fn formula_defaults() -> crate::settings::Settings {
+        crate::settings::Settings {
+            default_formula_category: Some("physics".to_string()),
+            default_formula_subcategory: Some("mechanics".to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_formula_fields_inherit_the_defaults() {
+        let mut formula = Formula::new_user("Force", "F = ma", " ");
+        formula.subcategory = Some(String::new());
+        apply_formula_defaults(&mut formula, &formula_defaults());
+        assert_eq!(formula.category, "physics");
+        assert_eq!(formula.subcategory.as_deref(), Some("mechanics"));
+
+        let mut formula = Formula::new_user("Force", "F = ma", "");
+        apply_formula_defaults(&mut formula, &formula_defaults());
+        assert_eq!(formula.subcategory.as_deref(), Some("mechanics"));
+    }
+
+    #[test]
+    fn provided_formula_fields_are_left_untouched() {
+        let mut formula = Formula::new_user("Sum", "\\sum_i x_i", "calculus");
+        formula.subcategory = Some("series".to_string());
+        apply_formula_defaults(&mut formula, &formula_defaults());
+        assert_eq!(formula.category, "calculus");
+        assert_eq!(formula.subcategory.as_deref(), Some("series"));
+
+        // Without defaults configured, empty fields stay empty
+        let mut formula = Formula::new_user("Sum", "\\sum_i x_i", "");
+        apply_formula_defaults(&mut formula, &Default::default());
+        assert_eq!(formula.category, "");
+        assert_eq!(formula.subcategory, None);
+    }
+
+    #[test]
+    fn quarantined_files_are_listed_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("settings.json"), "{broken").unwrap();
+        quarantine_file(dir.path(), &dir.path().join("settings.json")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        fs::write(dir.path().join("formulas.json"), "[broken").unwrap();
+        quarantine_file(dir.path(), &dir.path().join("formulas.json")).unwrap();
+        fs::write(
+            quarantine_dir(dir.path()).join("notes.txt"),
+            "not quarantined",
+        )
+        .unwrap();
+
+        let kinds: Vec<String> = quarantined_files(dir.path())
+            .into_iter()
+            .map(|f| f.kind)
+            .collect();
+        assert_eq!(kinds, ["formulas.json", "settings.json"]);
+        assert!(quarantined_files(&dir.path().join("empty")).is_empty());
+    }
+
+    #[test]
+    fn restoring_swaps_the_quarantined_file_back_in() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_file = dir.path().join("session.json");
+        fs::write(&data_file, "older session").unwrap();
+        let quarantined = quarantine_file(dir.path(), &data_file).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        fs::write(&data_file, "current session").unwrap();
+
+        let path = path_string(&quarantined);
+        let (original, target) = restore_from_quarantine(dir.path(), &path).unwrap();
+        assert_eq!(original, "session.json");
+        assert_eq!(target, data_file);
+        assert_eq!(fs::read_to_string(&data_file).unwrap(), "older session");
+        assert!(!quarantined.exists());
+
+        // The file it replaced is kept in quarantine
+        let remaining = quarantined_files(dir.path());
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(
+            fs::read_to_string(&remaining[0].path).unwrap(),
+            "current session"
+        );
+    }
+
+    #[test]
+    fn only_files_in_quarantine_can_be_restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = dir.path().join("settings-20240102T030405.678Z.json");
+        fs::write(&outside, "{}").unwrap();
+        let err = restore_from_quarantine(dir.path(), &path_string(&outside)).unwrap_err();
+        assert!(err.starts_with("Not a quarantined file"));
+
+        fs::create_dir_all(quarantine_dir(dir.path())).unwrap();
+        let unnamed = quarantine_dir(dir.path()).join("settings.json");
+        fs::write(&unnamed, "{}").unwrap();
+        assert!(restore_from_quarantine(dir.path(), &path_string(&unnamed)).is_err());
+        assert!(!dir.path().join("settings.json").exists());
+    }
+
+    #[test]
+    fn invalid_latex_is_flagged_non_renderable() {
+        let cache = Mutex::new(HashMap::new());
+        let valid = Formula::new_user("Fraction", "\\frac{1}{2}", "algebra");
+        let invalid = Formula::new_user("Broken", "\\frac{1}{2", "algebra");
+        assert!(is_renderable(&cache, &valid));
+        assert!(!is_renderable(&cache, &invalid));
+        assert_eq!(cache.lock().unwrap().len(), 2);
+
+        // The result is memoized by the LaTeX source
+        let same_source = Formula::new_user("Half", "\\frac{1}{2}", "other");
+        assert!(is_renderable(&cache, &same_source));
+        assert_eq!(cache.lock().unwrap().len(), 2);
+
+        // System formulas are trusted without a check
+        let mut system = invalid.clone();
+        system.is_system = true;
+        assert!(is_renderable(&cache, &system));
+        assert_eq!(cache.lock().unwrap().len(), 2);
+    }
+
+    fn asset(dest: &Path, bytes: &[u8]) -> AssetWrite {
+        AssetWrite {
+            bytes: bytes.to_vec(),
+            dest: path_string(dest),
+        }
+    }
+
+    /// Every file and dir under `dir`, relative to it, sorted
+    fn tree(dir: &Path) -> Vec<String> {
+        let mut found = vec![];
+        for entry in fs::read_dir(dir).unwrap().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() {
+                found.extend(
+                    tree(&entry.path())
+                        .into_iter()
+                        .map(|n| format!("{}/{}", name, n)),
+                );
+            }
+            found.push(name);
+        }
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn document_and_assets_are_saved_together() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("paper.lml");
+        fs::write(&document, "old").unwrap();
+        fs::create_dir(dir.path().join("images")).unwrap();
+        fs::write(dir.path().join("images/fig1.png"), "old figure").unwrap();
+
+        let assets = vec![
+            asset(&dir.path().join("images/fig1.png"), b"figure 1"),
+            asset(&dir.path().join("images/new/fig2.png"), b"figure 2"),
+        ];
+        save_document_with_assets(path_string(&document), "new".to_string(), assets).unwrap();
+
+        assert_eq!(fs::read_to_string(&document).unwrap(), "new");
+        assert_eq!(
+            fs::read(dir.path().join("images/fig1.png")).unwrap(),
+            b"figure 1"
+        );
+        assert_eq!(
+            fs::read(dir.path().join("images/new/fig2.png")).unwrap(),
+            b"figure 2"
+        );
+        // No staged or backup files are left behind
+        assert_eq!(
+            tree(dir.path()),
+            [
+                "images",
+                "images/fig1.png",
+                "images/new",
+                "images/new/fig2.png",
+                "paper.lml"
+            ]
+        );
+    }
+
+    #[test]
+    fn a_failed_save_leaves_no_partial_state() {
+        let dir = tempfile::tempdir().unwrap();
+        let document = dir.path().join("paper.lml");
+        fs::write(&document, "old").unwrap();
+        fs::write(dir.path().join("fig1.png"), "old figure").unwrap();
+        let before = tree(dir.path());
+
+        // The document's destination is a directory, so the save fails after
+        // both assets were staged
+        let blocked = dir.path().join("blocked.lml");
+        fs::create_dir(&blocked).unwrap();
+        let assets = vec![
+            asset(&dir.path().join("fig1.png"), b"figure 1"),
+            asset(&dir.path().join("images/deep/fig2.png"), b"figure 2"),
+        ];
+        let err = save_document_with_assets(path_string(&blocked), "new".to_string(), assets)
+            .unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)));
+
+        assert_eq!(
+            fs::read_to_string(dir.path().join("fig1.png")).unwrap(),
+            "old figure"
+        );
+        assert_eq!(fs::read_to_string(&document).unwrap(), "old");
+        let mut expected = before;
+        expected.push("blocked.lml".to_string());
+        expected.sort();
+        // Staged temp files and the directories created for them are gone
+        assert_eq!(tree(dir.path()), expected);
+    }
+
+    /// What the settings panel's `save()` sends: only the fields it edits
+    fn settings_panel_save() -> serde_json::Value {
+        serde_json::json!({
+            "editorFontSize": 15,
+            "editorFontFamily": "Fira Code",
+            "tabSize": 4,
+            "wordWrap": true,
+            "lineNumbers": true,
+            "minimap": false,
+            "theme": "light",
+            "livePreview": true,
+            "previewFontSize": 16,
+            "autoSave": true,
+            "autoSaveDelay": 3000,
+            "spellCheck": true,
+            "showOutline": false,
+        })
+    }
+
+    #[test]
+    fn a_settings_panel_save_keeps_the_quick_capture_shortcut() {
+        let dir = tempfile::tempdir().unwrap();
+        let (settings, _) = managers(dir.path());
+        let mut manager = settings.lock().unwrap();
+        let mut current = manager.get_settings();
+        current.quick_capture_shortcut = Some("CmdOrCtrl+Shift+N".to_string());
+        manager.update_settings(current);
+
+        let saved = apply_settings_update(&mut manager, settings_panel_save()).unwrap();
+        assert_eq!(
+            saved.quick_capture_shortcut.as_deref(),
+            Some("CmdOrCtrl+Shift+N")
+        );
+        assert_eq!(saved.theme, "light");
+    }
+
+    #[test]
+    fn a_settings_panel_save_keeps_network_and_diagnostic_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let (settings, _) = managers(dir.path());
+        let mut manager = settings.lock().unwrap();
+        let mut current = manager.get_settings();
+        current.http_proxy = Some("http://proxy.local:3128".to_string());
+        current.http_timeout_secs = 5;
+        current.offline_mode = true;
+        current.debug_logging = true;
+        current.formula_rank_weights.usage = 4.0;
+        manager.update_settings(current);
+
+        let saved = apply_settings_update(&mut manager, settings_panel_save()).unwrap();
+        assert_eq!(saved.http_proxy.as_deref(), Some("http://proxy.local:3128"));
+        assert_eq!(saved.http_timeout_secs, 5);
+        assert!(saved.offline_mode && saved.debug_logging);
+        assert_eq!(saved.formula_rank_weights.usage, 4.0);
+
+        // Fields that are sent are still validated
+        let bad_proxy = serde_json::json!({ "httpProxy": "ftp://proxy" });
+        assert!(apply_settings_update(&mut manager, bad_proxy).is_err());
+        assert!(apply_settings_update(&mut manager, serde_json::json!([])).is_err());
+        assert_eq!(
+            manager.get_settings().http_proxy.as_deref(),
+            Some("http://proxy.local:3128")
+        );
+    }
+}
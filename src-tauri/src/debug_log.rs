@@ -0,0 +1,184 @@
+use crate::settings::SettingsManager;
+use crate::AppState;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager, State};
+
+/// The active log is rotated to `debug.log.1` once it passes this size
+const MAX_DEBUG_LOG_BYTES: u64 = 512 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DebugLogEntry {
+    pub timestamp: String,
+    pub command: String,
+    pub outcome: String,
+}
+
+fn debug_log_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("debug.log")
+}
+
+fn rotated_log_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("debug.log.1")
+}
+
+/// Append a `timestamp<TAB>command<TAB>outcome` line, rotating the log first if it's full.
+/// Only the command name is recorded, never its arguments.
+pub fn record_command(app_data_dir: &Path, command: &str, outcome: &str) -> io::Result<()> {
+    let path = debug_log_path(app_data_dir);
+    if fs::metadata(&path).is_ok_and(|m| m.len() >= MAX_DEBUG_LOG_BYTES) {
+        fs::rename(&path, rotated_log_path(app_data_dir))?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}\t{}\t{}", Utc::now().to_rfc3339(), command, outcome)
+}
+
+/// Logged commands, oldest first. Malformed lines are skipped.
+pub fn read_debug_log(app_data_dir: &Path) -> Vec<DebugLogEntry> {
+    [rotated_log_path(app_data_dir), debug_log_path(app_data_dir)]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| {
+                    let mut fields = line.splitn(3, '\t');
+                    Some(DebugLogEntry {
+                        timestamp: fields.next()?.to_string(),
+                        command: fields.next()?.to_string(),
+                        outcome: fields.next()?.to_string(),
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Record how a command the frontend called turned out, when `debug_logging` is
+/// on. The invoke handler never sees a command's result, so the frontend's
+/// `invoke` wrapper reports each call once it settles.
+#[tauri::command]
+pub async fn record_command_outcome(
+    command: String,
+    ok: bool,
+    app: AppHandle,
+) -> Result<(), String> {
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        log_invocation(&state.settings, &state.app_data_dir, &command, ok);
+    })
+    .await
+    .map_err(|e| format!("Debug log failed: {}", e))
+}
+
+/// Record one call when `debug_logging` is on. The settings lock is released
+/// before the log is written.
+fn log_invocation(
+    settings: &Mutex<SettingsManager>,
+    app_data_dir: &Mutex<PathBuf>,
+    command: &str,
+    ok: bool,
+) {
+    let enabled = settings
+        .lock()
+        .is_ok_and(|s| s.get_settings().debug_logging);
+    if enabled {
+        if let Ok(app_data_dir) = app_data_dir.lock() {
+            let outcome = if ok { "ok" } else { "err" };
+            record_command(&app_data_dir, command, outcome).ok();
+        }
+    }
+}
+
+#[tauri::command]
+pub fn get_debug_log(state: State<AppState>) -> Result<Vec<DebugLogEntry>, String> {
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    Ok(read_debug_log(&app_data_dir))
+}
+
+#[tauri::command]
+pub fn clear_debug_log(state: State<AppState>) -> Result<(), String> {
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    clear_log(&app_data_dir)
+}
+
+fn clear_log(app_data_dir: &Path) -> Result<(), String> {
+    for path in [debug_log_path(app_data_dir), rotated_log_path(app_data_dir)] {
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| format!("Failed to clear debug log: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn managers(dir: &Path) -> (Mutex<SettingsManager>, Mutex<PathBuf>) {
+        let settings = SettingsManager::new(dir.join("settings.json"));
+        (Mutex::new(settings), Mutex::new(dir.to_path_buf()))
+    }
+
+    fn set_logging(settings: &Mutex<SettingsManager>, enabled: bool) {
+        let mut manager = settings.lock().unwrap();
+        let mut updated = manager.get_settings();
+        updated.debug_logging = enabled;
+        manager.update_settings(updated);
+    }
+
+    #[test]
+    fn calls_are_logged_only_while_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let (settings, app_data_dir) = managers(dir.path());
+
+        log_invocation(&settings, &app_data_dir, "read_file", true);
+        assert!(read_debug_log(dir.path()).is_empty());
+
+        set_logging(&settings, true);
+        log_invocation(&settings, &app_data_dir, "read_file", true);
+        log_invocation(&settings, &app_data_dir, "write_file", false);
+        set_logging(&settings, false);
+        log_invocation(&settings, &app_data_dir, "write_file", true);
+
+        let entries: Vec<(String, String)> = read_debug_log(dir.path())
+            .into_iter()
+            .map(|e| (e.command, e.outcome))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("read_file".to_string(), "ok".to_string()),
+                ("write_file".to_string(), "err".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_full_log_rotates_and_clearing_removes_both_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let line = "2024-01-01T00:00:00+00:00\told_command\tok\n";
+        let full = line.repeat(MAX_DEBUG_LOG_BYTES as usize / line.len() + 1);
+        fs::write(debug_log_path(dir.path()), &full).unwrap();
+
+        record_command(dir.path(), "new_command", "ok").unwrap();
+        assert!(rotated_log_path(dir.path()).exists());
+        let entries = read_debug_log(dir.path());
+        assert_eq!(entries.len(), full.lines().count() + 1);
+        assert_eq!(entries.last().unwrap().command, "new_command");
+
+        clear_log(dir.path()).unwrap();
+        assert!(read_debug_log(dir.path()).is_empty());
+        assert!(!rotated_log_path(dir.path()).exists());
+    }
+}
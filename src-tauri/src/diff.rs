@@ -0,0 +1,197 @@
+use serde::{Deserialize, Serialize};
+
+/// One line of a line-based diff
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp<'a> {
+    Equal(&'a str),
+    Insert(&'a str),
+    Delete(&'a str),
+}
+
+/// Line diff of `old` against `new` using Myers' algorithm. Common leading
+/// and trailing lines are stripped first so typical edits stay cheap.
+pub fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<DiffOp<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut ops: Vec<DiffOp<'a>> = old[..prefix].iter().map(|l| DiffOp::Equal(l)).collect();
+    ops.extend(myers(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    ));
+    ops.extend(old[old.len() - suffix..].iter().map(|l| DiffOp::Equal(l)));
+    ops
+}
+
+fn myers<'a>(a: &[&'a str], b: &[&'a str]) -> Vec<DiffOp<'a>> {
+    let n = a.len() as isize;
+    let m = b.len() as isize;
+    let max = (n + m) as usize;
+    if max == 0 {
+        return vec![];
+    }
+
+    let offset = max as isize;
+    let mut v = vec![0isize; 2 * max + 2];
+    let mut trace: Vec<Vec<isize>> = vec![];
+
+    // Forward pass: record the furthest-reaching x for each diagonal k at each edit distance d
+    'search: for d in 0..=max as isize {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Backtrack from the end to recover the edit script
+    let mut ops = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            ops.push(DiffOp::Equal(a[x as usize]));
+        }
+        if d > 0 {
+            if x == prev_x {
+                y -= 1;
+                ops.push(DiffOp::Insert(b[y as usize]));
+            } else {
+                x -= 1;
+                ops.push(DiffOp::Delete(a[x as usize]));
+            }
+        }
+    }
+
+    ops.reverse();
+    ops
+}
+
+/// Lines of unchanged context kept around each hunk
+pub const DIFF_CONTEXT_LINES: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffLineKind {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffLine {
+    pub kind: DiffLineKind,
+    pub text: String,
+}
+
+/// A run of changes with surrounding context, numbered like a unified diff
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffHunk {
+    /// 1-based; the line before the hunk when it covers no old lines
+    pub old_start: usize,
+    pub old_lines: usize,
+    pub new_start: usize,
+    pub new_lines: usize,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Line diff of two texts grouped into hunks. With `ignore_whitespace`, lines
+/// differing only in whitespace compare equal; the original text is reported.
+pub fn diff_hunks(old: &str, new: &str, context: usize, ignore_whitespace: bool) -> Vec<DiffHunk> {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let normalize = |lines: &[&str]| -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| match ignore_whitespace {
+                true => l.split_whitespace().collect::<Vec<_>>().join(" "),
+                false => l.to_string(),
+            })
+            .collect()
+    };
+    let old_keys = normalize(&old_lines);
+    let new_keys = normalize(&new_lines);
+    let old_refs: Vec<&str> = old_keys.iter().map(String::as_str).collect();
+    let new_refs: Vec<&str> = new_keys.iter().map(String::as_str).collect();
+
+    // Map the ops back to the original lines, tracking both line numbers
+    let (mut i, mut j) = (0, 0);
+    let mut lines: Vec<(DiffLine, usize, usize)> = vec![];
+    for op in diff_lines(&old_refs, &new_refs) {
+        let (kind, text) = match op {
+            DiffOp::Equal(_) => (DiffLineKind::Equal, old_lines[i]),
+            DiffOp::Insert(_) => (DiffLineKind::Insert, new_lines[j]),
+            DiffOp::Delete(_) => (DiffLineKind::Delete, old_lines[i]),
+        };
+        let text = text.to_string();
+        lines.push((DiffLine { kind, text }, i, j));
+        match kind {
+            DiffLineKind::Equal => (i, j) = (i + 1, j + 1),
+            DiffLineKind::Insert => j += 1,
+            DiffLineKind::Delete => i += 1,
+        }
+    }
+
+    let changes: Vec<usize> = (0..lines.len())
+        .filter(|&k| lines[k].0.kind != DiffLineKind::Equal)
+        .collect();
+    let mut hunks = vec![];
+    let mut index = 0;
+    while index < changes.len() {
+        let first = changes[index];
+        // Extend the hunk while the next change is close enough to share context
+        while index + 1 < changes.len() && changes[index + 1] - changes[index] <= 2 * context + 1 {
+            index += 1;
+        }
+        let start = first.saturating_sub(context);
+        let end = (changes[index] + context + 1).min(lines.len());
+        hunks.push(build_hunk(&lines[start..end]));
+        index += 1;
+    }
+    hunks
+}
+
+fn build_hunk(lines: &[(DiffLine, usize, usize)]) -> DiffHunk {
+    let count = |kind| lines.iter().filter(|(l, _, _)| l.kind != kind).count();
+    let old_lines = count(DiffLineKind::Insert);
+    let new_lines = count(DiffLineKind::Delete);
+    let (_, old_index, new_index) = lines[0];
+    DiffHunk {
+        // Unified diff convention: an empty side points at the line before it
+        old_start: old_index + usize::from(old_lines > 0),
+        old_lines,
+        new_start: new_index + usize::from(new_lines > 0),
+        new_lines,
+        lines: lines.iter().map(|(l, _, _)| l.clone()).collect(),
+    }
+}
@@ -0,0 +1,353 @@
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tauri::State;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LastExport {
+    pub directory: String,
+    pub format: String,
+}
+
+/// Where the editor was in a document when it was last left
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentPosition {
+    /// 1-based
+    pub cursor_line: u32,
+    pub scroll_top: f64,
+    /// Lines in the document when the position was saved
+    #[serde(default)]
+    pub line_count: u32,
+}
+
+impl DocumentPosition {
+    /// Fit a saved position to a document that now has `line_count` lines. If it
+    /// shrank, the cursor moves to the last line and the scroll offset is scaled down
+    /// by the same ratio.
+    pub fn clamped(self, line_count: u32) -> Self {
+        let line_count = line_count.max(1);
+        let mut scroll_top = self.scroll_top.max(0.0);
+        if self.line_count > line_count {
+            scroll_top *= line_count as f64 / self.line_count as f64;
+        }
+        Self {
+            cursor_line: self.cursor_line.clamp(1, line_count),
+            scroll_top,
+            line_count,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSettings {
+    #[serde(default)]
+    pub word_goal: Option<u32>,
+    #[serde(default)]
+    pub last_export: Option<LastExport>,
+    #[serde(default)]
+    pub position: Option<DocumentPosition>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct DocumentSettingsData {
+    pub documents: HashMap<String, DocumentSettings>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WordGoalProgress {
+    pub current: u32,
+    pub goal: u32,
+    pub percent: f64,
+}
+
+/// Settings that apply to a single document, keyed by its path
+pub struct DocumentSettingsManager {
+    path: PathBuf,
+    data: DocumentSettingsData,
+}
+
+impl DocumentSettingsManager {
+    pub fn new(path: PathBuf) -> Self {
+        let data = Self::load_from_path(&path).unwrap_or_default();
+        Self { path, data }
+    }
+
+    fn load_from_path(path: &PathBuf) -> io::Result<DocumentSettingsData> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn get(&self, document_path: &str) -> DocumentSettings {
+        self.data
+            .documents
+            .get(document_path)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Apply a change to a document's settings, creating the entry if needed
+    pub fn update<F: FnOnce(&mut DocumentSettings)>(&mut self, document_path: &str, f: F) {
+        let entry = self
+            .data
+            .documents
+            .entry(document_path.to_string())
+            .or_default();
+        f(entry);
+    }
+
+    /// Words in `current_content` against the document's goal; `None` without a goal
+    pub fn word_goal_progress(
+        &self,
+        document_path: &str,
+        current_content: &str,
+    ) -> Option<WordGoalProgress> {
+        let goal = self.get(document_path).word_goal.filter(|g| *g > 0)?;
+
+        let current = crate::versions::count_words(current_content);
+        Some(WordGoalProgress {
+            current,
+            goal,
+            percent: current as f64 / goal as f64 * 100.0,
+        })
+    }
+
+    /// The document's last export location, forgotten (and saved) once its
+    /// directory no longer exists
+    pub fn last_export(&mut self, document_path: &str) -> io::Result<Option<LastExport>> {
+        let Some(last_export) = self.get(document_path).last_export else {
+            return Ok(None);
+        };
+        if !std::path::Path::new(&last_export.directory).is_dir() {
+            self.update(document_path, |s| s.last_export = None);
+            self.save()?;
+            return Ok(None);
+        }
+        Ok(Some(last_export))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(&self.data)?;
+        fs::write(&self.path, content)
+    }
+}
+
+#[tauri::command]
+pub fn get_document_settings(document_path: String, state: State<AppState>) -> DocumentSettings {
+    let manager = state.document_settings.lock().unwrap();
+    manager.get(&document_path)
+}
+
+#[tauri::command]
+pub fn set_word_goal(
+    document_path: String,
+    goal: Option<u32>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.document_settings.lock().unwrap();
+    manager.update(&document_path, |s| s.word_goal = goal.filter(|g| *g > 0));
+    manager.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn word_goal_progress(
+    document_path: String,
+    current_content: String,
+    state: State<AppState>,
+) -> Option<WordGoalProgress> {
+    state
+        .document_settings
+        .lock()
+        .unwrap()
+        .word_goal_progress(&document_path, &current_content)
+}
+
+/// Where and how the document was last exported. Forgotten once the directory is gone.
+#[tauri::command]
+pub fn get_last_export(
+    document_path: String,
+    state: State<AppState>,
+) -> Result<Option<LastExport>, String> {
+    let mut manager = state.document_settings.lock().unwrap();
+    manager
+        .last_export(&document_path)
+        .map_err(|e| e.to_string())
+}
+
+/// Lines in the document on disk, or `None` if it can't be read
+fn document_line_count(document_path: &str) -> Option<u32> {
+    let content = fs::read_to_string(crate::paths::expand_path(document_path)).ok()?;
+    Some(content.lines().count() as u32)
+}
+
+/// Remember the cursor line and scroll offset so reopening the document returns there
+#[tauri::command]
+pub fn save_document_position(
+    path: String,
+    cursor_line: u32,
+    scroll_top: f64,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let mut manager = state.document_settings.lock().unwrap();
+    save_position(&mut manager, &path, cursor_line, scroll_top).map_err(|e| e.to_string())
+}
+
+fn save_position(
+    manager: &mut DocumentSettingsManager,
+    path: &str,
+    cursor_line: u32,
+    scroll_top: f64,
+) -> io::Result<()> {
+    let position = DocumentPosition {
+        cursor_line: cursor_line.max(1),
+        scroll_top: scroll_top.max(0.0),
+        line_count: document_line_count(path).unwrap_or(0),
+    };
+    manager.update(path, |s| s.position = Some(position));
+    manager.save()
+}
+
+/// The saved position, clamped to the document's current length
+#[tauri::command]
+pub fn get_document_position(path: String, state: State<AppState>) -> Option<DocumentPosition> {
+    position(&state.document_settings.lock().unwrap(), &path)
+}
+
+fn position(manager: &DocumentSettingsManager, path: &str) -> Option<DocumentPosition> {
+    let position = manager.get(path).position?;
+    Some(match document_line_count(path) {
+        Some(line_count) => position.clamped(line_count),
+        None => position,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> (tempfile::TempDir, DocumentSettingsManager) {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = DocumentSettingsManager::new(dir.path().join("document_settings.json"));
+        (dir, manager)
+    }
+
+    #[test]
+    fn goal_progress_counts_words_against_the_goal() {
+        let (_dir, mut manager) = manager();
+        manager.update("/docs/essay.lml", |s| s.word_goal = Some(8));
+
+        let progress = manager
+            .word_goal_progress("/docs/essay.lml", "one two three four\nfive six")
+            .unwrap();
+        assert_eq!(progress.current, 6);
+        assert_eq!(progress.goal, 8);
+        assert_eq!(progress.percent, 75.0);
+
+        // Going past the goal reports over 100%
+        let progress = manager
+            .word_goal_progress("/docs/essay.lml", &"word ".repeat(12))
+            .unwrap();
+        assert_eq!(progress.percent, 150.0);
+    }
+
+    #[test]
+    fn no_goal_means_no_progress() {
+        let (_dir, mut manager) = manager();
+        assert!(manager
+            .word_goal_progress("/docs/notes.lml", "some words")
+            .is_none());
+
+        manager.update("/docs/notes.lml", |s| s.word_goal = Some(0));
+        assert!(manager
+            .word_goal_progress("/docs/notes.lml", "some words")
+            .is_none());
+    }
+
+    #[test]
+    fn goals_survive_a_reload() {
+        let (dir, mut manager) = manager();
+        manager.update("/docs/essay.lml", |s| s.word_goal = Some(500));
+        manager.save().unwrap();
+
+        let reloaded = DocumentSettingsManager::new(dir.path().join("document_settings.json"));
+        assert_eq!(reloaded.get("/docs/essay.lml").word_goal, Some(500));
+    }
+
+    #[test]
+    fn last_export_is_forgotten_once_its_directory_is_gone() {
+        let (dir, mut manager) = manager();
+        let exports = dir.path().join("exports");
+        fs::create_dir(&exports).unwrap();
+        manager.update("/docs/a.lml", |s| {
+            s.last_export = Some(LastExport {
+                directory: exports.to_string_lossy().to_string(),
+                format: "pdf".to_string(),
+            })
+        });
+
+        let last = manager.last_export("/docs/a.lml").unwrap().unwrap();
+        assert_eq!(last.format, "pdf");
+        assert!(manager.last_export("/docs/other.lml").unwrap().is_none());
+
+        fs::remove_dir(&exports).unwrap();
+        assert!(manager.last_export("/docs/a.lml").unwrap().is_none());
+        // The cleared entry is saved
+        let reloaded = DocumentSettingsManager::new(dir.path().join("document_settings.json"));
+        assert!(reloaded.get("/docs/a.lml").last_export.is_none());
+    }
+
+    #[test]
+    fn positions_are_saved_per_document() {
+        let (dir, mut manager) = manager();
+        let doc = dir.path().join("long.lml");
+        fs::write(&doc, "line\n".repeat(200)).unwrap();
+        let doc = doc.to_string_lossy().to_string();
+
+        assert!(position(&manager, &doc).is_none());
+        save_position(&mut manager, &doc, 120, 2400.0).unwrap();
+        let expected = DocumentPosition {
+            cursor_line: 120,
+            scroll_top: 2400.0,
+            line_count: 200,
+        };
+        assert_eq!(position(&manager, &doc), Some(expected));
+
+        let reloaded = DocumentSettingsManager::new(dir.path().join("document_settings.json"));
+        assert_eq!(position(&reloaded, &doc), Some(expected));
+        assert!(position(&reloaded, "/docs/other.lml").is_none());
+
+        // Out-of-range input is normalised before saving
+        save_position(&mut manager, &doc, 0, -5.0).unwrap();
+        let saved = position(&manager, &doc).unwrap();
+        assert_eq!((saved.cursor_line, saved.scroll_top), (1, 0.0));
+    }
+
+    #[test]
+    fn positions_are_clamped_when_the_document_shrinks() {
+        let (dir, mut manager) = manager();
+        let doc = dir.path().join("shrinking.lml");
+        fs::write(&doc, "line\n".repeat(200)).unwrap();
+        let doc_path = doc.to_string_lossy().to_string();
+        save_position(&mut manager, &doc_path, 180, 3600.0).unwrap();
+
+        fs::write(&doc, "line\n".repeat(50)).unwrap();
+        let clamped = position(&manager, &doc_path).unwrap();
+        assert_eq!(clamped.cursor_line, 50);
+        assert_eq!(clamped.scroll_top, 900.0);
+        assert_eq!(clamped.line_count, 50);
+
+        // Growing the document leaves the position alone
+        fs::write(&doc, "line\n".repeat(400)).unwrap();
+        let grown = position(&manager, &doc_path).unwrap();
+        assert_eq!((grown.cursor_line, grown.scroll_top), (180, 3600.0));
+
+        // Without the file the saved position is returned as is
+        fs::remove_file(&doc).unwrap();
+        assert_eq!(position(&manager, &doc_path).unwrap().cursor_line, 180);
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Error returned to the frontend as `{ "kind": "...", "message": "..." }` so
+/// callers can branch on the category instead of parsing message text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "message")]
+pub enum AppError {
+    Io(String),
+    NotFound(String),
+    Permission(String),
+    Parse(String),
+    Network(String),
+    /// A lookup that needs the network was attempted in offline mode
+    Offline(String),
+    Validation(String),
+    /// A read that requires text hit a file that looks binary
+    NotTextFile(String),
+    /// The document is locked by another process or instance
+    Locked(String),
+    Cancelled(String),
+    Other(String),
+}
+
+impl AppError {
+    /// Wrap an I/O error with context, keeping not-found and permission failures distinct
+    pub fn io(context: &str, e: io::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        match e.kind() {
+            io::ErrorKind::NotFound => AppError::NotFound(message),
+            io::ErrorKind::PermissionDenied => AppError::Permission(message),
+            _ => AppError::Io(message),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            AppError::Io(m)
+            | AppError::NotFound(m)
+            | AppError::Permission(m)
+            | AppError::Parse(m)
+            | AppError::Network(m)
+            | AppError::Offline(m)
+            | AppError::Validation(m)
+            | AppError::NotTextFile(m)
+            | AppError::Locked(m)
+            | AppError::Cancelled(m)
+            | AppError::Other(m) => m,
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl std::error::Error for AppError {}
+
+impl From<io::Error> for AppError {
+    fn from(e: io::Error) -> Self {
+        AppError::io("I/O error", e)
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(e: serde_json::Error) -> Self {
+        AppError::Parse(format!("Invalid JSON: {}", e))
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(e: reqwest::Error) -> Self {
+        AppError::Network(format!("Request failed: {}", e))
+    }
+}
+
+/// Messages from helpers that still return `String` errors
+impl From<String> for AppError {
+    fn from(message: String) -> Self {
+        AppError::Other(message)
+    }
+}
+
+impl From<&str> for AppError {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+/// Lets `?` pass an `AppError` up through commands that still return `String`
+impl From<AppError> for String {
+    fn from(e: AppError) -> Self {
+        e.to_string()
+    }
+}
+
+/// Where a managed JSON file (settings, formulas, ...) failed to parse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonParseError {
+    pub file: String,
+    /// 1-based, as reported by serde_json
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl JsonParseError {
+    /// The parse position inside an `InvalidData` error from a manager's
+    /// `load_from_path`. Other I/O failures (e.g. a missing file) give `None`.
+    pub fn from_load_error(file: &Path, e: &io::Error) -> Option<Self> {
+        let json = e.get_ref()?.downcast_ref::<serde_json::Error>()?;
+        Some(Self {
+            file: file.to_string_lossy().to_string(),
+            line: json.line(),
+            column: json.column(),
+            message: json.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_failures_map_to_io_with_context() {
+        let e = io::Error::new(io::ErrorKind::WriteZero, "disk full");
+        let err = AppError::io("Failed to write file", e);
+        assert_eq!(
+            err,
+            AppError::Io("Failed to write file: disk full".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_and_forbidden_files_keep_their_own_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        let e = std::fs::read(dir.path().join("missing.lml")).unwrap_err();
+        let err = AppError::io("Failed to read file", e);
+        assert!(matches!(err, AppError::NotFound(_)));
+        assert!(err.message().starts_with("Failed to read file: "));
+
+        let e = io::Error::from(io::ErrorKind::PermissionDenied);
+        assert!(matches!(AppError::from(e), AppError::Permission(_)));
+    }
+
+    #[test]
+    fn serializes_as_kind_and_message() {
+        let err = AppError::Io("Failed to write file: disk full".to_string());
+        assert_eq!(
+            serde_json::to_value(&err).unwrap(),
+            serde_json::json!({ "kind": "Io", "message": "Failed to write file: disk full" })
+        );
+    }
+
+    #[test]
+    fn string_errors_are_not_categorized_by_their_text() {
+        let value = serde_json::to_value(AppError::from("Locked: a.lml")).unwrap();
+        assert_eq!(value["kind"], "Other");
+        assert_eq!(value["message"], "Locked: a.lml");
+
+        let value = serde_json::to_value(AppError::NotTextFile("a.png".to_string())).unwrap();
+        assert_eq!(value["kind"], "NotTextFile");
+    }
+}
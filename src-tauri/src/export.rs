@@ -0,0 +1,950 @@
+use crate::commands::write_file;
+use crate::document_settings::{DocumentSettingsManager, LastExport};
+use crate::images::{find_image_references, is_remote, resolve_image_path};
+use crate::operations::{Operation, CANCEL_POLL_INTERVAL};
+use crate::paths::expand_path;
+use crate::AppState;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex};
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_shell::process::CommandEvent;
+use tauri_plugin_shell::ShellExt;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MathDelimiters {
+    /// `$...$` and `$$...$$`
+    #[default]
+    Dollars,
+    /// `\(...\)` and `\[...\]`
+    Brackets,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PageMargins {
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+    pub left: f32,
+}
+
+impl Default for PageMargins {
+    fn default() -> Self {
+        Self {
+            top: 25.0,
+            right: 25.0,
+            bottom: 25.0,
+            left: 25.0,
+        }
+    }
+}
+
+/// Export target with its per-format options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum ExportFormat {
+    Pdf {
+        #[serde(default = "default_paper_size")]
+        paper_size: String,
+        /// Page margins in millimetres
+        #[serde(default)]
+        margins: PageMargins,
+    },
+    Html {
+        #[serde(default)]
+        template_path: Option<String>,
+        #[serde(default = "default_true")]
+        standalone: bool,
+    },
+    Markdown {
+        #[serde(default)]
+        math_delimiters: MathDelimiters,
+    },
+    Latex,
+    /// Any other format name; the content is written as-is
+    Other { name: String },
+}
+
+fn default_paper_size() -> String {
+    "A4".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl ExportFormat {
+    /// Short identifier for the format, matching the plain names accepted on input
+    pub fn id(&self) -> &str {
+        match self {
+            ExportFormat::Pdf { .. } => "pdf",
+            ExportFormat::Html { .. } => "html",
+            ExportFormat::Markdown { .. } => "markdown",
+            ExportFormat::Latex => "latex",
+            ExportFormat::Other { name } => name,
+        }
+    }
+}
+
+/// Plain format names (the original string-typed API) map to default options
+impl From<String> for ExportFormat {
+    fn from(name: String) -> Self {
+        match name.to_lowercase().as_str() {
+            "pdf" => ExportFormat::Pdf {
+                paper_size: default_paper_size(),
+                margins: PageMargins::default(),
+            },
+            "html" => ExportFormat::Html {
+                template_path: None,
+                standalone: true,
+            },
+            "markdown" | "md" => ExportFormat::Markdown {
+                math_delimiters: MathDelimiters::default(),
+            },
+            "latex" | "tex" => ExportFormat::Latex,
+            _ => ExportFormat::Other { name },
+        }
+    }
+}
+
+/// Accept either a format name string or a tagged options object
+fn deserialize_export_format<'de, D>(deserializer: D) -> Result<ExportFormat, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum FormatInput {
+        Name(String),
+        Detailed(ExportFormat),
+    }
+
+    Ok(match FormatInput::deserialize(deserializer)? {
+        FormatInput::Name(name) => name.into(),
+        FormatInput::Detailed(format) => format,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportOptions {
+    #[serde(deserialize_with = "deserialize_export_format")]
+    pub format: ExportFormat,
+    pub content: String,
+    pub output_path: String,
+    /// Source document; when given, the export location is remembered for it
+    #[serde(default)]
+    pub document_path: Option<String>,
+}
+
+/// Export content in the requested format. PDF exports go through the PDF engine
+/// from settings when one is configured, and can then be stopped with
+/// `cancel_operation` when an `operation_id` is given.
+#[tauri::command]
+pub async fn export_to_format(
+    options: ExportOptions,
+    operation_id: Option<String>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let pdf_engine = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings()
+        .pdf_engine_path
+        .filter(|p| !p.trim().is_empty());
+
+    let options = match (&options.format, pdf_engine) {
+        (
+            ExportFormat::Pdf {
+                paper_size,
+                margins,
+            },
+            Some(engine),
+        ) => {
+            let operation = Operation::begin(&state.operations, operation_id);
+            let args = PdfEngineArgs {
+                html: &options.content,
+                output_path: &options.output_path,
+                paper_size,
+                margins,
+            };
+            export_pdf(&app, &engine, args, &operation).await?;
+            options
+        }
+        // Large documents take a while to write; keep that off the main thread
+        _ => tauri::async_runtime::spawn_blocking(move || write_export(&options).map(|_| options))
+            .await
+            .map_err(|e| format!("Export failed: {}", e))??,
+    };
+
+    // The file is already written; failing to remember where only costs a default
+    if let Err(e) = record_last_export(&options, &mut state.document_settings.lock().unwrap()) {
+        app.emit(
+            "export-warning",
+            format!("Failed to remember export settings: {}", e),
+        )
+        .ok();
+    }
+    Ok(options.output_path)
+}
+
+/// Remember the export's directory and format for its source document, if any
+fn record_last_export(
+    options: &ExportOptions,
+    document_settings: &mut DocumentSettingsManager,
+) -> Result<(), String> {
+    let Some(document_path) = &options.document_path else {
+        return Ok(());
+    };
+    let output = expand_path(&options.output_path);
+    if let Some(directory) = output.parent() {
+        let last_export = LastExport {
+            directory: directory.to_string_lossy().to_string(),
+            format: options.format.id().to_string(),
+        };
+        document_settings.update(document_path, |s| s.last_export = Some(last_export));
+        document_settings.save().map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Write `options.content` to the output path in its format, applying the
+/// format's options. Without a PDF engine, PDF content is written as given.
+fn write_export(options: &ExportOptions) -> Result<(), String> {
+    // Content arrives already converted by the frontend; each branch applies
+    // its format-specific options before writing
+    match &options.format {
+        ExportFormat::Html {
+            template_path,
+            standalone,
+        } => {
+            let html = render_html(&options.content, template_path.as_deref(), *standalone)?;
+            write_file(options.output_path.clone(), html, None)?;
+        }
+        ExportFormat::Markdown { math_delimiters } => {
+            let markdown = match math_delimiters {
+                MathDelimiters::Dollars => options.content.clone(),
+                MathDelimiters::Brackets => dollars_to_brackets(&options.content),
+            };
+            write_file(options.output_path.clone(), markdown, None)?;
+        }
+        ExportFormat::Pdf { .. } | ExportFormat::Latex | ExportFormat::Other { .. } => {
+            write_file(options.output_path.clone(), options.content.clone(), None)?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportFormatInfo {
+    pub id: String,
+    pub name: String,
+    pub extension: String,
+    pub requires_binary: bool,
+    /// Whether the external binary was found; `None` for formats that need none
+    pub binary_available: Option<bool>,
+}
+
+/// Supported export formats, with `probe` deciding whether a configured binary is usable
+fn export_format_infos(
+    pdf_engine: Option<&str>,
+    probe: impl Fn(&str) -> bool,
+) -> Vec<ExportFormatInfo> {
+    let info = |id: &str, name: &str, extension: &str, binary_available: Option<bool>| {
+        ExportFormatInfo {
+            id: id.to_string(),
+            name: name.to_string(),
+            extension: extension.to_string(),
+            requires_binary: binary_available.is_some(),
+            binary_available,
+        }
+    };
+
+    vec![
+        info("pdf", "PDF", "pdf", pdf_engine.map(&probe)),
+        info("html", "HTML", "html", None),
+        info("markdown", "Markdown", "md", None),
+        info("latex", "LaTeX", "tex", None),
+    ]
+}
+
+/// A binary is available if the path names an existing file, or a bare
+/// command name resolves to a file on `PATH`
+fn binary_exists(path: &str) -> bool {
+    let expanded = expand_path(path);
+    if expanded.is_file() {
+        return true;
+    }
+    if expanded.components().count() > 1 {
+        return false;
+    }
+
+    let extensions: &[&str] = if cfg!(windows) { &["", ".exe", ".cmd", ".bat"] } else { &[""] };
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| {
+            extensions
+                .iter()
+                .any(|ext| dir.join(format!("{}{}", path, ext)).is_file())
+        })
+    })
+}
+
+#[tauri::command]
+pub fn get_export_formats(state: State<AppState>) -> Vec<ExportFormatInfo> {
+    let pdf_engine = state.settings.lock().unwrap().get_settings().pdf_engine_path;
+    let pdf_engine = pdf_engine.filter(|p| !p.trim().is_empty());
+    export_format_infos(pdf_engine.as_deref(), binary_exists)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportPreview {
+    pub output_path: String,
+    pub will_overwrite: bool,
+    /// False when exporting would have to create the output directory
+    pub parent_exists: bool,
+}
+
+/// Resolve where `export_to_format` would write, without writing anything
+#[tauri::command]
+pub fn export_preview(options: ExportOptions) -> ExportPreview {
+    let output = expand_path(&options.output_path);
+    ExportPreview {
+        output_path: output.to_string_lossy().to_string(),
+        will_overwrite: output.exists(),
+        parent_exists: output.parent().is_none_or(|p| p.as_os_str().is_empty() || p.is_dir()),
+    }
+}
+
+/// Apply an HTML template (`{{content}}` placeholder) or wrap a fragment in a
+/// minimal standalone document
+fn render_html(
+    content: &str,
+    template_path: Option<&str>,
+    standalone: bool,
+) -> Result<String, String> {
+    if let Some(template_path) = template_path {
+        let template = fs::read_to_string(expand_path(template_path))
+            .map_err(|e| format!("Failed to read HTML template: {}", e))?;
+        if !template.contains("{{content}}") {
+            return Err("HTML template is missing a {{content}} placeholder".to_string());
+        }
+        return Ok(template.replace("{{content}}", content));
+    }
+
+    let is_document = content.trim_start().to_lowercase().starts_with("<!doctype")
+        || content.trim_start().to_lowercase().starts_with("<html");
+    if !standalone || is_document {
+        return Ok(content.to_string());
+    }
+
+    Ok(format!(
+        concat!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n</head>\n",
+            "<body>\n{}\n</body>\n</html>\n"
+        ),
+        content
+    ))
+}
+
+/// Rewrite `$$...$$` as `\[...\]` and `$...$` as `\(...\)`, leaving escaped `\$` alone
+fn dollars_to_brackets(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut chars = content.chars().peekable();
+    let mut display_open = false;
+    let mut inline_open = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                out.push(c);
+                if let Some(next) = chars.next() {
+                    out.push(next);
+                }
+            }
+            '$' if chars.peek() == Some(&'$') && !inline_open => {
+                chars.next();
+                out.push_str(if display_open { "\\]" } else { "\\[" });
+                display_open = !display_open;
+            }
+            '$' if !display_open => {
+                out.push_str(if inline_open { "\\)" } else { "\\(" });
+                inline_open = !inline_open;
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// What a PDF engine run renders, and how
+struct PdfEngineArgs<'a> {
+    html: &'a str,
+    output_path: &'a str,
+    paper_size: &'a str,
+    margins: &'a PageMargins,
+}
+
+impl PdfEngineArgs<'_> {
+    /// wkhtmltopdf-style arguments reading the staged HTML from `input`
+    fn to_args(&self, input: &Path) -> Vec<String> {
+        let margin = |side: &str, mm: f32| [format!("--margin-{}", side), format!("{}mm", mm)];
+        let mut args = vec!["--page-size".to_string(), self.paper_size.to_string()];
+        args.extend(margin("top", self.margins.top));
+        args.extend(margin("right", self.margins.right));
+        args.extend(margin("bottom", self.margins.bottom));
+        args.extend(margin("left", self.margins.left));
+        args.push(input.to_string_lossy().to_string());
+        args.push(expand_path(self.output_path).to_string_lossy().to_string());
+        args
+    }
+}
+
+/// Render HTML content to PDF with an external wkhtmltopdf-compatible engine.
+/// The engine is killed if the operation is cancelled.
+async fn export_pdf(
+    app: &AppHandle,
+    engine: &str,
+    args: PdfEngineArgs<'_>,
+    operation: &Operation<'_>,
+) -> Result<(), String> {
+    let input = std::env::temp_dir().join(format!("lilia-export-{}.html", uuid::Uuid::new_v4()));
+    fs::write(&input, args.html).map_err(|e| format!("Failed to stage PDF input: {}", e))?;
+    let spawned = app
+        .shell()
+        .command(expand_path(engine))
+        .args(args.to_args(&input))
+        .set_raw_out(true)
+        .spawn();
+    let (mut events, child) = match spawned {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            fs::remove_file(&input).ok();
+            return Err(format!("Failed to run PDF engine: {}", e));
+        }
+    };
+
+    let child = Arc::new(Mutex::new(Some(child)));
+    let (done_tx, done_rx) = mpsc::channel::<()>();
+    {
+        let child = child.clone();
+        let cancelled = operation.flag();
+        std::thread::spawn(move || loop {
+            if done_rx.recv_timeout(CANCEL_POLL_INTERVAL) != Err(mpsc::RecvTimeoutError::Timeout) {
+                break;
+            }
+            if cancelled.load(Ordering::SeqCst) {
+                if let Some(child) = child.lock().unwrap().take() {
+                    child.kill().ok();
+                }
+                break;
+            }
+        });
+    }
+
+    let mut stderr = vec![];
+    let mut exit_code = None;
+    while let Some(event) = events.recv().await {
+        match event {
+            CommandEvent::Stderr(bytes) => stderr.extend(bytes),
+            CommandEvent::Terminated(payload) => exit_code = payload.code,
+            _ => {}
+        }
+    }
+    child.lock().unwrap().take();
+    done_tx.send(()).ok();
+    fs::remove_file(&input).ok();
+    operation.check()?;
+
+    if exit_code != Some(0) {
+        return Err(format!(
+            "PDF engine failed: {}",
+            String::from_utf8_lossy(&stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleResult {
+    pub output_path: String,
+    pub assets: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Zip a document with its local images. Image references are rewritten to
+/// `assets/<name>` inside the archive; remote images are left alone and
+/// missing ones are reported as warnings.
+#[tauri::command]
+pub fn export_bundle(document_path: String, output_zip: String) -> Result<BundleResult, String> {
+    let document = expand_path(&document_path);
+    let content =
+        fs::read_to_string(&document).map_err(|e| format!("Failed to read document: {}", e))?;
+    let base = document.parent().map(Path::to_path_buf).unwrap_or_default();
+    let document_name = document
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "document.lml".to_string());
+
+    // Map each distinct image file to a unique name under assets/
+    let mut asset_names: HashMap<PathBuf, String> = HashMap::new();
+    let mut used_names: HashSet<String> = HashSet::new();
+    let mut warnings = vec![];
+    let mut rewritten = String::with_capacity(content.len());
+    let mut cursor = 0;
+
+    for reference in find_image_references(&content) {
+        if is_remote(&reference.source) {
+            continue;
+        }
+        let path = resolve_image_path(&reference.source, &base);
+        if !path.is_file() {
+            warnings.push(format!("Image not found: {}", reference.source));
+            continue;
+        }
+
+        let name = asset_names
+            .entry(path.clone())
+            .or_insert_with(|| unique_asset_name(&path, &mut used_names))
+            .clone();
+        rewritten.push_str(&content[cursor..reference.span.start]);
+        rewritten.push_str(&format!("assets/{}", name));
+        cursor = reference.span.end;
+    }
+    rewritten.push_str(&content[cursor..]);
+
+    let mut assets: Vec<(&PathBuf, &String)> = asset_names.iter().collect();
+    assets.sort_by(|a, b| a.1.cmp(b.1));
+
+    let output = expand_path(&output_zip);
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+
+    // Build the archive beside the target and move it into place once complete,
+    // so a failure never leaves a truncated zip behind
+    let output_name = output.file_name().unwrap_or_default().to_string_lossy();
+    let partial =
+        output.with_file_name(format!(".{}.{}.partial", output_name, uuid::Uuid::new_v4()));
+    let written = fs::File::create(&partial)
+        .map_err(|e| format!("Failed to create bundle: {}", e))
+        .and_then(|file| write_bundle(file, &document_name, &rewritten, &assets))
+        .and_then(|()| {
+            fs::rename(&partial, &output).map_err(|e| format!("Failed to save bundle: {}", e))
+        });
+    if let Err(e) = written {
+        fs::remove_file(&partial).ok();
+        return Err(e);
+    }
+
+    Ok(BundleResult {
+        output_path: output.to_string_lossy().to_string(),
+        assets: assets.len(),
+        warnings,
+    })
+}
+
+/// Write the rewritten document and its images (as `assets/<name>`) into a zip
+fn write_bundle(
+    file: fs::File,
+    document_name: &str,
+    content: &str,
+    assets: &[(&PathBuf, &String)],
+) -> Result<(), String> {
+    let mut zip = ZipWriter::new(file);
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    let zip_err = |e: zip::result::ZipError| format!("Failed to write bundle: {}", e);
+
+    zip.start_file(document_name, options).map_err(zip_err)?;
+    zip.write_all(content.as_bytes())
+        .map_err(|e| format!("Failed to write bundle: {}", e))?;
+
+    for (path, name) in assets {
+        let bytes = fs::read(path).map_err(|e| format!("Failed to read image: {}", e))?;
+        zip.start_file(format!("assets/{}", name), options).map_err(zip_err)?;
+        zip.write_all(&bytes)
+            .map_err(|e| format!("Failed to write bundle: {}", e))?;
+    }
+    zip.finish().map_err(zip_err)?;
+    Ok(())
+}
+
+/// The image's file name, suffixed with `-2`, `-3`, ... if another image took it
+fn unique_asset_name(path: &Path, used: &mut HashSet<String>) -> String {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "image".to_string());
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut name = format!("{}{}", stem, extension);
+    let mut counter = 2;
+    while !used.insert(name.to_lowercase()) {
+        name = format!("{}-{}{}", stem, counter, extension);
+        counter += 1;
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn options(format: serde_json::Value, content: &str, output: &Path) -> ExportOptions {
+        serde_json::from_value(serde_json::json!({
+            "format": format,
+            "content": content,
+            "output_path": output.to_string_lossy(),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn deserializes_each_format_variant() {
+        let out = Path::new("/tmp/out");
+        let pdf = options(
+            serde_json::json!({
+                "type": "pdf",
+                "paper_size": "Letter",
+                "margins": { "top": 10.0, "right": 12.0, "bottom": 10.0, "left": 12.0 },
+            }),
+            "",
+            out,
+        );
+        assert!(matches!(
+            pdf.format,
+            ExportFormat::Pdf { ref paper_size, ref margins }
+                if paper_size == "Letter" && margins.right == 12.0
+        ));
+
+        let html = options(
+            serde_json::json!({ "type": "html", "template_path": "~/t.html", "standalone": false }),
+            "",
+            out,
+        );
+        assert!(matches!(
+            html.format,
+            ExportFormat::Html { template_path: Some(ref t), standalone: false } if t == "~/t.html"
+        ));
+
+        let markdown = options(
+            serde_json::json!({ "type": "markdown", "math_delimiters": "brackets" }),
+            "",
+            out,
+        );
+        assert!(matches!(
+            markdown.format,
+            ExportFormat::Markdown {
+                math_delimiters: MathDelimiters::Brackets
+            }
+        ));
+
+        // Omitted options fall back to their defaults
+        let pdf = options(serde_json::json!({ "type": "pdf" }), "", out);
+        assert!(matches!(
+            pdf.format,
+            ExportFormat::Pdf { ref paper_size, ref margins }
+                if paper_size == "A4" && margins.top == 25.0
+        ));
+        let latex = options(serde_json::json!({ "type": "latex" }), "", out);
+        assert!(matches!(latex.format, ExportFormat::Latex));
+    }
+
+    #[test]
+    fn plain_format_names_still_deserialize() {
+        let out = Path::new("/tmp/out");
+        let format = |name: &str| options(serde_json::json!(name), "", out).format;
+        assert!(matches!(format("PDF"), ExportFormat::Pdf { .. }));
+        assert!(matches!(
+            format("html"),
+            ExportFormat::Html {
+                template_path: None,
+                standalone: true
+            }
+        ));
+        assert!(matches!(format("md"), ExportFormat::Markdown { .. }));
+        assert!(matches!(format("tex"), ExportFormat::Latex));
+        assert_eq!(format("docx").id(), "docx");
+    }
+
+    #[test]
+    fn dispatches_html_and_markdown_options() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let html_path = dir.path().join("out.html");
+        let html = options(serde_json::json!("html"), "<p>Hi</p>", &html_path);
+        write_export(&html).unwrap();
+        let written = fs::read_to_string(&html_path).unwrap();
+        assert!(written.starts_with("<!DOCTYPE html>"));
+        assert!(written.contains("<body>\n<p>Hi</p>\n</body>"));
+
+        let template = dir.path().join("template.html");
+        fs::write(&template, "<main>{{content}}</main>").unwrap();
+        let templated = options(
+            serde_json::json!({ "type": "html", "template_path": template.to_string_lossy() }),
+            "<p>Hi</p>",
+            &html_path,
+        );
+        write_export(&templated).unwrap();
+        assert_eq!(
+            fs::read_to_string(&html_path).unwrap(),
+            "<main><p>Hi</p></main>"
+        );
+
+        let md_path = dir.path().join("out.md");
+        let markdown = options(
+            serde_json::json!({ "type": "markdown", "math_delimiters": "brackets" }),
+            "Euler: $e^{i\\pi} = -1$ costs \\$5\n$$x^2$$",
+            &md_path,
+        );
+        write_export(&markdown).unwrap();
+        assert_eq!(
+            fs::read_to_string(&md_path).unwrap(),
+            "Euler: \\(e^{i\\pi} = -1\\) costs \\$5\n\\[x^2\\]"
+        );
+    }
+
+    #[test]
+    fn pdf_export_without_an_engine_writes_the_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.pdf");
+        let pdf = options(serde_json::json!("pdf"), "%PDF-1.7 from the frontend", &output);
+        write_export(&pdf).unwrap();
+        assert_eq!(
+            fs::read_to_string(&output).unwrap(),
+            "%PDF-1.7 from the frontend"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pdf_engine_arguments_carry_paper_size_and_margins() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        // Records its arguments in the output file, which comes last
+        let engine = dir.path().join("engine.sh");
+        fs::write(
+            &engine,
+            "#!/bin/sh\nfor last; do :; done\necho \"$@\" > \"$last\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&engine, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let output = dir.path().join("out.pdf");
+        let margins = PageMargins {
+            top: 10.0,
+            right: 12.5,
+            bottom: 10.0,
+            left: 12.5,
+        };
+        let output_path = output.to_string_lossy().to_string();
+        let args = PdfEngineArgs {
+            html: "<p>Hi</p>",
+            output_path: &output_path,
+            paper_size: "Letter",
+            margins: &margins,
+        }
+        .to_args(&dir.path().join("in.html"));
+        let status = std::process::Command::new(&engine)
+            .args(&args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let recorded = fs::read_to_string(&output).unwrap();
+        assert!(recorded.starts_with("--page-size Letter --margin-top 10mm --margin-right 12.5mm"));
+        assert!(recorded.contains("--margin-left 12.5mm"));
+        assert!(recorded.trim_end().ends_with(&format!("in.html {}", output_path)));
+    }
+
+    #[test]
+    fn successful_exports_remember_directory_and_format() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut settings = DocumentSettingsManager::new(dir.path().join("document_settings.json"));
+        let out_dir = dir.path().join("out");
+        fs::create_dir(&out_dir).unwrap();
+
+        let mut markdown = options(serde_json::json!("md"), "text", &out_dir.join("a.md"));
+        markdown.document_path = Some("/docs/a.lml".to_string());
+        write_export(&markdown).unwrap();
+        record_last_export(&markdown, &mut settings).unwrap();
+
+        let last = settings.last_export("/docs/a.lml").unwrap().unwrap();
+        assert_eq!(last.directory, out_dir.to_string_lossy());
+        assert_eq!(last.format, "markdown");
+
+        // Exports without a source document aren't remembered
+        let loose = options(serde_json::json!("html"), "", &dir.path().join("b.html"));
+        record_last_export(&loose, &mut settings).unwrap();
+        assert_eq!(
+            settings.last_export("/docs/a.lml").unwrap().unwrap().format,
+            "markdown"
+        );
+    }
+
+    #[test]
+    fn pdf_availability_follows_the_probe() {
+        let pdf = |engine: Option<&str>, found: bool| {
+            let probed = std::cell::RefCell::new(vec![]);
+            let formats = export_format_infos(engine, |path| {
+                probed.borrow_mut().push(path.to_string());
+                found
+            });
+            (formats, probed.into_inner())
+        };
+
+        let (formats, probed) = pdf(Some("/opt/wkhtmltopdf"), true);
+        assert_eq!(probed, ["/opt/wkhtmltopdf"]);
+        assert!(formats[0].requires_binary);
+        assert_eq!(formats[0].binary_available, Some(true));
+
+        let (formats, _) = pdf(Some("/opt/wkhtmltopdf"), false);
+        assert_eq!(formats[0].binary_available, Some(false));
+
+        // Without an engine PDF content is written as-is, so nothing is probed
+        let (formats, probed) = pdf(None, true);
+        assert!(probed.is_empty());
+        assert!(!formats[0].requires_binary);
+        assert_eq!(formats[0].binary_available, None);
+
+        let ids: Vec<&str> = formats.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(ids, ["pdf", "html", "markdown", "latex"]);
+        assert!(formats[1..]
+            .iter()
+            .all(|f| !f.requires_binary && f.binary_available.is_none()));
+    }
+
+    #[test]
+    fn binaries_are_found_by_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let engine = dir.path().join("engine");
+        fs::write(&engine, "").unwrap();
+        assert!(binary_exists(&engine.to_string_lossy()));
+        assert!(!binary_exists(
+            &dir.path().join("missing").to_string_lossy()
+        ));
+        assert!(!binary_exists("lilia-no-such-command"));
+    }
+
+    #[test]
+    fn preview_reports_an_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("out.html");
+        fs::write(&output, "old export").unwrap();
+
+        let preview = export_preview(options(serde_json::json!("html"), "", &output));
+        assert_eq!(preview.output_path, output.to_string_lossy());
+        assert!(preview.will_overwrite);
+        assert!(preview.parent_exists);
+        assert_eq!(fs::read_to_string(&output).unwrap(), "old export");
+    }
+
+    #[test]
+    fn preview_of_a_new_path_writes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let fresh = dir.path().join("fresh.md");
+        let preview = export_preview(options(serde_json::json!("md"), "", &fresh));
+        assert!(!preview.will_overwrite);
+        assert!(preview.parent_exists);
+
+        // The output directory would have to be created
+        let nested = dir.path().join("exports/2024/out.pdf");
+        let preview = export_preview(options(serde_json::json!("pdf"), "", &nested));
+        assert!(!preview.will_overwrite);
+        assert!(!preview.parent_exists);
+        assert!(!dir.path().join("exports").exists());
+    }
+
+    #[test]
+    fn bundles_a_document_with_its_local_images() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("figures")).unwrap();
+        fs::write(dir.path().join("figures/plot.png"), b"png bytes").unwrap();
+        let document = dir.path().join("paper.lml");
+        fs::write(
+            &document,
+            "![Plot](figures/plot.png)\n\
+             ![Again](./figures/plot.png)\n\
+             ![Logo](https://example.com/logo.png)\n\
+             ![Gone](missing.png)\n",
+        )
+        .unwrap();
+
+        let output = dir.path().join("out/paper.zip");
+        let result = export_bundle(
+            document.to_string_lossy().to_string(),
+            output.to_string_lossy().to_string(),
+        )
+        .unwrap();
+        assert_eq!(result.assets, 1);
+        assert_eq!(result.warnings, ["Image not found: missing.png"]);
+
+        let mut archive = zip::ZipArchive::new(fs::File::open(&output).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["assets/plot.png", "paper.lml"]);
+
+        let mut content = String::new();
+        archive
+            .by_name("paper.lml")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(
+            content,
+            "![Plot](assets/plot.png)\n\
+             ![Again](assets/plot.png)\n\
+             ![Logo](https://example.com/logo.png)\n\
+             ![Gone](missing.png)\n"
+        );
+
+        let mut image = vec![];
+        archive
+            .by_name("assets/plot.png")
+            .unwrap()
+            .read_to_end(&mut image)
+            .unwrap();
+        assert_eq!(image, b"png bytes");
+
+        // Only the finished archive is left behind
+        let leftovers = fs::read_dir(dir.path().join("out")).unwrap().count();
+        assert_eq!(leftovers, 1);
+    }
+
+    #[test]
+    fn images_sharing_a_file_name_get_distinct_assets() {
+        let dir = tempfile::tempdir().unwrap();
+        for sub in ["a", "b"] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+            fs::write(dir.path().join(sub).join("fig.png"), sub).unwrap();
+        }
+        let document = dir.path().join("doc.lml");
+        fs::write(&document, "![](a/fig.png) ![](b/fig.png)").unwrap();
+
+        let output = dir.path().join("doc.zip");
+        let result = export_bundle(
+            document.to_string_lossy().to_string(),
+            output.to_string_lossy().to_string(),
+        )
+        .unwrap();
+        assert_eq!(result.assets, 2);
+
+        let archive = zip::ZipArchive::new(fs::File::open(&output).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, ["assets/fig-2.png", "assets/fig.png", "doc.lml"]);
+    }
+}
@@ -1,7 +1,13 @@
+use crate::errors::JsonParseError;
+use base64::Engine;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,34 +22,360 @@ pub struct Formula {
     pub tags: Vec<String>,
     pub is_favorite: bool,
     pub is_system: bool,
+    /// Stable id of a built-in formula, so upgrades still find it after a rename;
+    /// `None` for user formulas and for built-ins saved before keys existed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_key: Option<String>,
     pub usage_count: u32,
     pub created_at: String,
     pub updated_at: String,
+    #[serde(default)]
+    pub deleted_at: Option<String>,
+    /// When the formula was last inserted; set by `increment_usage`
+    #[serde(default)]
+    pub last_used_at: Option<String>,
+}
+
+impl Formula {
+    /// Build a new user (non-system) formula with a fresh id
+    pub fn new_user(name: &str, latex: &str, category: &str) -> Self {
+        let now = chrono::Utc::now().to_rfc3339();
+        Formula {
+            id: Uuid::new_v4().to_string(),
+            name: name.to_string(),
+            description: None,
+            latex_content: latex.to_string(),
+            lml_content: Some(lml_equation(name, latex)),
+            category: category.to_string(),
+            subcategory: None,
+            tags: vec![],
+            is_favorite: false,
+            is_system: false,
+            system_key: None,
+            usage_count: 0,
+            created_at: now.clone(),
+            updated_at: now,
+            deleted_at: None,
+            last_used_at: None,
+        }
+    }
+}
+
+/// Current version of the share blob payload
+const SHARE_FORMAT_VERSION: u8 = 1;
+
+/// Upper bound on a decoded share payload, to reject garbage early
+const MAX_SHARE_PAYLOAD_BYTES: u64 = 64 * 1024;
+
+/// The fields of a formula that travel in a share blob
+#[derive(Debug, Serialize, Deserialize)]
+struct SharedFormula {
+    v: u8,
+    name: String,
+    description: Option<String>,
+    latex: String,
+    category: String,
+    subcategory: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+impl Formula {
+    /// Encode the formula's essential fields as compressed, URL-safe base64 JSON
+    pub fn to_share_blob(&self) -> Result<String, String> {
+        let shared = SharedFormula {
+            v: SHARE_FORMAT_VERSION,
+            name: self.name.clone(),
+            description: self.description.clone(),
+            latex: self.latex_content.clone(),
+            category: self.category.clone(),
+            subcategory: self.subcategory.clone(),
+            tags: self.tags.clone(),
+        };
+        let json = serde_json::to_vec(&shared).map_err(|e| format!("Serialize error: {}", e))?;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&json)
+            .map_err(|e| format!("Compress error: {}", e))?;
+        let compressed = encoder
+            .finish()
+            .map_err(|e| format!("Compress error: {}", e))?;
+
+        Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(compressed))
+    }
+
+    /// Decode a share blob into a new user formula with a fresh id
+    pub fn from_share_blob(blob: &str) -> Result<Formula, String> {
+        let compressed = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(blob.trim().trim_end_matches('='))
+            .map_err(|_| "Invalid share code: not valid base64".to_string())?;
+
+        let mut json = Vec::new();
+        GzDecoder::new(compressed.as_slice())
+            .take(MAX_SHARE_PAYLOAD_BYTES + 1)
+            .read_to_end(&mut json)
+            .map_err(|_| "Invalid share code: corrupt payload".to_string())?;
+        if json.len() as u64 > MAX_SHARE_PAYLOAD_BYTES {
+            return Err("Invalid share code: payload too large".to_string());
+        }
+
+        let shared: SharedFormula = serde_json::from_slice(&json)
+            .map_err(|e| format!("Invalid share code: {}", e))?;
+        if shared.v > SHARE_FORMAT_VERSION {
+            return Err("Share code was created by a newer version of Lilia".to_string());
+        }
+        if shared.name.trim().is_empty() || shared.latex.trim().is_empty() {
+            return Err("Invalid share code: formula name and LaTeX are required".to_string());
+        }
+
+        let mut formula = Formula::new_user(shared.name.trim(), &shared.latex, &shared.category);
+        formula.description = shared.description;
+        formula.subcategory = shared.subcategory;
+        formula.tags = normalize_tags(shared.tags);
+        Ok(formula)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FormulaUsage {
+    pub id: String,
+    pub name: String,
+    pub usage_count: u32,
+}
+
+/// Weights for `FormulaManager::smart_rank`; each signal is scaled to 0..=1 first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormulaRankWeights {
+    #[serde(default = "default_favorite_weight")]
+    pub favorite: f64,
+    /// Applied to a recency score that halves every `RECENCY_HALF_LIFE_DAYS`
+    #[serde(default = "default_recency_weight")]
+    pub recency: f64,
+    /// Applied to usage count on a log scale relative to the most-used formula
+    #[serde(default = "default_usage_weight")]
+    pub usage: f64,
+}
+
+fn default_favorite_weight() -> f64 {
+    3.0
+}
+
+fn default_recency_weight() -> f64 {
+    2.0
+}
+
+fn default_usage_weight() -> f64 {
+    1.0
+}
+
+impl Default for FormulaRankWeights {
+    fn default() -> Self {
+        Self {
+            favorite: default_favorite_weight(),
+            recency: default_recency_weight(),
+            usage: default_usage_weight(),
+        }
+    }
+}
+
+/// Days after which a formula's recency score has dropped to half
+const RECENCY_HALF_LIFE_DAYS: f64 = 7.0;
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct FormulaStats {
+    pub total: usize,
+    pub by_category: BTreeMap<String, usize>,
+    pub favorites: usize,
+    pub user_count: usize,
+    pub system_count: usize,
+    /// Up to five most-used formulas, most used first
+    pub most_used: Vec<FormulaUsage>,
+    /// Built-in set version last merged into the library
+    pub system_formula_version: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImportConflict {
+    pub name: String,
+    pub existing_id: String,
+    pub existing_latex: String,
+    pub incoming_latex: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ImportPreview {
+    pub new_count: usize,
+    pub duplicate_count: usize,
+    pub conflict_count: usize,
+    pub new_names: Vec<String>,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+/// How to handle an incoming formula whose name matches a different existing one
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictResolution {
+    #[default]
+    Skip,
+    Overwrite,
+    KeepBoth,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ImportResult {
+    pub added: usize,
+    pub overwritten: usize,
+    pub skipped: usize,
+}
+
+/// Classification of an incoming formula against the current library
+enum ImportMatch {
+    New,
+    Duplicate,
+    Conflict(usize),
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct FormulaData {
     pub formulas: Vec<Formula>,
+    /// Version of the built-in set last merged into this file; 0 for files
+    /// written before versioning
+    #[serde(default)]
+    pub system_formula_version: u32,
+}
+
+/// Bump whenever `seed_system_formulas` gains or changes a formula, so existing
+/// libraries pick the changes up on the next launch
+pub const SYSTEM_FORMULA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SystemFormulaUpgrade {
+    pub from_version: u32,
+    pub to_version: u32,
+    pub added: usize,
+    pub updated: usize,
+}
+
+/// Snapshots kept before destructive operations; older ones are pruned
+const MAX_FORMULA_BACKUPS: usize = 10;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FormulaBackup {
+    pub name: String,
+    pub created_at: String,
+    pub size_bytes: u64,
+    pub formula_count: usize,
 }
 
 pub struct FormulaManager {
     path: PathBuf,
     data: FormulaData,
+    /// Set when the formulas file existed but could not be parsed
+    load_error: Option<JsonParseError>,
 }
 
 impl FormulaManager {
     pub fn new(path: PathBuf) -> Self {
-        let data = Self::load_from_path(&path).unwrap_or_else(|_| {
+        let mut load_error = None;
+        let mut data = Self::load_from_path(&path).unwrap_or_else(|e| {
+            load_error = JsonParseError::from_load_error(&path, &e);
             let seeded = FormulaData {
                 formulas: Self::seed_system_formulas(),
+                system_formula_version: SYSTEM_FORMULA_VERSION,
             };
-            // Save the seeded data
-            if let Ok(content) = serde_json::to_string_pretty(&seeded) {
-                let _ = fs::write(&path, content);
+            // Save the seeded data, unless that would overwrite a file the
+            // user may still want to fix by hand
+            if load_error.is_none() {
+                if let Ok(content) = serde_json::to_string_pretty(&seeded) {
+                    let _ = fs::write(&path, content);
+                }
             }
             seeded
         });
-        Self { path, data }
+        if load_error.is_none() && data.system_formula_version < SYSTEM_FORMULA_VERSION {
+            Self::merge_system_formulas(&mut data, Self::seed_system_formulas());
+            if let Ok(content) = serde_json::to_string_pretty(&data) {
+                let _ = fs::write(&path, content);
+            }
+        }
+        Self {
+            path,
+            data,
+            load_error,
+        }
+    }
+
+    /// Add built-in formulas missing from `data` and refresh changed ones, matched
+    /// by `system_key`. Built-ins saved before keys existed are matched by name
+    /// once and given their key. User formulas are never touched, and a system
+    /// formula keeps its id, favorite flag, usage count and trash state.
+    fn merge_system_formulas(data: &mut FormulaData, seed: Vec<Formula>) -> SystemFormulaUpgrade {
+        let mut upgrade = SystemFormulaUpgrade {
+            from_version: data.system_formula_version,
+            to_version: SYSTEM_FORMULA_VERSION,
+            ..Default::default()
+        };
+
+        for incoming in seed {
+            let index = data
+                .formulas
+                .iter()
+                .position(|f| f.is_system && f.system_key == incoming.system_key)
+                .or_else(|| {
+                    data.formulas.iter().position(|f| {
+                        f.is_system && f.system_key.is_none() && f.name == incoming.name
+                    })
+                });
+            match index {
+                Some(index) => {
+                    let formula = &mut data.formulas[index];
+                    formula.system_key = incoming.system_key;
+                    let changed = formula.name != incoming.name
+                        || formula.description != incoming.description
+                        || formula.latex_content != incoming.latex_content
+                        || formula.lml_content != incoming.lml_content
+                        || formula.category != incoming.category
+                        || formula.subcategory != incoming.subcategory
+                        || formula.tags != incoming.tags;
+                    if changed {
+                        formula.name = incoming.name;
+                        formula.description = incoming.description;
+                        formula.latex_content = incoming.latex_content;
+                        formula.lml_content = incoming.lml_content;
+                        formula.category = incoming.category;
+                        formula.subcategory = incoming.subcategory;
+                        formula.tags = incoming.tags;
+                        formula.updated_at = incoming.updated_at;
+                        upgrade.updated += 1;
+                    }
+                }
+                None => {
+                    data.formulas.push(incoming);
+                    upgrade.added += 1;
+                }
+            }
+        }
+
+        data.system_formula_version = SYSTEM_FORMULA_VERSION;
+        upgrade
+    }
+
+    /// Merge the current built-in set into the library if it's older than this build
+    pub fn upgrade_system_formulas(&mut self) -> SystemFormulaUpgrade {
+        if self.data.system_formula_version >= SYSTEM_FORMULA_VERSION {
+            return SystemFormulaUpgrade {
+                from_version: self.data.system_formula_version,
+                to_version: self.data.system_formula_version,
+                ..Default::default()
+            };
+        }
+        Self::merge_system_formulas(&mut self.data, Self::seed_system_formulas())
+    }
+
+    pub fn load_error(&self) -> Option<&JsonParseError> {
+        self.load_error.as_ref()
     }
 
     fn load_from_path(path: &PathBuf) -> io::Result<FormulaData> {
@@ -51,15 +383,103 @@ impl FormulaManager {
         serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
+    /// Formulas that haven't been moved to the trash
+    fn active(&self) -> impl Iterator<Item = &Formula> {
+        self.data.formulas.iter().filter(|f| f.deleted_at.is_none())
+    }
+
     pub fn get_all(&self) -> Vec<Formula> {
-        self.data.formulas.clone()
+        self.active().cloned().collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Formula> {
+        self.active().find(|f| f.id == id).cloned()
+    }
+
+    pub fn get_trashed(&self) -> Vec<Formula> {
+        self.data
+            .formulas
+            .iter()
+            .filter(|f| f.deleted_at.is_some())
+            .cloned()
+            .collect()
+    }
+
+    /// Search formulas by name, description, category, and tags.
+    /// With `fuzzy`, words are compared by edit distance so small typos still match.
+    pub fn search(&self, query: &str, fuzzy: bool) -> Vec<Formula> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return self.get_all();
+        }
+
+        if !fuzzy {
+            return self
+                .active()
+                .filter(|f| {
+                    f.name.to_lowercase().contains(&query)
+                        || f.category.to_lowercase().contains(&query)
+                        || f.description
+                            .as_deref()
+                            .is_some_and(|d| d.to_lowercase().contains(&query))
+                        || f.tags.iter().any(|t| t.to_lowercase().contains(&query))
+                })
+                .cloned()
+                .collect();
+        }
+
+        let mut scored: Vec<(f64, &Formula)> = self
+            .active()
+            .map(|f| (fuzzy_score(&query, f), f))
+            .filter(|(score, _)| *score >= FUZZY_THRESHOLD)
+            .collect();
+        scored.sort_by(|a, b| {
+            b.0.partial_cmp(&a.0)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.1.name.cmp(&b.1.name))
+        });
+        scored.into_iter().map(|(_, f)| f.clone()).collect()
+    }
+
+    /// `search` narrowed by facets: the category must be one of `categories`,
+    /// every tag in `tags` must be present, and with `favorites_only` only
+    /// favorites are kept. Empty facet lists don't filter. Search order is kept.
+    pub fn search_filtered(
+        &self,
+        query: &str,
+        fuzzy: bool,
+        categories: &[String],
+        tags: &[String],
+        favorites_only: bool,
+    ) -> Vec<Formula> {
+        let categories: Vec<String> = categories.iter().map(|c| c.to_lowercase()).collect();
+        let tags = normalize_tags(tags.to_vec());
+        self.search(query, fuzzy)
+            .into_iter()
+            .filter(|f| !favorites_only || f.is_favorite)
+            .filter(|f| categories.is_empty() || categories.contains(&f.category.to_lowercase()))
+            .filter(|f| {
+                let own = normalize_tags(f.tags.clone());
+                tags.iter().all(|t| own.contains(t))
+            })
+            .collect()
     }
 
-    pub fn add(&mut self, formula: Formula) -> Formula {
+    pub fn add(&mut self, mut formula: Formula) -> Formula {
+        formula.tags = normalize_tags(formula.tags);
         self.data.formulas.push(formula.clone());
         formula
     }
 
+    /// Every tag used by an active formula, sorted and deduplicated
+    pub fn all_tags(&self) -> Vec<String> {
+        self.active()
+            .flat_map(|f| normalize_tags(f.tags.clone()))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
     pub fn update(&mut self, id: &str, updates: FormulaUpdate) -> Option<Formula> {
         if let Some(formula) = self.data.formulas.iter_mut().find(|f| f.id == id && !f.is_system) {
             if let Some(name) = updates.name {
@@ -69,11 +489,7 @@ impl FormulaManager {
                 formula.description = Some(description);
             }
             if let Some(latex_content) = updates.latex_content {
-                let slug = slugify(&formula.name);
-                formula.lml_content = Some(format!(
-                    "\n@equation(label: eq:{}, mode: display)\n{}\n",
-                    slug, latex_content
-                ));
+                formula.lml_content = Some(lml_equation(&formula.name, &latex_content));
                 formula.latex_content = latex_content;
             }
             if let Some(category) = updates.category {
@@ -83,7 +499,7 @@ impl FormulaManager {
                 formula.subcategory = Some(subcategory);
             }
             if let Some(tags) = updates.tags {
-                formula.tags = tags;
+                formula.tags = normalize_tags(tags);
             }
             formula.updated_at = chrono::Utc::now().to_rfc3339();
             Some(formula.clone())
@@ -92,12 +508,272 @@ impl FormulaManager {
         }
     }
 
+    /// Add a user formula for each display equation in a LaTeX source that
+    /// isn't already in the library. Returns the formulas that were added.
+    pub fn import_latex_equations(
+        &mut self,
+        source: &str,
+        name_prefix: &str,
+        category: &str,
+    ) -> Vec<Formula> {
+        let mut imported = vec![];
+        for (index, (label, latex)) in extract_latex_equations(source).into_iter().enumerate() {
+            let exists = self
+                .data
+                .formulas
+                .iter()
+                .any(|f| normalize_latex(&f.latex_content) == normalize_latex(&latex));
+            if exists {
+                continue;
+            }
+
+            let name = match label {
+                Some(label) => label,
+                None => format!("{} Equation {}", name_prefix, index + 1),
+            };
+            let formula = Formula::new_user(&name, &latex, category);
+            self.data.formulas.push(formula.clone());
+            imported.push(formula);
+        }
+        imported
+    }
+
+    fn classify_import(&self, incoming: &Formula) -> ImportMatch {
+        let name = incoming.name.trim().to_lowercase();
+        let existing = self
+            .data
+            .formulas
+            .iter()
+            .position(|f| f.deleted_at.is_none() && f.name.trim().to_lowercase() == name);
+        match existing {
+            None => ImportMatch::New,
+            Some(index) => {
+                let current = normalize_latex(&self.data.formulas[index].latex_content);
+                if current == normalize_latex(&incoming.latex_content) {
+                    ImportMatch::Duplicate
+                } else {
+                    ImportMatch::Conflict(index)
+                }
+            }
+        }
+    }
+
+    /// Report how an import would merge, without changing the library
+    pub fn preview_import(&self, incoming: &[Formula]) -> ImportPreview {
+        let mut preview = ImportPreview::default();
+        for formula in incoming {
+            match self.classify_import(formula) {
+                ImportMatch::New => {
+                    preview.new_count += 1;
+                    preview.new_names.push(formula.name.clone());
+                }
+                ImportMatch::Duplicate => preview.duplicate_count += 1,
+                ImportMatch::Conflict(index) => {
+                    let existing = &self.data.formulas[index];
+                    preview.conflict_count += 1;
+                    preview.conflicts.push(ImportConflict {
+                        name: formula.name.clone(),
+                        existing_id: existing.id.clone(),
+                        existing_latex: existing.latex_content.clone(),
+                        incoming_latex: formula.latex_content.clone(),
+                    });
+                }
+            }
+        }
+        preview
+    }
+
+    /// Merge incoming formulas. Name conflicts use the resolution keyed by the
+    /// formula's name, skipping when none is given; identical duplicates are skipped.
+    pub fn apply_import(
+        &mut self,
+        incoming: Vec<Formula>,
+        resolutions: &HashMap<String, ConflictResolution>,
+    ) -> ImportResult {
+        let mut result = ImportResult::default();
+        for formula in incoming {
+            match self.classify_import(&formula) {
+                ImportMatch::New => {
+                    self.data.formulas.push(imported_copy(&formula, &formula.name));
+                    result.added += 1;
+                }
+                ImportMatch::Duplicate => result.skipped += 1,
+                ImportMatch::Conflict(index) => {
+                    let resolution = resolutions.get(&formula.name).copied().unwrap_or_default();
+                    match resolution {
+                        ConflictResolution::Skip => result.skipped += 1,
+                        // System formulas are read-only, so keep both instead
+                        ConflictResolution::Overwrite if !self.data.formulas[index].is_system => {
+                            let existing = &mut self.data.formulas[index];
+                            existing.lml_content =
+                                Some(lml_equation(&existing.name, &formula.latex_content));
+                            existing.latex_content = formula.latex_content;
+                            existing.description = formula.description;
+                            existing.category = formula.category;
+                            existing.subcategory = formula.subcategory;
+                            existing.tags = normalize_tags(formula.tags);
+                            existing.updated_at = chrono::Utc::now().to_rfc3339();
+                            result.overwritten += 1;
+                        }
+                        ConflictResolution::Overwrite | ConflictResolution::KeepBoth => {
+                            let name = format!("{} (imported)", formula.name);
+                            self.data.formulas.push(imported_copy(&formula, &name));
+                            result.added += 1;
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
     pub fn remove(&mut self, id: &str) -> bool {
         let len_before = self.data.formulas.len();
         self.data.formulas.retain(|f| f.id != id || f.is_system);
         self.data.formulas.len() < len_before
     }
 
+    /// Aggregate counts over the active (non-trashed) library
+    pub fn stats(&self) -> FormulaStats {
+        let mut stats = FormulaStats {
+            system_formula_version: self.data.system_formula_version,
+            ..Default::default()
+        };
+        let mut used: Vec<&Formula> = vec![];
+        for formula in self.active() {
+            stats.total += 1;
+            *stats.by_category.entry(formula.category.clone()).or_default() += 1;
+            if formula.is_favorite {
+                stats.favorites += 1;
+            }
+            if formula.is_system {
+                stats.system_count += 1;
+            } else {
+                stats.user_count += 1;
+            }
+            if formula.usage_count > 0 {
+                used.push(formula);
+            }
+        }
+
+        used.sort_by(|a, b| b.usage_count.cmp(&a.usage_count).then_with(|| a.name.cmp(&b.name)));
+        stats.most_used = used
+            .into_iter()
+            .take(5)
+            .map(|f| FormulaUsage {
+                id: f.id.clone(),
+                name: f.name.clone(),
+                usage_count: f.usage_count,
+            })
+            .collect();
+        stats
+    }
+
+    /// Move every user formula in `old` to `new`, optionally renaming one
+    /// subcategory `(from, to)` within it. Returns how many formulas changed.
+    pub fn rename_category(
+        &mut self,
+        old: &str,
+        new: &str,
+        subcategory: Option<(&str, &str)>,
+    ) -> usize {
+        let now = chrono::Utc::now().to_rfc3339();
+        let mut changed = 0;
+        for formula in self
+            .data
+            .formulas
+            .iter_mut()
+            .filter(|f| !f.is_system && f.category == old)
+        {
+            formula.category = new.to_string();
+            if let Some((from, to)) = subcategory {
+                if formula.subcategory.as_deref() == Some(from) {
+                    formula.subcategory = Some(to.to_string());
+                }
+            }
+            formula.updated_at = now.clone();
+            changed += 1;
+        }
+        changed
+    }
+
+    /// Soft-delete a user formula so it can be restored later
+    pub fn trash(&mut self, id: &str) -> Option<Formula> {
+        let formula = self
+            .data
+            .formulas
+            .iter_mut()
+            .find(|f| f.id == id && !f.is_system && f.deleted_at.is_none())?;
+        let now = chrono::Utc::now().to_rfc3339();
+        formula.deleted_at = Some(now.clone());
+        formula.updated_at = now;
+        Some(formula.clone())
+    }
+
+    pub fn restore(&mut self, id: &str) -> Option<Formula> {
+        let formula = self
+            .data
+            .formulas
+            .iter_mut()
+            .find(|f| f.id == id && f.deleted_at.is_some())?;
+        formula.deleted_at = None;
+        formula.updated_at = chrono::Utc::now().to_rfc3339();
+        Some(formula.clone())
+    }
+
+    /// Permanently remove formulas trashed more than `older_than_days` ago
+    pub fn purge_trashed(&mut self, older_than_days: u32) -> usize {
+        let cutoff = chrono::Utc::now() - chrono::Duration::days(older_than_days as i64);
+        let len_before = self.data.formulas.len();
+        self.data.formulas.retain(|f| {
+            match f.deleted_at.as_deref().map(chrono::DateTime::parse_from_rfc3339) {
+                Some(Ok(deleted_at)) => deleted_at > cutoff,
+                // Unparseable timestamps are purged along with old entries
+                Some(Err(_)) => false,
+                None => true,
+            }
+        });
+        len_before - self.data.formulas.len()
+    }
+
+    /// Active formulas ordered by a weighted score of favorite status, recency of
+    /// use and usage count, highest first. Ties are ordered by name.
+    pub fn smart_rank(
+        &self,
+        weights: &FormulaRankWeights,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> Vec<Formula> {
+        let max_usage = self.active().map(|f| f.usage_count).max().unwrap_or(0);
+        let score = |formula: &Formula| {
+            let favorite = if formula.is_favorite { 1.0 } else { 0.0 };
+            let recency = formula
+                .last_used_at
+                .as_deref()
+                .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                .map_or(0.0, |used| {
+                    let days =
+                        (now - used.with_timezone(&chrono::Utc)).num_seconds() as f64 / 86_400.0;
+                    0.5f64.powf(days.max(0.0) / RECENCY_HALF_LIFE_DAYS)
+                });
+            let usage = if max_usage == 0 {
+                0.0
+            } else {
+                (formula.usage_count as f64).ln_1p() / (max_usage as f64).ln_1p()
+            };
+            weights.favorite * favorite + weights.recency * recency + weights.usage * usage
+        };
+
+        let mut ranked: Vec<(f64, Formula)> =
+            self.active().map(|f| (score(f), f.clone())).collect();
+        ranked.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .total_cmp(a_score)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        ranked.into_iter().map(|(_, formula)| formula).collect()
+    }
+
     pub fn toggle_favorite(&mut self, id: &str) -> Option<Formula> {
         if let Some(formula) = self.data.formulas.iter_mut().find(|f| f.id == id) {
             formula.is_favorite = !formula.is_favorite;
@@ -110,152 +786,271 @@ impl FormulaManager {
 
     pub fn increment_usage(&mut self, id: &str) -> Option<Formula> {
         if let Some(formula) = self.data.formulas.iter_mut().find(|f| f.id == id) {
+            let now = chrono::Utc::now().to_rfc3339();
             formula.usage_count += 1;
-            formula.updated_at = chrono::Utc::now().to_rfc3339();
+            formula.updated_at = now.clone();
+            formula.last_used_at = Some(now);
             Some(formula.clone())
         } else {
             None
         }
     }
 
+    /// Render formulas as a two-column CSV (front, back) for flashcard import
+    pub fn to_flashcards_csv(&self, category: Option<&str>) -> String {
+        let mut csv = String::new();
+        for formula in self
+            .active()
+            .filter(|f| category.is_none_or(|c| f.category == c))
+        {
+            let front = match &formula.description {
+                Some(desc) if !desc.is_empty() => format!("{} — {}", formula.name, desc),
+                _ => formula.name.clone(),
+            };
+            csv.push_str(&csv_field(&front));
+            csv.push(',');
+            csv.push_str(&csv_field(&formula.latex_content));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Active formulas grouped by category, then subcategory, both sorted by name.
+    /// Formulas without a subcategory are grouped under an empty key.
+    pub fn grouped_by_category(
+        &self,
+        system_only: bool,
+        categories: Option<&[String]>,
+    ) -> BTreeMap<String, BTreeMap<String, Vec<&Formula>>> {
+        let mut groups: BTreeMap<String, BTreeMap<String, Vec<&Formula>>> = BTreeMap::new();
+        for formula in self
+            .active()
+            .filter(|f| !system_only || f.is_system)
+            .filter(|f| categories.is_none_or(|cats| cats.contains(&f.category)))
+        {
+            groups
+                .entry(formula.category.clone())
+                .or_default()
+                .entry(formula.subcategory.clone().unwrap_or_default())
+                .or_default()
+                .push(formula);
+        }
+        for subgroups in groups.values_mut() {
+            for formulas in subgroups.values_mut() {
+                formulas.sort_by(|a, b| a.name.cmp(&b.name));
+            }
+        }
+        groups
+    }
+
+    fn backups_dir(&self) -> PathBuf {
+        self.path
+            .parent()
+            .map(|p| p.join("formula_backups"))
+            .unwrap_or_else(|| PathBuf::from("formula_backups"))
+    }
+
+    /// Snapshot the current library to a timestamped backup, keeping the newest few
+    pub fn backup(&self) -> Result<String, String> {
+        let dir = self.backups_dir();
+        fs::create_dir_all(&dir).map_err(|e| format!("Failed to create backup dir: {}", e))?;
+
+        let name = format!(
+            "formulas-{}.json",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+        );
+        let content = serde_json::to_string_pretty(&self.data).map_err(|e| e.to_string())?;
+        fs::write(dir.join(&name), content)
+            .map_err(|e| format!("Failed to write formula backup: {}", e))?;
+
+        for old in self.list_backups().into_iter().skip(MAX_FORMULA_BACKUPS) {
+            fs::remove_file(dir.join(old.name)).ok();
+        }
+        Ok(name)
+    }
+
+    /// Backups newest first
+    pub fn list_backups(&self) -> Vec<FormulaBackup> {
+        let Ok(entries) = fs::read_dir(self.backups_dir()) else {
+            return vec![];
+        };
+        let mut backups: Vec<FormulaBackup> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                let stamp = name.strip_prefix("formulas-")?.strip_suffix(".json")?;
+                let created_at = chrono::NaiveDateTime::parse_from_str(stamp, "%Y%m%dT%H%M%S%.3fZ")
+                    .ok()?
+                    .and_utc()
+                    .to_rfc3339();
+                let formula_count = Self::load_from_path(&entry.path())
+                    .map(|d| d.formulas.len())
+                    .unwrap_or(0);
+                Some(FormulaBackup {
+                    size_bytes: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                    name,
+                    created_at,
+                    formula_count,
+                })
+            })
+            .collect();
+        backups.sort_by(|a, b| b.name.cmp(&a.name));
+        backups
+    }
+
+    /// Replace the library with a backup. The current library is backed up first
+    /// so the restore itself can be undone.
+    pub fn restore_backup(&mut self, name: &str) -> Result<usize, String> {
+        if !self.list_backups().iter().any(|b| b.name == name) {
+            return Err(format!("Formula backup not found: {}", name));
+        }
+        let data = Self::load_from_path(&self.backups_dir().join(name))
+            .map_err(|e| format!("Failed to read formula backup: {}", e))?;
+
+        self.backup()?;
+        self.data = data;
+        self.upgrade_system_formulas();
+        Ok(self.data.formulas.len())
+    }
+
     pub fn save(&self) -> io::Result<()> {
         let content = serde_json::to_string_pretty(&self.data)?;
         fs::write(&self.path, content)
     }
 
     fn seed_system_formulas() -> Vec<Formula> {
-        let formulas_data: Vec<(&str, &str, &str, &str, &str, Vec<&str>)> = vec![
+        // The first field is the formula's stable key; never change it once released
+        let formulas_data: Vec<(&str, &str, &str, &str, &str, &str, Vec<&str>)> = vec![
             // Math — Algebra
-            ("Quadratic Formula", "Solutions to ax² + bx + c = 0", "math", "algebra",
+            ("quadratic-formula", "Quadratic Formula", "Solutions to ax² + bx + c = 0", "math", "algebra",
              r"x = \frac{-b \pm \sqrt{b^2 - 4ac}}{2a}", vec!["roots", "polynomial"]),
-            ("Binomial Theorem", "Expansion of (a + b)^n", "math", "algebra",
+            ("binomial-theorem", "Binomial Theorem", "Expansion of (a + b)^n", "math", "algebra",
              r"(a + b)^n = \sum_{k=0}^{n} \binom{n}{k} a^{n-k} b^k", vec!["expansion", "combinatorics"]),
-            ("Logarithm Change of Base", "Change base of logarithm", "math", "algebra",
+            ("logarithm-change-of-base", "Logarithm Change of Base", "Change base of logarithm", "math", "algebra",
              r"\log_b a = \frac{\ln a}{\ln b}", vec!["logarithm", "base"]),
-            ("Geometric Series Sum", "Sum of infinite geometric series", "math", "algebra",
+            ("geometric-series-sum", "Geometric Series Sum", "Sum of infinite geometric series", "math", "algebra",
              r"\sum_{k=0}^{\infty} ar^k = \frac{a}{1-r}, \quad |r| < 1", vec!["series", "infinite"]),
 
             // Math — Calculus
-            ("Fundamental Theorem of Calculus", "Connects differentiation and integration", "math", "calculus",
+            ("fundamental-theorem-of-calculus", "Fundamental Theorem of Calculus", "Connects differentiation and integration", "math", "calculus",
              r"\int_a^b f'(x)\,dx = f(b) - f(a)", vec!["integral", "derivative"]),
-            ("Integration by Parts", "Product rule for integration", "math", "calculus",
+            ("integration-by-parts", "Integration by Parts", "Product rule for integration", "math", "calculus",
              r"\int u\,dv = uv - \int v\,du", vec!["integral", "technique"]),
-            ("Chain Rule", "Derivative of composite functions", "math", "calculus",
+            ("chain-rule", "Chain Rule", "Derivative of composite functions", "math", "calculus",
              r"\frac{d}{dx}[f(g(x))] = f'(g(x)) \cdot g'(x)", vec!["derivative", "composite"]),
-            ("Taylor Series", "Power series expansion of a function", "math", "calculus",
+            ("taylor-series", "Taylor Series", "Power series expansion of a function", "math", "calculus",
              r"f(x) = \sum_{n=0}^{\infty} \frac{f^{(n)}(a)}{n!}(x-a)^n", vec!["series", "expansion"]),
-            ("L'Hôpital's Rule", "Evaluate limits of indeterminate forms", "math", "calculus",
+            ("lhopitals-rule", "L'Hôpital's Rule", "Evaluate limits of indeterminate forms", "math", "calculus",
              r"\lim_{x \to c} \frac{f(x)}{g(x)} = \lim_{x \to c} \frac{f'(x)}{g'(x)}", vec!["limit"]),
-            ("Gaussian Integral", "Integral of e^(-x²)", "math", "calculus",
+            ("gaussian-integral", "Gaussian Integral", "Integral of e^(-x²)", "math", "calculus",
              r"\int_{-\infty}^{\infty} e^{-x^2}\,dx = \sqrt{\pi}", vec!["integral", "gaussian"]),
 
             // Math — Trigonometry
-            ("Pythagorean Identity", "Fundamental trig identity", "math", "trigonometry",
+            ("pythagorean-identity", "Pythagorean Identity", "Fundamental trig identity", "math", "trigonometry",
              r"\sin^2\theta + \cos^2\theta = 1", vec!["identity"]),
-            ("Euler's Formula", "Complex exponential and trig", "math", "trigonometry",
+            ("eulers-formula", "Euler's Formula", "Complex exponential and trig", "math", "trigonometry",
              r"e^{i\theta} = \cos\theta + i\sin\theta", vec!["complex", "euler"]),
-            ("Law of Cosines", "Generalized Pythagorean theorem", "math", "trigonometry",
+            ("law-of-cosines", "Law of Cosines", "Generalized Pythagorean theorem", "math", "trigonometry",
              r"c^2 = a^2 + b^2 - 2ab\cos C", vec!["triangle"]),
-            ("Double Angle (Sine)", "Sin of double angle", "math", "trigonometry",
+            ("double-angle-sine", "Double Angle (Sine)", "Sin of double angle", "math", "trigonometry",
              r"\sin 2\theta = 2\sin\theta\cos\theta", vec!["identity"]),
 
             // Math — Linear Algebra
-            ("Matrix Determinant (2×2)", "Determinant of a 2×2 matrix", "math", "linear-algebra",
+            ("matrix-determinant-2x2", "Matrix Determinant (2×2)", "Determinant of a 2×2 matrix", "math", "linear-algebra",
              r"\det\begin{pmatrix} a & b \\ c & d \end{pmatrix} = ad - bc", vec!["matrix", "determinant"]),
-            ("Eigenvalue Equation", "Definition of eigenvalues", "math", "linear-algebra",
+            ("eigenvalue-equation", "Eigenvalue Equation", "Definition of eigenvalues", "math", "linear-algebra",
              r"A\mathbf{v} = \lambda\mathbf{v}", vec!["eigenvalue"]),
-            ("Dot Product", "Inner product of vectors", "math", "linear-algebra",
+            ("dot-product", "Dot Product", "Inner product of vectors", "math", "linear-algebra",
              r"\mathbf{a} \cdot \mathbf{b} = \sum_{i=1}^{n} a_i b_i = \|\mathbf{a}\|\|\mathbf{b}\|\cos\theta", vec!["vector"]),
 
             // Math — Set Theory
-            ("De Morgan's Laws", "Complement of union/intersection", "math", "set-theory",
+            ("de-morgans-laws", "De Morgan's Laws", "Complement of union/intersection", "math", "set-theory",
              r"\overline{A \cup B} = \overline{A} \cap \overline{B}", vec!["sets"]),
 
             // Physics — Mechanics
-            ("Newton's Second Law", "Force equals mass times acceleration", "physics", "mechanics",
+            ("newtons-second-law", "Newton's Second Law", "Force equals mass times acceleration", "physics", "mechanics",
              r"\mathbf{F} = m\mathbf{a}", vec!["force", "newton"]),
-            ("Kinetic Energy", "Energy of motion", "physics", "mechanics",
+            ("kinetic-energy", "Kinetic Energy", "Energy of motion", "physics", "mechanics",
              r"E_k = \frac{1}{2}mv^2", vec!["energy"]),
-            ("Newton's Law of Gravitation", "Gravitational force between two masses", "physics", "mechanics",
+            ("newtons-law-of-gravitation", "Newton's Law of Gravitation", "Gravitational force between two masses", "physics", "mechanics",
              r"F = G\frac{m_1 m_2}{r^2}", vec!["gravity"]),
-            ("Simple Harmonic Motion", "Position in SHM", "physics", "mechanics",
+            ("simple-harmonic-motion", "Simple Harmonic Motion", "Position in SHM", "physics", "mechanics",
              r"x(t) = A\cos(\omega t + \phi)", vec!["oscillation"]),
 
             // Physics — Electromagnetism
-            ("Coulomb's Law", "Electric force between charges", "physics", "electromagnetism",
+            ("coulombs-law", "Coulomb's Law", "Electric force between charges", "physics", "electromagnetism",
              r"F = k_e \frac{q_1 q_2}{r^2}", vec!["electric", "charge"]),
-            ("Maxwell's Equations (Gauss)", "Gauss's law for electric fields", "physics", "electromagnetism",
+            ("maxwells-equations-gauss", "Maxwell's Equations (Gauss)", "Gauss's law for electric fields", "physics", "electromagnetism",
              r"\nabla \cdot \mathbf{E} = \frac{\rho}{\varepsilon_0}", vec!["maxwell"]),
-            ("Ohm's Law", "Voltage, current, resistance relationship", "physics", "electromagnetism",
+            ("ohms-law", "Ohm's Law", "Voltage, current, resistance relationship", "physics", "electromagnetism",
              r"V = IR", vec!["circuit"]),
 
             // Physics — Thermodynamics
-            ("Ideal Gas Law", "PV = nRT", "physics", "thermodynamics",
+            ("ideal-gas-law", "Ideal Gas Law", "PV = nRT", "physics", "thermodynamics",
              r"PV = nRT", vec!["gas"]),
-            ("Boltzmann Entropy", "Statistical definition of entropy", "physics", "thermodynamics",
+            ("boltzmann-entropy", "Boltzmann Entropy", "Statistical definition of entropy", "physics", "thermodynamics",
              r"S = k_B \ln \Omega", vec!["entropy"]),
 
             // Physics — Quantum Mechanics
-            ("Schrödinger Equation", "Time-dependent Schrödinger equation", "physics", "quantum-mechanics",
+            ("schrodinger-equation", "Schrödinger Equation", "Time-dependent Schrödinger equation", "physics", "quantum-mechanics",
              r"i\hbar\frac{\partial}{\partial t}\Psi = \hat{H}\Psi", vec!["wavefunction"]),
-            ("Heisenberg Uncertainty", "Position-momentum uncertainty", "physics", "quantum-mechanics",
+            ("heisenberg-uncertainty", "Heisenberg Uncertainty", "Position-momentum uncertainty", "physics", "quantum-mechanics",
              r"\Delta x \, \Delta p \geq \frac{\hbar}{2}", vec!["uncertainty"]),
 
             // Physics — Relativity
-            ("Mass-Energy Equivalence", "Einstein's famous equation", "physics", "relativity",
+            ("mass-energy-equivalence", "Mass-Energy Equivalence", "Einstein's famous equation", "physics", "relativity",
              r"E = mc^2", vec!["einstein"]),
-            ("Lorentz Factor", "Time dilation factor", "physics", "relativity",
+            ("lorentz-factor", "Lorentz Factor", "Time dilation factor", "physics", "relativity",
              r"\gamma = \frac{1}{\sqrt{1 - \frac{v^2}{c^2}}}", vec!["lorentz"]),
 
             // Chemistry
-            ("Nernst Equation", "Electrode potential under non-standard conditions", "chemistry", "physical-chemistry",
+            ("nernst-equation", "Nernst Equation", "Electrode potential under non-standard conditions", "chemistry", "physical-chemistry",
              r"E = E^\circ - \frac{RT}{nF}\ln Q", vec!["electrochemistry"]),
-            ("Arrhenius Equation", "Temperature dependence of reaction rates", "chemistry", "physical-chemistry",
+            ("arrhenius-equation", "Arrhenius Equation", "Temperature dependence of reaction rates", "chemistry", "physical-chemistry",
              r"k = A e^{-E_a / RT}", vec!["kinetics"]),
-            ("Henderson-Hasselbalch", "pH of buffer solutions", "chemistry", "general-chemistry",
+            ("henderson-hasselbalch", "Henderson-Hasselbalch", "pH of buffer solutions", "chemistry", "general-chemistry",
              r"\text{pH} = \text{p}K_a + \log\frac{[\text{A}^-]}{[\text{HA}]}", vec!["pH", "buffer"]),
-            ("Gibbs Free Energy", "Spontaneity of reactions", "chemistry", "physical-chemistry",
+            ("gibbs-free-energy", "Gibbs Free Energy", "Spontaneity of reactions", "chemistry", "physical-chemistry",
              r"\Delta G = \Delta H - T\Delta S", vec!["thermodynamics"]),
 
             // Statistics
-            ("Bayes' Theorem", "Conditional probability", "statistics", "probability",
+            ("bayes-theorem", "Bayes' Theorem", "Conditional probability", "statistics", "probability",
              r"P(A|B) = \frac{P(B|A)\,P(A)}{P(B)}", vec!["probability"]),
-            ("Normal Distribution", "Gaussian probability density function", "statistics", "distributions",
+            ("normal-distribution", "Normal Distribution", "Gaussian probability density function", "statistics", "distributions",
              r"f(x) = \frac{1}{\sigma\sqrt{2\pi}} e^{-\frac{(x-\mu)^2}{2\sigma^2}}", vec!["gaussian"]),
-            ("Standard Deviation", "Measure of dispersion", "statistics", "distributions",
+            ("standard-deviation", "Standard Deviation", "Measure of dispersion", "statistics", "distributions",
              r"\sigma = \sqrt{\frac{1}{N}\sum_{i=1}^{N}(x_i - \mu)^2}", vec!["variance"]),
 
             // Computer Science
-            ("Shannon Entropy", "Information entropy", "computer-science", "information-theory",
+            ("shannon-entropy", "Shannon Entropy", "Information entropy", "computer-science", "information-theory",
              r"H(X) = -\sum_{i} p(x_i) \log_2 p(x_i)", vec!["information"]),
-            ("Stirling's Approximation", "Approximation of factorial", "computer-science", "algorithms",
+            ("stirlings-approximation", "Stirling's Approximation", "Approximation of factorial", "computer-science", "algorithms",
              r"\ln n! \approx n\ln n - n", vec!["factorial"]),
-            ("Big-O Master Theorem", "Recurrence relation solution", "computer-science", "algorithms",
+            ("big-o-master-theorem", "Big-O Master Theorem", "Recurrence relation solution", "computer-science", "algorithms",
              r"T(n) = aT\!\left(\frac{n}{b}\right) + O(n^d)", vec!["complexity"]),
         ];
 
         formulas_data
             .into_iter()
-            .map(|(name, desc, cat, subcat, latex, tags)| {
-                let slug = slugify(name);
+            .map(|(key, name, desc, cat, subcat, latex, tags)| {
                 let now = chrono::Utc::now().to_rfc3339();
                 Formula {
                     id: Uuid::new_v4().to_string(),
                     name: name.to_string(),
                     description: Some(desc.to_string()),
                     latex_content: latex.to_string(),
-                    lml_content: Some(format!(
-                        "\n@equation(label: eq:{}, mode: display)\n{}\n",
-                        slug, latex
-                    )),
+                    lml_content: Some(lml_equation(name, latex)),
                     category: cat.to_string(),
                     subcategory: Some(subcat.to_string()),
                     tags: tags.into_iter().map(|t| t.to_string()).collect(),
                     is_favorite: false,
                     is_system: true,
+                    system_key: Some(key.to_string()),
                     usage_count: 0,
                     created_at: now.clone(),
                     updated_at: now,
+                    deleted_at: None,
+                    last_used_at: None,
                 }
             })
             .collect()
@@ -272,6 +1067,386 @@ pub struct FormulaUpdate {
     pub tags: Option<Vec<String>>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum PreviewMode {
+    Inline,
+    Display,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PreviewResult {
+    pub latex: String,
+    pub mode: PreviewMode,
+    pub lml_content: String,
+    /// SVG rendered with the local LaTeX tools
+    pub svg: Option<String>,
+    /// Why `svg` is missing: a LaTeX error, or the tools aren't installed
+    #[serde(default)]
+    pub render_error: Option<String>,
+}
+
+/// Canonical form for stored tags: trimmed, lowercased, inner whitespace
+/// collapsed to single spaces, empties dropped, and duplicates removed
+pub fn normalize_tags(tags: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = vec![];
+    for tag in tags {
+        let tag = tag
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Check LaTeX for structural errors: unbalanced braces, mismatched
+/// `\begin`/`\end` environments, unpaired `\left`/`\right`, and stray `$`
+pub fn validate_latex(latex: &str) -> Result<(), String> {
+    if latex.trim().is_empty() {
+        return Err("Formula is empty".to_string());
+    }
+
+    let mut depth: i32 = 0;
+    let mut environments: Vec<String> = vec![];
+    let mut left_right: i32 = 0;
+    let mut chars = latex.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                let rest = &latex[i + 1..];
+                let command: String =
+                    rest.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+                if command.is_empty() {
+                    // Escaped character such as \{ or \\; skip it
+                    if chars.next().is_none() {
+                        return Err("Formula ends with a lone backslash".to_string());
+                    }
+                    continue;
+                }
+                match command.as_str() {
+                    "begin" | "end" => {
+                        let after = &rest[command.len()..];
+                        let name = after
+                            .strip_prefix('{')
+                            .and_then(|a| a.split_once('}'))
+                            .map(|(name, _)| name.to_string())
+                            .ok_or_else(|| {
+                                format!("\\{} is missing an environment name", command)
+                            })?;
+                        if command == "begin" {
+                            environments.push(name);
+                        } else {
+                            match environments.pop() {
+                                Some(open) if open == name => {}
+                                Some(open) => {
+                                    return Err(format!(
+                                        "\\end{{{}}} does not match \\begin{{{}}}",
+                                        name, open
+                                    ))
+                                }
+                                None => {
+                                    return Err(format!("\\end{{{}}} without a \\begin", name))
+                                }
+                            }
+                        }
+                    }
+                    "left" => left_right += 1,
+                    "right" => {
+                        left_right -= 1;
+                        if left_right < 0 {
+                            return Err("\\right without a matching \\left".to_string());
+                        }
+                    }
+                    _ => {}
+                }
+                for _ in 0..command.chars().count() {
+                    chars.next();
+                }
+            }
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth < 0 {
+                    return Err(format!("Unexpected closing brace at position {}", i));
+                }
+            }
+            '$' => return Err("Math delimiters ($) are not allowed inside a formula".to_string()),
+            _ => {}
+        }
+    }
+
+    if depth > 0 {
+        return Err(format!("{} unclosed brace(s)", depth));
+    }
+    if let Some(open) = environments.pop() {
+        return Err(format!("\\begin{{{}}} is never closed", open));
+    }
+    if left_right > 0 {
+        return Err("\\left without a matching \\right".to_string());
+    }
+    Ok(())
+}
+
+/// Validate a formula and build its LML wrapping without saving anything.
+/// Rendering is left to the caller.
+pub fn build_preview(latex: &str, mode: PreviewMode) -> Result<PreviewResult, String> {
+    validate_latex(latex)?;
+    let lml_content = match mode {
+        PreviewMode::Display => format!("\n@equation(mode: display)\n{}\n", latex.trim()),
+        PreviewMode::Inline => format!("@equation(mode: inline) {}", latex.trim()),
+    };
+    Ok(PreviewResult {
+        latex: latex.to_string(),
+        mode,
+        lml_content,
+        svg: None,
+        render_error: None,
+    })
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SheetFormat {
+    Html,
+    Markdown,
+}
+
+/// Render grouped formulas as a printable reference sheet
+pub fn render_formula_sheet(
+    groups: &BTreeMap<String, BTreeMap<String, Vec<&Formula>>>,
+    format: SheetFormat,
+) -> String {
+    let mut out = String::new();
+    match format {
+        SheetFormat::Html => {
+            out.push_str(concat!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+                "<title>Formula Reference</title>\n</head>\n<body>\n",
+                "<h1>Formula Reference</h1>\n"
+            ));
+            for (category, subgroups) in groups {
+                out.push_str(&format!("<h2>{}</h2>\n", html_escape(category)));
+                for (subcategory, formulas) in subgroups {
+                    if !subcategory.is_empty() {
+                        out.push_str(&format!("<h3>{}</h3>\n", html_escape(subcategory)));
+                    }
+                    out.push_str("<dl>\n");
+                    for formula in formulas {
+                        let description = formula.description.as_deref().filter(|d| !d.is_empty());
+                        out.push_str(&format!("<dt>{}</dt>\n", html_escape(&formula.name)));
+                        if let Some(desc) = description {
+                            out.push_str(&format!("<dd>{}</dd>\n", html_escape(desc)));
+                        }
+                        out.push_str(&format!(
+                            "<dd><code>{}</code></dd>\n",
+                            html_escape(&formula.latex_content)
+                        ));
+                    }
+                    out.push_str("</dl>\n");
+                }
+            }
+            out.push_str("</body>\n</html>\n");
+        }
+        SheetFormat::Markdown => {
+            out.push_str("# Formula Reference\n");
+            for (category, subgroups) in groups {
+                out.push_str(&format!("\n## {}\n", markdown_escape(category)));
+                for (subcategory, formulas) in subgroups {
+                    if !subcategory.is_empty() {
+                        out.push_str(&format!("\n### {}\n", markdown_escape(subcategory)));
+                    }
+                    for formula in formulas {
+                        let description = formula.description.as_deref().filter(|d| !d.is_empty());
+                        out.push_str(&format!("\n**{}**", markdown_escape(&formula.name)));
+                        if let Some(desc) = description {
+                            out.push_str(&format!(" — {}", markdown_escape(desc)));
+                        }
+                        out.push_str(&format!("\n\n```latex\n{}\n```\n", formula.latex_content));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Backslash-escape characters Markdown would treat as formatting
+fn markdown_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\`*_[]#<>|".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Read formulas from an exported library file (`{"formulas": [...]}` or a bare array)
+pub fn read_import_file(path: &Path) -> Result<Vec<Formula>, String> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read file: {}", e))?;
+    if let Ok(data) = serde_json::from_str::<FormulaData>(&content) {
+        return Ok(data.formulas);
+    }
+    serde_json::from_str::<Vec<Formula>>(&content)
+        .map_err(|e| format!("Failed to parse formula library: {}", e))
+}
+
+/// A fresh user-owned copy of an imported formula
+fn imported_copy(formula: &Formula, name: &str) -> Formula {
+    let mut copy = Formula::new_user(name, &formula.latex_content, &formula.category);
+    copy.description = formula.description.clone();
+    copy.subcategory = formula.subcategory.clone();
+    copy.tags = normalize_tags(formula.tags.clone());
+    copy
+}
+
+/// LML display-equation block for a formula, labelled by its slugified name
+fn lml_equation(name: &str, latex: &str) -> String {
+    format!(
+        "\n@equation(label: eq:{}, mode: display)\n{}\n",
+        slugify(name),
+        latex
+    )
+}
+
+/// Compare equations ignoring whitespace differences
+fn normalize_latex(latex: &str) -> String {
+    latex.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Find `equation`/`equation*` environments and `$$...$$` blocks, returning
+/// each equation's `\label{}` (if any) and its body with the label removed.
+/// Equations repeated within the source are returned once.
+fn extract_latex_equations(source: &str) -> Vec<(Option<String>, String)> {
+    let mut found: Vec<(usize, Option<String>, String)> = vec![];
+
+    for env in ["equation", "equation*"] {
+        let begin = format!("\\begin{{{}}}", env);
+        let end = format!("\\end{{{}}}", env);
+        let mut rest = 0;
+        while let Some(start) = source[rest..].find(&begin) {
+            let body_start = rest + start + begin.len();
+            let Some(len) = source[body_start..].find(&end) else {
+                break;
+            };
+            let body = &source[body_start..body_start + len];
+            found.push((rest + start, extract_label(body), strip_label(body)));
+            rest = body_start + len + end.len();
+        }
+    }
+
+    let mut rest = 0;
+    while let Some(start) = source[rest..].find("$$") {
+        let body_start = rest + start + 2;
+        let Some(len) = source[body_start..].find("$$") else {
+            break;
+        };
+        let body = &source[body_start..body_start + len];
+        found.push((rest + start, extract_label(body), strip_label(body)));
+        rest = body_start + len + 2;
+    }
+
+    found.sort_by_key(|(pos, _, _)| *pos);
+
+    let mut seen = std::collections::HashSet::new();
+    found
+        .into_iter()
+        .filter(|(_, _, latex)| !latex.is_empty() && seen.insert(normalize_latex(latex)))
+        .map(|(_, label, latex)| (label, latex))
+        .collect()
+}
+
+fn extract_label(body: &str) -> Option<String> {
+    let start = body.find("\\label{")? + "\\label{".len();
+    let len = body[start..].find('}')?;
+    let label = body[start..start + len].trim();
+    let label = label.split_once(':').map_or(label, |(_, rest)| rest);
+    (!label.is_empty()).then(|| label.replace(['-', '_'], " "))
+}
+
+fn strip_label(body: &str) -> String {
+    let mut out = body.to_string();
+    while let Some(start) = out.find("\\label{") {
+        match out[start..].find('}') {
+            Some(len) => out.replace_range(start..start + len + 1, ""),
+            None => break,
+        }
+    }
+    out.trim().to_string()
+}
+
+/// Minimum similarity (0–1) for a fuzzy match
+const FUZZY_THRESHOLD: f64 = 0.75;
+
+/// Average, over the query's words, of each word's best similarity to a word
+/// in the formula's name or tags
+fn fuzzy_score(query: &str, formula: &Formula) -> f64 {
+    let candidates: Vec<String> = formula
+        .name
+        .split(|c: char| !c.is_alphanumeric())
+        .chain(formula.tags.iter().flat_map(|t| t.split(|c: char| !c.is_alphanumeric())))
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect();
+
+    let words: Vec<&str> = query.split_whitespace().collect();
+    if words.is_empty() || candidates.is_empty() {
+        return 0.0;
+    }
+
+    let total: f64 = words
+        .iter()
+        .map(|word| {
+            candidates
+                .iter()
+                .map(|c| similarity(word, c))
+                .fold(0.0, f64::max)
+        })
+        .sum();
+    total / words.len() as f64
+}
+
+/// Normalized Levenshtein similarity; a prefix of the candidate counts as a full match
+fn similarity(word: &str, candidate: &str) -> f64 {
+    if candidate.starts_with(word) {
+        return 1.0;
+    }
+    let max_len = word.chars().count().max(candidate.chars().count());
+    if max_len == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(word, candidate) as f64 / max_len as f64
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        curr[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev[b.len()]
+}
+
 fn slugify(name: &str) -> String {
     name.to_lowercase()
         .chars()
@@ -282,3 +1457,859 @@ fn slugify(name: &str) -> String {
         .collect::<Vec<_>>()
         .join("-")
 }
+
+/// Quote a CSV field if it contains a delimiter, quote, or line break
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A manager over a library holding exactly `formulas`, with no built-ins seeded
+    fn manager_with(formulas: Vec<Formula>) -> (tempfile::TempDir, FormulaManager) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("formulas.json");
+        let data = FormulaData {
+            formulas,
+            system_formula_version: SYSTEM_FORMULA_VERSION,
+        };
+        fs::write(&path, serde_json::to_string(&data).unwrap()).unwrap();
+        (dir, FormulaManager::new(path))
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a, b"), "\"a, b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn flashcards_escape_fields_and_filter_by_category() {
+        let mut quadratic = Formula::new_user("Quadratic", r"x = \frac{-b, c}{2a}", "math");
+        quadratic.description = Some("Roots of \"ax^2\"".to_string());
+        let ohm = Formula::new_user("Ohm's Law", "V = IR", "physics");
+        let (_dir, manager) = manager_with(vec![quadratic, ohm]);
+
+        let all = manager.to_flashcards_csv(None);
+        assert_eq!(
+            all,
+            "\"Quadratic — Roots of \"\"ax^2\"\"\",\"x = \\frac{-b, c}{2a}\"\nOhm's Law,V = IR\n"
+        );
+
+        let physics = manager.to_flashcards_csv(Some("physics"));
+        assert_eq!(physics, "Ohm's Law,V = IR\n");
+        assert_eq!(manager.to_flashcards_csv(Some("chemistry")), "");
+    }
+
+    #[test]
+    fn fuzzy_search_tolerates_a_one_character_typo() {
+        let (_dir, manager) = manager_with(vec![
+            Formula::new_user("Pythagorean Theorem", "a^2 + b^2 = c^2", "math"),
+            Formula::new_user("Ohm's Law", "V = IR", "physics"),
+        ]);
+        let names =
+            |found: Vec<Formula>| -> Vec<String> { found.into_iter().map(|f| f.name).collect() };
+
+        assert_eq!(
+            names(manager.search("pythagorian", true)),
+            ["Pythagorean Theorem"]
+        );
+        assert!(manager.search("pythagorian", false).is_empty());
+        // Exact substrings still match without fuzzy
+        assert_eq!(
+            names(manager.search("pythagorean", false)),
+            ["Pythagorean Theorem"]
+        );
+    }
+
+    #[test]
+    fn fuzzy_search_ranks_closer_matches_first() {
+        let (_dir, manager) = manager_with(vec![
+            Formula::new_user("Kinetic Energy", "E = mv^2/2", "physics"),
+            Formula::new_user("Kinematic Equation", "v = u + at", "physics"),
+        ]);
+        let found = manager.search("kinetik", true);
+        assert_eq!(found[0].name, "Kinetic Energy");
+        assert!(manager.search("thermodynamics", true).is_empty());
+    }
+
+    fn faceted_library() -> (tempfile::TempDir, FormulaManager) {
+        let formula = |name: &str, category: &str, tags: &[&str], favorite: bool| {
+            let mut formula = Formula::new_user(name, "x", category);
+            formula.tags = tags.iter().map(|t| t.to_string()).collect();
+            formula.is_favorite = favorite;
+            formula
+        };
+        manager_with(vec![
+            formula("Kinetic Energy", "physics", &["energy", "mechanics"], true),
+            formula("Kinematic Equation", "physics", &["mechanics"], false),
+            formula("Potential Energy", "physics", &["energy"], false),
+            formula("Energy Integral", "calculus", &["energy"], true),
+        ])
+    }
+
+    #[test]
+    fn tag_filters_narrow_a_text_query() {
+        let (_dir, manager) = faceted_library();
+        let names =
+            |found: Vec<Formula>| -> Vec<String> { found.into_iter().map(|f| f.name).collect() };
+        let mechanics = ["mechanics".to_string()];
+        let physics = ["Physics".to_string()];
+
+        let found = manager.search_filtered("energy", false, &[], &mechanics, false);
+        assert_eq!(names(found), ["Kinetic Energy"]);
+        let found = manager.search_filtered("energy", false, &physics, &[], false);
+        assert_eq!(names(found), ["Kinetic Energy", "Potential Energy"]);
+        // Fuzzy ranking survives the filter
+        let found = manager.search_filtered("kinetik", true, &[], &mechanics, false);
+        assert_eq!(found[0].name, "Kinetic Energy");
+    }
+
+    #[test]
+    fn favorites_only_and_empty_queries_filter_the_whole_library() {
+        let (_dir, manager) = faceted_library();
+        let names =
+            |found: Vec<Formula>| -> Vec<String> { found.into_iter().map(|f| f.name).collect() };
+
+        let mut favorites = names(manager.search_filtered("", false, &[], &[], true));
+        favorites.sort();
+        assert_eq!(favorites, ["Energy Integral", "Kinetic Energy"]);
+
+        let energy = ["energy".to_string()];
+        let calculus = ["calculus".to_string()];
+        let found = manager.search_filtered("", false, &calculus, &energy, true);
+        assert_eq!(names(found), ["Energy Integral"]);
+        assert!(manager
+            .search_filtered("kinematic", false, &[], &[], true)
+            .is_empty());
+    }
+
+    #[test]
+    fn levenshtein_counts_single_character_edits() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("same", "same"), 0);
+        assert_eq!(similarity("pyth", "pythagorean"), 1.0);
+    }
+
+    const TEX_FIXTURE: &str = r"\section{Mechanics}
+\begin{equation}\label{eq:newton-second}
+  F = ma
+\end{equation}
+Energy is conserved:
+$$E = mc^2$$
+and, restated,
+\begin{equation*}
+F   =   ma
+\end{equation*}
+";
+
+    #[test]
+    fn extracts_distinct_equations_with_labels() {
+        assert_eq!(
+            extract_latex_equations(TEX_FIXTURE),
+            [
+                (Some("newton second".to_string()), "F = ma".to_string()),
+                (None, "E = mc^2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn latex_import_skips_equations_already_in_the_library() {
+        let (_dir, mut manager) = manager_with(vec![Formula::new_user(
+            "Mass-energy",
+            "E =  mc^2",
+            "physics",
+        )]);
+
+        let imported = manager.import_latex_equations(TEX_FIXTURE, "mechanics", "physics");
+        assert_eq!(imported.len(), 1);
+        assert_eq!(imported[0].name, "newton second");
+        assert_eq!(imported[0].latex_content, "F = ma");
+        assert_eq!(imported[0].category, "physics");
+        assert_eq!(manager.get_all().len(), 2);
+
+        // Importing the same file again adds nothing
+        assert!(manager
+            .import_latex_equations(TEX_FIXTURE, "mechanics", "physics")
+            .is_empty());
+    }
+
+    #[test]
+    fn unlabeled_equations_are_named_from_the_file() {
+        let (_dir, mut manager) = manager_with(vec![]);
+        let imported = manager.import_latex_equations("$$a^2 + b^2 = c^2$$", "geometry", "math");
+        assert_eq!(imported[0].name, "geometry Equation 1");
+    }
+
+    #[test]
+    fn trashed_formulas_are_hidden_until_restored() {
+        let kept = Formula::new_user("Ohm's Law", "V = IR", "physics");
+        let trashed = Formula::new_user(
+            "Snell's Law",
+            "n_1 \\sin\\theta_1 = n_2 \\sin\\theta_2",
+            "physics",
+        );
+        let id = trashed.id.clone();
+        let (_dir, mut manager) = manager_with(vec![kept, trashed]);
+
+        assert!(manager.trash(&id).unwrap().deleted_at.is_some());
+        assert_eq!(manager.get_all().len(), 1);
+        assert!(manager.get(&id).is_none());
+        assert!(manager.search("snell", false).is_empty());
+        assert_eq!(manager.get_trashed()[0].id, id);
+        // Already in the trash
+        assert!(manager.trash(&id).is_none());
+
+        assert!(manager.restore(&id).unwrap().deleted_at.is_none());
+        assert_eq!(manager.get_all().len(), 2);
+        assert!(manager.get_trashed().is_empty());
+        assert!(manager.restore(&id).is_none());
+    }
+
+    #[test]
+    fn system_formulas_cannot_be_trashed() {
+        let mut builtin = Formula::new_user("Euler's Identity", "e^{i\\pi} + 1 = 0", "math");
+        builtin.is_system = true;
+        let id = builtin.id.clone();
+        let (_dir, mut manager) = manager_with(vec![builtin]);
+
+        assert!(manager.trash(&id).is_none());
+        assert_eq!(manager.get_all().len(), 1);
+    }
+
+    #[test]
+    fn purge_removes_only_old_trash() {
+        let days_ago = |days: i64| (chrono::Utc::now() - chrono::Duration::days(days)).to_rfc3339();
+        let mut old = Formula::new_user("Old", "a", "math");
+        old.deleted_at = Some(days_ago(40));
+        let mut recent = Formula::new_user("Recent", "b", "math");
+        recent.deleted_at = Some(days_ago(2));
+        let active = Formula::new_user("Active", "c", "math");
+        let (_dir, mut manager) = manager_with(vec![old, recent, active]);
+
+        assert_eq!(manager.purge_trashed(30), 1);
+        let trashed: Vec<String> = manager.get_trashed().into_iter().map(|f| f.name).collect();
+        assert_eq!(trashed, ["Recent"]);
+        assert_eq!(manager.get_all().len(), 1);
+    }
+
+    /// Gzip and encode a share payload the way `to_share_blob` does
+    fn share_blob_of(payload: &serde_json::Value) -> String {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(payload.to_string().as_bytes()).unwrap();
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(encoder.finish().unwrap())
+    }
+
+    #[test]
+    fn share_blob_round_trips_as_a_new_user_formula() {
+        let mut original = Formula::new_user(
+            "Bayes' Theorem",
+            r"P(A|B) = \frac{P(B|A)P(A)}{P(B)}",
+            "math",
+        );
+        original.description = Some("Conditional probability".to_string());
+        original.subcategory = Some("probability".to_string());
+        original.tags = vec!["statistics".to_string()];
+        original.is_system = true;
+        original.is_favorite = true;
+
+        let blob = original.to_share_blob().unwrap();
+        assert!(blob
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+
+        let decoded = Formula::from_share_blob(&blob).unwrap();
+        assert_ne!(decoded.id, original.id);
+        assert!(!decoded.is_system);
+        assert!(!decoded.is_favorite);
+        assert_eq!(decoded.name, original.name);
+        assert_eq!(decoded.latex_content, original.latex_content);
+        assert_eq!(decoded.category, original.category);
+        assert_eq!(decoded.description, original.description);
+        assert_eq!(decoded.subcategory, original.subcategory);
+        assert_eq!(decoded.tags, original.tags);
+    }
+
+    #[test]
+    fn invalid_share_blobs_are_rejected() {
+        let err = |blob: &str| Formula::from_share_blob(blob).unwrap_err();
+        assert!(err("not base64!").contains("not valid base64"));
+        assert!(err("aGVsbG8").contains("corrupt payload"));
+
+        let missing_latex = share_blob_of(&serde_json::json!({
+            "v": 1, "name": "Empty", "latex": " ", "category": "math",
+        }));
+        assert!(err(&missing_latex).contains("name and LaTeX are required"));
+
+        let newer = share_blob_of(&serde_json::json!({
+            "v": 99, "name": "Future", "latex": "x", "category": "math",
+        }));
+        assert!(err(&newer).contains("newer version"));
+
+        let oversized = share_blob_of(&serde_json::json!({
+            "v": 1, "name": "Huge", "latex": "x".repeat(100 * 1024), "category": "math",
+        }));
+        assert!(err(&oversized).contains("too large"));
+    }
+
+    /// A library with Ohm's and Newton's laws, and an import file holding one
+    /// formula of each category: new, identical duplicate, and name conflict
+    fn import_fixture() -> (tempfile::TempDir, FormulaManager, Vec<Formula>) {
+        let (dir, manager) = manager_with(vec![
+            Formula::new_user("Ohm's Law", "V = IR", "physics"),
+            Formula::new_user("Newton's Second Law", "F = ma", "physics"),
+        ]);
+        let path = dir.path().join("teammate.json");
+        let data = FormulaData {
+            formulas: vec![
+                Formula::new_user("Hooke's Law", "F = -kx", "physics"),
+                Formula::new_user("ohm's law", "V  =  IR", "physics"),
+                Formula::new_user("Newton's Second Law", "F = \\frac{dp}{dt}", "physics"),
+            ],
+            system_formula_version: 0,
+        };
+        fs::write(&path, serde_json::to_string(&data).unwrap()).unwrap();
+        let incoming = read_import_file(&path).unwrap();
+        (dir, manager, incoming)
+    }
+
+    #[test]
+    fn import_preview_reports_each_category_without_changes() {
+        let (_dir, manager, incoming) = import_fixture();
+
+        let preview = manager.preview_import(&incoming);
+        assert_eq!(preview.new_count, 1);
+        assert_eq!(preview.new_names, ["Hooke's Law"]);
+        assert_eq!(preview.duplicate_count, 1);
+        assert_eq!(preview.conflict_count, 1);
+        assert_eq!(preview.conflicts[0].name, "Newton's Second Law");
+        assert_eq!(preview.conflicts[0].existing_latex, "F = ma");
+        assert_eq!(preview.conflicts[0].incoming_latex, "F = \\frac{dp}{dt}");
+        assert_eq!(manager.get_all().len(), 2);
+    }
+
+    #[test]
+    fn import_applies_the_chosen_resolution() {
+        let resolve = |resolution| {
+            let (_dir, mut manager, incoming) = import_fixture();
+            let resolutions = HashMap::from([("Newton's Second Law".to_string(), resolution)]);
+            let result = manager.apply_import(incoming, &resolutions);
+            let mut names: Vec<(String, String)> = manager
+                .get_all()
+                .into_iter()
+                .map(|f| (f.name, f.latex_content))
+                .collect();
+            names.sort();
+            (result, names)
+        };
+
+        let (result, names) = resolve(ConflictResolution::Skip);
+        assert_eq!(
+            (result.added, result.overwritten, result.skipped),
+            (1, 0, 2)
+        );
+        assert_eq!(names.len(), 3);
+
+        let (result, names) = resolve(ConflictResolution::Overwrite);
+        assert_eq!(
+            (result.added, result.overwritten, result.skipped),
+            (1, 1, 1)
+        );
+        assert!(names.contains(&(
+            "Newton's Second Law".to_string(),
+            "F = \\frac{dp}{dt}".to_string()
+        )));
+
+        let (result, names) = resolve(ConflictResolution::KeepBoth);
+        assert_eq!(
+            (result.added, result.overwritten, result.skipped),
+            (2, 0, 1)
+        );
+        assert!(names.contains(&("Newton's Second Law".to_string(), "F = ma".to_string())));
+        assert!(names.contains(&(
+            "Newton's Second Law (imported)".to_string(),
+            "F = \\frac{dp}{dt}".to_string()
+        )));
+    }
+
+    #[test]
+    fn import_never_overwrites_system_formulas() {
+        let mut builtin = Formula::new_user("Euler's Identity", "e^{i\\pi} + 1 = 0", "math");
+        builtin.is_system = true;
+        let (_dir, mut manager) = manager_with(vec![builtin]);
+        let incoming = vec![Formula::new_user(
+            "Euler's Identity",
+            "e^{i\\pi} = -1",
+            "math",
+        )];
+        let resolutions = HashMap::from([(
+            "Euler's Identity".to_string(),
+            ConflictResolution::Overwrite,
+        )]);
+
+        let result = manager.apply_import(incoming, &resolutions);
+        assert_eq!((result.added, result.overwritten), (1, 0));
+        assert_eq!(manager.get_all().len(), 2);
+    }
+
+    #[test]
+    fn preview_wraps_valid_latex_for_each_mode() {
+        let inline = build_preview("  a^2 + b^2  ", PreviewMode::Inline).unwrap();
+        assert_eq!(inline.lml_content, "@equation(mode: inline) a^2 + b^2");
+        assert_eq!(inline.latex, "  a^2 + b^2  ");
+        assert!(inline.svg.is_none() && inline.render_error.is_none());
+
+        let display = build_preview(
+            "\\begin{aligned} x &= 1 \\end{aligned}",
+            PreviewMode::Display,
+        )
+        .unwrap();
+        assert_eq!(
+            display.lml_content,
+            "\n@equation(mode: display)\n\\begin{aligned} x &= 1 \\end{aligned}\n"
+        );
+    }
+
+    #[test]
+    fn preview_rejects_invalid_latex() {
+        let error = |latex: &str| build_preview(latex, PreviewMode::Inline).unwrap_err();
+        assert_eq!(error("   "), "Formula is empty");
+        assert_eq!(error("\\frac{a}{b"), "1 unclosed brace(s)");
+        assert_eq!(error("x \\"), "Formula ends with a lone backslash");
+        assert_eq!(error("\\left( x"), "\\left without a matching \\right");
+        assert_eq!(error("\\begin{cases} x"), "\\begin{cases} is never closed");
+    }
+
+    #[test]
+    fn renaming_a_category_updates_every_user_formula_in_it() {
+        let mut det = Formula::new_user("Determinant", "\\det A", "linear-algebra");
+        det.subcategory = Some("matrices".to_string());
+        let mut trace = Formula::new_user("Trace", "\\operatorname{tr} A", "linear-algebra");
+        trace.subcategory = Some("operators".to_string());
+        let norm = Formula::new_user("Norm", "\\|v\\|", "linear-algebra");
+        let other = Formula::new_user("Sum", "\\sum", "calculus");
+        let mut builtin = Formula::new_user("Identity", "I", "linear-algebra");
+        builtin.is_system = true;
+        let (_dir, mut manager) = manager_with(vec![det, trace, norm, other, builtin]);
+
+        let changed = manager.rename_category(
+            "linear-algebra",
+            "linalg",
+            Some(("matrices", "matrix-algebra")),
+        );
+        assert_eq!(changed, 3);
+
+        let category_of = |name: &str| {
+            let formula = manager
+                .data
+                .formulas
+                .iter()
+                .find(|f| f.name == name)
+                .unwrap();
+            (formula.category.clone(), formula.subcategory.clone())
+        };
+        assert_eq!(
+            category_of("Determinant"),
+            ("linalg".to_string(), Some("matrix-algebra".to_string()))
+        );
+        // Only the named subcategory is renamed
+        assert_eq!(
+            category_of("Trace"),
+            ("linalg".to_string(), Some("operators".to_string()))
+        );
+        assert_eq!(category_of("Norm"), ("linalg".to_string(), None));
+        assert_eq!(category_of("Sum").0, "calculus");
+        assert_eq!(category_of("Identity").0, "linear-algebra");
+
+        assert_eq!(manager.rename_category("linear-algebra", "linalg", None), 0);
+    }
+
+    fn tags(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn messy_tags_normalize_to_a_clean_set() {
+        let messy = tags(&[
+            "Integral",
+            " integral",
+            "integral ",
+            "",
+            "  ",
+            "Line\t  Integral",
+        ]);
+        assert_eq!(normalize_tags(messy), ["integral", "line integral"]);
+    }
+
+    #[test]
+    fn tags_are_normalized_on_create_and_update() {
+        let (_dir, mut manager) = manager_with(vec![]);
+        let mut formula = Formula::new_user("Gauss", "\\oint E \\cdot dA", "physics");
+        formula.tags = tags(&["Physics ", "physics", "Electro  Magnetism"]);
+        let created = manager.add(formula);
+        assert_eq!(created.tags, ["physics", "electro magnetism"]);
+
+        let update = FormulaUpdate {
+            name: None,
+            description: None,
+            latex_content: None,
+            category: None,
+            subcategory: None,
+            tags: Some(tags(&[" Maxwell", "MAXWELL", "", "Flux "])),
+        };
+        let updated = manager.update(&created.id, update).unwrap();
+        assert_eq!(updated.tags, ["maxwell", "flux"]);
+        assert_eq!(manager.all_tags(), ["flux", "maxwell"]);
+    }
+
+    #[test]
+    fn imported_and_shared_tags_are_normalized() {
+        let clean = tags(&["mechanics", "classical physics"]);
+        let import = |resolution| {
+            let (_dir, mut manager, mut incoming) = import_fixture();
+            for formula in &mut incoming {
+                formula.tags = tags(&["Mechanics ", "MECHANICS", "Classical  Physics"]);
+            }
+            let resolutions = HashMap::from([("Newton's Second Law".to_string(), resolution)]);
+            manager.apply_import(incoming, &resolutions);
+            let tags_of = |name: &str| {
+                let formula = manager.get_all().into_iter().find(|f| f.name == name);
+                formula.map(|f| f.tags)
+            };
+            [
+                tags_of("Hooke's Law"),
+                tags_of("Newton's Second Law"),
+                tags_of("Newton's Second Law (imported)"),
+            ]
+        };
+
+        // New formulas and overwritten ones
+        let [added, overwritten, _] = import(ConflictResolution::Overwrite);
+        assert_eq!(added.as_ref(), Some(&clean));
+        assert_eq!(overwritten.as_ref(), Some(&clean));
+        // Imported copies kept alongside the library's formula
+        let [_, _, copy] = import(ConflictResolution::KeepBoth);
+        assert_eq!(copy.as_ref(), Some(&clean));
+
+        let mut original = Formula::new_user("Hooke's Law", "F = -kx", "physics");
+        original.tags = tags(&["Mechanics ", "MECHANICS", "Classical  Physics"]);
+        let shared = Formula::from_share_blob(&original.to_share_blob().unwrap()).unwrap();
+        assert_eq!(shared.tags, clean);
+    }
+
+    #[test]
+    fn legacy_unnormalized_tags_still_match_and_list_cleanly() {
+        let mut old = Formula::new_user("Area", "\\pi r^2", "geometry");
+        old.tags = tags(&["Circle ", "Geometry"]);
+        let mut other = Formula::new_user("Perimeter", "2\\pi r", "geometry");
+        other.tags = tags(&["circle"]);
+        let (_dir, manager) = manager_with(vec![old, other]);
+
+        assert_eq!(manager.all_tags(), ["circle", "geometry"]);
+        let found = manager.search_filtered("", false, &[], &tags(&[" CIRCLE"]), false);
+        assert_eq!(found.len(), 2);
+    }
+
+    #[test]
+    fn malformed_formulas_report_the_position_and_keep_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("formulas.json");
+        let broken = "{\n  \"formulas\": [\n    {\"id\": 1,}\n  ]\n}\n";
+        fs::write(&path, broken).unwrap();
+
+        let manager = FormulaManager::new(path.clone());
+        let error = manager.load_error().unwrap();
+        assert_eq!((error.line, error.column), (3, 12));
+        // Built-ins are served, but the file is left for the user to fix
+        assert!(!manager.get_all().is_empty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), broken);
+    }
+
+    #[test]
+    fn stats_aggregate_the_seeded_and_user_formulas() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = FormulaManager::new(dir.path().join("formulas.json"));
+        let seeded = FormulaManager::seed_system_formulas();
+        assert!(!seeded.is_empty());
+
+        let mine = manager.add(Formula::new_user("Mine", "x", "custom"));
+        let other = manager.add(Formula::new_user("Other", "y", "custom"));
+        let binned = manager.add(Formula::new_user("Binned", "z", "custom"));
+        manager.toggle_favorite(&other.id);
+        for _ in 0..3 {
+            manager.increment_usage(&mine.id);
+        }
+        // Seeding assigns fresh ids, so take the library's own copy
+        let builtin = manager.get_all().into_iter().find(|f| f.is_system).unwrap();
+        manager.increment_usage(&builtin.id);
+        for _ in 0..10 {
+            manager.increment_usage(&binned.id);
+        }
+        manager.trash(&binned.id);
+
+        let stats = manager.stats();
+        assert_eq!(stats.total, seeded.len() + 2);
+        assert_eq!(stats.system_count, seeded.len());
+        assert_eq!(stats.user_count, 2);
+        let seeded_favorites = seeded.iter().filter(|f| f.is_favorite).count();
+        assert_eq!(stats.favorites, seeded_favorites + 1);
+        assert_eq!(stats.by_category.get("custom"), Some(&2));
+        let builtin_count = seeded
+            .iter()
+            .filter(|f| f.category == builtin.category)
+            .count();
+        assert_eq!(
+            stats.by_category.get(&builtin.category),
+            Some(&builtin_count)
+        );
+        assert_eq!(stats.by_category.values().sum::<usize>(), stats.total);
+        assert_eq!(stats.system_formula_version, SYSTEM_FORMULA_VERSION);
+
+        // Trashed formulas don't count, even when heavily used
+        let most_used: Vec<(&str, u32)> = stats
+            .most_used
+            .iter()
+            .map(|u| (u.name.as_str(), u.usage_count))
+            .collect();
+        assert_eq!(most_used, [("Mine", 3), (builtin.name.as_str(), 1)]);
+    }
+
+    /// A built-in formula in `category`, with an optional subcategory
+    #[test]
+    fn upgrading_adds_new_built_ins_and_keeps_user_edits() {
+        let seed = FormulaManager::seed_system_formulas();
+        let (newest, older) = seed.split_last().unwrap();
+
+        // A library saved by an older build: one built-in missing, one outdated
+        // and favorited, one saved before built-ins had keys
+        let mut formulas = older.to_vec();
+        formulas[0].latex_content = "outdated".to_string();
+        formulas[0].is_favorite = true;
+        formulas[0].usage_count = 7;
+        let kept_id = formulas[0].id.clone();
+        formulas[1].system_key = None;
+        let mut mine = Formula::new_user("My identity", "x = x", "custom");
+        mine.tags = vec!["personal".to_string()];
+        formulas.push(mine.clone());
+        let mut data = FormulaData {
+            formulas,
+            system_formula_version: 1,
+        };
+
+        let upgrade = FormulaManager::merge_system_formulas(&mut data, seed.clone());
+        assert_eq!(
+            (upgrade.from_version, upgrade.to_version),
+            (1, SYSTEM_FORMULA_VERSION)
+        );
+        assert_eq!((upgrade.added, upgrade.updated), (1, 1));
+        assert_eq!(data.system_formula_version, SYSTEM_FORMULA_VERSION);
+        assert_eq!(data.formulas.len(), seed.len() + 1);
+
+        let refreshed = data.formulas.iter().find(|f| f.id == kept_id).unwrap();
+        assert_eq!(refreshed.latex_content, seed[0].latex_content);
+        assert!(refreshed.is_favorite);
+        assert_eq!(refreshed.usage_count, 7);
+        assert_eq!(data.formulas[1].system_key, seed[1].system_key);
+        assert!(data
+            .formulas
+            .iter()
+            .any(|f| f.system_key == newest.system_key));
+        let user = data.formulas.iter().find(|f| f.id == mine.id).unwrap();
+        assert_eq!(
+            (&user.latex_content, &user.tags),
+            (&mine.latex_content, &mine.tags)
+        );
+
+        // Running the merge again adds and changes nothing
+        let again = FormulaManager::merge_system_formulas(&mut data, seed.clone());
+        assert_eq!((again.added, again.updated), (0, 0));
+        assert_eq!(data.formulas.len(), seed.len() + 1);
+    }
+
+    #[test]
+    fn an_older_library_is_upgraded_on_load() {
+        let seed = FormulaManager::seed_system_formulas();
+        let (_dir, manager) = manager_with(seed[1..].to_vec());
+        let path = manager.path.clone();
+        let mut data: FormulaData =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        data.system_formula_version = 1;
+        fs::write(&path, serde_json::to_string(&data).unwrap()).unwrap();
+
+        let upgraded = FormulaManager::new(path.clone());
+        assert_eq!(upgraded.get_all().len(), seed.len());
+        let saved: FormulaData = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved.system_formula_version, SYSTEM_FORMULA_VERSION);
+    }
+
+    fn builtin(name: &str, latex: &str, category: &str, subcategory: Option<&str>) -> Formula {
+        let mut formula = Formula::new_user(name, latex, category);
+        formula.subcategory = subcategory.map(str::to_string);
+        formula.is_system = true;
+        formula
+    }
+
+    fn sheet_library() -> (tempfile::TempDir, FormulaManager) {
+        let mut binomial = builtin(
+            "Binomial <n & k>",
+            "\\binom{n}{k}",
+            "algebra",
+            Some("counting"),
+        );
+        binomial.description = Some("Ways to pick k of n".to_string());
+        manager_with(vec![
+            binomial,
+            builtin("a*b_c", "a \\cdot b", "algebra", None),
+            builtin("Energy", "E = mc^2", "physics", None),
+            builtin("Derivative", "f'(x)", "calculus", None),
+            Formula::new_user("Mine", "x", "algebra"),
+        ])
+    }
+
+    #[test]
+    fn html_sheet_lists_requested_categories_escaped() {
+        let (_dir, manager) = sheet_library();
+        let categories = ["algebra".to_string(), "physics".to_string()];
+        let groups = manager.grouped_by_category(true, Some(&categories));
+        let html = render_formula_sheet(&groups, SheetFormat::Html);
+
+        assert!(html.contains("<h2>algebra</h2>"));
+        assert!(html.contains("<h3>counting</h3>"));
+        assert!(html.contains("<h2>physics</h2>"));
+        assert!(!html.contains("calculus"));
+        // User formulas stay off the built-in reference
+        assert!(!html.contains("Mine"));
+        assert!(html.contains("<dt>Binomial &lt;n &amp; k&gt;</dt>"));
+        assert!(html.contains("<dd>Ways to pick k of n</dd>"));
+        assert!(html.contains("<dd><code>\\binom{n}{k}</code></dd>"));
+        assert!(html.find("<h2>algebra</h2>") < html.find("<h2>physics</h2>"));
+    }
+
+    #[test]
+    fn markdown_sheet_escapes_formatting_characters() {
+        let (_dir, manager) = sheet_library();
+        let groups = manager.grouped_by_category(true, None);
+        let markdown = render_formula_sheet(&groups, SheetFormat::Markdown);
+
+        for category in ["## algebra", "## calculus", "## physics", "### counting"] {
+            assert!(markdown.contains(category), "{}", category);
+        }
+        assert!(markdown.contains("**a\\*b\\_c**"));
+        assert!(markdown.contains("**Binomial \\<n & k\\>** — Ways to pick k of n"));
+        // LaTeX sits in a code block, so it is left as written
+        assert!(markdown.contains("```latex\na \\cdot b\n```"));
+    }
+
+    #[test]
+    fn a_backup_taken_before_an_overwriting_import_restores_the_library() {
+        let (_dir, mut manager, incoming) = import_fixture();
+        let newton = |m: &FormulaManager| {
+            m.get_all()
+                .into_iter()
+                .find(|f| f.name == "Newton's Second Law")
+                .unwrap()
+                .latex_content
+        };
+
+        let name = manager.backup().unwrap();
+        let resolutions = HashMap::from([(
+            "Newton's Second Law".to_string(),
+            ConflictResolution::Overwrite,
+        )]);
+        manager.apply_import(incoming, &resolutions);
+        assert_eq!(newton(&manager), "F = \\frac{dp}{dt}");
+
+        let backups = manager.list_backups();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].name, name);
+        assert_eq!(backups[0].formula_count, 2);
+
+        // Backup names have millisecond resolution
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_eq!(manager.restore_backup(&name), Ok(2));
+        assert_eq!(newton(&manager), "F = ma");
+        // The restore backed up the imported state first, so it can be undone too
+        assert_eq!(manager.list_backups().len(), 2);
+        assert!(manager.restore_backup("formulas-missing.json").is_err());
+    }
+
+    #[test]
+    fn only_the_newest_backups_are_kept() {
+        let (_dir, manager) = manager_with(vec![Formula::new_user("A", "a", "math")]);
+        let mut names = vec![];
+        for _ in 0..MAX_FORMULA_BACKUPS + 2 {
+            names.push(manager.backup().unwrap());
+            // Backup names have millisecond resolution
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+
+        let kept: Vec<String> = manager.list_backups().into_iter().map(|b| b.name).collect();
+        names.reverse();
+        names.truncate(MAX_FORMULA_BACKUPS);
+        assert_eq!(kept, names);
+    }
+
+    #[test]
+    fn smart_rank_weighs_favorites_recency_and_usage() {
+        let now = chrono::Utc::now();
+        let formula = |name: &str, favorite: bool, used_days_ago: Option<i64>, usage: u32| {
+            let mut formula = Formula::new_user(name, "x", "algebra");
+            formula.is_favorite = favorite;
+            formula.usage_count = usage;
+            formula.last_used_at =
+                used_days_ago.map(|days| (now - chrono::Duration::days(days)).to_rfc3339());
+            formula
+        };
+        let mut deleted = formula("Zeta", true, Some(0), 500);
+        deleted.deleted_at = Some(now.to_rfc3339());
+        let (_dir, manager) = manager_with(vec![
+            formula("Echo", false, None, 0),
+            formula("Gamma", false, Some(7), 100),
+            formula("delta", false, None, 0),
+            formula("Beta", false, Some(0), 1),
+            formula("Alpha", true, None, 0),
+            deleted,
+        ]);
+        let names = |weights: FormulaRankWeights| -> Vec<String> {
+            manager
+                .smart_rank(&weights, now)
+                .into_iter()
+                .map(|f| f.name)
+                .collect()
+        };
+
+        // Favorite 3.0; Beta 2.0 + ~0.15; Gamma one half-life old, 1.0 + 1.0
+        assert_eq!(
+            names(FormulaRankWeights::default()),
+            ["Alpha", "Beta", "Gamma", "delta", "Echo"]
+        );
+        let usage_only = FormulaRankWeights {
+            favorite: 0.0,
+            recency: 0.0,
+            usage: 1.0,
+        };
+        assert_eq!(
+            names(usage_only),
+            ["Gamma", "Beta", "Alpha", "delta", "Echo"]
+        );
+        let recency_only = FormulaRankWeights {
+            favorite: 0.0,
+            recency: 1.0,
+            usage: 0.0,
+        };
+        assert_eq!(
+            names(recency_only),
+            ["Beta", "Gamma", "Alpha", "delta", "Echo"]
+        );
+    }
+}
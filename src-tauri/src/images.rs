@@ -0,0 +1,483 @@
+use crate::errors::AppError;
+use crate::paths::{expand_path, path_key};
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::ops::Range;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageStatus {
+    Exists,
+    Missing,
+    Remote,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ImageRef {
+    pub source: String,
+    pub resolved_path: String,
+    pub line: usize,
+    pub status: ImageStatus,
+}
+
+/// An image reference as written in the document, with the byte range of its path
+#[derive(Debug, Clone)]
+pub struct ImageReference {
+    pub source: String,
+    pub span: Range<usize>,
+    pub line: usize,
+}
+
+/// Images larger than this are left as file references when inlining
+const DEFAULT_INLINE_MAX_BYTES: u64 = 2 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct InlineImagesResult {
+    pub content: String,
+    pub inlined: usize,
+    pub warnings: Vec<String>,
+}
+
+/// Find image paths in Markdown `![alt](path)`, LML `@figure(src: path)`,
+/// and HTML `<img src="path">` syntax
+pub fn find_image_references(content: &str) -> Vec<ImageReference> {
+    let mut refs = vec![];
+
+    // Markdown images
+    let mut search_from = 0;
+    while let Some(pos) = content[search_from..].find("![") {
+        let start = search_from + pos;
+        search_from = start + 2;
+        let Some(close) = content[search_from..].find("](") else {
+            break;
+        };
+        let path_start = search_from + close + 2;
+        let Some(end) = content[path_start..].find(')') else {
+            break;
+        };
+        let inner = &content[path_start..path_start + end];
+
+        // Strip an optional title ("...") and angle brackets around the path
+        let trimmed_start = inner.len() - inner.trim_start().len();
+        let path_part = inner.trim_start();
+        let (offset, path) = if let Some(rest) = path_part.strip_prefix('<') {
+            (1, rest.split('>').next().unwrap_or(""))
+        } else {
+            (0, path_part.split_whitespace().next().unwrap_or(""))
+        };
+        if !path.is_empty() {
+            let begin = path_start + trimmed_start + offset;
+            refs.push(ImageReference {
+                source: path.to_string(),
+                span: begin..begin + path.len(),
+                line: line_of(content, begin),
+            });
+        }
+        search_from = path_start + end + 1;
+    }
+
+    // LML figure blocks
+    let mut search_from = 0;
+    while let Some(pos) = content[search_from..].find("@figure(") {
+        let params_start = search_from + pos + "@figure(".len();
+        let Some(end) = content[params_start..].find(')') else {
+            break;
+        };
+        let params = &content[params_start..params_start + end];
+        let mut offset = 0;
+        for param in params.split(',') {
+            let leading = param.len() - param.trim_start().len();
+            if let Some(value) = param.trim_start().strip_prefix("src:") {
+                let value_leading = value.len() - value.trim_start().len();
+                let path = value.trim();
+                if !path.is_empty() {
+                    let begin =
+                        params_start + offset + leading + "src:".len() + value_leading;
+                    refs.push(ImageReference {
+                        source: path.to_string(),
+                        span: begin..begin + path.len(),
+                        line: line_of(content, begin),
+                    });
+                }
+            }
+            offset += param.len() + 1;
+        }
+        search_from = params_start + end + 1;
+    }
+
+    // HTML img tags (rendered export output)
+    let mut search_from = 0;
+    while let Some(pos) = content[search_from..].find("<img") {
+        let tag_start = search_from + pos;
+        let Some(end) = content[tag_start..].find('>') else {
+            break;
+        };
+        let tag = &content[tag_start..tag_start + end];
+        if let Some(src_pos) = tag.find("src=") {
+            let value_start = src_pos + "src=".len();
+            let quote = tag[value_start..]
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'');
+            if let Some(quote) = quote {
+                let path_start = value_start + 1;
+                if let Some(len) = tag[path_start..].find(quote) {
+                    let begin = tag_start + path_start;
+                    refs.push(ImageReference {
+                        source: content[begin..begin + len].to_string(),
+                        span: begin..begin + len,
+                        line: line_of(content, begin),
+                    });
+                }
+            }
+        }
+        search_from = tag_start + end + 1;
+    }
+
+    refs.sort_by_key(|r| r.span.start);
+    refs
+}
+
+/// Whether a reference points somewhere other than the local filesystem
+pub fn is_remote(source: &str) -> bool {
+    let lower = source.to_ascii_lowercase();
+    lower.starts_with("http://")
+        || lower.starts_with("https://")
+        || lower.starts_with("data:")
+        || lower.starts_with("//")
+}
+
+/// Resolve a local image reference against the document's directory
+pub fn resolve_image_path(source: &str, base_dir: &Path) -> PathBuf {
+    let source = source.strip_prefix("file://").unwrap_or(source);
+    let expanded = expand_path(source);
+    let joined = if expanded.is_absolute() {
+        expanded
+    } else {
+        base_dir.join(expanded)
+    };
+    std::path::absolute(&joined).unwrap_or(joined)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AssetPath {
+    /// Relative to the document's directory with `/` separators, or absolute
+    /// when no relative path exists
+    pub path: String,
+    pub warning: Option<String>,
+}
+
+/// Components of an absolute path with `.` and `..` resolved lexically
+fn normalized_components(path: &Path) -> Vec<Component<'_>> {
+    let mut components = vec![];
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(components.last(), Some(Component::Normal(_))) {
+                    components.pop();
+                }
+            }
+            _ => components.push(component),
+        }
+    }
+    components
+}
+
+/// `/`-separated path from `from_dir` to `to`, both absolute. `None` when they
+/// don't share a root, e.g. different drives on Windows.
+pub fn relative_path(from_dir: &Path, to: &Path) -> Option<String> {
+    let from = normalized_components(from_dir);
+    let to = normalized_components(to);
+    let key = |c: &Component| path_key(&c.as_os_str().to_string_lossy());
+    let is_root = |c: &Component| matches!(c, Component::Prefix(_) | Component::RootDir);
+
+    let root = |components: &[Component<'_>]| -> Vec<String> {
+        components.iter().take_while(|c| is_root(c)).map(key).collect()
+    };
+    let from_root = root(&from);
+    if from_root.is_empty() || from_root != root(&to) {
+        return None;
+    }
+
+    let common = from.iter().zip(&to).take_while(|(a, b)| key(a) == key(b)).count();
+    let parts: Vec<String> = std::iter::repeat_n("..".to_string(), from.len() - common)
+        .chain(to[common..].iter().map(|c| c.as_os_str().to_string_lossy().to_string()))
+        .collect();
+    Some(if parts.is_empty() { ".".to_string() } else { parts.join("/") })
+}
+
+/// Detect an image MIME type from magic bytes, falling back to the extension
+pub fn detect_image_mime(bytes: &[u8], path: &Path) -> Option<&'static str> {
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        return Some("image/png");
+    }
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
+    }
+    if bytes.starts_with(b"GIF8") {
+        return Some("image/gif");
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    if bytes.starts_with(b"BM") {
+        return Some("image/bmp");
+    }
+
+    let ext = path.extension()?.to_str()?.to_ascii_lowercase();
+    match ext.as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "bmp" => Some("image/bmp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+fn line_of(content: &str, byte_pos: usize) -> usize {
+    content[..byte_pos].matches('\n').count() + 1
+}
+
+#[tauri::command]
+pub fn validate_image_references(content: String, base_dir: String) -> Vec<ImageRef> {
+    let base = expand_path(&base_dir);
+    find_image_references(&content)
+        .into_iter()
+        .map(|r| {
+            if is_remote(&r.source) {
+                return ImageRef {
+                    resolved_path: r.source.clone(),
+                    source: r.source,
+                    line: r.line,
+                    status: ImageStatus::Remote,
+                };
+            }
+            let resolved = resolve_image_path(&r.source, &base);
+            let status = if resolved.is_file() {
+                ImageStatus::Exists
+            } else {
+                ImageStatus::Missing
+            };
+            ImageRef {
+                source: r.source,
+                resolved_path: resolved.to_string_lossy().to_string(),
+                line: r.line,
+                status,
+            }
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn inline_images(
+    content: String,
+    base_dir: String,
+    max_image_bytes: Option<u64>,
+) -> Result<InlineImagesResult, String> {
+    let base = expand_path(&base_dir);
+    let max_bytes = max_image_bytes.unwrap_or(DEFAULT_INLINE_MAX_BYTES);
+
+    let mut output = String::with_capacity(content.len());
+    let mut warnings = vec![];
+    let mut inlined = 0;
+    let mut cursor = 0;
+
+    for reference in find_image_references(&content) {
+        if is_remote(&reference.source) {
+            continue;
+        }
+
+        let path = resolve_image_path(&reference.source, &base);
+        let size = match fs::metadata(&path) {
+            Ok(m) => m.len(),
+            Err(_) => {
+                warnings.push(format!("Image not found: {}", reference.source));
+                continue;
+            }
+        };
+        if size > max_bytes {
+            warnings.push(format!(
+                "Image {} is {} KB, over the {} KB limit; left as a file reference",
+                reference.source,
+                size / 1024,
+                max_bytes / 1024
+            ));
+            continue;
+        }
+
+        let bytes = fs::read(&path).map_err(|e| format!("Failed to read image: {}", e))?;
+        let Some(mime) = detect_image_mime(&bytes, &path) else {
+            warnings.push(format!("Unrecognized image type: {}", reference.source));
+            continue;
+        };
+
+        output.push_str(&content[cursor..reference.span.start]);
+        output.push_str(&format!(
+            "data:{};base64,{}",
+            mime,
+            base64::engine::general_purpose::STANDARD.encode(&bytes)
+        ));
+        cursor = reference.span.end;
+        inlined += 1;
+    }
+    output.push_str(&content[cursor..]);
+
+    Ok(InlineImagesResult {
+        content: output,
+        inlined,
+        warnings,
+    })
+}
+
+/// Path to reference an asset by from a document, for portable image links
+#[tauri::command]
+pub fn relative_asset_path(
+    document_path: String,
+    asset_path: String,
+) -> Result<AssetPath, AppError> {
+    let document = std::path::absolute(expand_path(&document_path))
+        .map_err(|e| AppError::Validation(format!("Invalid document path: {}", e)))?;
+    let base_dir = document.parent().ok_or_else(|| {
+        AppError::Validation(format!("Document has no parent directory: {}", document_path))
+    })?;
+    let asset = std::path::absolute(expand_path(&asset_path))
+        .map_err(|e| AppError::Validation(format!("Invalid asset path: {}", e)))?;
+
+    Ok(match relative_path(base_dir, &asset) {
+        Some(path) => AssetPath {
+            path,
+            warning: None,
+        },
+        None => AssetPath {
+            path: asset.to_string_lossy().replace('\\', "/"),
+            warning: Some(format!(
+                "{} is on a different drive than the document; using an absolute path",
+                asset_path
+            )),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_present_and_missing_images() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("img")).unwrap();
+        fs::write(dir.path().join("img/plot.png"), [0x89, b'P', b'N', b'G']).unwrap();
+        let content = "# Results\n\
+                       ![Plot](img/plot.png \"The plot\")\n\
+                       @figure(src: img/missing.svg, caption: Setup)\n\
+                       ![Logo](https://example.com/logo.png)\n";
+
+        let refs = validate_image_references(
+            content.to_string(),
+            dir.path().to_string_lossy().to_string(),
+        );
+        assert_eq!(refs.len(), 3);
+
+        assert_eq!(refs[0].source, "img/plot.png");
+        assert_eq!(refs[0].line, 2);
+        assert_eq!(refs[0].status, ImageStatus::Exists);
+        assert_eq!(
+            PathBuf::from(&refs[0].resolved_path),
+            dir.path().join("img/plot.png")
+        );
+
+        assert_eq!(refs[1].source, "img/missing.svg");
+        assert_eq!(refs[1].line, 3);
+        assert_eq!(refs[1].status, ImageStatus::Missing);
+        assert!(Path::new(&refs[1].resolved_path).is_absolute());
+
+        assert_eq!(refs[2].status, ImageStatus::Remote);
+        assert_eq!(refs[2].resolved_path, "https://example.com/logo.png");
+    }
+
+    #[test]
+    fn inlines_local_images_and_skips_remote_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let png = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+        fs::write(dir.path().join("dot.png"), png).unwrap();
+        let content = "![Dot](dot.png)\n![Logo](https://example.com/logo.png)\n";
+
+        let result = inline_images(
+            content.to_string(),
+            dir.path().to_string_lossy().to_string(),
+            None,
+        )
+        .unwrap();
+
+        let encoded = base64::engine::general_purpose::STANDARD.encode(png);
+        assert_eq!(
+            result.content,
+            format!(
+                "![Dot](data:image/png;base64,{})\n![Logo](https://example.com/logo.png)\n",
+                encoded
+            )
+        );
+        assert_eq!(result.inlined, 1);
+        assert!(result.warnings.is_empty());
+    }
+
+    #[test]
+    fn leaves_oversized_and_missing_images_as_references() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("big.png"), vec![0u8; 2048]).unwrap();
+        let content = "![Big](big.png) ![Gone](gone.png)";
+
+        let result = inline_images(
+            content.to_string(),
+            dir.path().to_string_lossy().to_string(),
+            Some(1024),
+        )
+        .unwrap();
+
+        assert_eq!(result.content, content);
+        assert_eq!(result.inlined, 0);
+        assert_eq!(result.warnings.len(), 2);
+        assert!(result.warnings[0].contains("over the 1 KB limit"));
+        assert!(result.warnings[1].contains("Image not found: gone.png"));
+    }
+
+    fn relative(document: &str, asset: &str) -> String {
+        let result = relative_asset_path(document.to_string(), asset.to_string()).unwrap();
+        assert!(result.warning.is_none());
+        result.path
+    }
+
+    #[test]
+    fn asset_paths_are_relative_to_the_document() {
+        let doc = "/work/thesis/chapter.lml";
+        assert_eq!(relative(doc, "/work/thesis/plot.png"), "plot.png");
+        assert_eq!(
+            relative(doc, "/work/thesis/img/fig/plot.png"),
+            "img/fig/plot.png"
+        );
+        assert_eq!(relative(doc, "/work/shared/logo.svg"), "../shared/logo.svg");
+        assert_eq!(relative(doc, "/logo.svg"), "../../logo.svg");
+        // Dot segments are resolved before comparing
+        assert_eq!(relative(doc, "/work/thesis/./img/../plot.png"), "plot.png");
+        assert_eq!(relative(doc, "/work/thesis"), ".");
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn assets_on_another_drive_stay_absolute() {
+        let result =
+            relative_asset_path("C:\\docs\\a.lml".to_string(), "D:\\img\\b.png".to_string())
+                .unwrap();
+        assert_eq!(result.path, "D:/img/b.png");
+        assert!(result.warning.unwrap().contains("different drive"));
+        assert_eq!(
+            relative("C:\\docs\\a.lml", "C:\\docs\\img\\b.png"),
+            "img/b.png"
+        );
+    }
+}
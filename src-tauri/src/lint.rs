@@ -0,0 +1,243 @@
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use tauri::State;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    Info,
+    Warning,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LintRule {
+    TrailingWhitespace,
+    MixedIndentation,
+    LongLine,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LintIssue {
+    /// 1-based
+    pub line: usize,
+    pub rule: LintRule,
+    pub severity: LintSeverity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Indent {
+    None,
+    Spaces,
+    Tabs,
+    Mixed,
+}
+
+fn leading_whitespace(line: &str) -> &str {
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+fn indent_kind(line: &str) -> Indent {
+    let indent = leading_whitespace(line);
+    match (indent.contains(' '), indent.contains('\t')) {
+        (false, false) => Indent::None,
+        (true, false) => Indent::Spaces,
+        (false, true) => Indent::Tabs,
+        (true, true) => Indent::Mixed,
+    }
+}
+
+/// Whether tab indentation outnumbers space indentation across the document
+fn prefers_tabs(lines: &[&str]) -> bool {
+    let (tabs, spaces) = lines
+        .iter()
+        .fold((0, 0), |(t, s), line| match indent_kind(line) {
+            Indent::Tabs => (t + 1, s),
+            Indent::Spaces => (t, s + 1),
+            _ => (t, s),
+        });
+    tabs > spaces
+}
+
+/// Report trailing whitespace, indentation that mixes tabs and spaces (within a
+/// line, or against the document's dominant style), and lines longer than
+/// `max_line_length` characters. A `max_line_length` of 0 disables that check.
+pub fn lint_content(content: &str, max_line_length: usize) -> Vec<LintIssue> {
+    let lines: Vec<&str> = content.lines().collect();
+    let tabs = prefers_tabs(&lines);
+    let mut issues = vec![];
+
+    for (index, line) in lines.iter().enumerate() {
+        let line_number = index + 1;
+        let mut report = |rule, severity, message: String| {
+            issues.push(LintIssue {
+                line: line_number,
+                rule,
+                severity,
+                message,
+            })
+        };
+
+        if line.trim_end() != *line && !line.trim().is_empty() {
+            report(
+                LintRule::TrailingWhitespace,
+                LintSeverity::Warning,
+                "Trailing whitespace".to_string(),
+            );
+        } else if line.trim().is_empty() && !line.is_empty() {
+            report(
+                LintRule::TrailingWhitespace,
+                LintSeverity::Info,
+                "Whitespace-only line".to_string(),
+            );
+        }
+
+        match indent_kind(line) {
+            Indent::Mixed if !line.trim().is_empty() => report(
+                LintRule::MixedIndentation,
+                LintSeverity::Warning,
+                "Indentation mixes tabs and spaces".to_string(),
+            ),
+            Indent::Spaces if tabs => report(
+                LintRule::MixedIndentation,
+                LintSeverity::Info,
+                "Indented with spaces; the rest of the document uses tabs".to_string(),
+            ),
+            Indent::Tabs if !tabs => report(
+                LintRule::MixedIndentation,
+                LintSeverity::Info,
+                "Indented with tabs; the rest of the document uses spaces".to_string(),
+            ),
+            _ => {}
+        }
+
+        let length = line.chars().count();
+        if max_line_length > 0 && length > max_line_length {
+            report(
+                LintRule::LongLine,
+                LintSeverity::Info,
+                format!(
+                    "Line is {} characters long (limit {})",
+                    length, max_line_length
+                ),
+            );
+        }
+    }
+    issues
+}
+
+/// Strip trailing whitespace and rewrite indentation in the document's dominant
+/// style, expanding tabs to `tab_size` columns. Line endings are preserved.
+pub fn autofix_content(content: &str, tab_size: usize) -> String {
+    let tab_size = tab_size.max(1);
+    let tabs = prefers_tabs(&content.lines().collect::<Vec<_>>());
+    let line_ending = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    content
+        .split('\n')
+        .map(|line| {
+            let line = line.trim_end();
+            let indent = leading_whitespace(line);
+            let width = indent.chars().fold(0, |col, c| match c {
+                '\t' => col + tab_size - col % tab_size,
+                _ => col + 1,
+            });
+            let indent = if tabs {
+                format!(
+                    "{}{}",
+                    "\t".repeat(width / tab_size),
+                    " ".repeat(width % tab_size)
+                )
+            } else {
+                " ".repeat(width)
+            };
+            format!("{}{}", indent, line.trim_start_matches([' ', '\t']))
+        })
+        .collect::<Vec<_>>()
+        .join(line_ending)
+}
+
+/// Lint using the `lint_max_line_length` setting
+#[tauri::command]
+pub fn lint_document(content: String, state: State<AppState>) -> Vec<LintIssue> {
+    let max_line_length = state
+        .settings
+        .lock()
+        .unwrap()
+        .get_settings()
+        .lint_max_line_length;
+    lint_content(&content, max_line_length as usize)
+}
+
+/// Fix trailing whitespace and indentation, expanding tabs by the `tab_size` setting
+#[tauri::command]
+pub fn autofix_document(content: String, state: State<AppState>) -> String {
+    let tab_size = state.settings.lock().unwrap().get_settings().tab_size;
+    autofix_content(&content, tab_size as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(issues: &[LintIssue]) -> Vec<(usize, LintRule, LintSeverity)> {
+        issues
+            .iter()
+            .map(|i| (i.line, i.rule, i.severity))
+            .collect()
+    }
+
+    #[test]
+    fn reports_trailing_whitespace() {
+        let issues = lint_content("clean\ntrailing  \n   \n\nend\t", 0);
+        assert_eq!(
+            summary(&issues),
+            [
+                (2, LintRule::TrailingWhitespace, LintSeverity::Warning),
+                (3, LintRule::TrailingWhitespace, LintSeverity::Info),
+                (5, LintRule::TrailingWhitespace, LintSeverity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_mixed_indentation() {
+        let content = "list:\n    one\n    two\n\tthree\n \tfour";
+        assert_eq!(
+            summary(&lint_content(content, 0)),
+            [
+                (4, LintRule::MixedIndentation, LintSeverity::Info),
+                (5, LintRule::MixedIndentation, LintSeverity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_long_lines_against_the_threshold() {
+        let content = format!("{}\n{}\n", "a".repeat(80), "é".repeat(81));
+        let issues = lint_content(&content, 80);
+        assert_eq!(
+            summary(&issues),
+            [(2, LintRule::LongLine, LintSeverity::Info)]
+        );
+        assert_eq!(issues[0].message, "Line is 81 characters long (limit 80)");
+        assert!(lint_content(&content, 0).is_empty());
+    }
+
+    #[test]
+    fn autofix_strips_trailing_whitespace_and_normalizes_indentation() {
+        let spaces = "a  \n    b\n\tc\n  \t d \n";
+        assert_eq!(autofix_content(spaces, 4), "a\n    b\n    c\n     d\n");
+
+        let tabs = "\tone\n\ttwo\n      three\r\n";
+        assert_eq!(autofix_content(tabs, 4), "\tone\r\n\ttwo\r\n\t  three\r\n");
+
+        let fixed = autofix_content(spaces, 4);
+        assert!(lint_content(&fixed, 0).is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::errors::AppError;
+use crate::paths::expand_path;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct LockToken {
+    pub id: String,
+    pub document_path: String,
+    pub pid: u32,
+    pub acquired_at: String,
+}
+
+/// Sidecar lock file next to the document, e.g. `notes.lml.lock`
+fn lock_path(document_path: &Path) -> PathBuf {
+    let mut name = document_path
+        .file_name()
+        .map(|n| n.to_os_string())
+        .unwrap_or_default();
+    name.push(".lock");
+    document_path.with_file_name(name)
+}
+
+fn read_lock(path: &Path) -> Option<LockToken> {
+    let content = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Lock files are written immediately after creation, so an unreadable one
+/// that is more than a few seconds old was left behind by a crash
+fn lock_file_settled(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.elapsed().ok())
+        .is_none_or(|age| age.as_secs() >= 5)
+}
+
+/// Whether a process with the given PID is still running
+fn process_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        Path::new("/proc").join(pid.to_string()).exists()
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        std::process::Command::new("ps")
+            .args(["-p", &pid.to_string()])
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(true)
+    }
+
+    #[cfg(windows)]
+    {
+        std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH"])
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).contains(&pid.to_string()))
+            .unwrap_or(true)
+    }
+}
+
+#[tauri::command]
+pub fn acquire_document_lock(path: String) -> Result<LockToken, AppError> {
+    let document_path = expand_path(&path);
+    let lock_file = lock_path(&document_path);
+
+    let token = LockToken {
+        id: uuid::Uuid::new_v4().to_string(),
+        document_path: document_path.to_string_lossy().to_string(),
+        pid: std::process::id(),
+        acquired_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let content = serde_json::to_string_pretty(&token)?;
+
+    // Two attempts: the second only after reclaiming a stale lock
+    for _ in 0..2 {
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_file)
+        {
+            Ok(mut file) => {
+                file.write_all(content.as_bytes())
+                    .map_err(|e| AppError::io("Failed to write lock file", e))?;
+                return Ok(token);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match read_lock(&lock_file) {
+                Some(holder) if process_alive(holder.pid) => {
+                    return Err(AppError::Locked(format!(
+                        "Document is being edited by process {} since {}",
+                        holder.pid, holder.acquired_at
+                    )));
+                }
+                // Another instance may still be writing a fresh lock file
+                None if !lock_file_settled(&lock_file) => {
+                    return Err(AppError::Locked(
+                        "Document is being opened by another instance".to_string(),
+                    ));
+                }
+                // Dead holder or corrupt lock file: reclaim it
+                _ => {
+                    fs::remove_file(&lock_file)
+                        .map_err(|e| AppError::io("Failed to remove stale lock", e))?;
+                }
+            },
+            Err(e) => return Err(AppError::io("Failed to create lock file", e)),
+        }
+    }
+
+    Err(AppError::Locked(
+        "Document lock was taken by another instance".to_string(),
+    ))
+}
+
+#[tauri::command]
+pub fn release_document_lock(token: LockToken) -> Result<(), AppError> {
+    let lock_file = lock_path(Path::new(&token.document_path));
+
+    match read_lock(&lock_file) {
+        Some(holder) if holder.id == token.id => {
+            fs::remove_file(&lock_file).map_err(|e| AppError::io("Failed to remove lock file", e))
+        }
+        Some(_) => Err(AppError::Locked(
+            "Lock is held by a different token".to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(dir: &Path) -> String {
+        dir.join("notes.lml").to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn acquire_and_release() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = acquire_document_lock(document(dir.path())).unwrap();
+        let lock_file = dir.path().join("notes.lml.lock");
+        assert_eq!(token.pid, std::process::id());
+        assert_eq!(read_lock(&lock_file).unwrap().id, token.id);
+
+        // A live holder (this process) keeps the lock
+        let err = acquire_document_lock(document(dir.path())).unwrap_err();
+        assert!(matches!(err, AppError::Locked(_)), "{:?}", err);
+
+        release_document_lock(token.clone()).unwrap();
+        assert!(!lock_file.exists());
+        // Releasing twice is harmless
+        release_document_lock(token).unwrap();
+    }
+
+    #[test]
+    fn reclaims_lock_from_dead_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let stale = LockToken {
+            id: "stale".to_string(),
+            document_path: document(dir.path()),
+            pid: u32::MAX,
+            acquired_at: "2024-01-01T00:00:00+00:00".to_string(),
+        };
+        let lock_file = dir.path().join("notes.lml.lock");
+        fs::write(&lock_file, serde_json::to_string(&stale).unwrap()).unwrap();
+
+        let token = acquire_document_lock(document(dir.path())).unwrap();
+        assert_ne!(token.id, "stale");
+        assert_eq!(read_lock(&lock_file).unwrap().id, token.id);
+    }
+
+    #[test]
+    fn corrupt_lock_is_reclaimed_only_once_settled() {
+        let dir = tempfile::tempdir().unwrap();
+        let lock_file = dir.path().join("notes.lml.lock");
+        fs::write(&lock_file, "").unwrap();
+
+        // Fresh and unreadable: another instance may still be writing it
+        let err = acquire_document_lock(document(dir.path())).unwrap_err();
+        assert!(matches!(err, AppError::Locked(_)), "{:?}", err);
+
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        fs::File::options()
+            .write(true)
+            .open(&lock_file)
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        assert!(acquire_document_lock(document(dir.path())).is_ok());
+    }
+
+    #[test]
+    fn release_with_another_token_keeps_the_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let token = acquire_document_lock(document(dir.path())).unwrap();
+        let other = LockToken {
+            id: "other".to_string(),
+            ..token.clone()
+        };
+
+        assert!(release_document_lock(other).is_err());
+        assert!(dir.path().join("notes.lml.lock").exists());
+        release_document_lock(token).unwrap();
+    }
+}
@@ -1,27 +1,92 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod analysis;
+mod bib_index;
 mod bibliography;
+mod build_command;
 mod commands;
+mod debug_log;
+mod diff;
+mod document_settings;
+mod errors;
+mod export;
 mod formulas;
+mod images;
+mod lint;
+mod locks;
+mod mathml;
+mod metadata;
+mod operations;
+mod paths;
+mod quick_capture;
 mod recent_files;
+mod recovery;
+mod render_cache;
+mod search;
+mod session;
 mod settings;
+mod tables;
+mod usage_log;
+mod vacuum;
 mod versions;
+mod writing_session;
 
+use analysis::*;
+use bib_index::*;
 use bibliography::*;
+use build_command::*;
 use commands::*;
+use debug_log::*;
+use document_settings::*;
+use export::*;
 use formulas::FormulaManager;
+use images::*;
+use lint::*;
+use locks::*;
+use mathml::*;
+use operations::*;
+use quick_capture::*;
 use recent_files::RecentFilesManager;
+use recovery::*;
+use render_cache::*;
+use search::*;
+use session::*;
 use settings::SettingsManager;
+use tables::*;
+use usage_log::*;
+use vacuum::*;
 use versions::*;
+use writing_session::*;
+use std::collections::HashMap;
 use std::path::PathBuf;
-use std::sync::Mutex;
+use std::sync::{Mutex, RwLock};
 
 pub struct AppState {
     pub recent_files: Mutex<RecentFilesManager>,
     pub settings: Mutex<SettingsManager>,
     pub formulas: Mutex<FormulaManager>,
+    pub bib_cache: Mutex<BibCache>,
+    pub document_settings: Mutex<DocumentSettingsManager>,
+    pub session: Mutex<SessionManager>,
+    /// Per-document version manifest locks
+    pub manifest_locks: versions::ManifestLocks,
+    /// Held shared by version operations and exclusively while the store is relocated
+    pub version_store_lock: RwLock<()>,
+    /// Active writing sessions keyed by document path; not persisted
+    pub writing_sessions: Mutex<HashMap<String, WritingSession>>,
+    /// Formula previews keyed by a hash of mode and LaTeX
+    pub preview_cache: Mutex<HashMap<String, formulas::PreviewResult>>,
+    /// Latest analysis per document; replaced when the content hash changes
+    pub analysis_cache: Mutex<HashMap<String, DocumentAnalysis>>,
+    /// Formula LaTeX validation results keyed by a hash of the source
+    pub renderable_cache: Mutex<HashMap<String, bool>>,
+    /// Parsed .bib files for citation autocomplete
+    pub bib_index_cache: BibIndexCache,
+    /// Cancellation flags for in-flight lookups, exports, and builds
+    pub operations: CancelFlags,
     pub app_data_dir: Mutex<PathBuf>,
+    pub storage_status: paths::StorageStatus,
 }
 
 fn main() {
@@ -31,27 +96,67 @@ fn main() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_global_shortcut::Builder::new().build())
         .setup(|app| {
-            use tauri::Manager;
+            use tauri::{Emitter, Manager};
 
-            let app_dir = app
+            let preferred_dir = app
                 .path()
                 .app_data_dir()
                 .expect("Failed to get app data dir");
 
-            // Ensure app directory exists
-            std::fs::create_dir_all(&app_dir).ok();
+            // Ensure app directory exists and is writable, falling back to a temp dir
+            let (app_dir, storage_status) = paths::resolve_app_data_dir(&preferred_dir);
+            if storage_status.warning.is_some() {
+                app.handle().emit("storage-warning", storage_status.clone()).ok();
+            }
 
             let recent_files = RecentFilesManager::new(app_dir.join("recent_files.json"));
-            let settings = SettingsManager::new(app_dir.join("settings.json"));
+            let mut settings = SettingsManager::new(app_dir.join("settings.json"));
             let formulas = FormulaManager::new(app_dir.join("formulas.json"));
+            let bib_cache = BibCache::new(app_dir.join("bib_cache.json"));
+            let document_settings =
+                DocumentSettingsManager::new(app_dir.join("document_settings.json"));
+            let session = SessionManager::new(app_dir.join("session.json"));
+
+            for error in [settings.load_error(), formulas.load_error()].into_iter().flatten() {
+                app.handle().emit("data-file-error", error.clone()).ok();
+            }
+
+            let max_cache_bytes = render_cache_max_bytes(&settings.get_settings());
+            evict_render_cache(&render_cache_dir(&app_dir), max_cache_bytes);
+            let quick_capture_shortcut = settings.get_settings().quick_capture_shortcut;
+
+            // Windows from the config exist by now; forget state saved for any others
+            let active_labels: Vec<String> = app.webview_windows().into_keys().collect();
+            if settings.prune_window_states(&active_labels) > 0 {
+                settings.save().ok();
+            }
 
             app.manage(AppState {
                 recent_files: Mutex::new(recent_files),
                 settings: Mutex::new(settings),
                 formulas: Mutex::new(formulas),
+                bib_cache: Mutex::new(bib_cache),
+                document_settings: Mutex::new(document_settings),
+                session: Mutex::new(session),
+                manifest_locks: Mutex::new(HashMap::new()),
+                version_store_lock: RwLock::new(()),
+                writing_sessions: Mutex::new(HashMap::new()),
+                preview_cache: Mutex::new(HashMap::new()),
+                analysis_cache: Mutex::new(HashMap::new()),
+                renderable_cache: Mutex::new(HashMap::new()),
+                bib_index_cache: Mutex::new(HashMap::new()),
+                operations: Mutex::new(HashMap::new()),
                 app_data_dir: Mutex::new(app_dir.clone()),
+                storage_status,
             });
 
+            // Restore the quick-capture shortcut saved from a previous run
+            if let Some(accelerator) = quick_capture_shortcut {
+                if let Err(e) = bind_quick_capture(app.handle(), &accelerator) {
+                    app.handle().emit("quick-capture-error", e).ok();
+                }
+            }
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -60,38 +165,153 @@ fn main() {
             write_file,
             file_exists,
             get_file_info,
+            diff_files,
+            open_document,
+            save_as,
+            detect_encoding,
+            is_text_file,
+            // Document locks
+            acquire_document_lock,
+            release_document_lock,
             // Recent files
             get_recent_files,
+            refresh_recent_files,
+            get_recent_files_grouped,
             add_recent_file,
+            repair_recent_files,
+            export_recent_files,
+            import_recent_files,
             clear_recent_files,
+            // Recovery drafts
+            write_recovery_draft,
+            get_recovery_draft,
+            // Session
+            save_session,
+            get_session,
             // Settings
             get_settings,
             update_settings,
+            toggle_line_numbers,
+            toggle_minimap,
+            toggle_word_wrap,
+            // Writing sessions
+            begin_writing_session,
+            session_stats,
+            end_writing_session,
+            // Per-document settings
+            get_document_settings,
+            get_last_export,
+            save_document_position,
+            get_document_position,
+            set_word_goal,
+            word_goal_progress,
+            // Document analysis
+            analyze_document,
             // Export
             export_to_format,
+            export_bundle,
+            export_preview,
+            get_export_formats,
+            // Custom build
+            run_build_command,
+            // Long-running operations
+            cancel_operation,
+            // Quick capture
+            register_quick_capture,
+            // Text tools
+            format_table,
+            lint_document,
+            autofix_document,
+            // Directory search
+            search_in_directory,
+            search_in_directory_streaming,
             // Image operations
             save_image,
             save_image_bytes,
+            save_document_with_assets,
+            validate_image_references,
+            inline_images,
+            relative_asset_path,
+            // External links
+            open_url,
+            // App info
+            get_app_info,
+            get_storage_status,
+            get_data_file_errors,
+            list_quarantined_files,
+            restore_quarantined_file,
+            vacuum_app_data,
+            // Diagnostics
+            get_debug_log,
+            clear_debug_log,
+            record_command_outcome,
             // Window state
             get_window_state,
             save_window_state,
+            prune_window_states,
             // Formula library
             get_formulas,
+            get_formulas_smart,
+            search_formulas,
+            search_formulas_filtered,
+            get_all_formula_tags,
+            rename_formula_category,
+            formula_stats,
+            preview_formula,
+            render_formulas_batch,
+            render_cache_stats,
+            clear_render_cache,
             create_formula,
+            import_formulas_from_latex,
+            import_formula_mathml,
+            mathml_to_latex,
+            preview_formula_import,
+            apply_formula_import,
             update_formula,
             delete_formula,
+            trash_formula,
+            restore_formula,
+            get_trashed_formulas,
+            purge_trashed_formulas,
+            list_formula_backups,
+            restore_formula_backup,
             toggle_formula_favorite,
             increment_formula_usage,
+            export_usage_log,
+            export_formulas_flashcards,
+            export_formula_sheet,
+            encode_formula_share,
+            decode_formula_share,
             // Bibliography
             read_bib_file,
+            suggest_citation_keys,
             write_bib_file,
+            serialize_bib_entries,
             lookup_doi,
+            lookup_dois,
+            import_bib_from_doi_file,
+            generate_citation_key,
             lookup_isbn,
+            validate_bib_entries,
+            merge_bib_entries,
+            format_references,
             // Version history
             create_version,
             list_versions,
+            list_versions_paged,
             restore_version,
+            get_version_content,
             delete_version,
+            tag_version,
+            clear_version_history,
+            migrate_version_history,
+            enforce_version_quota,
+            repair_version_store,
+            version_quota_status,
+            relocate_version_store,
+            export_version_changelog,
+            version_change_stats,
+            count_document_words,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,584 @@
+use std::collections::BTreeSet;
+
+/// A parsed MathML node. Namespace prefixes (`m:mfrac`) are dropped from names.
+#[derive(Debug)]
+enum Node {
+    Element {
+        name: String,
+        attrs: Vec<(String, String)>,
+        children: Vec<Node>,
+    },
+    Text(String),
+}
+
+impl Node {
+    fn attr(&self, key: &str) -> Option<&str> {
+        match self {
+            Node::Element { attrs, .. } => attrs
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str()),
+            Node::Text(_) => None,
+        }
+    }
+
+    /// Child elements, skipping whitespace between tags
+    fn elements(&self) -> Vec<&Node> {
+        match self {
+            Node::Element { children, .. } => children
+                .iter()
+                .filter(|c| matches!(c, Node::Element { .. }))
+                .collect(),
+            Node::Text(_) => vec![],
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Node::Text(text) => text.clone(),
+            Node::Element { children, .. } => children.iter().map(Node::text).collect(),
+        }
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        let after = &rest[pos + 1..];
+        let Some(end) = after.find(';') else {
+            out.push('&');
+            rest = after;
+            continue;
+        };
+        let entity = &after[..end];
+        let decoded = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix("#x")
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Just enough XML for MathML: elements, attributes, text, and entities.
+/// Comments, processing instructions, and doctypes are skipped.
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_past(&mut self, marker: &str) -> Result<(), String> {
+        let end = self
+            .rest()
+            .find(marker)
+            .ok_or_else(|| format!("Invalid MathML: missing '{}'", marker))?;
+        self.pos += end + marker.len();
+        Ok(())
+    }
+
+    fn parse_nodes(&mut self, parent: Option<&str>) -> Result<Vec<Node>, String> {
+        let mut nodes = vec![];
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return match parent {
+                    Some(name) => Err(format!("Invalid MathML: <{}> is never closed", name)),
+                    None => Ok(nodes),
+                };
+            }
+
+            if rest.starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if rest.starts_with("<!") {
+                self.skip_past(">")?;
+            } else if let Some(closing) = rest.strip_prefix("</") {
+                let end = closing
+                    .find('>')
+                    .ok_or_else(|| "Invalid MathML: unterminated closing tag".to_string())?;
+                let name = local_name(closing[..end].trim());
+                if parent.map(local_name) != Some(name) {
+                    return Err(format!("Invalid MathML: unexpected </{}>", name));
+                }
+                self.pos += 2 + end + 1;
+                return Ok(nodes);
+            } else if rest.starts_with('<') {
+                nodes.push(self.parse_element()?);
+            } else {
+                let end = rest.find('<').unwrap_or(rest.len());
+                nodes.push(Node::Text(decode_entities(&rest[..end])));
+                self.pos += end;
+            }
+        }
+    }
+
+    fn parse_element(&mut self) -> Result<Node, String> {
+        let rest = self.rest();
+        let end = rest
+            .find('>')
+            .ok_or_else(|| "Invalid MathML: unterminated tag".to_string())?;
+        let tag = &rest[1..end];
+        let self_closing = tag.ends_with('/');
+        let tag = tag.trim_end_matches('/').trim();
+        self.pos += end + 1;
+
+        let (raw_name, mut attr_text) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let mut attrs = vec![];
+        while let Some(eq) = attr_text.find('=') {
+            let key = attr_text[..eq].trim().to_string();
+            let value = attr_text[eq + 1..].trim_start();
+            let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+                return Err(format!("Invalid MathML: unquoted attribute '{}'", key));
+            };
+            let close = value[1..]
+                .find(quote)
+                .ok_or_else(|| format!("Invalid MathML: unterminated attribute '{}'", key))?;
+            attrs.push((
+                local_name(&key).to_string(),
+                decode_entities(&value[1..close + 1]),
+            ));
+            attr_text = &value[close + 2..];
+        }
+
+        let children = if self_closing {
+            vec![]
+        } else {
+            self.parse_nodes(Some(raw_name))?
+        };
+        Ok(Node::Element {
+            name: local_name(raw_name).to_string(),
+            attrs,
+            children,
+        })
+    }
+}
+
+fn local_name(name: &str) -> &str {
+    name.rsplit_once(':').map_or(name, |(_, local)| local)
+}
+
+const FUNCTION_NAMES: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "exp", "lim", "max", "min", "sup", "inf", "det", "gcd", "deg",
+];
+
+/// LaTeX for a single character that has no plain-text form
+fn symbol_command(c: char) -> Option<&'static str> {
+    Some(match c {
+        'α' => "\\alpha",
+        'β' => "\\beta",
+        'γ' => "\\gamma",
+        'δ' => "\\delta",
+        'ε' => "\\epsilon",
+        'ζ' => "\\zeta",
+        'η' => "\\eta",
+        'θ' => "\\theta",
+        'ι' => "\\iota",
+        'κ' => "\\kappa",
+        'λ' => "\\lambda",
+        'μ' => "\\mu",
+        'ν' => "\\nu",
+        'ξ' => "\\xi",
+        'π' => "\\pi",
+        'ρ' => "\\rho",
+        'σ' => "\\sigma",
+        'τ' => "\\tau",
+        'υ' => "\\upsilon",
+        'φ' => "\\phi",
+        'χ' => "\\chi",
+        'ψ' => "\\psi",
+        'ω' => "\\omega",
+        'Γ' => "\\Gamma",
+        'Δ' => "\\Delta",
+        'Θ' => "\\Theta",
+        'Λ' => "\\Lambda",
+        'Ξ' => "\\Xi",
+        'Π' => "\\Pi",
+        'Σ' => "\\Sigma",
+        'Φ' => "\\Phi",
+        'Ψ' => "\\Psi",
+        'Ω' => "\\Omega",
+        '∑' => "\\sum",
+        '∏' => "\\prod",
+        '∫' => "\\int",
+        '∮' => "\\oint",
+        '±' => "\\pm",
+        '∓' => "\\mp",
+        '×' => "\\times",
+        '÷' => "\\div",
+        '·' | '⋅' => "\\cdot",
+        '∘' => "\\circ",
+        '≤' => "\\leq",
+        '≥' => "\\geq",
+        '≠' => "\\neq",
+        '≈' => "\\approx",
+        '≡' => "\\equiv",
+        '∝' => "\\propto",
+        '∼' => "\\sim",
+        '→' => "\\to",
+        '←' => "\\leftarrow",
+        '⇒' => "\\Rightarrow",
+        '⇔' => "\\Leftrightarrow",
+        '∞' => "\\infty",
+        '∂' => "\\partial",
+        '∇' => "\\nabla",
+        '∈' => "\\in",
+        '∉' => "\\notin",
+        '⊂' => "\\subset",
+        '⊆' => "\\subseteq",
+        '∪' => "\\cup",
+        '∩' => "\\cap",
+        '∀' => "\\forall",
+        '∃' => "\\exists",
+        '∅' => "\\emptyset",
+        '¬' => "\\neg",
+        '∧' => "\\wedge",
+        '∨' => "\\vee",
+        '…' => "\\ldots",
+        '⋯' => "\\cdots",
+        'ℏ' => "\\hbar",
+        'ℓ' => "\\ell",
+        '{' => "\\{",
+        '}' => "\\}",
+        '%' => "\\%",
+        '#' => "\\#",
+        '&' => "\\&",
+        '_' => "\\_",
+        _ => return None,
+    })
+}
+
+/// Convert token text, mapping symbols to commands. Invisible operators
+/// (function application, invisible times and separator) produce nothing.
+fn token_latex(text: &str) -> String {
+    let text = text.trim();
+    if FUNCTION_NAMES.contains(&text) {
+        return format!("\\{}", text);
+    }
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '\u{2061}'..='\u{2064}' => {}
+            '−' => out.push('-'),
+            _ => match symbol_command(c) {
+                Some(command) => push_latex(&mut out, command),
+                None => push_latex(&mut out, c.encode_utf8(&mut [0; 4])),
+            },
+        }
+    }
+    out
+}
+
+/// Append LaTeX, separating a trailing control word from a following letter
+fn push_latex(out: &mut String, piece: &str) {
+    let ends_in_command = out
+        .rfind('\\')
+        .is_some_and(|i| out.len() - i > 1 && out[i + 1..].chars().all(char::is_alphabetic));
+    if ends_in_command && piece.starts_with(char::is_alphabetic) {
+        out.push(' ');
+    }
+    out.push_str(piece);
+}
+
+/// Wrap in braces unless `latex` is already a single character or command
+fn group(latex: &str) -> String {
+    let single_command =
+        latex.starts_with('\\') && latex.len() > 1 && latex[1..].chars().all(char::is_alphabetic);
+    if latex.chars().count() == 1 || single_command {
+        latex.to_string()
+    } else {
+        format!("{{{}}}", latex)
+    }
+}
+
+/// Operators whose limits go below and above rather than beside
+fn is_big_operator(latex: &str) -> bool {
+    matches!(
+        latex,
+        "\\sum" | "\\prod" | "\\int" | "\\oint" | "\\lim" | "\\max" | "\\min"
+    )
+}
+
+struct Converter {
+    unsupported: BTreeSet<String>,
+}
+
+impl Converter {
+    fn children(&mut self, node: &Node) -> String {
+        let mut out = String::new();
+        for child in node.elements() {
+            let latex = self.convert(child);
+            push_latex(&mut out, &latex);
+        }
+        out
+    }
+
+    /// Convert exactly `N` child elements, as required by fractions, scripts, and roots
+    fn args<const N: usize>(&mut self, node: &Node, name: &str) -> Option<[String; N]> {
+        let elements = node.elements();
+        if elements.len() != N {
+            self.unsupported
+                .insert(format!("{} with {} children", name, elements.len()));
+            return None;
+        }
+        Some(std::array::from_fn(|i| self.convert(elements[i])))
+    }
+
+    fn convert(&mut self, node: &Node) -> String {
+        let Node::Element { name, .. } = node else {
+            return String::new();
+        };
+        match name.as_str() {
+            "math" | "mrow" | "mstyle" | "mpadded" | "merror" | "mphantom" => self.children(node),
+            // Prefer a TeX annotation when the source provides one
+            "semantics" => {
+                let elements = node.elements();
+                let tex = elements.iter().find(|e| {
+                    e.attr("encoding")
+                        .is_some_and(|enc| enc.contains("tex") || enc.contains("latex"))
+                });
+                match (tex, elements.first()) {
+                    (Some(annotation), _) => annotation.text().trim().to_string(),
+                    (None, Some(first)) => self.convert(first),
+                    (None, None) => String::new(),
+                }
+            }
+            "annotation" | "annotation-xml" | "none" | "mprescripts" => String::new(),
+            "mi" => {
+                let text = node.text();
+                let text = text.trim();
+                if text.chars().count() > 1 && !FUNCTION_NAMES.contains(&text) {
+                    format!("\\mathrm{{{}}}", text)
+                } else {
+                    token_latex(text)
+                }
+            }
+            "mn" => node.text().trim().to_string(),
+            "mo" => token_latex(&node.text()),
+            "mtext" => {
+                let text = node.text();
+                if text.trim().is_empty() {
+                    "\\ ".to_string()
+                } else {
+                    format!("\\text{{{}}}", text)
+                }
+            }
+            "mspace" => "\\,".to_string(),
+            "mfrac" => match self.args::<2>(node, name) {
+                Some([num, den]) => format!("\\frac{{{}}}{{{}}}", num, den),
+                None => String::new(),
+            },
+            "msqrt" => format!("\\sqrt{{{}}}", self.children(node)),
+            "mroot" => match self.args::<2>(node, name) {
+                Some([base, index]) => format!("\\sqrt[{}]{{{}}}", index, base),
+                None => String::new(),
+            },
+            "msup" => match self.args::<2>(node, name) {
+                Some([base, sup]) => format!("{}^{{{}}}", group(&base), sup),
+                None => String::new(),
+            },
+            "msub" => match self.args::<2>(node, name) {
+                Some([base, sub]) => format!("{}_{{{}}}", group(&base), sub),
+                None => String::new(),
+            },
+            "msubsup" => match self.args::<3>(node, name) {
+                Some([base, sub, sup]) => format!("{}_{{{}}}^{{{}}}", group(&base), sub, sup),
+                None => String::new(),
+            },
+            "munder" => match self.args::<2>(node, name) {
+                Some([base, under]) if is_big_operator(&base) => format!("{}_{{{}}}", base, under),
+                Some([base, under]) => match under.as_str() {
+                    "_" | "\u{332}" => format!("\\underline{{{}}}", base),
+                    _ => format!("\\underset{{{}}}{{{}}}", under, base),
+                },
+                None => String::new(),
+            },
+            "mover" => match self.args::<2>(node, name) {
+                Some([base, over]) if is_big_operator(&base) => format!("{}^{{{}}}", base, over),
+                Some([base, over]) => match over.as_str() {
+                    "^" | "\u{302}" | "ˆ" => format!("\\hat{{{}}}", base),
+                    "~" | "˜" | "\\sim" => format!("\\tilde{{{}}}", base),
+                    "\\to" | "⃗" => format!("\\vec{{{}}}", base),
+                    "˙" | "\\cdot" | "." => format!("\\dot{{{}}}", base),
+                    "¨" => format!("\\ddot{{{}}}", base),
+                    "¯" | "‾" | "-" | "\u{305}" => format!("\\overline{{{}}}", base),
+                    _ => format!("\\overset{{{}}}{{{}}}", over, base),
+                },
+                None => String::new(),
+            },
+            "munderover" => match self.args::<3>(node, name) {
+                Some([base, under, over]) if is_big_operator(&base) => {
+                    format!("{}_{{{}}}^{{{}}}", base, under, over)
+                }
+                Some([base, under, over]) => {
+                    format!(
+                        "\\overset{{{}}}{{\\underset{{{}}}{{{}}}}}",
+                        over, under, base
+                    )
+                }
+                None => String::new(),
+            },
+            "mfenced" => {
+                let open = node.attr("open").unwrap_or("(");
+                let close = node.attr("close").unwrap_or(")");
+                let separator = node.attr("separators").unwrap_or(",").trim();
+                let items: Vec<String> = node.elements().iter().map(|e| self.convert(e)).collect();
+                format!(
+                    "\\left{} {} \\right{}",
+                    fence(open),
+                    items.join(&format!("{} ", separator.chars().next().unwrap_or(','))),
+                    fence(close)
+                )
+            }
+            "mtable" => {
+                let rows: Vec<String> = node
+                    .elements()
+                    .iter()
+                    .map(|row| {
+                        // A bare `mtd` outside an `mtr` is a one-cell row
+                        let cells = if row_name(row) == "mtd" {
+                            vec![*row]
+                        } else {
+                            row.elements()
+                        };
+                        cells
+                            .iter()
+                            .map(|cell| self.children(cell))
+                            .collect::<Vec<_>>()
+                            .join(" & ")
+                    })
+                    .collect();
+                format!("\\begin{{matrix}} {} \\end{{matrix}}", rows.join(" \\\\ "))
+            }
+            other => {
+                self.unsupported.insert(other.to_string());
+                String::new()
+            }
+        }
+    }
+}
+
+fn row_name(node: &Node) -> &str {
+    match node {
+        Node::Element { name, .. } => name,
+        Node::Text(_) => "",
+    }
+}
+
+/// `\left`/`\right` delimiter for an `mfenced` open or close attribute
+fn fence(delimiter: &str) -> &str {
+    match delimiter {
+        "" => ".",
+        "{" => "\\{",
+        "}" => "\\}",
+        "|" => "|",
+        "‖" => "\\|",
+        "⟨" | "〈" => "\\langle",
+        "⟩" | "〉" => "\\rangle",
+        other => other,
+    }
+}
+
+/// Convert MathML markup to LaTeX. Fails with the list of elements that
+/// have no LaTeX mapping rather than emitting partial output.
+pub fn convert_mathml(mathml: &str) -> Result<String, String> {
+    let mut parser = Parser {
+        input: mathml.trim(),
+        pos: 0,
+    };
+    let nodes = parser.parse_nodes(None)?;
+    let root = nodes
+        .iter()
+        .find(|n| matches!(n, Node::Element { .. }))
+        .ok_or_else(|| "Invalid MathML: no elements found".to_string())?;
+
+    let mut converter = Converter {
+        unsupported: BTreeSet::new(),
+    };
+    let latex = converter.convert(root);
+    if !converter.unsupported.is_empty() {
+        let names: Vec<String> = converter.unsupported.into_iter().collect();
+        return Err(format!("Unsupported MathML elements: {}", names.join(", ")));
+    }
+    Ok(latex.trim().to_string())
+}
+
+#[tauri::command]
+pub fn mathml_to_latex(mathml: String) -> Result<String, String> {
+    convert_mathml(&mathml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRACTION: &str = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">
+  <mfrac>
+    <mrow><mi>a</mi><mo>+</mo><mn>1</mn></mrow>
+    <mn>2</mn>
+  </mfrac>
+</math>"#;
+
+    const SUPERSCRIPT: &str = r#"<math>
+  <mrow>
+    <msup><mi>e</mi><mrow><mi>i</mi><mi>&#x3C0;</mi></mrow></msup>
+    <mo>+</mo><mn>1</mn><mo>=</mo><mn>0</mn>
+  </mrow>
+</math>"#;
+
+    #[test]
+    fn converts_a_fraction() {
+        assert_eq!(convert_mathml(FRACTION).unwrap(), "\\frac{a+1}{2}");
+    }
+
+    #[test]
+    fn converts_a_superscript() {
+        assert_eq!(convert_mathml(SUPERSCRIPT).unwrap(), "e^{i\\pi}+1=0");
+    }
+
+    #[test]
+    fn converts_roots_and_big_operators() {
+        let root = "<math><mroot><mi>x</mi><mn>3</mn></mroot></math>";
+        assert_eq!(convert_mathml(root).unwrap(), "\\sqrt[3]{x}");
+        let sum = "<math><munderover><mo>&#x2211;</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn>\
+                   </mrow><mi>n</mi></munderover><mi>i</mi></math>";
+        assert_eq!(convert_mathml(sum).unwrap(), "\\sum_{i=1}^{n}i");
+    }
+
+    #[test]
+    fn unsupported_elements_are_listed_instead_of_converted() {
+        let mathml = "<math><mrow><maction><mi>x</mi></maction><mglyph/>\
+                      <mfrac><mn>1</mn></mfrac></mrow></math>";
+        assert_eq!(
+            convert_mathml(mathml).unwrap_err(),
+            "Unsupported MathML elements: maction, mfrac with 1 children, mglyph"
+        );
+        assert!(convert_mathml("just text").is_err());
+    }
+}
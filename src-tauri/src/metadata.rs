@@ -0,0 +1,599 @@
+use crate::bibliography::{
+    citation_key, normalize_doi, normalize_isbn, BibEntry, DEFAULT_CITATION_KEY_PATTERN,
+};
+use crate::errors::AppError;
+use crate::settings::Settings;
+use serde::Deserialize;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+/// Issues GET requests for providers, so tests can swap in canned responses
+pub trait HttpClient: Send + Sync {
+    fn get_json(&self, url: &str, user_agent: &str) -> Result<serde_json::Value, AppError>;
+}
+
+/// Default for the `http_timeout_secs` setting
+pub const DEFAULT_HTTP_TIMEOUT_SECS: u64 = 30;
+
+/// Network options for lookups, taken from settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpConfig {
+    /// When unset, reqwest uses `HTTP_PROXY`/`HTTPS_PROXY`/`ALL_PROXY` from the environment
+    pub proxy: Option<String>,
+    pub timeout: Duration,
+}
+
+impl HttpConfig {
+    pub fn from_settings(settings: &Settings) -> Result<Self, AppError> {
+        let proxy = settings
+            .http_proxy
+            .as_deref()
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(validate_proxy_url)
+            .transpose()?;
+        Ok(Self {
+            proxy,
+            timeout: Duration::from_secs(settings.http_timeout_secs.max(1)),
+        })
+    }
+}
+
+/// Check a proxy URL is `http`, `https`, `socks5` or `socks5h` with a host
+pub fn validate_proxy_url(proxy: &str) -> Result<String, AppError> {
+    let invalid =
+        |reason: &str| AppError::Validation(format!("Invalid proxy URL '{}': {}", proxy, reason));
+    let url = reqwest::Url::parse(proxy).map_err(|e| invalid(&e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(invalid("scheme must be http, https, socks5 or socks5h"));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("missing host"));
+    }
+    Ok(proxy.to_string())
+}
+
+/// The real network client
+pub struct ReqwestClient {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestClient {
+    pub fn new(config: &HttpConfig) -> Result<Self, AppError> {
+        let mut builder = reqwest::blocking::Client::builder().timeout(config.timeout);
+        if let Some(proxy) = &config.proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .map_err(|e| AppError::Validation(format!("Invalid proxy URL: {}", e)))?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder
+            .build()
+            .map_err(|e| AppError::Network(format!("HTTP client error: {}", e)))?;
+        Ok(Self { client })
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn get_json(&self, url: &str, user_agent: &str) -> Result<serde_json::Value, AppError> {
+        self.client
+            .get(url)
+            .header(reqwest::header::USER_AGENT, user_agent)
+            .send()
+            .map_err(|e| AppError::Network(format!("Request failed: {}", e)))?
+            .json()
+            .map_err(|e| AppError::Parse(format!("Failed to parse response: {}", e)))
+    }
+}
+
+/// A `ReqwestClient` built on the first request, so offline and cached lookups
+/// never construct one and aren't failed by a bad proxy setting
+pub struct LazyReqwestClient {
+    config: Result<HttpConfig, AppError>,
+    client: OnceLock<Result<ReqwestClient, AppError>>,
+}
+
+impl LazyReqwestClient {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Self {
+            config: HttpConfig::from_settings(settings),
+            client: OnceLock::new(),
+        }
+    }
+}
+
+impl HttpClient for LazyReqwestClient {
+    fn get_json(&self, url: &str, user_agent: &str) -> Result<serde_json::Value, AppError> {
+        self.client
+            .get_or_init(|| ReqwestClient::new(self.config.as_ref().map_err(Clone::clone)?))
+            .as_ref()
+            .map_err(Clone::clone)?
+            .get_json(url, user_agent)
+    }
+}
+
+/// Resolves one kind of identifier (DOI, ISBN, ...) to a bibliography entry
+pub trait MetadataProvider: Send + Sync {
+    /// Identifier kind handled, e.g. `doi`; also the registry and cache key prefix
+    fn kind(&self) -> &'static str;
+
+    /// Validate an identifier and bring it into canonical form
+    fn normalize(&self, identifier: &str) -> Result<String, AppError>;
+
+    /// Key under which a normalized identifier is stored in the lookup cache
+    fn cache_key(&self, identifier: &str) -> String {
+        format!("{}:{}", self.kind(), identifier)
+    }
+
+    /// Look up a normalized identifier
+    fn fetch(&self, identifier: &str) -> Result<BibEntry, AppError>;
+}
+
+/// Providers by identifier kind
+#[derive(Default)]
+pub struct ProviderRegistry {
+    providers: Vec<Box<dyn MetadataProvider>>,
+}
+
+impl ProviderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// CrossRef for DOIs and OpenLibrary for ISBNs
+    pub fn with_defaults(http: Arc<dyn HttpClient>, crossref_user_agent: String) -> Self {
+        let mut registry = Self::new();
+        registry.register(Box::new(CrossRefProvider {
+            http: http.clone(),
+            user_agent: crossref_user_agent,
+        }));
+        registry.register(Box::new(OpenLibraryProvider { http }));
+        registry
+    }
+
+    /// Add a provider, replacing any existing one for the same kind
+    pub fn register(&mut self, provider: Box<dyn MetadataProvider>) {
+        self.providers.retain(|p| p.kind() != provider.kind());
+        self.providers.push(provider);
+    }
+
+    pub fn get(&self, kind: &str) -> Result<&dyn MetadataProvider, AppError> {
+        self.providers
+            .iter()
+            .find(|p| p.kind() == kind)
+            .map(|p| p.as_ref())
+            .ok_or_else(|| AppError::Validation(format!("No metadata provider for '{}'", kind)))
+    }
+}
+
+// CrossRef API response types
+#[derive(Deserialize)]
+struct CrossRefResponse {
+    message: CrossRefMessage,
+}
+
+#[derive(Deserialize)]
+struct CrossRefMessage {
+    title: Option<Vec<String>>,
+    author: Option<Vec<CrossRefAuthor>>,
+    #[serde(rename = "container-title")]
+    container_title: Option<Vec<String>>,
+    #[serde(rename = "published-print")]
+    published_print: Option<CrossRefDate>,
+    #[serde(rename = "published-online")]
+    published_online: Option<CrossRefDate>,
+    volume: Option<String>,
+    page: Option<String>,
+    publisher: Option<String>,
+    #[serde(rename = "DOI")]
+    doi: Option<String>,
+    #[serde(rename = "URL")]
+    url: Option<String>,
+    #[serde(rename = "type")]
+    work_type: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CrossRefAuthor {
+    given: Option<String>,
+    family: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CrossRefDate {
+    #[serde(rename = "date-parts")]
+    date_parts: Option<Vec<Vec<u32>>>,
+}
+
+// OpenLibrary response types
+#[derive(Deserialize)]
+struct OpenLibraryBook {
+    title: Option<String>,
+    authors: Option<Vec<OpenLibraryAuthor>>,
+    publishers: Option<Vec<OpenLibraryPublisher>>,
+    publish_date: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenLibraryAuthor {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpenLibraryPublisher {
+    name: Option<String>,
+}
+
+pub struct CrossRefProvider {
+    http: Arc<dyn HttpClient>,
+    /// Carries the `mailto:` contact for CrossRef's polite pool
+    user_agent: String,
+}
+
+impl MetadataProvider for CrossRefProvider {
+    fn kind(&self) -> &'static str {
+        "doi"
+    }
+
+    fn normalize(&self, identifier: &str) -> Result<String, AppError> {
+        normalize_doi(identifier)
+    }
+
+    // DOIs are case-insensitive, but the original case is kept for the request
+    fn cache_key(&self, identifier: &str) -> String {
+        format!("doi:{}", identifier.to_lowercase())
+    }
+
+    fn fetch(&self, doi: &str) -> Result<BibEntry, AppError> {
+        let url = format!("https://api.crossref.org/works/{}", doi);
+        let response: CrossRefResponse =
+            serde_json::from_value(self.http.get_json(&url, &self.user_agent)?).map_err(|e| {
+                AppError::Parse(format!("Failed to parse CrossRef response: {}", e))
+            })?;
+
+        let msg = response.message;
+
+        let title = msg
+            .title
+            .and_then(|t| t.into_iter().next())
+            .unwrap_or_default();
+
+        let author = msg
+            .author
+            .map(|authors| {
+                authors
+                    .iter()
+                    .map(|a| {
+                        let family = a.family.as_deref().unwrap_or("");
+                        let given = a.given.as_deref().unwrap_or("");
+                        if given.is_empty() {
+                            family.to_string()
+                        } else {
+                            format!("{}, {}", family, given)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            })
+            .unwrap_or_default();
+
+        let year = msg
+            .published_print
+            .or(msg.published_online)
+            .and_then(|d| d.date_parts)
+            .and_then(|parts| parts.into_iter().next())
+            .and_then(|parts| parts.into_iter().next())
+            .unwrap_or(0);
+
+        let entry_type = match msg.work_type.as_deref() {
+            Some("journal-article") => "article",
+            Some("book") | Some("monograph") => "book",
+            Some("proceedings-article") => "inproceedings",
+            _ => "misc",
+        };
+
+        let mut entry = BibEntry {
+            key: String::new(),
+            entry_type: entry_type.to_string(),
+            author,
+            title,
+            year,
+            journal: msg.container_title.and_then(|t| t.into_iter().next()),
+            publisher: msg.publisher,
+            volume: msg.volume,
+            pages: msg.page,
+            doi: msg.doi,
+            url: msg.url,
+            isbn: None,
+            booktitle: None,
+        };
+        entry.key = citation_key(&entry, DEFAULT_CITATION_KEY_PATTERN);
+        Ok(entry)
+    }
+}
+
+pub struct OpenLibraryProvider {
+    http: Arc<dyn HttpClient>,
+}
+
+impl MetadataProvider for OpenLibraryProvider {
+    fn kind(&self) -> &'static str {
+        "isbn"
+    }
+
+    fn normalize(&self, identifier: &str) -> Result<String, AppError> {
+        normalize_isbn(identifier)
+    }
+
+    fn fetch(&self, clean_isbn: &str) -> Result<BibEntry, AppError> {
+        let url = format!(
+            "https://openlibrary.org/api/books?bibkeys=ISBN:{}&format=json&jscmd=data",
+            clean_isbn
+        );
+        let response = self.http.get_json(&url, "Lilia-Desktop/0.1.0")?;
+
+        let bib_key = format!("ISBN:{}", clean_isbn);
+        let book_data = response
+            .get(&bib_key)
+            .ok_or_else(|| AppError::NotFound(format!("ISBN {} not found", clean_isbn)))?;
+
+        let book: OpenLibraryBook = serde_json::from_value(book_data.clone())
+            .map_err(|e| AppError::Parse(format!("Failed to parse book data: {}", e)))?;
+
+        let title = book.title.unwrap_or_default();
+        let author = book
+            .authors
+            .map(|a| {
+                a.iter()
+                    .filter_map(|a| a.name.clone())
+                    .collect::<Vec<_>>()
+                    .join(" and ")
+            })
+            .unwrap_or_default();
+
+        let publisher = book
+            .publishers
+            .and_then(|p| p.into_iter().next())
+            .and_then(|p| p.name);
+
+        // Try to extract year from publish_date
+        let year = book
+            .publish_date
+            .and_then(|d| {
+                d.chars()
+                    .filter(|c| c.is_ascii_digit())
+                    .collect::<String>()
+                    .get(..4)
+                    .and_then(|y| y.parse::<u32>().ok())
+            })
+            .unwrap_or(0);
+
+        let mut entry = BibEntry {
+            key: String::new(),
+            entry_type: "book".to_string(),
+            author,
+            title,
+            year,
+            journal: None,
+            publisher,
+            volume: None,
+            pages: None,
+            doi: None,
+            url: book.url,
+            isbn: Some(clean_isbn.to_string()),
+            booktitle: None,
+        };
+        entry.key = citation_key(&entry, DEFAULT_CITATION_KEY_PATTERN);
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    /// Serves one canned body and records each requested URL
+    struct CannedHttp {
+        body: serde_json::Value,
+        urls: Mutex<Vec<String>>,
+    }
+
+    impl CannedHttp {
+        fn new(body: serde_json::Value) -> Arc<Self> {
+            Arc::new(Self {
+                body,
+                urls: Mutex::new(vec![]),
+            })
+        }
+    }
+
+    impl HttpClient for CannedHttp {
+        fn get_json(&self, url: &str, _user_agent: &str) -> Result<serde_json::Value, AppError> {
+            self.urls.lock().unwrap().push(url.to_string());
+            Ok(self.body.clone())
+        }
+    }
+
+    /// Answers every identifier with a fixed title, for registry tests
+    struct FakeProvider {
+        kind: &'static str,
+        title: &'static str,
+    }
+
+    impl MetadataProvider for FakeProvider {
+        fn kind(&self) -> &'static str {
+            self.kind
+        }
+
+        fn normalize(&self, identifier: &str) -> Result<String, AppError> {
+            Ok(identifier.trim().to_string())
+        }
+
+        fn fetch(&self, identifier: &str) -> Result<BibEntry, AppError> {
+            Ok(BibEntry {
+                key: identifier.to_string(),
+                entry_type: "misc".to_string(),
+                author: String::new(),
+                title: self.title.to_string(),
+                year: 0,
+                journal: None,
+                publisher: None,
+                volume: None,
+                pages: None,
+                doi: None,
+                url: None,
+                isbn: None,
+                booktitle: None,
+            })
+        }
+    }
+
+    #[test]
+    fn registry_dispatches_by_kind() {
+        let mut registry = ProviderRegistry::new();
+        registry.register(Box::new(FakeProvider {
+            kind: "arxiv",
+            title: "Attention Is All You Need",
+        }));
+
+        let provider = registry.get("arxiv").unwrap();
+        let identifier = provider.normalize(" 1706.03762 ").unwrap();
+        assert_eq!(provider.cache_key(&identifier), "arxiv:1706.03762");
+        let entry = provider.fetch(&identifier).unwrap();
+        assert_eq!(entry.title, "Attention Is All You Need");
+
+        assert!(matches!(registry.get("isbn"), Err(AppError::Validation(_))));
+    }
+
+    #[test]
+    fn registering_a_kind_again_replaces_the_provider() {
+        let http = CannedHttp::new(json!({}));
+        let mut registry = ProviderRegistry::with_defaults(http.clone(), "ua".to_string());
+        registry.register(Box::new(FakeProvider {
+            kind: "doi",
+            title: "From the fake",
+        }));
+
+        let entry = registry.get("doi").unwrap().fetch("10.1000/x").unwrap();
+        assert_eq!(entry.title, "From the fake");
+        assert!(http.urls.lock().unwrap().is_empty());
+        assert_eq!(registry.get("isbn").unwrap().kind(), "isbn");
+    }
+
+    #[test]
+    fn crossref_responses_become_entries() {
+        let http = CannedHttp::new(json!({
+            "message": {
+                "title": ["Deep Learning"],
+                "author": [
+                    {"family": "LeCun", "given": "Yann"},
+                    {"family": "Bengio", "given": "Yoshua"}
+                ],
+                "published-print": {"date-parts": [[2015, 5]]},
+                "type": "journal-article",
+                "container-title": ["Nature"],
+                "volume": "521",
+                "page": "436-444",
+                "DOI": "10.1038/nature14539"
+            }
+        }));
+        let registry = ProviderRegistry::with_defaults(http.clone(), "ua".to_string());
+
+        let entry = registry
+            .get("doi")
+            .unwrap()
+            .fetch("10.1038/nature14539")
+            .unwrap();
+        assert_eq!(
+            http.urls.lock().unwrap().as_slice(),
+            ["https://api.crossref.org/works/10.1038/nature14539"]
+        );
+        assert_eq!(entry.entry_type, "article");
+        assert_eq!(entry.author, "LeCun, Yann and Bengio, Yoshua");
+        assert_eq!(entry.year, 2015);
+        assert_eq!(entry.journal.as_deref(), Some("Nature"));
+        assert_eq!(entry.key, "lecun2015");
+    }
+
+    #[test]
+    fn openlibrary_reports_unknown_isbns_as_not_found() {
+        let isbn = "9780262035613";
+        let http = CannedHttp::new(json!({
+            format!("ISBN:{}", isbn): {
+                "title": "Deep Learning",
+                "authors": [{"name": "Ian Goodfellow"}],
+                "publishers": [{"name": "MIT Press"}],
+                "publish_date": "November 18, 2016"
+            }
+        }));
+        let registry = ProviderRegistry::with_defaults(http, "ua".to_string());
+        let provider = registry.get("isbn").unwrap();
+
+        let entry = provider.fetch(isbn).unwrap();
+        assert_eq!(entry.entry_type, "book");
+        assert_eq!(entry.publisher.as_deref(), Some("MIT Press"));
+        assert_eq!(entry.isbn.as_deref(), Some(isbn));
+
+        let missing = provider.fetch("9780000000002");
+        assert!(matches!(missing, Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn http_config_reads_proxy_and_timeout_from_settings() {
+        let defaults = HttpConfig::from_settings(&Settings::default()).unwrap();
+        assert_eq!(defaults.proxy, None);
+        assert_eq!(
+            defaults.timeout,
+            Duration::from_secs(DEFAULT_HTTP_TIMEOUT_SECS)
+        );
+
+        let settings = Settings {
+            http_proxy: Some("  http://proxy.local:3128  ".to_string()),
+            http_timeout_secs: 5,
+            ..Settings::default()
+        };
+        let config = HttpConfig::from_settings(&settings).unwrap();
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy.local:3128"));
+        assert_eq!(config.timeout, Duration::from_secs(5));
+        assert!(ReqwestClient::new(&config).is_ok());
+
+        let blank = Settings {
+            http_proxy: Some("   ".to_string()),
+            http_timeout_secs: 0,
+            ..Settings::default()
+        };
+        let config = HttpConfig::from_settings(&blank).unwrap();
+        assert_eq!(config.proxy, None);
+        assert_eq!(config.timeout, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn proxy_urls_are_validated() {
+        for ok in [
+            "http://proxy:8080",
+            "https://proxy",
+            "socks5://127.0.0.1:1080",
+        ] {
+            assert_eq!(validate_proxy_url(ok).unwrap(), ok);
+        }
+        for bad in ["proxy:8080", "ftp://proxy", "not a url", "http://"] {
+            assert!(
+                matches!(validate_proxy_url(bad), Err(AppError::Validation(_))),
+                "{}",
+                bad
+            );
+        }
+
+        let settings = Settings {
+            http_proxy: Some("ftp://proxy".to_string()),
+            ..Settings::default()
+        };
+        assert!(HttpConfig::from_settings(&settings).is_err());
+        // The lazy client defers the error to the first network request
+        let lazy = LazyReqwestClient::from_settings(&settings);
+        let err = lazy.get_json("http://example.invalid", "ua").unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)));
+    }
+}
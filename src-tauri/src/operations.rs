@@ -0,0 +1,141 @@
+use crate::errors::AppError;
+use crate::AppState;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::State;
+
+/// How often watchdogs for external processes check for cancellation
+pub const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Cancellation flags for running operations, keyed by caller-chosen operation id
+pub type CancelFlags = Mutex<HashMap<String, Arc<AtomicBool>>>;
+
+/// A long-running command's registration in `CancelFlags`. Commands called
+/// without an operation id get a token that can never be cancelled.
+pub struct Operation<'a> {
+    flags: &'a CancelFlags,
+    id: Option<String>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl<'a> Operation<'a> {
+    pub fn begin(flags: &'a CancelFlags, id: Option<String>) -> Self {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Some(id) = &id {
+            flags.lock().unwrap().insert(id.clone(), cancelled.clone());
+        }
+        Self {
+            flags,
+            id,
+            cancelled,
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Shared flag for code that can't borrow the operation, e.g. a spawned thread
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    /// Fails with `AppError::Cancelled` once the operation has been cancelled
+    pub fn check(&self) -> Result<(), AppError> {
+        if self.is_cancelled() {
+            return Err(AppError::Cancelled(format!(
+                "Operation {} was cancelled",
+                self.id.as_deref().unwrap_or_default()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Operation<'_> {
+    fn drop(&mut self) {
+        if let Some(id) = &self.id {
+            if let Ok(mut flags) = self.flags.lock() {
+                flags.remove(id);
+            }
+        }
+    }
+}
+
+/// Ask a running operation to stop. Returns false if no operation has that id.
+#[tauri::command]
+pub fn cancel_operation(operation_id: String, state: State<AppState>) -> bool {
+    cancel(&state.operations, &operation_id)
+}
+
+pub(crate) fn cancel(flags: &CancelFlags, operation_id: &str) -> bool {
+    match flags.lock().unwrap().get(operation_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn cancelling_mid_operation_stops_the_loop() {
+        let flags = CancelFlags::default();
+        let (steps, err) = thread::scope(|scope| {
+            let worker = scope.spawn(|| {
+                let operation = Operation::begin(&flags, Some("export".to_string()));
+                let mut steps = 0;
+                while operation.check().is_ok() {
+                    steps += 1;
+                    assert!(steps < 10_000, "loop was never cancelled");
+                    thread::sleep(Duration::from_millis(1));
+                }
+                (steps, operation.check().unwrap_err())
+            });
+            while !cancel(&flags, "export") {
+                thread::sleep(Duration::from_millis(1));
+            }
+            worker.join().unwrap()
+        });
+        assert!(steps > 0);
+        assert_eq!(
+            err,
+            AppError::Cancelled("Operation export was cancelled".to_string())
+        );
+        // Finished operations are unregistered
+        assert!(flags.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_ids_and_anonymous_operations_cannot_be_cancelled() {
+        let flags = CancelFlags::default();
+        assert!(!cancel(&flags, "missing"));
+
+        let anonymous = Operation::begin(&flags, None);
+        assert!(flags.lock().unwrap().is_empty());
+        assert!(anonymous.check().is_ok());
+    }
+
+    #[test]
+    fn cancelling_one_operation_leaves_others_running() {
+        let flags = CancelFlags::default();
+        let lookup = Operation::begin(&flags, Some("lookup".to_string()));
+        let build = Operation::begin(&flags, Some("build".to_string()));
+
+        assert!(cancel(&flags, "lookup"));
+        assert!(lookup.is_cancelled());
+        assert!(!build.is_cancelled());
+
+        drop(lookup);
+        assert!(!cancel(&flags, "lookup"));
+        assert!(cancel(&flags, "build"));
+    }
+}
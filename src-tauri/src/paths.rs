@@ -0,0 +1,331 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Where app data ended up being stored for this session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageStatus {
+    pub app_data_dir: String,
+    pub using_fallback: bool,
+    pub warning: Option<String>,
+}
+
+/// Expand a leading `~` and `$VAR` / `${VAR}` / `%VAR%` environment variables.
+/// Already-absolute paths are returned untouched.
+pub fn expand_path(path: &str) -> PathBuf {
+    if Path::new(path).is_absolute() {
+        return PathBuf::from(path);
+    }
+
+    let expanded = expand_env_vars(path);
+
+    if expanded == "~" {
+        if let Some(home) = dirs::home_dir() {
+            return home;
+        }
+    } else if let Some(rest) = expanded
+        .strip_prefix("~/")
+        .or_else(|| expanded.strip_prefix("~\\"))
+    {
+        if let Some(home) = dirs::home_dir() {
+            return home.join(rest);
+        }
+    }
+
+    PathBuf::from(expanded)
+}
+
+/// Substitute environment variables, leaving unknown ones as written
+fn expand_env_vars(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(pos) = rest.find(['$', '%']) {
+        out.push_str(&rest[..pos]);
+        let marker = &rest[pos..];
+
+        let (name, consumed) = if let Some(braced) = marker.strip_prefix("${") {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 3),
+                None => ("", 0),
+            }
+        } else if let Some(after) = marker.strip_prefix('$') {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            (&after[..end], end + 1)
+        } else {
+            let after = &marker[1..];
+            match after.find('%') {
+                Some(end) => (&after[..end], end + 2),
+                None => ("", 0),
+            }
+        };
+
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => {
+                out.push_str(&value);
+                rest = &marker[consumed..];
+            }
+            _ => {
+                // Not a variable we can resolve; keep the marker character literally
+                out.push_str(&marker[..1]);
+                rest = &marker[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Where a document really lives: the canonical path with every symlink resolved,
+/// including symlinked parent directories. Paths that can't be resolved (e.g. not
+/// created yet) are returned as given.
+pub fn resolve_symlink(path: &str) -> String {
+    match fs::canonicalize(expand_path(path)) {
+        Ok(target) => strip_verbatim_prefix(&target.to_string_lossy()),
+        Err(_) => path.to_string(),
+    }
+}
+
+/// `canonicalize` returns `\\?\C:\...` on Windows; keep the plain drive form so
+/// resolved paths compare equal to what the user opened
+fn strip_verbatim_prefix(path: &str) -> String {
+    match path.strip_prefix(r"\\?\") {
+        Some(rest) if !rest.starts_with("UNC\\") => rest.to_string(),
+        _ => path.to_string(),
+    }
+}
+
+/// Comparison key for paths: case-insensitive on Windows and macOS (whose default
+/// filesystems are), case-sensitive elsewhere. The original spelling is kept for display.
+pub fn path_key(path: &str) -> String {
+    #[cfg(windows)]
+    {
+        path.replace('/', "\\").to_lowercase()
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        path.to_lowercase()
+    }
+
+    #[cfg(not(any(windows, target_os = "macos")))]
+    {
+        path.to_string()
+    }
+}
+
+/// Check that a directory exists (creating it if needed) and accepts writes
+pub fn probe_writable(dir: &Path) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("cannot create {}: {}", dir.display(), e))?;
+
+    let probe = dir.join(format!(".write-probe-{}", std::process::id()));
+    fs::write(&probe, b"ok").map_err(|e| format!("cannot write to {}: {}", dir.display(), e))?;
+    fs::remove_file(&probe).ok();
+    Ok(())
+}
+
+/// Use `preferred` if writable, otherwise fall back to a per-session temp directory
+pub fn resolve_app_data_dir(preferred: &Path) -> (PathBuf, StorageStatus) {
+    match probe_writable(preferred) {
+        Ok(()) => (
+            preferred.to_path_buf(),
+            StorageStatus {
+                app_data_dir: preferred.to_string_lossy().to_string(),
+                using_fallback: false,
+                warning: None,
+            },
+        ),
+        Err(reason) => {
+            let fallback = std::env::temp_dir().join("lilia-desktop");
+            let fallback_ok = probe_writable(&fallback).is_ok();
+            let outcome = if fallback_ok {
+                format!("are being stored in {} for this session only", fallback.display())
+            } else {
+                "will not be saved".to_string()
+            };
+            let warning = format!(
+                "The app data directory is not writable ({}). Settings, formulas, and \
+                 version history {}. Check the folder's permissions or free disk space.",
+                reason, outcome
+            );
+            (
+                fallback.clone(),
+                StorageStatus {
+                    app_data_dir: fallback.to_string_lossy().to_string(),
+                    using_fallback: true,
+                    warning: Some(warning),
+                },
+            )
+        }
+    }
+}
+
+/// Timestamp embedded in quarantined file names
+const QUARANTINE_TIMESTAMP_FORMAT: &str = "%Y%m%dT%H%M%S%.3fZ";
+
+pub fn quarantine_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("quarantine")
+}
+
+/// Move a corrupt data file into `quarantine/` under the app data dir as
+/// `<stem>-<timestamp>.<ext>`, so it can be inspected or restored later
+pub fn quarantine_file(app_data_dir: &Path, file: &Path) -> io::Result<PathBuf> {
+    let dir = quarantine_dir(app_data_dir);
+    fs::create_dir_all(&dir)?;
+
+    let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+    let timestamp = chrono::Utc::now().format(QUARANTINE_TIMESTAMP_FORMAT);
+    let name = match file.extension() {
+        Some(ext) => format!("{}-{}.{}", stem, timestamp, ext.to_string_lossy()),
+        None => format!("{}-{}", stem, timestamp),
+    };
+    let target = dir.join(name);
+
+    // Rename fails across filesystems; fall back to copy and remove
+    if fs::rename(file, &target).is_err() {
+        fs::copy(file, &target)?;
+        fs::remove_file(file)?;
+    }
+    Ok(target)
+}
+
+/// The original file name and quarantine time encoded by `quarantine_file`,
+/// or `None` for names it did not produce
+pub fn parse_quarantine_name(name: &str) -> Option<(String, chrono::DateTime<chrono::Utc>)> {
+    let (stem, rest) = name.rsplit_once('-')?;
+    // The timestamp always has the same width, e.g. `20240102T030405.678Z`
+    let timestamp = rest.get(..20)?;
+    let quarantined_at =
+        chrono::NaiveDateTime::parse_from_str(timestamp, QUARANTINE_TIMESTAMP_FORMAT).ok()?;
+    Some((format!("{}{}", stem, &rest[20..]), quarantined_at.and_utc()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_leading_tilde_to_home() {
+        let home = dirs::home_dir().unwrap();
+        assert_eq!(expand_path("~"), home);
+        assert_eq!(
+            expand_path("~/Documents/notes.lml"),
+            home.join("Documents/notes.lml")
+        );
+        // Only a leading tilde is special
+        assert_eq!(expand_path("notes~/a.lml"), PathBuf::from("notes~/a.lml"));
+    }
+
+    #[test]
+    fn substitutes_environment_variables() {
+        std::env::set_var("LILIA_TEST_EXPAND_DIR", "projects");
+        assert_eq!(
+            expand_path("$LILIA_TEST_EXPAND_DIR/a.lml"),
+            PathBuf::from("projects/a.lml")
+        );
+        assert_eq!(
+            expand_path("${LILIA_TEST_EXPAND_DIR}/a.lml"),
+            PathBuf::from("projects/a.lml")
+        );
+        assert_eq!(
+            expand_path("%LILIA_TEST_EXPAND_DIR%/a.lml"),
+            PathBuf::from("projects/a.lml")
+        );
+        // Unknown variables are left as written
+        assert_eq!(
+            expand_path("$LILIA_TEST_UNSET_VAR/a.lml"),
+            PathBuf::from("$LILIA_TEST_UNSET_VAR/a.lml")
+        );
+    }
+
+    #[test]
+    fn leaves_absolute_paths_untouched() {
+        let absolute = std::env::temp_dir().join("$HOME").join("~");
+        assert_eq!(expand_path(&absolute.to_string_lossy()), absolute);
+    }
+
+    #[test]
+    fn probe_accepts_writable_dirs_and_creates_missing_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("a/b");
+        probe_writable(&nested).unwrap();
+        assert!(nested.is_dir());
+        // The probe file is cleaned up
+        assert_eq!(fs::read_dir(&nested).unwrap().count(), 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn probe_rejects_read_only_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let locked = dir.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o555)).unwrap();
+
+        // Permission bits don't bind root; nothing to check there
+        let enforced = fs::write(locked.join("check"), b"").is_err();
+        let result = probe_writable(&locked);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        if enforced {
+            let err = result.unwrap_err();
+            assert!(err.starts_with("cannot write to"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn probe_rejects_dir_that_cannot_be_created() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+
+        let err = probe_writable(&file.join("data")).unwrap_err();
+        assert!(err.starts_with("cannot create"), "{}", err);
+    }
+
+    #[test]
+    fn unwritable_app_data_dir_falls_back_with_a_warning() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, status) = resolve_app_data_dir(dir.path());
+        assert_eq!(path, dir.path());
+        assert!(!status.using_fallback);
+        assert!(status.warning.is_none());
+
+        let file = dir.path().join("file");
+        fs::write(&file, "").unwrap();
+        let (path, status) = resolve_app_data_dir(&file.join("data"));
+        assert_eq!(path, std::env::temp_dir().join("lilia-desktop"));
+        assert!(status.using_fallback);
+        assert!(status.warning.unwrap().contains("for this session only"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_resolve_to_their_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let target = root.join("real.lml");
+        fs::write(&target, "").unwrap();
+        std::os::unix::fs::symlink(&target, root.join("link.lml")).unwrap();
+        std::os::unix::fs::symlink(&root, root.join("linked-dir")).unwrap();
+
+        let target = target.to_string_lossy().to_string();
+        let link = root.join("link.lml").to_string_lossy().to_string();
+        let through_dir = root
+            .join("linked-dir/real.lml")
+            .to_string_lossy()
+            .to_string();
+        assert_eq!(resolve_symlink(&link), target);
+        assert_eq!(resolve_symlink(&through_dir), target);
+        assert_eq!(resolve_symlink(&target), target);
+
+        // Paths that don't exist yet are kept as written
+        let missing = root.join("new.lml").to_string_lossy().to_string();
+        assert_eq!(resolve_symlink(&missing), missing);
+    }
+}
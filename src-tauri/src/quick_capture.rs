@@ -0,0 +1,217 @@
+use crate::paths::expand_path;
+use crate::settings::SettingsManager;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_global_shortcut::{GlobalShortcutExt, Shortcut, ShortcutState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuickCaptureEvent {
+    pub path: String,
+}
+
+/// Notes go to `quick_capture_dir`, or `quick-capture/` in the app data dir when unset
+fn quick_capture_dir(configured: Option<&str>, app_data_dir: &Path) -> PathBuf {
+    match configured.filter(|d| !d.trim().is_empty()) {
+        Some(dir) => expand_path(dir),
+        None => app_data_dir.join("quick-capture"),
+    }
+}
+
+/// `note-<timestamp>.lml`, or `note-<timestamp>-<n>.lml` from the second note on
+fn quick_capture_file_name(now: chrono::DateTime<chrono::Local>, n: u32) -> String {
+    let stem = format!("note-{}", now.format("%Y%m%d-%H%M%S"));
+    if n <= 1 {
+        format!("{}.lml", stem)
+    } else {
+        format!("{}-{}.lml", stem, n)
+    }
+}
+
+/// Create an empty note in `dir` named after `now`, with a numeric suffix if the
+/// name is taken. `create_new` checks and creates in one step, so captures in
+/// the same second never overwrite each other.
+pub fn create_quick_capture_note(
+    dir: &Path,
+    now: chrono::DateTime<chrono::Local>,
+) -> io::Result<PathBuf> {
+    let mut n = 1;
+    loop {
+        let path = dir.join(quick_capture_file_name(now, n));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Create an empty note and bring the main window forward to open it
+fn capture_note(app: &AppHandle) -> Result<PathBuf, String> {
+    let state = app.state::<AppState>();
+    let configured = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings()
+        .quick_capture_dir;
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .clone();
+
+    let dir = quick_capture_dir(configured.as_deref(), &app_data_dir);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = create_quick_capture_note(&dir, chrono::Local::now())
+        .map_err(|e| format!("Failed to create note: {}", e))?;
+
+    if let Some(window) = app.get_webview_window("main") {
+        window.unminimize().ok();
+        window.show().ok();
+        window.set_focus().ok();
+    }
+    Ok(path)
+}
+
+/// Register the global shortcut that triggers a quick capture
+pub fn bind_quick_capture(app: &AppHandle, accelerator: &str) -> Result<(), String> {
+    app.global_shortcut()
+        .on_shortcut(accelerator, |app, _shortcut, event| {
+            if event.state != ShortcutState::Pressed {
+                return;
+            }
+            match capture_note(app) {
+                Ok(path) => {
+                    let path = path.to_string_lossy().to_string();
+                    app.emit("quick-capture", QuickCaptureEvent { path }).ok();
+                }
+                Err(e) => {
+                    app.emit(
+                        "quick-capture-error",
+                        format!("Quick capture failed: {}", e),
+                    )
+                    .ok();
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to register shortcut {}: {}", accelerator, e))
+}
+
+/// Bind the quick-capture shortcut, replacing the previous one, and save it in
+/// settings. An empty accelerator removes the binding.
+#[tauri::command]
+pub fn register_quick_capture(
+    accelerator: String,
+    app: AppHandle,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let accelerator = accelerator.trim();
+    if !accelerator.is_empty() {
+        accelerator
+            .parse::<Shortcut>()
+            .map_err(|e| format!("Invalid shortcut '{}': {}", accelerator, e))?;
+    }
+
+    let mut manager = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+
+    // Bind the new shortcut before dropping the old one, so a failure leaves
+    // the saved shortcut working
+    let previous = manager
+        .get_settings()
+        .quick_capture_shortcut
+        .filter(|p| p != accelerator);
+    let bind_new = !accelerator.is_empty() && !app.global_shortcut().is_registered(accelerator);
+    if bind_new {
+        bind_quick_capture(&app, accelerator)?;
+    }
+    if let Some(previous) = previous.as_deref() {
+        if app.global_shortcut().is_registered(previous) {
+            if let Err(e) = app.global_shortcut().unregister(previous) {
+                if bind_new {
+                    app.global_shortcut().unregister(accelerator).ok();
+                }
+                return Err(format!("Failed to unregister shortcut {}: {}", previous, e));
+            }
+        }
+    }
+
+    save_shortcut(&mut manager, accelerator)
+}
+
+/// Persist the quick-capture binding; an empty accelerator clears it
+fn save_shortcut(manager: &mut SettingsManager, accelerator: &str) -> Result<(), String> {
+    let mut settings = manager.get_settings();
+    settings.quick_capture_shortcut = Some(accelerator.to_string()).filter(|a| !a.is_empty());
+    manager.update_settings(settings);
+    manager
+        .save()
+        .map_err(|e| format!("Failed to save settings: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn noon() -> chrono::DateTime<chrono::Local> {
+        chrono::Local
+            .with_ymd_and_hms(2024, 3, 5, 12, 30, 45)
+            .unwrap()
+    }
+
+    #[test]
+    fn notes_go_to_the_configured_dir_or_app_data() {
+        let app_data = Path::new("/data/lilia");
+        assert_eq!(
+            quick_capture_dir(None, app_data),
+            app_data.join("quick-capture")
+        );
+        assert_eq!(
+            quick_capture_dir(Some(" "), app_data),
+            app_data.join("quick-capture")
+        );
+        assert_eq!(
+            quick_capture_dir(Some("/notes/inbox"), app_data),
+            PathBuf::from("/notes/inbox")
+        );
+    }
+
+    #[test]
+    fn notes_are_named_by_time_and_never_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = create_quick_capture_note(dir.path(), noon()).unwrap();
+        let second = create_quick_capture_note(dir.path(), noon()).unwrap();
+        let third = create_quick_capture_note(dir.path(), noon()).unwrap();
+
+        let name = |path: &Path| path.file_name().unwrap().to_string_lossy().to_string();
+        assert_eq!(name(&first), "note-20240305-123045.lml");
+        assert_eq!(name(&second), "note-20240305-123045-2.lml");
+        assert_eq!(name(&third), "note-20240305-123045-3.lml");
+        assert_eq!(fs::read_to_string(&first).unwrap(), "");
+    }
+
+    #[test]
+    fn the_shortcut_is_saved_and_cleared_in_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let mut manager = SettingsManager::new(path.clone());
+
+        save_shortcut(&mut manager, "CmdOrCtrl+Shift+N").unwrap();
+        let reloaded = SettingsManager::new(path.clone()).get_settings();
+        assert_eq!(
+            reloaded.quick_capture_shortcut.as_deref(),
+            Some("CmdOrCtrl+Shift+N")
+        );
+
+        save_shortcut(&mut manager, "").unwrap();
+        let reloaded = SettingsManager::new(path).get_settings();
+        assert_eq!(reloaded.quick_capture_shortcut, None);
+    }
+}
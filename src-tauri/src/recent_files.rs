@@ -1,24 +1,80 @@
+use crate::paths::{path_key, resolve_symlink};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::fs;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
 
 const MAX_RECENT_FILES: usize = 10;
 
+/// How long an existence check stays valid before the path is stat'ed again
+const EXISTS_CACHE_TTL: Duration = Duration::from_secs(5);
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct RecentFilesData {
     pub files: Vec<String>,
+    /// Path -> fingerprint, used to recognise files renamed outside the app
+    #[serde(default)]
+    pub fingerprints: HashMap<String, FileFingerprint>,
+}
+
+/// What a listed file looked like when it was last added
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct FileFingerprint {
+    pub size: u64,
+    /// Milliseconds since the Unix epoch
+    pub modified_ms: u64,
+    /// SHA-256 of the contents
+    pub hash: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecentFilesRepair {
+    pub recovered: usize,
+    pub dropped: usize,
+    /// Where the damaged original was moved, if it needed repairing
+    pub quarantined_to: Option<String>,
+}
+
+/// Portable copy of the recents list, written by `export_recent_files`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecentFilesExport {
+    pub exported_at: String,
+    /// Most recent first
+    pub files: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecentFilesImport {
+    pub imported: usize,
+    /// Entries dropped because the file doesn't exist or is already listed
+    pub skipped: usize,
+}
+
+/// Recent files sharing a parent directory, most recent first
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecentFileGroup {
+    pub directory: String,
+    pub files: Vec<String>,
 }
 
 pub struct RecentFilesManager {
     path: PathBuf,
     data: RecentFilesData,
+    /// Path -> (exists, checked at); avoids re-stat'ing slow network paths
+    exists_cache: HashMap<String, (bool, Instant)>,
 }
 
 impl RecentFilesManager {
     pub fn new(path: PathBuf) -> Self {
         let data = Self::load_from_path(&path).unwrap_or_default();
-        Self { path, data }
+        Self {
+            path,
+            data,
+            exists_cache: HashMap::new(),
+        }
     }
 
     fn load_from_path(path: &PathBuf) -> io::Result<RecentFilesData> {
@@ -26,31 +82,183 @@ impl RecentFilesManager {
         serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
     }
 
-    pub fn get_files(&self) -> Vec<String> {
-        // Filter out files that no longer exist
-        self.data
-            .files
-            .iter()
-            .filter(|f| PathBuf::from(f).exists())
-            .cloned()
+    pub fn get_files(&mut self) -> Vec<String> {
+        self.files_at(Instant::now())
+    }
+
+    /// `get_files` as of `now`, which decides whether cached existence checks expired
+    fn files_at(&mut self, now: Instant) -> Vec<String> {
+        // Hide files that don't exist right now, but keep them stored in case
+        // they're only temporarily unreachable
+        let files = self.data.files.clone();
+        files
+            .into_iter()
+            .filter(|f| self.cached_exists(f, now))
             .collect()
     }
 
-    pub fn add_file(&mut self, path: &str) {
+    /// Existing recent files grouped by parent directory. Groups are ordered by
+    /// their most recent file, and files keep recency order within a group.
+    pub fn get_files_grouped(&mut self) -> Vec<RecentFileGroup> {
+        let mut groups: Vec<RecentFileGroup> = vec![];
+        for file in self.get_files() {
+            let directory = Path::new(&file)
+                .parent()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let key = path_key(&directory);
+            match groups.iter_mut().find(|g| path_key(&g.directory) == key) {
+                Some(group) => group.files.push(file),
+                None => groups.push(RecentFileGroup {
+                    directory,
+                    files: vec![file],
+                }),
+            }
+        }
+        groups
+    }
+
+    fn cached_exists(&mut self, path: &str, now: Instant) -> bool {
+        if let Some((exists, checked_at)) = self.exists_cache.get(path) {
+            if now.duration_since(*checked_at) < EXISTS_CACHE_TTL {
+                return *exists;
+            }
+        }
+        let exists = PathBuf::from(path).exists();
+        self.exists_cache.insert(path.to_string(), (exists, now));
+        exists
+    }
+
+    /// Forget cached existence checks so the next listing re-stats every path
+    pub fn invalidate_exists_cache(&mut self) {
+        self.exists_cache.clear();
+    }
+
+    /// Move `path` to the top. With `resolve_symlinks`, an entry reaching the same
+    /// file through a different symlink is replaced rather than kept alongside.
+    pub fn add_file(&mut self, path: &str, resolve_symlinks: bool) {
         // Remove if already exists (to move to top)
-        self.data.files.retain(|f| f != path);
+        let dedup_key = |p: &str| match resolve_symlinks {
+            true => path_key(&resolve_symlink(p)),
+            false => path_key(p),
+        };
+        let key = dedup_key(path);
+        self.data.files.retain(|f| dedup_key(f) != key);
+
+        // An entry that is gone from its (still reachable) directory and had
+        // identical content was most likely renamed to this path. Entries on an
+        // unreachable drive or share are kept.
+        let fingerprint = fingerprint(Path::new(path), self.data.fingerprints.get(path));
+        if let Some(new) = &fingerprint {
+            let fingerprints = &self.data.fingerprints;
+            self.data.files.retain(|f| {
+                let same_content = fingerprints
+                    .get(f)
+                    .is_some_and(|old| old.size == new.size && old.hash == new.hash);
+                !same_content || !vanished(Path::new(f))
+            });
+        }
 
-        // Add to front
+        // Add to front; it was just opened, so any cached "missing" result is stale
+        self.exists_cache.remove(path);
         self.data.files.insert(0, path.to_string());
 
         // Trim to max size
         if self.data.files.len() > MAX_RECENT_FILES {
             self.data.files.truncate(MAX_RECENT_FILES);
         }
+
+        match fingerprint {
+            Some(fingerprint) => self.data.fingerprints.insert(path.to_string(), fingerprint),
+            None => self.data.fingerprints.remove(path),
+        };
+        let files = &self.data.files;
+        self.data.fingerprints.retain(|f, _| files.contains(f));
+    }
+
+    /// The full stored list, including entries that are currently unreachable
+    pub fn export(&self) -> RecentFilesExport {
+        RecentFilesExport {
+            exported_at: chrono::Utc::now().to_rfc3339(),
+            files: self.data.files.clone(),
+        }
+    }
+
+    /// Load an exported list. Paths that don't exist here are skipped. With `merge`,
+    /// current entries stay first and imported ones follow in their exported order;
+    /// otherwise the imported list replaces the current one. Duplicates are detected
+    /// by canonical path, so different spellings of the same file are listed once.
+    pub fn import(&mut self, files: Vec<String>, merge: bool) -> RecentFilesImport {
+        let mut list = if merge { self.data.files.clone() } else { vec![] };
+        let mut seen: Vec<String> = list.iter().map(|f| canonical_key(f)).collect();
+        let mut result = RecentFilesImport {
+            imported: 0,
+            skipped: 0,
+        };
+
+        for file in files {
+            let key = canonical_key(&file);
+            if !Path::new(&file).exists() || seen.contains(&key) {
+                result.skipped += 1;
+                continue;
+            }
+            seen.push(key);
+            list.push(file);
+            result.imported += 1;
+        }
+        list.truncate(MAX_RECENT_FILES);
+
+        self.data.files = list;
+        let files = &self.data.files;
+        self.data.fingerprints.retain(|f, _| files.contains(f));
+        self.exists_cache.clear();
+        result
+    }
+
+    /// Salvage entries from a recent files JSON that no longer parses. The
+    /// original is moved to quarantine and replaced with what could be recovered.
+    pub fn repair(&mut self, app_data_dir: &Path) -> io::Result<RecentFilesRepair> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        if content.trim().is_empty() {
+            return Ok(RecentFilesRepair {
+                recovered: self.data.files.len(),
+                dropped: 0,
+                quarantined_to: None,
+            });
+        }
+        if let Ok(data) = serde_json::from_str::<RecentFilesData>(&content) {
+            self.data = data;
+            return Ok(RecentFilesRepair {
+                recovered: self.data.files.len(),
+                dropped: 0,
+                quarantined_to: None,
+            });
+        }
+
+        let (files, dropped) = salvage_paths(&content);
+        let quarantined = crate::paths::quarantine_file(app_data_dir, &self.path)?;
+        self.data = RecentFilesData {
+            files,
+            fingerprints: HashMap::new(),
+        };
+        self.exists_cache.clear();
+        self.save()?;
+
+        Ok(RecentFilesRepair {
+            recovered: self.data.files.len(),
+            dropped,
+            quarantined_to: Some(quarantined.to_string_lossy().to_string()),
+        })
     }
 
     pub fn clear(&mut self) {
         self.data.files.clear();
+        self.data.fingerprints.clear();
+        self.exists_cache.clear();
     }
 
     pub fn save(&self) -> io::Result<()> {
@@ -58,3 +266,419 @@ impl RecentFilesManager {
         fs::write(&self.path, content)
     }
 }
+
+/// Comparison key for a path after resolving symlinks and `..`, falling back
+/// to the path as given when it can't be resolved
+fn canonical_key(path: &str) -> String {
+    match fs::canonicalize(path) {
+        Ok(canonical) => path_key(&canonical.to_string_lossy()),
+        Err(_) => path_key(path),
+    }
+}
+
+/// Whether `path` is missing from a directory that is itself still there
+fn vanished(path: &Path) -> bool {
+    !path.exists() && path.parent().is_some_and(Path::is_dir)
+}
+
+/// Size, mtime and content hash of a file, or `None` if it can't be read. The
+/// hash in `previous` is reused while the size and mtime still match it.
+fn fingerprint(path: &Path, previous: Option<&FileFingerprint>) -> Option<FileFingerprint> {
+    let metadata = fs::metadata(path).ok()?;
+    let size = metadata.len();
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let modified_ms = modified.as_millis() as u64;
+    let hash = match previous {
+        Some(p) if p.size == size && p.modified_ms == modified_ms => p.hash.clone(),
+        _ => content_hash(path)?,
+    };
+    Some(FileFingerprint {
+        size,
+        modified_ms,
+        hash,
+    })
+}
+
+/// SHA-256 of the file's contents, or `None` if it can't be read
+fn content_hash(path: &Path) -> Option<String> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher).ok()?;
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// Pull path strings out of a damaged recent files JSON, one entry per line.
+/// Only the `files` array is scanned when its key is still recognisable.
+/// Returns the unique paths recovered and the number of entry lines dropped.
+fn salvage_paths(content: &str) -> (Vec<String>, usize) {
+    let lines: Vec<&str> = content.lines().collect();
+    let (start, end) = match lines.iter().position(|l| l.contains("\"files\"")) {
+        Some(key) => {
+            let end = lines[key + 1..]
+                .iter()
+                .position(|l| l.trim_start().starts_with(']'))
+                .map_or(lines.len(), |i| key + 1 + i);
+            (key + 1, end)
+        }
+        None => (0, lines.len()),
+    };
+
+    let mut files: Vec<String> = vec![];
+    let mut dropped = 0;
+    for line in &lines[start..end] {
+        let entry = line.trim().trim_end_matches(',').trim();
+        if !entry.contains('"') {
+            continue;
+        }
+        match serde_json::from_str::<String>(entry) {
+            Ok(path) if !path.trim().is_empty() => {
+                let key = path_key(&path);
+                if !files.iter().any(|f| path_key(f) == key) {
+                    files.push(path);
+                }
+            }
+            _ => dropped += 1,
+        }
+    }
+    files.truncate(MAX_RECENT_FILES);
+    (files, dropped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manager() -> (tempfile::TempDir, RecentFilesManager) {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = RecentFilesManager::new(dir.path().join("recent_files.json"));
+        (dir, manager)
+    }
+
+    #[test]
+    fn re_adding_a_file_moves_it_to_the_top() {
+        let (_dir, mut manager) = manager();
+        manager.add_file("/docs/a.lml", false);
+        manager.add_file("/docs/b.lml", false);
+        manager.add_file("/docs/a.lml", false);
+        assert_eq!(manager.data.files, ["/docs/a.lml", "/docs/b.lml"]);
+    }
+
+    #[cfg(any(windows, target_os = "macos"))]
+    #[test]
+    fn paths_differing_only_in_case_are_one_entry() {
+        let (_dir, mut manager) = manager();
+        manager.add_file("/Docs/Thesis.lml", false);
+        manager.add_file("/docs/THESIS.lml", false);
+        // The most recent spelling is kept
+        assert_eq!(manager.data.files, ["/docs/THESIS.lml"]);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_drive_letters_and_separators_are_one_entry() {
+        let (_dir, mut manager) = manager();
+        manager.add_file(r"C:\Docs\a.lml", false);
+        manager.add_file("c:/docs/A.LML", false);
+        assert_eq!(manager.data.files, ["c:/docs/A.LML"]);
+    }
+
+    #[cfg(not(any(windows, target_os = "macos")))]
+    #[test]
+    fn paths_differing_only_in_case_are_separate_entries() {
+        let (_dir, mut manager) = manager();
+        manager.add_file("/Docs/Thesis.lml", false);
+        manager.add_file("/docs/THESIS.lml", false);
+        assert_eq!(manager.data.files, ["/docs/THESIS.lml", "/Docs/Thesis.lml"]);
+    }
+
+    #[test]
+    fn existence_checks_are_cached_until_the_ttl_expires() {
+        let (dir, mut manager) = manager();
+        let file = dir.path().join("a.lml");
+        fs::write(&file, "x").unwrap();
+        let path = file.to_string_lossy().to_string();
+        manager.add_file(&path, false);
+
+        let start = Instant::now();
+        assert_eq!(manager.files_at(start), vec![path.clone()]);
+
+        fs::remove_file(&file).unwrap();
+        // Still inside the TTL, so the stale answer is served without a stat
+        assert_eq!(
+            manager.files_at(start + Duration::from_secs(1)),
+            vec![path.clone()]
+        );
+        // Expired, so the path is checked again and hidden
+        assert!(manager.files_at(start + EXISTS_CACHE_TTL).is_empty());
+        // Hidden, not dropped from storage
+        assert_eq!(manager.data.files, [path]);
+    }
+
+    #[test]
+    fn invalidating_forces_revalidation() {
+        let (dir, mut manager) = manager();
+        let file = dir.path().join("a.lml");
+        fs::write(&file, "x").unwrap();
+        let path = file.to_string_lossy().to_string();
+        manager.add_file(&path, false);
+
+        let start = Instant::now();
+        assert_eq!(manager.files_at(start).len(), 1);
+        fs::remove_file(&file).unwrap();
+        manager.invalidate_exists_cache();
+        assert!(manager.files_at(start).is_empty());
+
+        // It reappears once reachable again and the cache is refreshed
+        fs::write(&file, "x").unwrap();
+        manager.invalidate_exists_cache();
+        assert_eq!(manager.files_at(start), [path]);
+    }
+
+    #[test]
+    fn groups_by_directory_in_recency_order() {
+        let (dir, mut manager) = manager();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        let file = |parent: &Path, name: &str| {
+            let path = parent.join(name);
+            fs::write(&path, name).unwrap();
+            path.to_string_lossy().to_string()
+        };
+        let (a1, b1, a2, b2) = (
+            file(&a, "a1"),
+            file(&b, "b1"),
+            file(&a, "a2"),
+            file(&b, "b2"),
+        );
+        let gone = dir.path().join("c/gone.lml").to_string_lossy().to_string();
+
+        for path in [&a1, &gone, &b1, &a2, &b2] {
+            manager.add_file(path, false);
+        }
+
+        let groups = manager.get_files_grouped();
+        let summary: Vec<(String, Vec<String>)> =
+            groups.into_iter().map(|g| (g.directory, g.files)).collect();
+        // b holds the newest file, and missing files form no group
+        assert_eq!(
+            summary,
+            [
+                (b.to_string_lossy().to_string(), vec![b2, b1]),
+                (a.to_string_lossy().to_string(), vec![a2, a1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_file_renamed_on_disk_replaces_its_stale_entry() {
+        let (dir, mut manager) = manager();
+        let old = dir.path().join("draft.lml");
+        let other = dir.path().join("other.lml");
+        fs::write(&old, "chapter one").unwrap();
+        fs::write(&other, "unrelated").unwrap();
+        let old_path = old.to_string_lossy().to_string();
+        let other_path = other.to_string_lossy().to_string();
+        manager.add_file(&old_path, false);
+        manager.add_file(&other_path, false);
+
+        let new = dir.path().join("final.lml");
+        fs::rename(&old, &new).unwrap();
+        let new_path = new.to_string_lossy().to_string();
+        manager.add_file(&new_path, false);
+
+        assert_eq!(manager.data.files, [new_path.clone(), other_path]);
+        assert!(!manager.data.fingerprints.contains_key(&old_path));
+        assert!(manager.data.fingerprints.contains_key(&new_path));
+    }
+
+    #[test]
+    fn entries_on_an_unreachable_drive_are_not_taken_for_renames() {
+        let (dir, mut manager) = manager();
+        let share = dir.path().join("share");
+        fs::create_dir(&share).unwrap();
+        let remote = share.join("paper.lml");
+        fs::write(&remote, "same").unwrap();
+        let remote_path = remote.to_string_lossy().to_string();
+        manager.add_file(&remote_path, false);
+
+        // A local copy is opened while the share is offline
+        let local = dir.path().join("paper.lml");
+        fs::copy(&remote, &local).unwrap();
+        fs::remove_dir_all(&share).unwrap();
+        let local_path = local.to_string_lossy().to_string();
+        manager.add_file(&local_path, false);
+
+        assert_eq!(manager.data.files, [local_path, remote_path]);
+    }
+
+    #[test]
+    fn unchanged_files_are_not_hashed_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.lml");
+        fs::write(&path, "content").unwrap();
+        let first = fingerprint(&path, None).unwrap();
+        assert_eq!(first.hash, content_hash(&path).unwrap());
+
+        let known = FileFingerprint {
+            hash: "hash from an earlier add".to_string(),
+            ..first.clone()
+        };
+        assert_eq!(fingerprint(&path, Some(&known)).unwrap(), known);
+
+        // A different size means the file changed, so it is hashed again
+        let stale = FileFingerprint {
+            size: first.size + 1,
+            ..known
+        };
+        assert_eq!(fingerprint(&path, Some(&stale)).unwrap(), first);
+    }
+
+    #[test]
+    fn a_copy_does_not_replace_the_original() {
+        let (dir, mut manager) = manager();
+        let original = dir.path().join("a.lml");
+        let copy = dir.path().join("b.lml");
+        fs::write(&original, "same").unwrap();
+        fs::write(&copy, "same").unwrap();
+        let original_path = original.to_string_lossy().to_string();
+        let copy_path = copy.to_string_lossy().to_string();
+        manager.add_file(&original_path, false);
+        manager.add_file(&copy_path, false);
+
+        // The original still exists, so it is not a stale entry
+        assert_eq!(manager.data.files, [copy_path, original_path]);
+    }
+
+    #[test]
+    fn repair_salvages_the_valid_entries_of_a_corrupt_file() {
+        let (dir, mut manager) = manager();
+        let corrupt = r#"{
+  "files": [
+    "/docs/thesis.lml",
+    "/docs/notes.lml",
+    "/docs/broken.lml
+    "/docs/THESIS.lml",
+    "/docs/bad\q.lml",
+    "/docs/paper.lml"
+  ],
+  "hashes": {"#;
+        fs::write(&manager.path, corrupt).unwrap();
+
+        let report = manager.repair(dir.path()).unwrap();
+
+        let mut expected = vec!["/docs/thesis.lml", "/docs/notes.lml", "/docs/paper.lml"];
+        if path_key("/docs/THESIS.lml") != path_key("/docs/thesis.lml") {
+            expected.insert(2, "/docs/THESIS.lml");
+        }
+        assert_eq!(manager.data.files, expected);
+        assert_eq!(report.recovered, expected.len());
+        assert_eq!(report.dropped, 2);
+
+        // The damaged original is kept aside and replaced with the salvage
+        let quarantined = report.quarantined_to.unwrap();
+        assert_eq!(fs::read_to_string(quarantined).unwrap(), corrupt);
+        let saved = RecentFilesManager::new(manager.path.clone());
+        assert_eq!(saved.data.files, expected);
+    }
+
+    #[test]
+    fn repair_leaves_a_valid_file_alone() {
+        let (dir, mut manager) = manager();
+        manager.add_file("/docs/a.lml", false);
+        manager.save().unwrap();
+
+        let report = manager.repair(dir.path()).unwrap();
+        assert_eq!((report.recovered, report.dropped), (1, 0));
+        assert!(report.quarantined_to.is_none());
+        assert_eq!(manager.data.files, ["/docs/a.lml"]);
+    }
+
+    /// Existing files named `names` in `dir`, as path strings
+    fn existing(dir: &Path, names: &[&str]) -> Vec<String> {
+        names
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                fs::write(&path, name).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn merged_imports_keep_current_entries_first_without_duplicates() {
+        let (dir, mut manager) = manager();
+        let files = existing(dir.path(), &["a.lml", "b.lml", "c.lml"]);
+        manager.add_file(&files[1], false);
+        manager.add_file(&files[0], false);
+
+        // The same file spelled through `..` is still a duplicate
+        let respelled = dir
+            .path()
+            .join("sub/../b.lml")
+            .to_string_lossy()
+            .to_string();
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        let import = vec![files[2].clone(), respelled, files[0].clone()];
+        let result = manager.import(import, true);
+
+        assert_eq!((result.imported, result.skipped), (1, 2));
+        assert_eq!(
+            manager.data.files,
+            [files[0].clone(), files[1].clone(), files[2].clone()]
+        );
+    }
+
+    #[test]
+    fn imports_skip_missing_paths_and_can_replace_the_list() {
+        let (dir, mut manager) = manager();
+        let files = existing(dir.path(), &["a.lml", "b.lml"]);
+        manager.add_file(&files[0], false);
+        let missing = dir.path().join("gone.lml").to_string_lossy().to_string();
+
+        let result = manager.import(vec![missing, files[1].clone()], false);
+        assert_eq!((result.imported, result.skipped), (1, 1));
+        assert_eq!(manager.data.files, [files[1].clone()]);
+    }
+
+    #[test]
+    fn an_export_round_trips_into_another_manager() {
+        let (dir, mut manager) = manager();
+        let files = existing(dir.path(), &["a.lml", "b.lml"]);
+        for file in &files {
+            manager.add_file(file, false);
+        }
+        let json = serde_json::to_string(&manager.export()).unwrap();
+
+        let other_dir = tempfile::tempdir().unwrap();
+        let mut other = RecentFilesManager::new(other_dir.path().join("recent_files.json"));
+        let export: RecentFilesExport = serde_json::from_str(&json).unwrap();
+        other.import(export.files, true);
+        assert_eq!(other.data.files, [files[1].clone(), files[0].clone()]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_to_the_same_file_are_one_entry_when_resolving() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("real.lml");
+        fs::write(&target, "").unwrap();
+        let link = dir.path().join("link.lml");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let target = target.to_string_lossy().to_string();
+        let link = link.to_string_lossy().to_string();
+
+        let (_dir, mut resolving) = manager();
+        resolving.add_file(&target, true);
+        resolving.add_file(&link, true);
+        assert_eq!(resolving.data.files, vec![link.clone()]);
+
+        // Opting out keeps each link as its own entry
+        let (_dir, mut literal) = manager();
+        literal.add_file(&target, false);
+        literal.add_file(&link, false);
+        assert_eq!(literal.data.files, vec![link, target]);
+    }
+}
@@ -0,0 +1,170 @@
+use crate::paths::{expand_path, path_key};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RecoveryDraft {
+    pub document_path: String,
+    pub content: String,
+    pub saved_at: String,
+}
+
+/// Directory for recovery drafts: the `recovery_dir` setting when set,
+/// otherwise `recovery/` under the app data dir
+pub fn recovery_dir(state: &AppState) -> Result<PathBuf, String> {
+    let configured = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings()
+        .recovery_dir;
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    Ok(resolve_recovery_dir(configured.as_deref(), &app_data_dir))
+}
+
+fn resolve_recovery_dir(configured: Option<&str>, app_data_dir: &Path) -> PathBuf {
+    match configured.filter(|d| !d.trim().is_empty()) {
+        Some(dir) => expand_path(dir),
+        None => app_data_dir.join("recovery"),
+    }
+}
+
+/// Drafts are stored under a hash of the document path so any path maps to a safe file name
+fn draft_path(dir: &Path, document_path: &str) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(path_key(document_path).as_bytes());
+    dir.join(format!("{}.draft.json", hex::encode(&hasher.finalize()[..16])))
+}
+
+/// Recovery drafts sorted by whether they can safely be removed
+#[derive(Debug, Default)]
+pub struct DraftScan {
+    /// Drafts older than the last save of their (existing) document
+    pub stale: Vec<PathBuf>,
+    /// Unreadable drafts and drafts whose document can't be found, e.g. on an
+    /// unmounted drive. They may hold the only copy, so they are only reported.
+    pub unverified: Vec<PathBuf>,
+}
+
+/// Sort the drafts in `dir`; drafts saved after their document are in neither list
+pub fn scan_recovery_drafts(dir: &Path) -> DraftScan {
+    let mut scan = DraftScan::default();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return scan;
+    };
+    let drafts = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.to_string_lossy().ends_with(".draft.json"));
+    for path in drafts {
+        let Some(draft) = fs::read_to_string(&path)
+            .ok()
+            .and_then(|c| serde_json::from_str::<RecoveryDraft>(&c).ok())
+        else {
+            scan.unverified.push(path);
+            continue;
+        };
+        let Ok(metadata) = fs::metadata(expand_path(&draft.document_path)) else {
+            scan.unverified.push(path);
+            continue;
+        };
+        let saved_at = chrono::DateTime::parse_from_rfc3339(&draft.saved_at);
+        let modified = metadata.modified().map(chrono::DateTime::<chrono::Utc>::from);
+        if matches!((saved_at, modified), (Ok(saved_at), Ok(modified)) if modified > saved_at) {
+            scan.stale.push(path);
+        }
+    }
+    scan
+}
+
+#[tauri::command]
+pub fn write_recovery_draft(
+    document_path: String,
+    content: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    write_draft(&recovery_dir(&state)?, document_path, content)
+}
+
+fn write_draft(dir: &Path, document_path: String, content: String) -> Result<(), String> {
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create recovery dir: {}", e))?;
+
+    let path = draft_path(dir, &document_path);
+    let draft = RecoveryDraft {
+        document_path,
+        content,
+        saved_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let json = serde_json::to_string(&draft).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| format!("Failed to write recovery draft: {}", e))
+}
+
+#[tauri::command]
+pub fn get_recovery_draft(
+    document_path: String,
+    state: State<AppState>,
+) -> Result<Option<RecoveryDraft>, String> {
+    read_draft(&recovery_dir(&state)?, &document_path)
+}
+
+fn read_draft(dir: &Path, document_path: &str) -> Result<Option<RecoveryDraft>, String> {
+    let path = draft_path(dir, document_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let content =
+        fs::read_to_string(&path).map_err(|e| format!("Failed to read recovery draft: {}", e))?;
+    let draft = serde_json::from_str(&content)
+        .map_err(|e| format!("Failed to parse recovery draft: {}", e))?;
+    Ok(Some(draft))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draft_files(dir: &Path) -> Vec<PathBuf> {
+        fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|e| e.path()).collect())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn drafts_go_to_the_configured_directory() {
+        let app_data = tempfile::tempdir().unwrap();
+        let custom = tempfile::tempdir().unwrap();
+        let configured = custom.path().join("drafts");
+        let dir = resolve_recovery_dir(Some(&configured.to_string_lossy()), app_data.path());
+        assert_eq!(dir, configured);
+
+        write_draft(&dir, "/docs/a.lml".to_string(), "unsaved".to_string()).unwrap();
+        assert_eq!(draft_files(&configured).len(), 1);
+        assert!(!app_data.path().join("recovery").exists());
+
+        let draft = read_draft(&dir, "/docs/a.lml").unwrap().unwrap();
+        assert_eq!(draft.document_path, "/docs/a.lml");
+        assert_eq!(draft.content, "unsaved");
+    }
+
+    #[test]
+    fn drafts_default_to_the_app_data_dir() {
+        let app_data = tempfile::tempdir().unwrap();
+        let default = app_data.path().join("recovery");
+        assert_eq!(resolve_recovery_dir(None, app_data.path()), default);
+        // A blank setting counts as unset
+        let dir = resolve_recovery_dir(Some("  "), app_data.path());
+        assert_eq!(dir, default);
+
+        write_draft(&dir, "/docs/a.lml".to_string(), "unsaved".to_string()).unwrap();
+        assert_eq!(draft_files(&default).len(), 1);
+        assert!(read_draft(&dir, "/docs/other.lml").unwrap().is_none());
+    }
+}
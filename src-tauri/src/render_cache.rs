@@ -0,0 +1,489 @@
+use crate::formulas::{validate_latex, PreviewMode};
+use crate::paths::expand_path;
+use crate::AppState;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::time::SystemTime;
+use tauri::State;
+
+/// Cache budget used when `render_cache_max_mb` is unset
+pub const DEFAULT_RENDER_CACHE_MB: u64 = 64;
+
+/// Formulas rendered at once by `render_formulas_batch`; each runs a LaTeX process
+const RENDER_WORKERS: usize = 4;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderFormat {
+    Svg,
+    Png,
+}
+
+impl RenderFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            RenderFormat::Svg => "svg",
+            RenderFormat::Png => "png",
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FormulaRenderResult {
+    pub id: String,
+    /// SVG markup, or base64-encoded PNG data
+    pub output: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenderCacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// Rendered formula images (SVG/PNG) live under `render-cache/` in the app data dir
+pub fn render_cache_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("render-cache")
+}
+
+/// Files in the cache with their size and last-modified time
+fn cache_entries(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return vec![];
+    };
+    read_dir
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((entry.path(), metadata.len(), modified))
+        })
+        .collect()
+}
+
+/// Cache entries that aren't the SVG or PNG rendering of any of `latex_sources`
+pub fn orphaned_render_cache_entries(dir: &Path, latex_sources: &[String]) -> Vec<PathBuf> {
+    let live: HashSet<PathBuf> = latex_sources
+        .iter()
+        .flat_map(|latex| {
+            [RenderFormat::Svg, RenderFormat::Png].map(|f| render_cache_path(dir, latex, f))
+        })
+        .collect();
+    cache_entries(dir)
+        .into_iter()
+        .map(|(path, _, _)| path)
+        .filter(|path| !live.contains(path))
+        .collect()
+}
+
+/// Delete least-recently-used entries until the cache fits in `max_bytes`. Cache
+/// hits refresh an entry's mtime, so it orders entries by last use.
+/// Returns how many files were removed.
+pub fn evict_render_cache(dir: &Path, max_bytes: u64) -> usize {
+    let mut entries = cache_entries(dir);
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    entries.sort_by_key(|(_, _, modified)| *modified);
+
+    let mut removed = 0;
+    for (path, size, _) in entries {
+        if total <= max_bytes {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total -= size;
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// The cache budget from settings, in bytes
+pub fn render_cache_max_bytes(settings: &crate::settings::Settings) -> u64 {
+    settings
+        .render_cache_max_mb
+        .unwrap_or(DEFAULT_RENDER_CACHE_MB)
+        .saturating_mul(1024 * 1024)
+}
+
+/// Cache file for a formula, keyed by format and LaTeX source
+pub(crate) fn render_cache_path(dir: &Path, latex: &str, format: RenderFormat) -> PathBuf {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\0{}", format.extension(), latex.trim()).as_bytes());
+    dir.join(format!(
+        "{}.{}",
+        hex::encode(hasher.finalize()),
+        format.extension()
+    ))
+}
+
+/// `name` inside `bin_dir`, or the bare name to be found on `PATH`
+fn tool_path(bin_dir: Option<&str>, name: &str) -> PathBuf {
+    match bin_dir.filter(|d| !d.trim().is_empty()) {
+        Some(dir) => expand_path(dir).join(name),
+        None => PathBuf::from(name),
+    }
+}
+
+fn run_tool(command: &mut Command, name: &str) -> Result<(), String> {
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("Failed to run {}: {}", name, e))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    // LaTeX reports errors on stdout as `! message` lines
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let message = stdout
+        .lines()
+        .find(|l| l.starts_with('!'))
+        .map(|l| l.trim_start_matches('!').trim().to_string())
+        .unwrap_or_else(|| String::from_utf8_lossy(&output.stderr).trim().to_string());
+    Err(format!("{} failed: {}", name, message))
+}
+
+/// Typeset a formula with `latex`, then convert the DVI with `dvisvgm` or `dvipng`
+fn typeset_formula(
+    latex: &str,
+    format: RenderFormat,
+    bin_dir: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let work_dir = std::env::temp_dir().join(format!("lilia-render-{}", uuid::Uuid::new_v4()));
+    fs::create_dir_all(&work_dir).map_err(|e| format!("Failed to create render dir: {}", e))?;
+    let result = typeset_in(&work_dir, latex, format, bin_dir);
+    fs::remove_dir_all(&work_dir).ok();
+    result
+}
+
+fn typeset_in(
+    work_dir: &Path,
+    latex: &str,
+    format: RenderFormat,
+    bin_dir: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let source = format!(
+        "\\documentclass[preview]{{standalone}}\n\
+         \\usepackage{{amsmath,amssymb}}\n\
+         \\begin{{document}}\n$\\displaystyle {}$\n\\end{{document}}\n",
+        latex.trim()
+    );
+    fs::write(work_dir.join("formula.tex"), source)
+        .map_err(|e| format!("Failed to stage formula: {}", e))?;
+    run_tool(
+        Command::new(tool_path(bin_dir, "latex"))
+            .args(["-interaction=nonstopmode", "-halt-on-error", "formula.tex"])
+            .current_dir(work_dir),
+        "latex",
+    )?;
+
+    let output = work_dir.join(format!("formula.{}", format.extension()));
+    let (tool, args): (&str, &[&str]) = match format {
+        RenderFormat::Svg => ("dvisvgm", &["--no-fonts", "--exact-bbox", "-o"]),
+        RenderFormat::Png => (
+            "dvipng",
+            &["-D", "300", "-T", "tight", "-bg", "Transparent", "-o"],
+        ),
+    };
+    run_tool(
+        Command::new(tool_path(bin_dir, tool))
+            .args(args)
+            .arg(&output)
+            .arg("formula.dvi")
+            .current_dir(work_dir),
+        tool,
+    )?;
+    fs::read(&output).map_err(|e| format!("Failed to read rendered formula: {}", e))
+}
+
+/// Render one formula, reusing a cached image when the same LaTeX was rendered before
+pub fn render_formula(
+    cache_dir: &Path,
+    latex: &str,
+    format: RenderFormat,
+    bin_dir: Option<&str>,
+) -> Result<String, String> {
+    validate_latex(latex)?;
+    let cache_path = render_cache_path(cache_dir, latex, format);
+    let bytes = match fs::read(&cache_path) {
+        Ok(bytes) => {
+            // Mark the entry as recently used for eviction
+            fs::File::options()
+                .write(true)
+                .open(&cache_path)
+                .and_then(|f| f.set_modified(SystemTime::now()))
+                .ok();
+            bytes
+        }
+        Err(_) => {
+            let bytes = typeset_formula(latex, format, bin_dir)?;
+            // A failed cache write only costs a re-render next time
+            if fs::create_dir_all(cache_dir).is_ok() {
+                fs::write(&cache_path, &bytes).ok();
+            }
+            bytes
+        }
+    };
+    Ok(match format {
+        RenderFormat::Svg => String::from_utf8_lossy(&bytes).to_string(),
+        RenderFormat::Png => base64::engine::general_purpose::STANDARD.encode(bytes),
+    })
+}
+
+/// SVG for a live formula preview. Inline formulas are typeset in text style;
+/// the prefix also keeps their cache entries apart from display renders.
+pub fn render_preview_svg(
+    cache_dir: &Path,
+    latex: &str,
+    mode: PreviewMode,
+    bin_dir: Option<&str>,
+) -> Result<String, String> {
+    match mode {
+        PreviewMode::Display => render_formula(cache_dir, latex, RenderFormat::Svg, bin_dir),
+        PreviewMode::Inline => render_formula(
+            cache_dir,
+            &format!("\\textstyle {}", latex.trim()),
+            RenderFormat::Svg,
+            bin_dir,
+        ),
+    }
+}
+
+/// Render `(id, latex)` pairs on up to `RENDER_WORKERS` threads. Results keep input
+/// order; a `None` source is reported as an unknown formula.
+pub fn render_formulas(
+    formulas: &[(String, Option<String>)],
+    format: RenderFormat,
+    cache_dir: &Path,
+    bin_dir: Option<&str>,
+) -> Vec<FormulaRenderResult> {
+    let total = formulas.len();
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<Option<FormulaRenderResult>> = vec![None; total];
+
+    std::thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        for _ in 0..RENDER_WORKERS.min(total) {
+            let tx = tx.clone();
+            let next = &next;
+            scope.spawn(move || loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some((id, latex)) = formulas.get(index) else {
+                    break;
+                };
+                let outcome = match latex {
+                    Some(latex) => render_formula(cache_dir, latex, format, bin_dir),
+                    None => Err(format!("Formula not found: {}", id)),
+                };
+                if tx.send((index, outcome)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(tx);
+
+        for (index, outcome) in rx {
+            let (output, error) = match outcome {
+                Ok(output) => (Some(output), None),
+                Err(e) => (None, Some(e)),
+            };
+            let id = formulas[index].0.clone();
+            results[index] = Some(FormulaRenderResult { id, output, error });
+        }
+    });
+
+    results.into_iter().flatten().collect()
+}
+
+/// Pre-render formulas, e.g. for a printable sheet. Each formula succeeds or fails
+/// on its own, so one bad entry doesn't stop the rest.
+#[tauri::command]
+pub fn render_formulas_batch(
+    ids: Vec<String>,
+    format: RenderFormat,
+    state: State<AppState>,
+) -> Vec<FormulaRenderResult> {
+    let formulas: Vec<(String, Option<String>)> = {
+        let manager = state.formulas.lock().unwrap();
+        ids.into_iter()
+            .map(|id| {
+                let latex = manager.get(&id).map(|f| f.latex_content);
+                (id, latex)
+            })
+            .collect()
+    };
+    let settings = state.settings.lock().unwrap().get_settings();
+    let dir = render_cache_dir(&state.app_data_dir.lock().unwrap());
+
+    let results = render_formulas(&formulas, format, &dir, settings.latex_bin_dir.as_deref());
+    evict_render_cache(&dir, render_cache_max_bytes(&settings));
+    results
+}
+
+#[tauri::command]
+pub fn render_cache_stats(state: State<AppState>) -> RenderCacheStats {
+    cache_stats(&render_cache_dir(&state.app_data_dir.lock().unwrap()))
+}
+
+fn cache_stats(dir: &Path) -> RenderCacheStats {
+    let entries = cache_entries(dir);
+    RenderCacheStats {
+        entries: entries.len(),
+        bytes: entries.iter().map(|(_, size, _)| size).sum(),
+    }
+}
+
+#[tauri::command]
+pub fn clear_render_cache(state: State<AppState>) -> Result<(), String> {
+    clear_cache(&render_cache_dir(&state.app_data_dir.lock().unwrap()))
+}
+
+fn clear_cache(dir: &Path) -> Result<(), String> {
+    for (path, _, _) in cache_entries(dir) {
+        fs::remove_file(&path).map_err(|e| format!("Failed to clear render cache: {}", e))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A cache file of `size` bytes last written `age_secs` ago
+    fn cached(dir: &Path, name: &str, size: usize, age_secs: u64) -> PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, vec![0u8; size]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs(age_secs);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        path
+    }
+
+    #[test]
+    fn clearing_empties_the_cache() {
+        let app_data = tempfile::tempdir().unwrap();
+        let dir = render_cache_dir(app_data.path());
+        fs::create_dir_all(&dir).unwrap();
+        cached(&dir, "a.svg", 10, 0);
+        cached(&dir, "b.png", 30, 0);
+
+        let stats = cache_stats(&dir);
+        assert_eq!((stats.entries, stats.bytes), (2, 40));
+
+        clear_cache(&dir).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        assert_eq!(cache_stats(&dir).entries, 0);
+        // A cache that was never created is already clear
+        assert!(clear_cache(&app_data.path().join("missing")).is_ok());
+    }
+
+    #[test]
+    fn eviction_removes_the_oldest_entries_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let oldest = cached(dir.path(), "oldest.svg", 100, 300);
+        let older = cached(dir.path(), "older.svg", 100, 200);
+        let newer = cached(dir.path(), "newer.svg", 100, 100);
+        let newest = cached(dir.path(), "newest.svg", 100, 0);
+
+        assert_eq!(evict_render_cache(dir.path(), 250), 2);
+        assert!(!oldest.exists() && !older.exists());
+        assert!(newer.exists() && newest.exists());
+
+        // Already under budget
+        assert_eq!(evict_render_cache(dir.path(), 250), 0);
+        assert_eq!(evict_render_cache(dir.path(), 0), 2);
+    }
+
+    #[test]
+    fn cache_hits_protect_entries_from_eviction() {
+        let dir = tempfile::tempdir().unwrap();
+        let hit = render_cache_path(dir.path(), "x^2", RenderFormat::Svg);
+        fs::write(&hit, "<svg>x</svg>").unwrap();
+        let age = |path: &Path, secs| {
+            let modified = SystemTime::now() - Duration::from_secs(secs);
+            let file = fs::File::options().write(true).open(path).unwrap();
+            file.set_modified(modified).unwrap();
+        };
+        age(&hit, 300);
+        let unused = cached(dir.path(), "unused.svg", 12, 100);
+
+        // Written earlier, but used more recently than `unused`
+        render_formula(dir.path(), "x^2", RenderFormat::Svg, None).unwrap();
+        assert_eq!(evict_render_cache(dir.path(), 12), 1);
+        assert!(hit.exists());
+        assert!(!unused.exists());
+    }
+
+    #[test]
+    fn orphans_are_entries_for_formulas_no_longer_present() {
+        let dir = tempfile::tempdir().unwrap();
+        let live = "x^2".to_string();
+        let svg = render_cache_path(dir.path(), &live, RenderFormat::Svg);
+        let png = render_cache_path(dir.path(), &live, RenderFormat::Png);
+        let stale = render_cache_path(dir.path(), "y^2", RenderFormat::Svg);
+        for path in [&svg, &png, &stale] {
+            fs::write(path, "").unwrap();
+        }
+        assert_eq!(orphaned_render_cache_entries(dir.path(), &[live]), [stale]);
+    }
+
+    #[test]
+    fn batch_reports_each_formula_on_its_own() {
+        let dir = tempfile::tempdir().unwrap();
+        // Cached renders stand in for a LaTeX install
+        for (latex, svg) in [("x^2", "<svg>x</svg>"), ("y^2", "<svg>y</svg>")] {
+            fs::write(render_cache_path(dir.path(), latex, RenderFormat::Svg), svg).unwrap();
+        }
+        fs::write(
+            render_cache_path(dir.path(), "x^2", RenderFormat::Png),
+            [1u8, 2, 3],
+        )
+        .unwrap();
+
+        let formulas: Vec<(String, Option<String>)> = vec![
+            ("a".to_string(), Some("x^2".to_string())),
+            ("missing".to_string(), None),
+            ("b".to_string(), Some("\\frac{1}{2".to_string())),
+            ("c".to_string(), Some("y^2".to_string())),
+            ("d".to_string(), Some("  ".to_string())),
+            ("e".to_string(), Some("x^2".to_string())),
+        ];
+        let results = render_formulas(&formulas, RenderFormat::Svg, dir.path(), None);
+
+        let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["a", "missing", "b", "c", "d", "e"]);
+        assert_eq!(results[0].output.as_deref(), Some("<svg>x</svg>"));
+        assert_eq!(results[3].output.as_deref(), Some("<svg>y</svg>"));
+        assert_eq!(results[5].output.as_deref(), Some("<svg>x</svg>"));
+        assert_eq!(
+            results[1].error.as_deref(),
+            Some("Formula not found: missing")
+        );
+        for failed in [&results[1], &results[2], &results[4]] {
+            assert!(
+                failed.output.is_none() && failed.error.is_some(),
+                "{}",
+                failed.id
+            );
+        }
+
+        let png = render_formulas(&formulas[..1], RenderFormat::Png, dir.path(), None);
+        assert_eq!(png[0].output.as_deref(), Some("AQID"));
+        assert!(render_formulas(&[], RenderFormat::Svg, dir.path(), None).is_empty());
+    }
+}
@@ -0,0 +1,345 @@
+use crate::operations::Operation;
+use crate::paths::expand_path;
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
+
+/// Document types searched when no extensions are given
+const DEFAULT_SEARCH_EXTENSIONS: &[&str] = &["lml", "md", "markdown", "tex", "txt", "bib"];
+
+/// Larger files are skipped rather than read into memory
+const MAX_SEARCH_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchOptions {
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// File extensions to search, without the dot
+    #[serde(default)]
+    pub extensions: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub path: String,
+    /// 1-based line and character column of the match
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResultEvent {
+    pub search_id: String,
+    pub result: SearchMatch,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchCompleteEvent {
+    pub search_id: String,
+    pub total_matches: usize,
+    pub files_searched: usize,
+    pub cancelled: bool,
+}
+
+/// Walk `dir` in sorted order, skipping hidden entries, and call `on_match` for
+/// each line containing `query`. Stops as soon as `on_match` returns false or
+/// `operation` is cancelled. Returns the number of files searched.
+pub fn walk_matches(
+    dir: &Path,
+    query: &str,
+    options: &SearchOptions,
+    operation: Option<&Operation>,
+    on_match: &mut dyn FnMut(SearchMatch) -> bool,
+) -> usize {
+    let extensions: Vec<String> = match &options.extensions {
+        Some(exts) => exts
+            .iter()
+            .map(|e| e.trim_start_matches('.').to_lowercase())
+            .collect(),
+        None => DEFAULT_SEARCH_EXTENSIONS.iter().map(|e| e.to_string()).collect(),
+    };
+
+    let mut files_searched = 0;
+    let mut pending: Vec<PathBuf> = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&current) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries
+            .flatten()
+            .filter(|e| !e.file_name().to_string_lossy().starts_with('.'))
+            .collect();
+        // Reversed so popping from the stack visits entries in name order
+        entries.sort_by_key(|e| std::cmp::Reverse(e.file_name()));
+
+        for entry in entries {
+            if operation.is_some_and(Operation::is_cancelled) {
+                return files_searched;
+            }
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                pending.push(path);
+                continue;
+            }
+            let matches_extension = path
+                .extension()
+                .is_some_and(|e| extensions.contains(&e.to_string_lossy().to_lowercase()));
+            let small_enough = entry
+                .metadata()
+                .is_ok_and(|m| m.len() <= MAX_SEARCH_FILE_BYTES);
+            if !matches_extension || !small_enough {
+                continue;
+            }
+            let Ok(content) = fs::read_to_string(&path) else {
+                continue;
+            };
+
+            files_searched += 1;
+            for (i, line) in content.lines().enumerate() {
+                let Some(column) = match_column(line, query, options.case_sensitive) else {
+                    continue;
+                };
+                let found = SearchMatch {
+                    path: path.to_string_lossy().to_string(),
+                    line: i + 1,
+                    column,
+                    text: line.to_string(),
+                };
+                if !on_match(found) {
+                    return files_searched;
+                }
+            }
+        }
+    }
+    files_searched
+}
+
+/// 1-based character column of the first occurrence of `query` in `line`.
+/// Lowercasing can change a character's length (e.g. `İ` becomes two chars),
+/// so case-insensitive positions are mapped back to characters of `line`.
+fn match_column(line: &str, query: &str, case_sensitive: bool) -> Option<usize> {
+    if case_sensitive {
+        let pos = line.find(query)?;
+        return Some(line[..pos].chars().count() + 1);
+    }
+    let needle: String = query.chars().flat_map(char::to_lowercase).collect();
+    let mut haystack = String::with_capacity(line.len());
+    // Index in `line` of the character each byte of `haystack` came from
+    let mut origin = Vec::with_capacity(line.len());
+    for (index, c) in line.chars().enumerate() {
+        for lower in c.to_lowercase() {
+            origin.extend(std::iter::repeat_n(index, lower.len_utf8()));
+            haystack.push(lower);
+        }
+    }
+    let pos = haystack.find(&needle)?;
+    Some(origin[pos] + 1)
+}
+
+/// Every line under `directory` containing `query`
+#[tauri::command]
+pub fn search_in_directory(
+    directory: String,
+    query: String,
+    options: Option<SearchOptions>,
+) -> Result<Vec<SearchMatch>, String> {
+    if query.is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+    let options = options.unwrap_or_default();
+    let limit = options.max_results.unwrap_or(usize::MAX);
+    let mut results = vec![];
+    walk_matches(&expand_path(&directory), &query, &options, None, &mut |found| {
+        results.push(found);
+        results.len() < limit
+    });
+    Ok(results)
+}
+
+/// Like `search_in_directory`, but each match is emitted as a `search-result`
+/// event as soon as it is found, followed by one `search-complete` event.
+/// `search_id` tags the events and doubles as the id for `cancel_operation`.
+#[tauri::command]
+pub async fn search_in_directory_streaming(
+    search_id: String,
+    directory: String,
+    query: String,
+    options: Option<SearchOptions>,
+    app: AppHandle,
+) -> Result<(), String> {
+    if query.is_empty() {
+        return Err("Search query is empty".to_string());
+    }
+    let options = options.unwrap_or_default();
+
+    tauri::async_runtime::spawn_blocking(move || {
+        let state = app.state::<AppState>();
+        let operation = Operation::begin(&state.operations, Some(search_id.clone()));
+        let complete = stream_search(
+            search_id,
+            &expand_path(&directory),
+            &query,
+            &options,
+            &operation,
+            &mut |event| {
+                app.emit("search-result", event).ok();
+            },
+        );
+        app.emit("search-complete", complete).ok();
+    })
+    .await
+    .map_err(|e| format!("Search failed: {}", e))
+}
+
+/// Hand each match under `root` to `emit` as it is found, stopping at
+/// `max_results` or on cancellation, and summarize the run for `search-complete`
+fn stream_search(
+    search_id: String,
+    root: &Path,
+    query: &str,
+    options: &SearchOptions,
+    operation: &Operation,
+    emit: &mut dyn FnMut(SearchResultEvent),
+) -> SearchCompleteEvent {
+    let limit = options.max_results.unwrap_or(usize::MAX);
+    let mut total_matches = 0;
+    let files_searched = walk_matches(root, query, options, Some(operation), &mut |found| {
+        total_matches += 1;
+        emit(SearchResultEvent {
+            search_id: search_id.clone(),
+            result: found,
+        });
+        total_matches < limit
+    });
+
+    SearchCompleteEvent {
+        search_id,
+        total_matches,
+        files_searched,
+        cancelled: operation.is_cancelled(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operations::CancelFlags;
+
+    /// Streamed events in arrival order, with the completion event last
+    enum Event {
+        Result(SearchResultEvent),
+        Complete(SearchCompleteEvent),
+    }
+
+    fn project() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("chapters")).unwrap();
+        fs::create_dir_all(dir.path().join(".git")).unwrap();
+        fs::write(dir.path().join("a.lml"), "Energy\nno match\nenergy again").unwrap();
+        fs::write(dir.path().join("chapters/b.md"), "Kinetic energy").unwrap();
+        fs::write(dir.path().join("figure.png"), "energy").unwrap();
+        fs::write(dir.path().join(".git/notes.txt"), "energy").unwrap();
+        dir
+    }
+
+    fn stream(
+        root: &Path,
+        options: &SearchOptions,
+        operation: &Operation,
+        on_result: impl Fn(&SearchResultEvent),
+    ) -> Vec<Event> {
+        let mut events = vec![];
+        let complete = stream_search(
+            "s1".to_string(),
+            root,
+            "energy",
+            options,
+            operation,
+            &mut |event| {
+                on_result(&event);
+                events.push(Event::Result(event));
+            },
+        );
+        events.push(Event::Complete(complete));
+        events
+    }
+
+    #[test]
+    fn completion_follows_the_last_streamed_match() {
+        let dir = project();
+        let flags = CancelFlags::default();
+        let operation = Operation::begin(&flags, Some("s1".to_string()));
+        let events = stream(dir.path(), &SearchOptions::default(), &operation, |_| {});
+
+        let (last, results) = events.split_last().unwrap();
+        let found: Vec<(String, usize, usize)> = results
+            .iter()
+            .map(|event| match event {
+                Event::Result(e) => {
+                    assert_eq!(e.search_id, "s1");
+                    let relative = Path::new(&e.result.path).strip_prefix(dir.path()).unwrap();
+                    let relative = relative.to_string_lossy().replace('\\', "/");
+                    (relative, e.result.line, e.result.column)
+                }
+                Event::Complete(_) => panic!("search-complete fired before a result"),
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("a.lml".to_string(), 1, 1),
+                ("a.lml".to_string(), 3, 1),
+                ("chapters/b.md".to_string(), 1, 9),
+            ]
+        );
+        let Event::Complete(complete) = last else {
+            panic!("search-complete was not last");
+        };
+        assert_eq!(complete.search_id, "s1");
+        assert_eq!((complete.total_matches, complete.files_searched), (3, 2));
+        assert!(!complete.cancelled);
+    }
+
+    #[test]
+    fn columns_count_characters_of_the_original_line() {
+        // `İ` lowercases to two characters, which must not shift the column
+        let line = "İstanbul Café, café";
+        assert_eq!(match_column(line, "café", false), Some(10));
+        assert_eq!(match_column(line, "CAFÉ", false), Some(10));
+        assert_eq!(match_column(line, "café", true), Some(16));
+        assert_eq!(match_column(line, "i̇stanbul", false), Some(1));
+        assert_eq!(match_column(line, "tea", false), None);
+    }
+
+    #[test]
+    fn results_stop_at_the_limit_or_on_cancellation() {
+        let dir = project();
+        let flags = CancelFlags::default();
+        let operation = Operation::begin(&flags, Some("s1".to_string()));
+        let options = SearchOptions {
+            max_results: Some(2),
+            ..Default::default()
+        };
+        let events = stream(dir.path(), &options, &operation, |_| {});
+        assert_eq!(events.len(), 3);
+
+        let flag = operation.flag();
+        let events = stream(dir.path(), &SearchOptions::default(), &operation, |_| {
+            flag.store(true, std::sync::atomic::Ordering::SeqCst)
+        });
+        // The file being read finishes, but no further files are opened
+        let Some(Event::Complete(complete)) = events.last() else {
+            panic!("search-complete was not last");
+        };
+        assert!(complete.cancelled);
+        assert_eq!((complete.total_matches, complete.files_searched), (2, 1));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// An open tab and where the user was in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionFile {
+    pub path: String,
+    #[serde(default)]
+    pub cursor_line: u32,
+    #[serde(default)]
+    pub scroll_top: f64,
+}
+
+/// Open tabs in display order, plus the one that had focus
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    #[serde(default)]
+    pub files: Vec<SessionFile>,
+    #[serde(default)]
+    pub active_path: Option<String>,
+}
+
+pub struct SessionManager {
+    path: PathBuf,
+    session: Session,
+}
+
+impl SessionManager {
+    pub fn new(path: PathBuf) -> Self {
+        let session = Self::load_from_path(&path).unwrap_or_default();
+        Self { path, session }
+    }
+
+    fn load_from_path(path: &Path) -> io::Result<Session> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// The saved session with tabs for deleted or moved files dropped
+    pub fn get_session(&self) -> Session {
+        let mut session = self.session.clone();
+        session.files.retain(|f| Path::new(&f.path).exists());
+        if session
+            .active_path
+            .as_ref()
+            .is_some_and(|active| !session.files.iter().any(|f| &f.path == active))
+        {
+            session.active_path = session.files.first().map(|f| f.path.clone());
+        }
+        session
+    }
+
+    pub fn set_session(&mut self, session: Session) {
+        self.session = session;
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let content = serde_json::to_string_pretty(&self.session)?;
+        fs::write(&self.path, content)
+    }
+}
+
+#[tauri::command]
+pub fn save_session(session: Session, state: State<AppState>) -> Result<(), String> {
+    let mut manager = state.session.lock().unwrap();
+    manager.set_session(session);
+    manager.save().map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn get_session(state: State<AppState>) -> Session {
+    let manager = state.session.lock().unwrap();
+    manager.get_session()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(path: &Path, cursor_line: u32, scroll_top: f64) -> SessionFile {
+        SessionFile {
+            path: path.to_string_lossy().to_string(),
+            cursor_line,
+            scroll_top,
+        }
+    }
+
+    #[test]
+    fn tab_order_and_positions_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = ["c.lml", "a.lml", "b.lml"];
+        let files: Vec<SessionFile> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let path = dir.path().join(name);
+                fs::write(&path, "").unwrap();
+                open(&path, i as u32 * 10, i as f64 * 100.5)
+            })
+            .collect();
+        let active = files[1].path.clone();
+
+        let session_path = dir.path().join("session.json");
+        let mut manager = SessionManager::new(session_path.clone());
+        manager.set_session(Session {
+            files: files.clone(),
+            active_path: Some(active.clone()),
+        });
+        manager.save().unwrap();
+
+        let restored = SessionManager::new(session_path).get_session();
+        let paths: Vec<&str> = restored.files.iter().map(|f| f.path.as_str()).collect();
+        let expected: Vec<&str> = files.iter().map(|f| f.path.as_str()).collect();
+        assert_eq!(paths, expected);
+        assert_eq!(restored.files[2].cursor_line, 20);
+        assert_eq!(restored.files[2].scroll_top, 201.0);
+        assert_eq!(restored.active_path, Some(active));
+    }
+
+    #[test]
+    fn missing_files_are_pruned_on_restore() {
+        let dir = tempfile::tempdir().unwrap();
+        let kept = dir.path().join("kept.lml");
+        let gone = dir.path().join("gone.lml");
+        fs::write(&kept, "").unwrap();
+
+        let mut manager = SessionManager::new(dir.path().join("session.json"));
+        manager.set_session(Session {
+            files: vec![open(&gone, 3, 0.0), open(&kept, 7, 12.0)],
+            active_path: Some(gone.to_string_lossy().to_string()),
+        });
+
+        let restored = manager.get_session();
+        assert_eq!(restored.files.len(), 1);
+        assert_eq!(restored.files[0].path, kept.to_string_lossy());
+        assert_eq!(restored.files[0].cursor_line, 7);
+        // Focus falls back to the first surviving tab
+        assert_eq!(
+            restored.active_path,
+            Some(kept.to_string_lossy().to_string())
+        );
+    }
+
+    #[test]
+    fn unreadable_session_file_starts_empty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        fs::write(&path, "{ not json").unwrap();
+
+        let session = SessionManager::new(path).get_session();
+        assert!(session.files.is_empty());
+        assert_eq!(session.active_path, None);
+    }
+}
@@ -1,9 +1,16 @@
 use crate::commands::WindowState;
+use crate::errors::JsonParseError;
+use crate::formulas::FormulaRankWeights;
+use crate::versions::WordCountOptions;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::path::PathBuf;
 
+/// Bumped whenever the persisted settings shape changes incompatibly
+pub const SETTINGS_SCHEMA_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Settings {
@@ -20,6 +27,9 @@ pub struct Settings {
     pub line_numbers: bool,
     #[serde(default = "default_true")]
     pub minimap: bool,
+    /// Lines longer than this are reported by the linter; 0 turns the check off
+    #[serde(default = "default_lint_max_line_length")]
+    pub lint_max_line_length: u32,
 
     // Theme
     #[serde(default = "default_theme")]
@@ -40,10 +50,101 @@ pub struct Settings {
     // Window state
     #[serde(default)]
     pub window_state: Option<WindowState>,
+    /// Saved state of windows other than `main`, by window label
+    #[serde(default)]
+    pub window_states: HashMap<String, WindowState>,
 
     // Last opened directory
     #[serde(default)]
     pub last_directory: Option<String>,
+
+    // Symlinked documents
+    /// Key version history and recent files by the fully resolved path, so every
+    /// link to a file shares them. Off by default: history saved under a link's
+    /// own path isn't found again once this is turned on.
+    #[serde(default)]
+    pub resolve_symlinks: bool,
+
+    // Recovery drafts; defaults to the app data dir when unset
+    #[serde(default)]
+    pub recovery_dir: Option<String>,
+
+    // Version history
+    #[serde(default)]
+    pub version_storage_quota_mb: Option<u64>,
+    /// Where version history is kept; defaults to `versions/` in the app data dir
+    #[serde(default)]
+    pub version_store_dir: Option<String>,
+    /// How word counts are computed for version history
+    #[serde(default)]
+    pub word_count_options: WordCountOptions,
+    /// gzip level for snapshots, 0 (fastest) to 9 (smallest)
+    #[serde(default = "default_version_compression_level")]
+    pub version_compression_level: u32,
+    /// Treat saves differing only in trailing whitespace as unchanged
+    #[serde(default)]
+    pub version_dedup_ignore_whitespace: bool,
+    /// Treat saves differing only in CRLF/LF line endings as unchanged
+    #[serde(default)]
+    pub version_dedup_normalize_line_endings: bool,
+    /// Store each document's version manifest as `manifest.json.gz`
+    #[serde(default)]
+    pub compress_version_manifests: bool,
+
+    // Formula library
+    /// Filled in on new formulas created without a category
+    #[serde(default)]
+    pub default_formula_category: Option<String>,
+    #[serde(default)]
+    pub default_formula_subcategory: Option<String>,
+    /// How `get_formulas_smart` orders the quick-insert picker
+    #[serde(default)]
+    pub formula_rank_weights: FormulaRankWeights,
+
+    // Bibliography lookups
+    #[serde(default)]
+    pub crossref_contact_email: Option<String>,
+    #[serde(default)]
+    pub offline_mode: bool,
+    /// Citation key template, e.g. `{author}{year}{title:1}`
+    #[serde(default)]
+    pub citation_key_pattern: Option<String>,
+    /// e.g. `http://proxy.example.com:8080`; the environment's proxy is used when unset
+    #[serde(default)]
+    pub http_proxy: Option<String>,
+    #[serde(default = "default_http_timeout_secs")]
+    pub http_timeout_secs: u64,
+
+    // Formula rendering
+    /// Size budget for the render cache; `DEFAULT_RENDER_CACHE_MB` when unset
+    #[serde(default)]
+    pub render_cache_max_mb: Option<u64>,
+    /// Directory holding `latex`, `dvisvgm` and `dvipng`; they're looked up on `PATH` when unset
+    #[serde(default)]
+    pub latex_bin_dir: Option<String>,
+
+    // Export
+    #[serde(default)]
+    pub pdf_engine_path: Option<String>,
+
+    // Custom build, e.g. `latexmk -pdf {file}`
+    #[serde(default)]
+    pub build_command: Option<String>,
+    #[serde(default = "default_build_timeout_secs")]
+    pub build_timeout_secs: u64,
+
+    // Quick capture
+    /// Global accelerator, e.g. `CmdOrCtrl+Shift+N`
+    #[serde(default)]
+    pub quick_capture_shortcut: Option<String>,
+    /// Where captured notes are created; defaults to `quick-capture/` in the app data dir
+    #[serde(default)]
+    pub quick_capture_dir: Option<String>,
+
+    // Diagnostics
+    /// Record each invoked command name and outcome to `debug.log`
+    #[serde(default)]
+    pub debug_logging: bool,
 }
 
 fn default_font_size() -> u32 {
@@ -62,6 +163,10 @@ fn default_true() -> bool {
     true
 }
 
+fn default_lint_max_line_length() -> u32 {
+    100
+}
+
 fn default_theme() -> String {
     "dark".to_string()
 }
@@ -74,6 +179,18 @@ fn default_auto_save_delay() -> u32 {
     5000
 }
 
+fn default_http_timeout_secs() -> u64 {
+    crate::metadata::DEFAULT_HTTP_TIMEOUT_SECS
+}
+
+fn default_build_timeout_secs() -> u64 {
+    120
+}
+
+fn default_version_compression_level() -> u32 {
+    6
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
@@ -83,13 +200,40 @@ impl Default for Settings {
             word_wrap: false,
             line_numbers: true,
             minimap: true,
+            lint_max_line_length: default_lint_max_line_length(),
             theme: default_theme(),
             live_preview: true,
             preview_font_size: default_preview_font_size(),
             auto_save: false,
             auto_save_delay: default_auto_save_delay(),
             window_state: None,
+            window_states: HashMap::new(),
             last_directory: None,
+            resolve_symlinks: false,
+            recovery_dir: None,
+            version_storage_quota_mb: None,
+            version_store_dir: None,
+            word_count_options: WordCountOptions::default(),
+            version_compression_level: default_version_compression_level(),
+            version_dedup_ignore_whitespace: false,
+            version_dedup_normalize_line_endings: false,
+            compress_version_manifests: false,
+            default_formula_category: None,
+            default_formula_subcategory: None,
+            formula_rank_weights: FormulaRankWeights::default(),
+            crossref_contact_email: None,
+            offline_mode: false,
+            citation_key_pattern: None,
+            http_proxy: None,
+            http_timeout_secs: default_http_timeout_secs(),
+            render_cache_max_mb: None,
+            latex_bin_dir: None,
+            pdf_engine_path: None,
+            build_command: None,
+            build_timeout_secs: default_build_timeout_secs(),
+            quick_capture_shortcut: None,
+            quick_capture_dir: None,
+            debug_logging: false,
         }
     }
 }
@@ -97,12 +241,25 @@ impl Default for Settings {
 pub struct SettingsManager {
     path: PathBuf,
     settings: Settings,
+    /// Set when the settings file existed but could not be parsed
+    load_error: Option<JsonParseError>,
 }
 
 impl SettingsManager {
     pub fn new(path: PathBuf) -> Self {
-        let settings = Self::load_from_path(&path).unwrap_or_default();
-        Self { path, settings }
+        let (settings, load_error) = match Self::load_from_path(&path) {
+            Ok(settings) => (settings, None),
+            Err(e) => (Settings::default(), JsonParseError::from_load_error(&path, &e)),
+        };
+        Self {
+            path,
+            settings,
+            load_error,
+        }
+    }
+
+    pub fn load_error(&self) -> Option<&JsonParseError> {
+        self.load_error.as_ref()
     }
 
     fn load_from_path(path: &PathBuf) -> io::Result<Settings> {
@@ -118,8 +275,95 @@ impl SettingsManager {
         self.settings = settings;
     }
 
+    /// The current settings with the fields present in `patch` replaced. The
+    /// frontend only sends the fields it edits, so anything it leaves out, such
+    /// as `versionStoreDir` or `httpProxy`, keeps its saved value.
+    pub fn merged_settings(&self, patch: serde_json::Value) -> Result<Settings, String> {
+        let serde_json::Value::Object(patch) = patch else {
+            return Err("Settings update must be an object".to_string());
+        };
+        let mut merged = serde_json::to_value(&self.settings).map_err(|e| e.to_string())?;
+        if let serde_json::Value::Object(fields) = &mut merged {
+            fields.extend(patch);
+        }
+        serde_json::from_value(merged).map_err(|e| format!("Invalid settings: {}", e))
+    }
+
+    /// Forget saved state for windows not in `active_labels`. The main window's
+    /// state is kept separately and never pruned. Returns how many were dropped.
+    pub fn prune_window_states(&mut self, active_labels: &[String]) -> usize {
+        let before = self.settings.window_states.len();
+        self.settings
+            .window_states
+            .retain(|label, _| active_labels.contains(label));
+        before - self.settings.window_states.len()
+    }
+
     pub fn save(&self) -> io::Result<()> {
         let content = serde_json::to_string_pretty(&self.settings)?;
         fs::write(&self.path, content)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_settings_report_the_line_and_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(&path, "{\n  \"theme\": \"dark\"\n  \"autoSave\": true\n}\n").unwrap();
+
+        let manager = SettingsManager::new(path.clone());
+        let error = manager.load_error().unwrap();
+        assert_eq!(error.file, path.to_string_lossy());
+        assert_eq!((error.line, error.column), (3, 3));
+        assert!(error.message.contains("line 3 column 3"));
+        // Defaults are used until the file is fixed
+        assert_eq!(manager.get_settings().theme, Settings::default().theme);
+    }
+
+    #[test]
+    fn a_missing_settings_file_is_not_a_parse_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let manager = SettingsManager::new(dir.path().join("settings.json"));
+        assert!(manager.load_error().is_none());
+    }
+
+    #[test]
+    fn stale_window_states_are_pruned_but_main_is_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut manager = SettingsManager::new(dir.path().join("settings.json"));
+        let state = WindowState {
+            width: 800,
+            height: 600,
+            x: 10,
+            y: 20,
+            maximized: false,
+        };
+        let mut settings = manager.get_settings();
+        settings.window_state = Some(state.clone());
+        for label in ["preview", "formulas", "old-inspector"] {
+            settings
+                .window_states
+                .insert(label.to_string(), state.clone());
+        }
+        manager.update_settings(settings);
+
+        let active = [
+            "main".to_string(),
+            "preview".to_string(),
+            "formulas".to_string(),
+        ];
+        assert_eq!(manager.prune_window_states(&active), 1);
+
+        let settings = manager.get_settings();
+        let mut labels: Vec<&String> = settings.window_states.keys().collect();
+        labels.sort();
+        assert_eq!(labels, ["formulas", "preview"]);
+        // The main window's state is stored apart and survives any label set
+        assert_eq!(manager.prune_window_states(&[]), 2);
+        assert!(manager.get_settings().window_state.is_some());
+    }
+}
@@ -0,0 +1,205 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    None,
+    Left,
+    Center,
+    Right,
+}
+
+/// Split a table row on unescaped pipes, dropping the outer pipes
+fn split_row(line: &str) -> Vec<String> {
+    let trimmed = line.trim();
+    let inner = trimmed.strip_prefix('|').unwrap_or(trimmed);
+    let inner = match inner.strip_suffix('|') {
+        Some(rest) if !rest.ends_with('\\') => rest,
+        _ => inner,
+    };
+
+    let mut cells = vec![];
+    let mut current = String::new();
+    let mut chars = inner.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'|') => {
+                current.push('\\');
+                current.push(chars.next().unwrap_or('|'));
+            }
+            '|' => cells.push(std::mem::take(&mut current).trim().to_string()),
+            _ => current.push(c),
+        }
+    }
+    cells.push(current.trim().to_string());
+    cells
+}
+
+/// A separator row cell looks like `---`, `:---`, `---:`, or `:---:`
+fn parse_separator(cells: &[String]) -> Option<Vec<Align>> {
+    cells
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_start_matches(':').trim_end_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Align::Center,
+                (true, false) => Align::Left,
+                (false, true) => Align::Right,
+                (false, false) => Align::None,
+            })
+        })
+        .collect()
+}
+
+fn pad(cell: &str, width: usize, align: Align) -> String {
+    let fill = width.saturating_sub(cell.chars().count());
+    match align {
+        Align::Right => format!("{}{}", " ".repeat(fill), cell),
+        Align::Center => format!(
+            "{}{}{}",
+            " ".repeat(fill / 2),
+            cell,
+            " ".repeat(fill - fill / 2)
+        ),
+        Align::None | Align::Left => format!("{}{}", cell, " ".repeat(fill)),
+    }
+}
+
+fn separator_cell(width: usize, align: Align) -> String {
+    match align {
+        Align::None => "-".repeat(width),
+        Align::Left => format!(":{}", "-".repeat(width - 1)),
+        Align::Right => format!("{}:", "-".repeat(width - 1)),
+        Align::Center => format!(":{}:", "-".repeat(width - 2)),
+    }
+}
+
+/// Re-emit a pipe-delimited table with aligned columns and a normalized
+/// separator row. Ragged rows are padded with empty cells.
+pub fn format_table_text(table_text: &str) -> Result<String, String> {
+    let lines: Vec<&str> = table_text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    if lines.is_empty() {
+        return Err("Table is empty".to_string());
+    }
+    if let Some(line) = lines.iter().find(|l| !l.contains('|')) {
+        return Err(format!("Not a table row: {}", line));
+    }
+
+    let mut rows: Vec<Vec<String>> = lines.iter().map(|l| split_row(l)).collect();
+
+    // The second row is the separator when present; otherwise the first row is the header
+    let mut aligns = match rows.get(1).and_then(|r| parse_separator(r)) {
+        Some(aligns) => {
+            rows.remove(1);
+            aligns
+        }
+        None => vec![],
+    };
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    aligns.resize(columns, Align::None);
+    for row in &mut rows {
+        row.resize(columns, String::new());
+    }
+
+    let widths: Vec<usize> = (0..columns)
+        .map(|col| {
+            rows.iter()
+                .map(|r| r[col].chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+
+    let render = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+
+    let mut out = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        out.push(render(
+            row.iter()
+                .zip(&widths)
+                .zip(&aligns)
+                .map(|((cell, w), a)| pad(cell, *w, *a))
+                .collect(),
+        ));
+        if i == 0 {
+            out.push(render(
+                widths
+                    .iter()
+                    .zip(&aligns)
+                    .map(|(w, a)| separator_cell(*w, *a))
+                    .collect(),
+            ));
+        }
+    }
+
+    Ok(out.join("\n"))
+}
+
+#[tauri::command]
+pub fn format_table(table_text: String) -> Result<String, String> {
+    format_table_text(&table_text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_a_misaligned_table() {
+        let table = "| Name | Value |\n|:-|--:|\n|alpha|1|\n| b | 12345 |";
+        assert_eq!(
+            format_table_text(table).unwrap(),
+            "| Name  | Value |\n\
+             | :---- | ----: |\n\
+             | alpha |     1 |\n\
+             | b     | 12345 |"
+        );
+    }
+
+    #[test]
+    fn pads_rows_with_missing_cells() {
+        let table = "| a | b | c |\n|---|---|---|\n| 1 | 2 |\n| 1 | 2 | 3 |";
+        assert_eq!(
+            format_table_text(table).unwrap(),
+            "| a   | b   | c   |\n\
+             | --- | --- | --- |\n\
+             | 1   | 2   |     |\n\
+             | 1   | 2   | 3   |"
+        );
+    }
+
+    #[test]
+    fn adds_a_separator_and_keeps_escaped_pipes() {
+        let table = "x | y\nleft :---: | a \\| b";
+        assert_eq!(
+            format_table_text(table).unwrap(),
+            "| x          | y      |\n\
+             | ---------- | ------ |\n\
+             | left :---: | a \\| b |"
+        );
+    }
+
+    #[test]
+    fn centers_cells() {
+        let table = "|h|\n|:-:|\n|ab|";
+        assert_eq!(
+            format_table_text(table).unwrap(),
+            "|  h  |\n| :-: |\n| ab  |"
+        );
+    }
+
+    #[test]
+    fn rejects_text_that_is_not_a_table() {
+        assert!(format_table_text("  \n").is_err());
+        assert_eq!(
+            format_table_text("| a |\nplain text").unwrap_err(),
+            "Not a table row: plain text"
+        );
+    }
+}
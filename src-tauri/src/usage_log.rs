@@ -0,0 +1,183 @@
+use crate::formulas::{csv_field, FormulaManager};
+use crate::AppState;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+/// The active log is rotated to `formula_usage.log.1` once it passes this size
+const MAX_USAGE_LOG_BYTES: u64 = 1024 * 1024;
+
+fn usage_log_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("formula_usage.log")
+}
+
+fn rotated_log_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join("formula_usage.log.1")
+}
+
+/// Append a `timestamp,formula_id` line, rotating the log first if it's full.
+/// Only one rotated file is kept, so history is bounded to about twice the cap.
+pub fn record_usage(app_data_dir: &Path, formula_id: &str) -> io::Result<()> {
+    let path = usage_log_path(app_data_dir);
+    if fs::metadata(&path).is_ok_and(|m| m.len() >= MAX_USAGE_LOG_BYTES) {
+        fs::rename(&path, rotated_log_path(app_data_dir))?;
+    }
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{},{}", Utc::now().to_rfc3339(), formula_id)
+}
+
+/// Logged `(timestamp, formula_id)` pairs, oldest first. Malformed lines are skipped.
+pub fn read_usage_log(app_data_dir: &Path) -> Vec<(DateTime<Utc>, String)> {
+    [rotated_log_path(app_data_dir), usage_log_path(app_data_dir)]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| {
+                    let (timestamp, id) = line.split_once(',')?;
+                    let timestamp = DateTime::parse_from_rfc3339(timestamp).ok()?;
+                    Some((timestamp.with_timezone(&Utc), id.to_string()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Accept a full RFC 3339 timestamp or a plain `YYYY-MM-DD` date (midnight UTC)
+fn parse_since(since: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(since) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(since, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|d| d.and_utc())
+        .ok_or_else(|| format!("Invalid date '{}': expected YYYY-MM-DD or RFC 3339", since))
+}
+
+/// Write the usage log as CSV (`timestamp,formula_id,formula_name`), optionally
+/// only entries at or after `since`
+#[tauri::command]
+pub fn export_usage_log(
+    path: String,
+    since: Option<String>,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let since = since.as_deref().map(parse_since).transpose()?;
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .clone();
+
+    let csv = usage_csv(&app_data_dir, since, &state.formulas.lock().unwrap());
+    Ok(crate::commands::write_file(path, csv, None)?)
+}
+
+fn usage_csv(
+    app_data_dir: &Path,
+    since: Option<DateTime<Utc>>,
+    formulas: &FormulaManager,
+) -> String {
+    let mut csv = String::from("timestamp,formula_id,formula_name\n");
+    for (timestamp, id) in read_usage_log(app_data_dir) {
+        if since.is_some_and(|since| timestamp < since) {
+            continue;
+        }
+        let name = formulas.get(&id).map(|f| f.name).unwrap_or_default();
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            timestamp.to_rfc3339(),
+            csv_field(&id),
+            csv_field(&name)
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formulas::Formula;
+
+    #[test]
+    fn recording_usage_appends_a_log_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let before = Utc::now();
+        record_usage(dir.path(), "f1").unwrap();
+        record_usage(dir.path(), "f2").unwrap();
+
+        let entries = read_usage_log(dir.path());
+        let ids: Vec<&str> = entries.iter().map(|(_, id)| id.as_str()).collect();
+        assert_eq!(ids, ["f1", "f2"]);
+        assert!(entries.iter().all(|(timestamp, _)| *timestamp >= before));
+    }
+
+    #[test]
+    fn a_full_log_is_rotated_before_appending() {
+        let dir = tempfile::tempdir().unwrap();
+        let line = "2024-01-01T00:00:00+00:00,old\n";
+        let full = line.repeat(MAX_USAGE_LOG_BYTES as usize / line.len() + 1);
+        fs::write(usage_log_path(dir.path()), &full).unwrap();
+
+        record_usage(dir.path(), "new").unwrap();
+        assert_eq!(
+            fs::read_to_string(rotated_log_path(dir.path())).unwrap(),
+            full
+        );
+        let entries = read_usage_log(dir.path());
+        assert_eq!(entries.last().unwrap().1, "new");
+        assert_eq!(entries.len(), full.lines().count() + 1);
+    }
+
+    #[test]
+    fn export_filters_by_the_since_date() {
+        let dir = tempfile::tempdir().unwrap();
+        let formulas_path = dir.path().join("formulas.json");
+        let mut formulas = FormulaManager::new(formulas_path);
+        let euler = formulas.add(Formula::new_user(
+            "Euler, identity",
+            "e^{i\\pi} = -1",
+            "math",
+        ));
+        let log = format!(
+            "2024-03-01T09:00:00+00:00,{id}\n\
+             not a log line\n\
+             2024-03-02T09:00:00+00:00,deleted\n\
+             2024-03-03T09:00:00+00:00,{id}\n",
+            id = euler.id
+        );
+        fs::write(usage_log_path(dir.path()), log).unwrap();
+
+        let since = parse_since("2024-03-02").unwrap();
+        let csv = usage_csv(dir.path(), Some(since), &formulas);
+        assert_eq!(
+            csv,
+            format!(
+                "timestamp,formula_id,formula_name\n\
+                 2024-03-02T09:00:00+00:00,deleted,\n\
+                 2024-03-03T09:00:00+00:00,{},\"Euler, identity\"\n",
+                euler.id
+            )
+        );
+        assert_eq!(usage_csv(dir.path(), None, &formulas).lines().count(), 4);
+    }
+
+    #[test]
+    fn since_accepts_dates_and_timestamps() {
+        assert_eq!(
+            parse_since("2024-03-02").unwrap().to_rfc3339(),
+            "2024-03-02T00:00:00+00:00"
+        );
+        assert_eq!(
+            parse_since("2024-03-02T10:00:00+02:00")
+                .unwrap()
+                .to_rfc3339(),
+            "2024-03-02T08:00:00+00:00"
+        );
+        assert!(parse_since("March 2nd").is_err());
+    }
+}
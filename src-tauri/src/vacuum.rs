@@ -0,0 +1,334 @@
+use crate::paths::quarantine_dir;
+use crate::recovery::{recovery_dir, scan_recovery_drafts};
+use crate::render_cache::{orphaned_render_cache_entries, render_cache_dir};
+use crate::versions::{
+    empty_version_dirs, open_version_store, remove_empty_version_dir, ManifestLocks,
+};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::State;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VacuumCategory {
+    pub items: usize,
+    pub bytes: u64,
+    pub paths: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct VacuumReport {
+    pub dry_run: bool,
+    /// Rendered images no formula in the library maps to
+    pub render_cache: VacuumCategory,
+    /// Damaged data files moved aside by earlier repairs
+    pub quarantine: VacuumCategory,
+    pub recovery_drafts: VacuumCategory,
+    /// Drafts left in place because their document couldn't be checked
+    pub unverified_drafts: Vec<String>,
+    pub empty_version_dirs: VacuumCategory,
+    /// Reclaimable bytes for a dry run, otherwise bytes actually freed
+    pub total_bytes: u64,
+    /// Items that were found but could not be removed
+    pub errors: Vec<String>,
+}
+
+/// Where vacuuming looks; everything outside these locations is left alone
+pub struct VacuumTargets<'a> {
+    pub app_data_dir: &'a Path,
+    pub recovery_dir: &'a Path,
+    pub version_store_root: &'a Path,
+    /// LaTeX of every formula in the library, whose renderings are kept
+    pub formula_sources: &'a [String],
+}
+
+/// Size of a file, or of everything under a directory
+fn disk_size(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| disk_size(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Measure each candidate and, unless `dry_run`, remove it with `remove`. Items
+/// that fail to delete are reported in `errors` and not counted.
+fn sweep(
+    candidates: Vec<PathBuf>,
+    dry_run: bool,
+    errors: &mut Vec<String>,
+    remove: impl Fn(&Path) -> std::io::Result<bool>,
+) -> VacuumCategory {
+    let mut category = VacuumCategory::default();
+    for path in candidates {
+        let bytes = disk_size(&path);
+        if !dry_run {
+            match remove(&path) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    errors.push(format!("Failed to remove {}: {}", path.display(), e));
+                    continue;
+                }
+            }
+        }
+        category.items += 1;
+        category.bytes += bytes;
+        category.paths.push(path.to_string_lossy().to_string());
+    }
+    category
+}
+
+/// Find (and unless `dry_run`, delete) orphaned render cache files, quarantined
+/// data files, stale recovery drafts and empty version dirs
+pub fn vacuum(targets: &VacuumTargets, locks: &ManifestLocks, dry_run: bool) -> VacuumReport {
+    let remove_file = |path: &Path| fs::remove_file(path).map(|_| true);
+    let mut errors = vec![];
+
+    let render_cache = sweep(
+        orphaned_render_cache_entries(
+            &render_cache_dir(targets.app_data_dir),
+            targets.formula_sources,
+        ),
+        dry_run,
+        &mut errors,
+        remove_file,
+    );
+    let quarantine = sweep(
+        files_in(&quarantine_dir(targets.app_data_dir)),
+        dry_run,
+        &mut errors,
+        remove_file,
+    );
+    let drafts = scan_recovery_drafts(targets.recovery_dir);
+    let recovery_drafts = sweep(drafts.stale, dry_run, &mut errors, remove_file);
+    let empty_version_dirs = sweep(
+        empty_version_dirs(targets.version_store_root),
+        dry_run,
+        &mut errors,
+        |dir| remove_empty_version_dir(locks, dir),
+    );
+
+    VacuumReport {
+        dry_run,
+        total_bytes: render_cache.bytes
+            + quarantine.bytes
+            + recovery_drafts.bytes
+            + empty_version_dirs.bytes,
+        render_cache,
+        quarantine,
+        recovery_drafts,
+        unverified_drafts: drafts
+            .unverified
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect(),
+        empty_version_dirs,
+        errors,
+    }
+}
+
+/// Reclaim space from leftovers in the app data dir. Live data files, version
+/// snapshots and renderings of library formulas are never removed.
+#[tauri::command]
+pub fn vacuum_app_data(dry_run: bool, state: State<AppState>) -> Result<VacuumReport, String> {
+    let formula_sources: Vec<String> = state
+        .formulas
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_all()
+        .into_iter()
+        .map(|f| f.latex_content)
+        .collect();
+    let recovery_dir = recovery_dir(&state)?;
+    let (_store, version_store_root) = open_version_store(&state)?;
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .clone();
+
+    let targets = VacuumTargets {
+        app_data_dir: &app_data_dir,
+        recovery_dir: &recovery_dir,
+        version_store_root: &version_store_root,
+        formula_sources: &formula_sources,
+    };
+    Ok(vacuum(&targets, &state.manifest_locks, dry_run))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_cache::{render_cache_path, RenderFormat};
+
+    struct Layout {
+        _dir: tempfile::TempDir,
+        app_data: PathBuf,
+        recovery: PathBuf,
+        versions: PathBuf,
+        /// Files and dirs that vacuuming must leave in place
+        live: Vec<PathBuf>,
+    }
+
+    fn write(path: &Path, content: &str) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        path.to_path_buf()
+    }
+
+    fn draft(document: &Path, saved_at: chrono::DateTime<chrono::Utc>) -> String {
+        serde_json::json!({
+            "document_path": document.to_string_lossy(),
+            "content": "draft",
+            "saved_at": saved_at.to_rfc3339(),
+        })
+        .to_string()
+    }
+
+    /// An app data dir with one reclaimable item and some live data per category
+    fn layout() -> Layout {
+        let dir = tempfile::tempdir().unwrap();
+        let app_data = dir.path().join("app");
+        let recovery = app_data.join("recovery");
+        let versions = app_data.join("versions");
+        let cache = render_cache_dir(&app_data);
+        let now = chrono::Utc::now();
+        let document = write(&dir.path().join("docs/a.lml"), "saved");
+
+        let live = vec![
+            write(&app_data.join("settings.json"), "{}"),
+            write(&app_data.join("formulas.json"), "[]"),
+            write(
+                &render_cache_path(&cache, "x^2", RenderFormat::Svg),
+                "<svg/>",
+            ),
+            write(
+                &recovery.join("a.draft.json"),
+                &draft(&document, now + chrono::Duration::hours(1)),
+            ),
+            // Unverifiable drafts may be the only copy of their document
+            write(
+                &recovery.join("gone.draft.json"),
+                &draft(&dir.path().join("docs/gone.lml"), now),
+            ),
+            write(&recovery.join("broken.draft.json"), "not json"),
+            write(&versions.join("doc1/v0.lml.gz"), "snapshot"),
+        ];
+        write(
+            &render_cache_path(&cache, "y^2", RenderFormat::Png),
+            "12345",
+        );
+        write(
+            &app_data.join("quarantine/settings-20240102T030405.678Z.json"),
+            "{bad",
+        );
+        write(
+            &recovery.join("old.draft.json"),
+            &draft(&document, now - chrono::Duration::hours(1)),
+        );
+        fs::create_dir_all(versions.join("doc2")).unwrap();
+
+        Layout {
+            _dir: dir,
+            app_data,
+            recovery,
+            versions,
+            live,
+        }
+    }
+
+    fn run(layout: &Layout, dry_run: bool) -> VacuumReport {
+        let sources = vec!["x^2".to_string()];
+        let targets = VacuumTargets {
+            app_data_dir: &layout.app_data,
+            recovery_dir: &layout.recovery,
+            version_store_root: &layout.versions,
+            formula_sources: &sources,
+        };
+        vacuum(&targets, &ManifestLocks::default(), dry_run)
+    }
+
+    #[test]
+    fn dry_run_reports_without_removing() {
+        let layout = layout();
+        let report = run(&layout, true);
+
+        assert!(report.dry_run);
+        assert_eq!(
+            (report.render_cache.items, report.render_cache.bytes),
+            (1, 5)
+        );
+        assert_eq!((report.quarantine.items, report.quarantine.bytes), (1, 4));
+        assert_eq!(report.recovery_drafts.items, 1);
+        assert!(report.recovery_drafts.paths[0].ends_with("old.draft.json"));
+        assert_eq!(report.unverified_drafts.len(), 2);
+        assert_eq!(report.empty_version_dirs.items, 1);
+        assert!(report.empty_version_dirs.paths[0].ends_with("doc2"));
+        assert_eq!(
+            report.total_bytes,
+            5 + 4 + report.recovery_drafts.bytes + report.empty_version_dirs.bytes
+        );
+        assert!(report.errors.is_empty());
+
+        let reported = [
+            &report.render_cache,
+            &report.quarantine,
+            &report.recovery_drafts,
+            &report.empty_version_dirs,
+        ];
+        for path in reported.iter().flat_map(|c| &c.paths) {
+            assert!(Path::new(path).exists(), "{}", path);
+        }
+    }
+
+    #[test]
+    fn vacuuming_removes_leftovers_and_keeps_live_data() {
+        let layout = layout();
+        let report = run(&layout, false);
+
+        assert!(!report.dry_run);
+        assert_eq!(report.render_cache.items, 1);
+        assert_eq!(report.quarantine.items, 1);
+        assert_eq!(report.recovery_drafts.items, 1);
+        assert_eq!(report.empty_version_dirs.items, 1);
+        let reported = [
+            &report.render_cache,
+            &report.quarantine,
+            &report.recovery_drafts,
+            &report.empty_version_dirs,
+        ];
+        for path in reported.iter().flat_map(|c| &c.paths) {
+            assert!(!Path::new(path).exists(), "{}", path);
+        }
+        for path in &layout.live {
+            assert!(path.exists(), "{}", path.display());
+        }
+
+        // Nothing is left to reclaim
+        let again = run(&layout, false);
+        assert_eq!(again.total_bytes, 0);
+        assert_eq!(again.unverified_drafts.len(), 2);
+        assert_eq!(
+            again.recovery_drafts.items + again.empty_version_dirs.items,
+            0
+        );
+    }
+}
@@ -1,3 +1,7 @@
+use crate::diff::{diff_lines, DiffOp};
+use crate::errors::AppError;
+use crate::paths::{expand_path, probe_writable, resolve_symlink};
+use crate::settings::SettingsManager;
 use crate::AppState;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
@@ -5,8 +9,10 @@ use flate2::Compression;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLockReadGuard};
 use tauri::State;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -18,6 +24,105 @@ pub struct VersionEntry {
     pub word_count: u32,
     pub file_size_bytes: u64,
     pub content_hash: String,
+    /// `dedup_hash` of the content under the dedup settings in effect when it was saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dedup_hash: Option<String>,
+    /// Tiny snapshots are stored as plain `.lml`; older entries are all gzipped
+    #[serde(default = "default_true")]
+    pub compressed: bool,
+    /// Checkpoint explicitly tagged by the user; the storage quota never evicts it
+    #[serde(default)]
+    pub tagged: bool,
+}
+
+impl VersionEntry {
+    fn file_name(&self) -> String {
+        snapshot_file_name(&self.id, self.compressed)
+    }
+}
+
+/// Content up to this many bytes is stored uncompressed; gzip only adds overhead
+const UNCOMPRESSED_SNAPSHOT_BYTES: usize = 256;
+
+fn snapshot_file_name(id: &str, compressed: bool) -> String {
+    if compressed {
+        format!("{}.lml.gz", id)
+    } else {
+        format!("{}.lml", id)
+    }
+}
+
+/// Version id and compression of a snapshot file name, `None` for other files
+fn parse_snapshot_file_name(name: &str) -> Option<(&str, bool)> {
+    name.strip_suffix(".lml.gz")
+        .map(|id| (id, true))
+        .or_else(|| name.strip_suffix(".lml").map(|id| (id, false)))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionPage {
+    pub entries: Vec<VersionEntry>,
+    pub total: usize,
+}
+
+/// A stored version opened for viewing, without restoring it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionContent {
+    pub content: String,
+    pub entry: VersionEntry,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ChangeStats {
+    pub added: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EvictedVersion {
+    pub document_path: String,
+    pub version_id: String,
+    pub timestamp: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct QuotaReport {
+    pub quota_bytes: u64,
+    pub used_bytes_before: u64,
+    pub used_bytes_after: u64,
+    pub evicted: Vec<EvictedVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuotaStatus {
+    pub used_mb: f64,
+    /// `None` when version storage is unlimited
+    pub quota_mb: Option<u64>,
+    /// Share of the quota in use; 0 when unlimited
+    pub percent: f64,
+}
+
+/// What to do with snapshot files that have no manifest entry
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RepairMode {
+    /// Rebuild manifest entries from the snapshot contents
+    Readd,
+    Delete,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct RepairReport {
+    /// Orphaned snapshots added back to the manifest
+    pub readded: Vec<String>,
+    /// Orphaned snapshots removed from disk
+    pub deleted: Vec<String>,
+    /// Orphaned snapshots that could not be decompressed and were left alone
+    pub unreadable: Vec<String>,
+    /// Manifest entries whose snapshot file is missing
+    pub missing_files: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -25,6 +130,54 @@ struct VersionManifest {
     versions: Vec<VersionEntry>,
 }
 
+/// Per-document locks serializing manifest read-modify-write, keyed by versions dir name
+pub type ManifestLocks = Mutex<HashMap<String, Arc<Mutex<()>>>>;
+
+/// The lock for one document's versions dir, created on first use
+fn manifest_lock(locks: &ManifestLocks, dir: &Path) -> Arc<Mutex<()>> {
+    let key = dir
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    locks
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(key)
+        .or_default()
+        .clone()
+}
+
+/// Content with trailing whitespace dropped from every line and from the end,
+/// for comparisons that should ignore cosmetic-only edits
+fn trim_trailing_whitespace(content: &str) -> String {
+    let lines: Vec<&str> = content.lines().map(str::trim_end).collect();
+    lines.join("\n").trim_end().to_string()
+}
+
+/// Hash of the form compared when deduplicating versions: line endings unified
+/// and/or trailing whitespace dropped, as configured. `None` when neither is on.
+/// The mode is part of the value so hashes from different settings never match.
+/// Stored snapshots stay exact.
+fn dedup_hash(content: &str, settings: &crate::settings::Settings) -> Option<String> {
+    if settings.version_dedup_ignore_whitespace {
+        // Splitting into lines already treats CRLF and LF alike
+        Some(format!("ws:{}", content_hash(&trim_trailing_whitespace(content))))
+    } else if settings.version_dedup_normalize_line_endings {
+        let unified = content.replace("\r\n", "\n").replace('\r', "\n");
+        Some(format!("eol:{}", content_hash(&unified)))
+    } else {
+        None
+    }
+}
+
+/// The configured storage quota in bytes; unset and 0 both mean unlimited
+fn storage_quota_bytes(settings: &crate::settings::Settings) -> Option<u64> {
+    settings
+        .version_storage_quota_mb
+        .filter(|q| *q > 0)
+        .map(|q| q.saturating_mul(1024 * 1024))
+}
+
 /// Hash a document path to create a stable directory name
 fn document_hash(path: &str) -> String {
     let mut hasher = Sha256::new();
@@ -33,38 +186,208 @@ fn document_hash(path: &str) -> String {
     hex::encode(&result[..8])
 }
 
-/// Get the versions directory for a document
-fn versions_dir(app_data_dir: &PathBuf, document_path: &str) -> PathBuf {
-    let hash = document_hash(document_path);
-    app_data_dir.join("versions").join(hash)
-}
-
-/// Read the manifest file for a document
-fn read_manifest(dir: &PathBuf) -> VersionManifest {
-    let manifest_path = dir.join("manifest.json");
-    if manifest_path.exists() {
-        if let Ok(content) = fs::read_to_string(&manifest_path) {
-            if let Ok(manifest) = serde_json::from_str(&content) {
-                return manifest;
-            }
-        }
+/// Root directory holding every document's versions: `version_store_dir`
+/// when configured, otherwise `versions/` under the app data dir
+fn resolve_version_store_root(configured: Option<&str>, app_data_dir: &Path) -> PathBuf {
+    match configured.filter(|d| !d.trim().is_empty()) {
+        Some(dir) => expand_path(dir),
+        None => app_data_dir.join("versions"),
+    }
+}
+
+fn version_store_root(state: &AppState) -> Result<PathBuf, String> {
+    let configured = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings()
+        .version_store_dir;
+    let app_data_dir = state
+        .app_data_dir
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    Ok(resolve_version_store_root(configured.as_deref(), &app_data_dir))
+}
+
+/// The version store root, held shared so the store can't be relocated until
+/// the returned guard is dropped
+pub fn open_version_store(
+    state: &AppState,
+) -> Result<(RwLockReadGuard<'_, ()>, PathBuf), String> {
+    let guard = state
+        .version_store_lock
+        .read()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    Ok((guard, version_store_root(state)?))
+}
+
+/// The path a document's history is filed under: its resolved path when
+/// `resolve_symlinks` is on, so editing through any link shares one history
+pub fn history_path(state: &AppState, document_path: &str) -> String {
+    let resolve = state
+        .settings
+        .lock()
+        .is_ok_and(|s| s.get_settings().resolve_symlinks);
+    if resolve {
+        resolve_symlink(document_path)
+    } else {
+        document_path.to_string()
     }
-    VersionManifest { versions: vec![] }
 }
 
-/// Write the manifest file
-fn write_manifest(dir: &PathBuf, manifest: &VersionManifest) -> Result<(), String> {
-    let manifest_path = dir.join("manifest.json");
-    let content =
-        serde_json::to_string_pretty(manifest).map_err(|e| format!("Serialize error: {}", e))?;
-    fs::write(&manifest_path, content).map_err(|e| format!("Write manifest error: {}", e))
+/// Get the versions directory for a document
+fn versions_dir(store_root: &Path, document_path: &str) -> PathBuf {
+    store_root.join(document_hash(document_path))
+}
+
+const MANIFEST_FILE: &str = "manifest.json";
+const COMPRESSED_MANIFEST_FILE: &str = "manifest.json.gz";
+
+/// Read the manifest file for a document, gzipped or plain
+fn read_manifest(dir: &Path) -> VersionManifest {
+    let compressed_path = dir.join(COMPRESSED_MANIFEST_FILE);
+    let content = if compressed_path.exists() {
+        fs::File::open(&compressed_path).ok().and_then(|file| {
+            let mut content = String::new();
+            GzDecoder::new(file).read_to_string(&mut content).ok()?;
+            Some(content)
+        })
+    } else {
+        fs::read_to_string(dir.join(MANIFEST_FILE)).ok()
+    };
+
+    content
+        .and_then(|c| serde_json::from_str(&c).ok())
+        .unwrap_or(VersionManifest { versions: vec![] })
+}
+
+/// Write the manifest file, keeping whichever form is already on disk
+fn write_manifest(dir: &Path, manifest: &VersionManifest) -> Result<(), AppError> {
+    let compressed = dir.join(COMPRESSED_MANIFEST_FILE).exists();
+    write_manifest_as(dir, manifest, compressed)
+}
+
+/// Write the manifest in the given form and remove the other one, so a document
+/// switches between plain and gzipped manifests on its next write
+fn write_manifest_as(
+    dir: &Path,
+    manifest: &VersionManifest,
+    compressed: bool,
+) -> Result<(), AppError> {
+    let (path, stale) = if compressed {
+        (dir.join(COMPRESSED_MANIFEST_FILE), dir.join(MANIFEST_FILE))
+    } else {
+        (dir.join(MANIFEST_FILE), dir.join(COMPRESSED_MANIFEST_FILE))
+    };
+
+    if compressed {
+        let content = serde_json::to_vec(manifest)?;
+        let file = fs::File::create(&path).map_err(|e| AppError::io("Write manifest error", e))?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder
+            .write_all(&content)
+            .and_then(|_| encoder.finish().map(|_| ()))
+            .map_err(|e| AppError::io("Write manifest error", e))?;
+    } else {
+        let content = serde_json::to_string_pretty(manifest)?;
+        fs::write(&path, content).map_err(|e| AppError::io("Write manifest error", e))?;
+    }
+
+    if stale.exists() {
+        fs::remove_file(&stale).map_err(|e| AppError::io("Remove old manifest error", e))?;
+    }
+    Ok(())
 }
 
 /// Count words in content
-fn count_words(content: &str) -> u32 {
+pub fn count_words(content: &str) -> u32 {
     content.split_whitespace().count() as u32
 }
 
+/// How `count_words_ex` decides what counts as a word. The defaults match `count_words`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WordCountOptions {
+    /// Drop `\commands`, math delimiters, and braces, and skip tokens with no letters or digits
+    #[serde(default)]
+    pub strip_latex: bool,
+    /// Count `state-of-the-art` as one word rather than four
+    #[serde(default = "default_true")]
+    pub join_hyphenated: bool,
+    /// Ignore fenced ``` code blocks
+    #[serde(default)]
+    pub exclude_code_blocks: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for WordCountOptions {
+    fn default() -> Self {
+        Self {
+            strip_latex: false,
+            join_hyphenated: true,
+            exclude_code_blocks: false,
+        }
+    }
+}
+
+/// Count words with configurable handling of LaTeX, hyphens, and code blocks
+pub fn count_words_ex(content: &str, options: &WordCountOptions) -> u32 {
+    let mut text = String::with_capacity(content.len());
+    let mut in_code = false;
+    for line in content.lines() {
+        if options.exclude_code_blocks && line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if !in_code || !options.exclude_code_blocks {
+            text.push_str(line);
+            text.push('\n');
+        }
+    }
+
+    if options.strip_latex {
+        text = strip_latex_markup(&text);
+    }
+
+    text.split_whitespace()
+        .flat_map(|token| {
+            if options.join_hyphenated {
+                vec![token]
+            } else {
+                token.split('-').filter(|part| !part.is_empty()).collect()
+            }
+        })
+        .filter(|token| !options.strip_latex || token.chars().any(char::is_alphanumeric))
+        .count() as u32
+}
+
+/// Replace LaTeX commands and math syntax characters with spaces
+fn strip_latex_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                // Skip the command name (or the single escaped character)
+                if chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    while chars.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                        chars.next();
+                    }
+                } else {
+                    chars.next();
+                }
+                out.push(' ');
+            }
+            '{' | '}' | '$' | '^' | '_' | '&' => out.push(' '),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
 /// Hash content for dedup
 fn content_hash(content: &str) -> String {
     let mut hasher = Sha256::new();
@@ -72,55 +395,400 @@ fn content_hash(content: &str) -> String {
     hex::encode(&hasher.finalize()[..8])
 }
 
+/// Number of a document's versions created at or after an RFC 3339 timestamp
+pub fn versions_since(store_root: &Path, document_path: &str, since: &str) -> usize {
+    let Ok(since) = chrono::DateTime::parse_from_rfc3339(since) else {
+        return 0;
+    };
+    read_manifest(&versions_dir(store_root, document_path))
+        .versions
+        .iter()
+        .filter(|v| chrono::DateTime::parse_from_rfc3339(&v.timestamp).is_ok_and(|t| t >= since))
+        .count()
+}
+
+/// Bytes fed to the hasher and encoder at a time when compressing a snapshot
+const SNAPSHOT_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Gzip everything read from `content` into `out`, hashing the same bytes as they
+/// stream through so the content is read once. Returns the `content_hash` digest.
+fn compress_and_hash(
+    mut content: impl Read,
+    out: impl Write,
+    compression: Compression,
+) -> io::Result<String> {
+    let mut encoder = GzEncoder::new(out, compression);
+    let mut hasher = Sha256::new();
+    let mut chunk = vec![0; SNAPSHOT_CHUNK_BYTES];
+    loop {
+        let read = match content.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&chunk[..read]);
+        encoder.write_all(&chunk[..read])?;
+    }
+    encoder.finish()?;
+    Ok(hex::encode(&hasher.finalize()[..8]))
+}
+
+/// Read a stored snapshot, decompressing it if needed
+fn read_version_content(
+    dir: &Path,
+    version_id: &str,
+    compressed: bool,
+) -> Result<String, AppError> {
+    let path = dir.join(snapshot_file_name(version_id, compressed));
+    if !path.exists() {
+        return Err(AppError::NotFound(format!("Version file not found: {}", version_id)));
+    }
+    if !compressed {
+        return fs::read_to_string(&path).map_err(|e| AppError::io("Read version error", e));
+    }
+
+    let file = fs::File::open(&path).map_err(|e| AppError::io("Open gz file error", e))?;
+    let mut decoder = GzDecoder::new(file);
+    let mut content = String::new();
+    decoder
+        .read_to_string(&mut content)
+        .map_err(|e| AppError::io("Decompress error", e))?;
+
+    Ok(content)
+}
+
+/// Iterate over every per-document versions directory
+fn document_dirs(root: &Path) -> Vec<PathBuf> {
+    fs::read_dir(root)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// No versions in the manifest and no snapshot files that could be re-added
+fn is_empty_version_dir(dir: &Path) -> bool {
+    read_manifest(dir).versions.is_empty()
+        && fs::read_dir(dir).is_ok_and(|entries| {
+            !entries
+                .flatten()
+                .any(|e| parse_snapshot_file_name(&e.file_name().to_string_lossy()).is_some())
+        })
+}
+
+/// Per-document versions dirs left with nothing in them
+pub fn empty_version_dirs(root: &Path) -> Vec<PathBuf> {
+    document_dirs(root)
+        .into_iter()
+        .filter(|dir| is_empty_version_dir(dir))
+        .collect()
+}
+
+/// Delete a versions dir if it's still empty once its manifest lock is held.
+/// Returns whether it was removed.
+pub fn remove_empty_version_dir(locks: &ManifestLocks, dir: &Path) -> io::Result<bool> {
+    let lock = manifest_lock(locks, dir);
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    if !is_empty_version_dir(dir) {
+        return Ok(false);
+    }
+    fs::remove_dir_all(dir)?;
+    Ok(true)
+}
+
+/// Total size of all snapshots across documents
+fn version_storage_bytes(root: &Path) -> u64 {
+    document_dirs(root)
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.flatten())
+        .filter(|e| parse_snapshot_file_name(&e.file_name().to_string_lossy()).is_some())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+/// Evict the oldest untagged versions across all documents until total
+/// storage fits within `quota_bytes`. Tagged versions and the `keep` version
+/// (the one just created) are never evicted.
+fn enforce_quota(
+    root: &Path,
+    quota_bytes: u64,
+    locks: &ManifestLocks,
+    keep: Option<&str>,
+) -> QuotaReport {
+    let used_before = version_storage_bytes(root);
+    let mut report = QuotaReport {
+        quota_bytes,
+        used_bytes_before: used_before,
+        used_bytes_after: used_before,
+        evicted: vec![],
+    };
+    if used_before <= quota_bytes {
+        return report;
+    }
+
+    let mut candidates: Vec<(PathBuf, VersionEntry, u64)> = vec![];
+    for dir in document_dirs(root) {
+        for entry in read_manifest(&dir).versions {
+            if entry.tagged || keep == Some(entry.id.as_str()) {
+                continue;
+            }
+            let bytes = fs::metadata(dir.join(entry.file_name()))
+                .map(|m| m.len())
+                .unwrap_or(entry.file_size_bytes);
+            candidates.push((dir.clone(), entry, bytes));
+        }
+    }
+    // RFC 3339 UTC timestamps sort chronologically as strings
+    candidates.sort_by(|a, b| a.1.timestamp.cmp(&b.1.timestamp));
+
+    // Choose victims from a snapshot of the manifests, then evict each document's
+    // share under its manifest lock, re-reading the manifest there so a concurrent
+    // update never sees a deleted snapshot or writes one back
+    let mut planned: HashMap<PathBuf, Vec<String>> = HashMap::new();
+    let mut projected = used_before;
+    for (dir, entry, bytes) in candidates {
+        if projected <= quota_bytes {
+            break;
+        }
+        projected = projected.saturating_sub(bytes);
+        planned.entry(dir).or_default().push(entry.id);
+    }
+
+    let mut used = used_before;
+    for (dir, ids) in planned {
+        let lock = manifest_lock(locks, &dir);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut manifest = read_manifest(&dir);
+        let before = manifest.versions.len();
+        let mut kept = Vec::with_capacity(before);
+        for entry in manifest.versions {
+            if !ids.contains(&entry.id) {
+                kept.push(entry);
+                continue;
+            }
+            let path = dir.join(entry.file_name());
+            let bytes = fs::metadata(&path).map_or(entry.file_size_bytes, |m| m.len());
+            if fs::remove_file(&path).is_err() {
+                kept.push(entry);
+                continue;
+            }
+            used = used.saturating_sub(bytes);
+            report.evicted.push(EvictedVersion {
+                document_path: entry.document_path,
+                version_id: entry.id,
+                timestamp: entry.timestamp,
+                bytes,
+            });
+        }
+        manifest.versions = kept;
+        if manifest.versions.len() != before {
+            write_manifest(&dir, &manifest).ok();
+        }
+    }
+    report.evicted.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+
+    report.used_bytes_after = used;
+    report
+}
+
+/// Render a Markdown changelog from newest-first version entries
+fn render_changelog(document_path: &str, versions: &[VersionEntry]) -> String {
+    let name = Path::new(document_path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(document_path);
+
+    let mut out = format!("# Version History: {}\n\n", name);
+    if versions.is_empty() {
+        out.push_str("_No versions recorded._\n");
+        return out;
+    }
+
+    for (i, version) in versions.iter().enumerate() {
+        // The previous version is the next (older) entry in the manifest
+        let delta = match versions.get(i + 1) {
+            Some(prev) => {
+                let diff = version.word_count as i64 - prev.word_count as i64;
+                format!("{:+}", diff)
+            }
+            None => "initial".to_string(),
+        };
+
+        let timestamp = chrono::DateTime::parse_from_rfc3339(&version.timestamp)
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+            .unwrap_or_else(|_| version.timestamp.clone());
+
+        out.push_str(&format!("## {}\n\n", timestamp));
+        if let Some(comment) = version.comment.as_deref().filter(|c| !c.trim().is_empty()) {
+            out.push_str(&format!("- Comment: {}\n", comment.trim()));
+        }
+        out.push_str(&format!(
+            "- Words: {} ({})\n\n",
+            version.word_count, delta
+        ));
+    }
+
+    out
+}
+
+/// Copy a document's snapshots and manifest entries to another path's history,
+/// merging with any history the destination already has. Returns the number copied.
+pub fn copy_version_history(
+    store_root: &Path,
+    locks: &ManifestLocks,
+    from_path: &str,
+    to_path: &str,
+) -> Result<usize, AppError> {
+    let from_dir = versions_dir(store_root, from_path);
+    let to_dir = versions_dir(store_root, to_path);
+    if from_dir == to_dir {
+        return Ok(0);
+    }
+
+    let source = read_manifest(&from_dir);
+    if source.versions.is_empty() {
+        return Ok(0);
+    }
+    fs::create_dir_all(&to_dir).map_err(|e| AppError::io("Failed to create versions dir", e))?;
+
+    let lock = manifest_lock(locks, &to_dir);
+    let _guard = lock
+        .lock()
+        .map_err(|e| AppError::Other(format!("Lock error: {}", e)))?;
+    let mut target = read_manifest(&to_dir);
+    let mut copied = 0;
+    for version in source.versions {
+        if target.versions.iter().any(|v| v.id == version.id) {
+            continue;
+        }
+        let file_name = version.file_name();
+        fs::copy(from_dir.join(&file_name), to_dir.join(&file_name))
+            .map_err(|e| AppError::io(&format!("Failed to copy version {}", version.id), e))?;
+        target.versions.push(VersionEntry {
+            document_path: to_path.to_string(),
+            ..version
+        });
+        copied += 1;
+    }
+
+    // Newest first, as create_version keeps it
+    target.versions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    write_manifest(&to_dir, &target)?;
+    Ok(copied)
+}
+
+/// Count words using the given options, or the `word_count_options` setting
+#[tauri::command]
+pub fn count_document_words(
+    content: String,
+    options: Option<WordCountOptions>,
+    state: State<AppState>,
+) -> Result<u32, AppError> {
+    let options = match options {
+        Some(options) => options,
+        None => {
+            state
+                .settings
+                .lock()
+                .map_err(|e| format!("Lock error: {}", e))?
+                .get_settings()
+                .word_count_options
+        }
+    };
+    Ok(count_words_ex(&content, &options))
+}
+
 #[tauri::command]
 pub fn create_version(
     document_path: String,
     content: String,
     comment: Option<String>,
     state: State<AppState>,
-) -> Result<VersionEntry, String> {
-    let app_data_dir = state
-        .app_data_dir
+) -> Result<VersionEntry, AppError> {
+    let settings = state
+        .settings
         .lock()
-        .map_err(|e| format!("Lock error: {}", e))?;
-    let dir = versions_dir(&app_data_dir, &document_path);
-    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create versions dir: {}", e))?;
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings();
 
-    let mut manifest = read_manifest(&dir);
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    store_version(
+        &root,
+        &dir,
+        &state.manifest_locks,
+        document_path,
+        content,
+        comment,
+        &settings,
+    )
+}
+
+/// Snapshot `content` into a document's versions dir `dir` under `root`
+fn store_version(
+    root: &Path,
+    dir: &Path,
+    locks: &ManifestLocks,
+    document_path: String,
+    content: String,
+    comment: Option<String>,
+    settings: &crate::settings::Settings,
+) -> Result<VersionEntry, AppError> {
+    let quota_bytes = storage_quota_bytes(settings);
+    // Out-of-range levels are clamped to the strongest compression
+    let compression = Compression::new(settings.version_compression_level.min(9));
+    fs::create_dir_all(dir).map_err(|e| AppError::io("Failed to create versions dir", e))?;
 
-    // Check if content hasn't changed since last version
-    let hash = content_hash(&content);
+    // Hold this document's manifest lock across the read-modify-write
+    let lock = manifest_lock(locks, dir);
+    let guard = lock.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let mut manifest = read_manifest(dir);
+
+    // Tiny snapshots are stored as-is. Larger ones are compressed in memory and
+    // hashed in the same pass; nothing touches disk until the content is known to
+    // have changed, so an unchanged autosave writes nothing.
+    let compressed = content.len() > UNCOMPRESSED_SNAPSHOT_BYTES;
+    let (hash, gzipped) = if compressed {
+        let mut gzipped = vec![];
+        let hash = compress_and_hash(content.as_bytes(), &mut gzipped, compression)
+            .map_err(|e| AppError::io("Compress error", e))?;
+        (hash, Some(gzipped))
+    } else {
+        (content_hash(&content), None)
+    };
+    let normalized_hash = dedup_hash(&content, settings);
     if let Some(last) = manifest.versions.first() {
-        if last.content_hash == hash {
+        let unchanged = last.content_hash == hash
+            || (normalized_hash.is_some() && last.dedup_hash == normalized_hash);
+        if unchanged {
             return Ok(last.clone());
         }
     }
 
     let id = uuid::Uuid::new_v4().to_string();
-
-    // Compress content with gzip
-    let gz_path = dir.join(format!("{}.lml.gz", id));
-    let file = fs::File::create(&gz_path).map_err(|e| format!("Create gz file error: {}", e))?;
-    let mut encoder = GzEncoder::new(file, Compression::default());
-    encoder
-        .write_all(content.as_bytes())
-        .map_err(|e| format!("Compress error: {}", e))?;
-    encoder
-        .finish()
-        .map_err(|e| format!("Finish compress error: {}", e))?;
-
-    let file_size = fs::metadata(&gz_path)
-        .map(|m| m.len())
-        .unwrap_or(0);
+    let snapshot_path = dir.join(snapshot_file_name(&id, compressed));
+    let snapshot = gzipped.as_deref().unwrap_or(content.as_bytes());
+    fs::write(&snapshot_path, snapshot).map_err(|e| AppError::io("Write version error", e))?;
+    let file_size = snapshot.len() as u64;
 
     let entry = VersionEntry {
         id,
-        document_path: document_path.clone(),
+        document_path,
         timestamp: chrono::Utc::now().to_rfc3339(),
         comment,
-        word_count: count_words(&content),
+        word_count: count_words_ex(&content, &settings.word_count_options),
         file_size_bytes: file_size,
         content_hash: hash,
+        dedup_hash: normalized_hash,
+        compressed,
+        tagged: false,
     };
 
     // Insert at front (newest first)
@@ -130,12 +798,18 @@ pub fn create_version(
     if manifest.versions.len() > 100 {
         let removed = manifest.versions.split_off(100);
         for v in removed {
-            let path = dir.join(format!("{}.lml.gz", v.id));
+            let path = dir.join(v.file_name());
             fs::remove_file(&path).ok();
         }
     }
 
-    write_manifest(&dir, &manifest)?;
+    write_manifest_as(dir, &manifest, settings.compress_version_manifests)?;
+    drop(guard);
+
+    // Opportunistically keep total history storage under the configured quota
+    if let Some(quota_bytes) = quota_bytes {
+        enforce_quota(root, quota_bytes, locks, Some(&entry.id));
+    }
 
     Ok(entry)
 }
@@ -145,40 +819,93 @@ pub fn list_versions(
     document_path: String,
     state: State<AppState>,
 ) -> Vec<VersionEntry> {
-    let app_data_dir = match state.app_data_dir.lock() {
-        Ok(dir) => dir,
-        Err(_) => return vec![],
+    let Ok((_store, root)) = open_version_store(&state) else {
+        return vec![];
     };
-    let dir = versions_dir(&app_data_dir, &document_path);
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
     let manifest = read_manifest(&dir);
     manifest.versions
 }
 
+/// One window of a document's newest-first versions, with the total count
+fn versions_page(dir: &Path, offset: usize, limit: usize) -> VersionPage {
+    let manifest = read_manifest(dir);
+    let total = manifest.versions.len();
+
+    // Move the requested window out of the manifest instead of cloning it
+    let entries = manifest
+        .versions
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .collect();
+
+    VersionPage { entries, total }
+}
+
+#[tauri::command]
+pub fn list_versions_paged(
+    document_path: String,
+    offset: usize,
+    limit: usize,
+    state: State<AppState>,
+) -> VersionPage {
+    let Ok((_store, root)) = open_version_store(&state) else {
+        return VersionPage {
+            entries: vec![],
+            total: 0,
+        };
+    };
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    versions_page(&dir, offset, limit)
+}
+
 #[tauri::command]
 pub fn restore_version(
     version_id: String,
     document_path: String,
     state: State<AppState>,
-) -> Result<String, String> {
-    let app_data_dir = state
-        .app_data_dir
-        .lock()
-        .map_err(|e| format!("Lock error: {}", e))?;
-    let dir = versions_dir(&app_data_dir, &document_path);
+) -> Result<String, AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    restore_from(&dir, &version_id)
+}
 
-    let gz_path = dir.join(format!("{}.lml.gz", version_id));
-    if !gz_path.exists() {
-        return Err(format!("Version file not found: {}", version_id));
-    }
+/// Content of a version in `dir`, read as compressed or plain per its manifest entry
+fn restore_from(dir: &Path, version_id: &str) -> Result<String, AppError> {
+    // Snapshots missing from the manifest are found by whichever file exists
+    let compressed = read_manifest(dir)
+        .versions
+        .iter()
+        .find(|v| v.id == version_id)
+        .map_or_else(
+            || dir.join(snapshot_file_name(version_id, true)).exists(),
+            |v| v.compressed,
+        );
+    read_version_content(dir, version_id, compressed)
+}
 
-    let file = fs::File::open(&gz_path).map_err(|e| format!("Open gz file error: {}", e))?;
-    let mut decoder = GzDecoder::new(file);
-    let mut content = String::new();
-    decoder
-        .read_to_string(&mut content)
-        .map_err(|e| format!("Decompress error: {}", e))?;
+/// A version's content together with its manifest entry, for read-only display
+#[tauri::command]
+pub fn get_version_content(
+    document_path: String,
+    version_id: String,
+    state: State<AppState>,
+) -> Result<VersionContent, AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    version_content(&dir, &version_id)
+}
 
-    Ok(content)
+fn version_content(dir: &Path, version_id: &str) -> Result<VersionContent, AppError> {
+    let entry = read_manifest(dir)
+        .versions
+        .into_iter()
+        .find(|v| v.id == version_id)
+        .ok_or_else(|| AppError::NotFound(format!("Version not found: {}", version_id)))?;
+
+    let content = read_version_content(dir, &entry.id, entry.compressed)?;
+    Ok(VersionContent { content, entry })
 }
 
 #[tauri::command]
@@ -186,17 +913,18 @@ pub fn delete_version(
     version_id: String,
     document_path: String,
     state: State<AppState>,
-) -> Result<(), String> {
-    let app_data_dir = state
-        .app_data_dir
-        .lock()
-        .map_err(|e| format!("Lock error: {}", e))?;
-    let dir = versions_dir(&app_data_dir, &document_path);
+) -> Result<(), AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    let lock = manifest_lock(&state.manifest_locks, &dir);
+    let _guard = lock.lock().map_err(|e| format!("Lock error: {}", e))?;
 
-    // Remove compressed file
-    let gz_path = dir.join(format!("{}.lml.gz", version_id));
-    if gz_path.exists() {
-        fs::remove_file(&gz_path).map_err(|e| format!("Delete file error: {}", e))?;
+    // Remove the snapshot, whichever form it was stored in
+    for compressed in [true, false] {
+        let path = dir.join(snapshot_file_name(&version_id, compressed));
+        if path.exists() {
+            fs::remove_file(&path).map_err(|e| AppError::io("Delete file error", e))?;
+        }
     }
 
     // Update manifest
@@ -206,3 +934,1472 @@ pub fn delete_version(
 
     Ok(())
 }
+
+/// Tag or untag version `version_id` in `dir`, returning the updated entry
+fn set_version_tag(
+    dir: &Path,
+    locks: &ManifestLocks,
+    version_id: &str,
+    tagged: bool,
+) -> Result<VersionEntry, AppError> {
+    let lock = manifest_lock(locks, dir);
+    let _guard = lock.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let mut manifest = read_manifest(dir);
+    let entry = manifest
+        .versions
+        .iter_mut()
+        .find(|v| v.id == version_id)
+        .ok_or_else(|| AppError::NotFound(format!("Version not found: {}", version_id)))?;
+    entry.tagged = tagged;
+    let updated = entry.clone();
+    write_manifest(dir, &manifest)?;
+    Ok(updated)
+}
+
+#[tauri::command]
+pub fn tag_version(
+    version_id: String,
+    document_path: String,
+    tagged: bool,
+    state: State<AppState>,
+) -> Result<VersionEntry, AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    set_version_tag(&dir, &state.manifest_locks, &version_id, tagged)
+}
+
+#[tauri::command]
+pub fn clear_version_history(
+    document_path: String,
+    state: State<AppState>,
+) -> Result<usize, AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    clear_history(&dir, &state.manifest_locks)
+}
+
+/// Delete every snapshot in a document's versions dir and empty its manifest.
+/// Returns how many versions the manifest listed.
+fn clear_history(dir: &Path, locks: &ManifestLocks) -> Result<usize, AppError> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let lock = manifest_lock(locks, dir);
+    let _guard = lock.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let manifest = read_manifest(dir);
+    let removed = manifest.versions.len();
+
+    // Remove every snapshot, including any not referenced by the manifest
+    let entries = fs::read_dir(dir).map_err(|e| AppError::io("Read versions dir error", e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if parse_snapshot_file_name(&entry.file_name().to_string_lossy()).is_some() {
+            fs::remove_file(&path).map_err(|e| AppError::io("Delete file error", e))?;
+        }
+    }
+
+    // Keep the directory so future versions can be created
+    write_manifest(dir, &VersionManifest { versions: vec![] })?;
+
+    Ok(removed)
+}
+
+/// Move a document's history to its new path after it was moved outside the app
+#[tauri::command]
+pub fn migrate_version_history(
+    old_path: String,
+    new_path: String,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let (old_path, new_path) = (history_path(&state, &old_path), history_path(&state, &new_path));
+    migrate_history(&root, &state.manifest_locks, &old_path, &new_path)
+}
+
+/// Rename `old_path`'s history directory to `new_path`'s and rewrite its entries,
+/// merging into any history `new_path` already has
+fn migrate_history(
+    root: &Path,
+    locks: &ManifestLocks,
+    old_path: &str,
+    new_path: &str,
+) -> Result<(), AppError> {
+    let from_dir = versions_dir(root, old_path);
+    let to_dir = versions_dir(root, new_path);
+    if read_manifest(&from_dir).versions.is_empty() {
+        return Err(AppError::NotFound(format!("No version history for {}", old_path)));
+    }
+    if from_dir == to_dir {
+        return Ok(());
+    }
+
+    // The new path already has history of its own; merge into it instead of replacing it
+    if !read_manifest(&to_dir).versions.is_empty() {
+        copy_version_history(root, locks, old_path, new_path)?;
+        let lock = manifest_lock(locks, &from_dir);
+        let _guard = lock.lock().map_err(|e| format!("Lock error: {}", e))?;
+        return fs::remove_dir_all(&from_dir)
+            .map_err(|e| AppError::io("Remove old history error", e));
+    }
+
+    let from_lock = manifest_lock(locks, &from_dir);
+    let _from_guard = from_lock.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let to_lock = manifest_lock(locks, &to_dir);
+    let _to_guard = to_lock.lock().map_err(|e| format!("Lock error: {}", e))?;
+
+    // An empty directory left over from an earlier clear would block the rename
+    if to_dir.exists() {
+        fs::remove_dir_all(&to_dir).map_err(|e| AppError::io("Remove empty history error", e))?;
+    }
+    fs::rename(&from_dir, &to_dir).map_err(|e| AppError::io("Move history error", e))?;
+
+    let mut manifest = read_manifest(&to_dir);
+    for version in &mut manifest.versions {
+        version.document_path = new_path.to_string();
+    }
+    write_manifest(&to_dir, &manifest)
+}
+
+/// Reconcile a document's manifest with the snapshot files on disk
+#[tauri::command]
+pub fn repair_version_store(
+    document_path: String,
+    mode: RepairMode,
+    state: State<AppState>,
+) -> Result<RepairReport, AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    repair_history(&dir, &state.manifest_locks, &document_path, mode)
+}
+
+/// Reconcile a document's versions dir with its manifest; see `RepairReport`
+fn repair_history(
+    dir: &Path,
+    locks: &ManifestLocks,
+    document_path: &str,
+    mode: RepairMode,
+) -> Result<RepairReport, AppError> {
+    let lock = manifest_lock(locks, dir);
+    let _guard = lock.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let mut manifest = read_manifest(dir);
+    let mut report = RepairReport {
+        missing_files: manifest
+            .versions
+            .iter()
+            .filter(|v| !dir.join(v.file_name()).exists())
+            .map(|v| v.id.clone())
+            .collect(),
+        ..Default::default()
+    };
+
+    let orphans: Vec<(String, bool, PathBuf)> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .filter_map(|e| {
+                    let name = e.file_name().to_string_lossy().to_string();
+                    let (id, compressed) = parse_snapshot_file_name(&name)?;
+                    Some((id.to_string(), compressed, e.path()))
+                })
+                .filter(|(id, _, _)| !manifest.versions.iter().any(|v| &v.id == id))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    for (id, compressed, path) in orphans {
+        match mode {
+            RepairMode::Delete => {
+                fs::remove_file(&path).map_err(|e| AppError::io("Delete file error", e))?;
+                report.deleted.push(id);
+            }
+            RepairMode::Readd => {
+                let Ok(content) = read_version_content(dir, &id, compressed) else {
+                    report.unreadable.push(id);
+                    continue;
+                };
+                let metadata = fs::metadata(&path).ok();
+                let timestamp = metadata
+                    .as_ref()
+                    .and_then(|m| m.modified().ok())
+                    .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339())
+                    .unwrap_or_else(|| chrono::Utc::now().to_rfc3339());
+                manifest.versions.push(VersionEntry {
+                    id: id.clone(),
+                    document_path: document_path.to_string(),
+                    timestamp,
+                    comment: None,
+                    word_count: count_words(&content),
+                    file_size_bytes: metadata.map(|m| m.len()).unwrap_or(0),
+                    content_hash: content_hash(&content),
+                    dedup_hash: None,
+                    compressed,
+                    tagged: false,
+                });
+                report.readded.push(id);
+            }
+        }
+    }
+
+    if !report.readded.is_empty() {
+        manifest.versions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        write_manifest(dir, &manifest)?;
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+pub fn version_quota_status(state: State<AppState>) -> Result<QuotaStatus, AppError> {
+    let settings = state
+        .settings
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get_settings();
+
+    let (_store, root) = open_version_store(&state)?;
+    Ok(quota_status(version_storage_bytes(&root), &settings))
+}
+
+fn quota_status(used_bytes: u64, settings: &crate::settings::Settings) -> QuotaStatus {
+    let quota_bytes = storage_quota_bytes(settings);
+    QuotaStatus {
+        used_mb: used_bytes as f64 / (1024.0 * 1024.0),
+        quota_mb: quota_bytes.and(settings.version_storage_quota_mb),
+        percent: quota_bytes.map_or(0.0, |q| used_bytes as f64 / q as f64 * 100.0),
+    }
+}
+
+fn copy_tree(from: &Path, to: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_tree(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Size of every file under `dir`
+fn tree_bytes(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some(if metadata.is_dir() {
+                tree_bytes(&entry.path())
+            } else {
+                metadata.len()
+            })
+        })
+        .sum()
+}
+
+/// Undo a partial relocation, leaving a pre-existing target directory in place
+fn discard_relocation(target: &Path, target_existed: bool) {
+    if !target_existed {
+        fs::remove_dir_all(target).ok();
+        return;
+    }
+    for entry in fs::read_dir(target).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            fs::remove_dir_all(&path).ok();
+        } else {
+            fs::remove_file(&path).ok();
+        }
+    }
+}
+
+/// Copy the store at `current` into `target`, then commit the move with
+/// `save_setting`. The old tree is removed only after both succeed; otherwise
+/// the copy is discarded and `current` is left as it was. Returns a warning
+/// when the move succeeded but the old tree could not be removed.
+fn move_version_store(
+    current: &Path,
+    target: &Path,
+    save_setting: impl FnOnce() -> Result<(), AppError>,
+) -> Result<Option<String>, AppError> {
+    if target.starts_with(current) {
+        return Err(AppError::Validation(
+            "The version store can't be moved inside itself".to_string(),
+        ));
+    }
+    if fs::read_dir(target).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(AppError::Validation(format!(
+            "Target directory is not empty: {}",
+            target.display()
+        )));
+    }
+    let target_existed = target.exists();
+    probe_writable(target)
+        .map_err(|e| AppError::Permission(format!("Target is not writable: {}", e)))?;
+
+    let needed = tree_bytes(current);
+    let available =
+        fs4::available_space(target).map_err(|e| AppError::io("Failed to check free space", e))?;
+    if available < needed {
+        discard_relocation(target, target_existed);
+        return Err(AppError::Validation(format!(
+            "Not enough free space in {}: {} bytes needed, {} available",
+            target.display(),
+            needed,
+            available
+        )));
+    }
+
+    if current.exists() {
+        if let Err(e) = copy_tree(current, target) {
+            discard_relocation(target, target_existed);
+            return Err(AppError::io("Failed to copy version store", e));
+        }
+    }
+    if let Err(e) = save_setting() {
+        discard_relocation(target, target_existed);
+        return Err(e);
+    }
+
+    if let Err(e) = fs::remove_dir_all(current) {
+        if current.exists() {
+            return Ok(Some(format!(
+                "Version history was moved, but the old copy in {} could not be removed: {}",
+                current.display(),
+                e
+            )));
+        }
+    }
+    Ok(None)
+}
+
+/// Point `version_store_dir` at `target` and save, keeping the old value if saving fails
+fn save_store_dir(settings: &Mutex<SettingsManager>, target: &Path) -> Result<(), AppError> {
+    let mut manager = settings.lock().map_err(|e| format!("Lock error: {}", e))?;
+    let previous = manager.get_settings();
+    let mut updated = previous.clone();
+    updated.version_store_dir = Some(target.to_string_lossy().to_string());
+    manager.update_settings(updated);
+    manager.save().map_err(|e| {
+        manager.update_settings(previous);
+        AppError::io("Failed to save settings", e)
+    })
+}
+
+/// Where the version store ended up
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoreRelocation {
+    pub path: String,
+    /// Set when the old store is still on disk after the move
+    pub warning: Option<String>,
+}
+
+/// Move the whole version store to `new_dir` and point `version_store_dir` at
+/// it. The target must be an absolute path to an empty or missing directory
+/// with room for the store. Every other version operation waits until the move
+/// is done, and sees the new location afterwards.
+#[tauri::command]
+pub fn relocate_version_store(
+    new_dir: String,
+    state: State<AppState>,
+) -> Result<StoreRelocation, AppError> {
+    let target = expand_path(&new_dir);
+    if !target.is_absolute() {
+        return Err(AppError::Validation(format!(
+            "The version store directory must be an absolute path: {}",
+            new_dir
+        )));
+    }
+
+    let _store = state
+        .version_store_lock
+        .write()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    let current = version_store_root(&state)?;
+    let warning = if target == current {
+        None
+    } else {
+        move_version_store(&current, &target, || save_store_dir(&state.settings, &target))?
+    };
+    Ok(StoreRelocation {
+        path: target.to_string_lossy().to_string(),
+        warning,
+    })
+}
+
+#[tauri::command]
+pub fn enforce_version_quota(state: State<AppState>) -> Result<QuotaReport, AppError> {
+    let quota_bytes = storage_quota_bytes(
+        &state
+            .settings
+            .lock()
+            .map_err(|e| format!("Lock error: {}", e))?
+            .get_settings(),
+    );
+
+    let (_store, root) = open_version_store(&state)?;
+
+    match quota_bytes {
+        Some(quota_bytes) => Ok(enforce_quota(&root, quota_bytes, &state.manifest_locks, None)),
+        None => {
+            let used = version_storage_bytes(&root);
+            Ok(QuotaReport {
+                quota_bytes: 0,
+                used_bytes_before: used,
+                used_bytes_after: used,
+                evicted: vec![],
+            })
+        }
+    }
+}
+
+#[tauri::command]
+pub fn export_version_changelog(
+    document_path: String,
+    output_path: String,
+    state: State<AppState>,
+) -> Result<(), AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    let manifest = read_manifest(&dir);
+
+    let changelog = render_changelog(&document_path, &manifest.versions);
+    crate::commands::write_file(output_path, changelog, None)
+}
+
+#[tauri::command]
+pub fn version_change_stats(
+    document_path: String,
+    current_content: String,
+    state: State<AppState>,
+) -> Result<ChangeStats, AppError> {
+    let (_store, root) = open_version_store(&state)?;
+    let dir = versions_dir(&root, &history_path(&state, &document_path));
+    change_stats(&dir, &current_content)
+}
+
+/// Line counts added, removed and unchanged in `current_content` relative to
+/// the latest version in `dir`; everything counts as added when there is none
+fn change_stats(dir: &Path, current_content: &str) -> Result<ChangeStats, AppError> {
+    let manifest = read_manifest(dir);
+
+    let previous = match manifest.versions.first() {
+        Some(latest) => read_version_content(dir, &latest.id, latest.compressed)?,
+        None => String::new(),
+    };
+
+    let old_lines: Vec<&str> = previous.lines().collect();
+    let new_lines: Vec<&str> = current_content.lines().collect();
+
+    let mut stats = ChangeStats::default();
+    for op in diff_lines(&old_lines, &new_lines) {
+        match op {
+            DiffOp::Equal(_) => stats.unchanged += 1,
+            DiffOp::Insert(_) => stats.added += 1,
+            DiffOp::Delete(_) => stats.removed += 1,
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, timestamp: &str) -> VersionEntry {
+        VersionEntry {
+            id: id.to_string(),
+            document_path: "/docs/a.lml".to_string(),
+            timestamp: timestamp.to_string(),
+            comment: None,
+            word_count: 0,
+            file_size_bytes: 0,
+            content_hash: String::new(),
+            dedup_hash: None,
+            compressed: true,
+            tagged: false,
+        }
+    }
+
+    /// `count` entries, newest first, one minute apart
+    fn entries(count: usize) -> Vec<VersionEntry> {
+        (0..count)
+            .rev()
+            .map(|i| {
+                entry(
+                    &format!("v{}", i),
+                    &format!("2024-01-01T00:{:02}:00+00:00", i),
+                )
+            })
+            .collect()
+    }
+
+    /// Gzip `content` to `path`, as a compressed snapshot is stored
+    fn write_gz(path: &Path, content: &str) {
+        let file = fs::File::create(path).unwrap();
+        compress_and_hash(content.as_bytes(), file, Compression::default()).unwrap();
+    }
+
+    /// Store `count` versions (see `entries`) as gzipped snapshots with a manifest
+    fn write_versions(dir: &Path, count: usize) -> Vec<VersionEntry> {
+        fs::create_dir_all(dir).unwrap();
+        let versions = entries(count);
+        for version in &versions {
+            let content = format!("content of {}", version.id);
+            write_gz(&dir.join(version.file_name()), &content);
+        }
+        let manifest = VersionManifest {
+            versions: versions.clone(),
+        };
+        write_manifest_as(dir, &manifest, false).unwrap();
+        versions
+    }
+
+    fn snapshot_files(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .flatten()
+            .map(|e| e.file_name().to_string_lossy().to_string())
+            .filter(|name| parse_snapshot_file_name(name).is_some())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn clearing_history_removes_every_snapshot() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("doc");
+        write_versions(&dir, 3);
+        // A snapshot the manifest doesn't know about goes too
+        fs::write(dir.join("orphan.lml"), "orphan").unwrap();
+        let locks = ManifestLocks::default();
+
+        assert_eq!(clear_history(&dir, &locks).unwrap(), 3);
+        assert!(read_manifest(&dir).versions.is_empty());
+        assert!(snapshot_files(&dir).is_empty());
+        assert!(dir.is_dir(), "the versions dir is kept for new versions");
+
+        // Clearing a document with no history is a no-op
+        let missing = root.path().join("missing");
+        assert_eq!(clear_history(&missing, &locks).unwrap(), 0);
+    }
+
+    /// Store a 100-byte plain snapshot at the front of `dir`'s manifest
+    fn store_sized(dir: &Path, id: &str, minute: u32, comment: Option<&str>, tagged: bool) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(snapshot_file_name(id, false)), [b'x'; 100]).unwrap();
+        let mut manifest = read_manifest(dir);
+        manifest.versions.insert(
+            0,
+            VersionEntry {
+                comment: comment.map(str::to_string),
+                tagged,
+                file_size_bytes: 100,
+                compressed: false,
+                ..entry(id, &format!("2024-01-01T00:{:02}:00+00:00", minute))
+            },
+        );
+        write_manifest(dir, &manifest).unwrap();
+    }
+
+    fn manifest_ids(dir: &Path) -> Vec<String> {
+        read_manifest(dir)
+            .versions
+            .into_iter()
+            .map(|v| v.id)
+            .collect()
+    }
+
+    #[test]
+    fn quota_evicts_oldest_untagged_versions_across_documents() {
+        let root = tempfile::tempdir().unwrap();
+        let (a, b) = (root.path().join("a"), root.path().join("b"));
+        store_sized(&a, "a0", 0, None, true);
+        store_sized(&b, "b1", 1, None, false);
+        store_sized(&a, "a2", 2, None, false);
+        store_sized(&b, "b3", 3, Some("typo fixes"), false);
+        store_sized(&a, "a4", 4, None, false);
+        store_sized(&b, "b5", 5, None, false);
+
+        let report = enforce_quota(root.path(), 350, &ManifestLocks::default(), None);
+
+        // The tagged a0 is skipped; a comment alone doesn't count as a tag
+        let evicted: Vec<&str> = report
+            .evicted
+            .iter()
+            .map(|v| v.version_id.as_str())
+            .collect();
+        assert_eq!(evicted, ["b1", "a2", "b3"]);
+        assert_eq!(report.used_bytes_before, 600);
+        assert_eq!(report.used_bytes_after, 300);
+        assert_eq!(version_storage_bytes(root.path()), 300);
+        assert_eq!(manifest_ids(&a), ["a4", "a0"]);
+        assert_eq!(manifest_ids(&b), ["b5"]);
+        assert!(!b.join("b1.lml").exists());
+    }
+
+    #[test]
+    fn quota_keeps_tagged_versions_even_when_over_budget() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("a");
+        store_sized(&dir, "a0", 0, Some("submitted"), true);
+        store_sized(&dir, "a1", 1, None, false);
+
+        let report = enforce_quota(root.path(), 0, &ManifestLocks::default(), None);
+        assert_eq!(report.evicted.len(), 1);
+        assert_eq!(report.used_bytes_after, 100);
+        assert_eq!(manifest_ids(&dir), ["a0"]);
+    }
+
+    #[test]
+    fn quota_under_budget_evicts_nothing() {
+        let root = tempfile::tempdir().unwrap();
+        store_sized(&root.path().join("a"), "a0", 0, None, false);
+
+        let report = enforce_quota(root.path(), 1024, &ManifestLocks::default(), None);
+        assert!(report.evicted.is_empty());
+        assert_eq!(report.used_bytes_after, 100);
+    }
+
+    #[test]
+    fn quota_never_evicts_the_version_just_created() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("a");
+        store_sized(&dir, "a0", 0, None, false);
+        // An older-looking timestamp (e.g. after a clock change) must not matter
+        store_sized(&dir, "new", 0, None, false);
+
+        let report = enforce_quota(root.path(), 0, &ManifestLocks::default(), Some("new"));
+        let evicted: Vec<&str> = report
+            .evicted
+            .iter()
+            .map(|v| v.version_id.as_str())
+            .collect();
+        assert_eq!(evicted, ["a0"]);
+        assert_eq!(manifest_ids(&dir), ["new"]);
+    }
+
+    #[test]
+    fn tagged_versions_survive_the_quota_until_untagged() {
+        let root = tempfile::tempdir().unwrap();
+        let locks = ManifestLocks::default();
+        let dir = root.path().join("a");
+        store_sized(&dir, "a0", 0, Some("submitted"), false);
+
+        let tagged = set_version_tag(&dir, &locks, "a0", true).unwrap();
+        assert!(tagged.tagged);
+        assert!(enforce_quota(root.path(), 0, &locks, None).evicted.is_empty());
+
+        set_version_tag(&dir, &locks, "a0", false).unwrap();
+        assert_eq!(enforce_quota(root.path(), 0, &locks, None).evicted.len(), 1);
+        assert!(matches!(
+            set_version_tag(&dir, &locks, "a0", true),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn zero_or_unset_quota_is_unlimited() {
+        let mut settings = crate::settings::Settings {
+            version_storage_quota_mb: None,
+            ..Default::default()
+        };
+        assert_eq!(storage_quota_bytes(&settings), None);
+        settings.version_storage_quota_mb = Some(0);
+        assert_eq!(storage_quota_bytes(&settings), None);
+        settings.version_storage_quota_mb = Some(2);
+        assert_eq!(storage_quota_bytes(&settings), Some(2 * 1024 * 1024));
+        settings.version_storage_quota_mb = Some(u64::MAX);
+        assert_eq!(storage_quota_bytes(&settings), Some(u64::MAX));
+    }
+
+    #[test]
+    fn storage_size_sums_snapshots_across_documents() {
+        let root = tempfile::tempdir().unwrap();
+        let a = root.path().join("a");
+        let b = root.path().join("b");
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        fs::write(a.join("v1.lml.gz"), [0u8; 100]).unwrap();
+        fs::write(a.join("v2.lml"), [0u8; 50]).unwrap();
+        fs::write(b.join("v3.lml.gz"), [0u8; 200]).unwrap();
+        // Manifests, stray files, and files outside a document dir don't count
+        fs::write(a.join("manifest.json"), [0u8; 1000]).unwrap();
+        fs::write(b.join("notes.txt"), [0u8; 1000]).unwrap();
+        fs::write(root.path().join("v4.lml.gz"), [0u8; 1000]).unwrap();
+
+        assert_eq!(version_storage_bytes(root.path()), 350);
+        assert_eq!(version_storage_bytes(&root.path().join("missing")), 0);
+    }
+
+    #[test]
+    fn quota_status_reports_usage_against_the_quota() {
+        let mut settings = crate::settings::Settings {
+            version_storage_quota_mb: Some(2),
+            ..Default::default()
+        };
+        let status = quota_status(1024 * 1024, &settings);
+        assert_eq!(status.used_mb, 1.0);
+        assert_eq!(status.quota_mb, Some(2));
+        assert_eq!(status.percent, 50.0);
+
+        for quota in [None, Some(0)] {
+            settings.version_storage_quota_mb = quota;
+            let status = quota_status(1024 * 1024, &settings);
+            assert_eq!(status.quota_mb, None);
+            assert_eq!(status.percent, 0.0);
+        }
+    }
+
+    #[test]
+    fn history_is_copied_to_a_new_path() {
+        let root = tempfile::tempdir().unwrap();
+        let locks = ManifestLocks::default();
+        let from_dir = versions_dir(root.path(), "/docs/a.lml");
+        let to_dir = versions_dir(root.path(), "/docs/b.lml");
+        write_versions(&from_dir, 3);
+
+        let copied = copy_version_history(root.path(), &locks, "/docs/a.lml", "/docs/b.lml");
+        assert_eq!(copied, Ok(3));
+        let copies = read_manifest(&to_dir).versions;
+        let ids: Vec<&str> = copies.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, ["v2", "v1", "v0"]);
+        assert!(copies.iter().all(|v| v.document_path == "/docs/b.lml"));
+        assert_eq!(snapshot_files(&to_dir), snapshot_files(&from_dir));
+        // The source history is left as it was
+        assert_eq!(read_manifest(&from_dir).versions.len(), 3);
+
+        // Copying again, or onto the same path, adds nothing
+        let again = copy_version_history(root.path(), &locks, "/docs/a.lml", "/docs/b.lml");
+        assert_eq!(again, Ok(0));
+        let same = copy_version_history(root.path(), &locks, "/docs/a.lml", "/docs/a.lml");
+        assert_eq!(same, Ok(0));
+    }
+
+    #[test]
+    fn migrated_history_is_listed_under_the_new_path() {
+        let root = tempfile::tempdir().unwrap();
+        let locks = ManifestLocks::default();
+        let from_dir = versions_dir(root.path(), "/docs/a.lml");
+        write_versions(&from_dir, 3);
+        let files = snapshot_files(&from_dir);
+
+        migrate_history(root.path(), &locks, "/docs/a.lml", "/moved/a.lml").unwrap();
+
+        let to_dir = versions_dir(root.path(), "/moved/a.lml");
+        let migrated = read_manifest(&to_dir).versions;
+        let ids: Vec<&str> = migrated.iter().map(|v| v.id.as_str()).collect();
+        assert_eq!(ids, ["v2", "v1", "v0"]);
+        assert!(migrated.iter().all(|v| v.document_path == "/moved/a.lml"));
+        assert_eq!(snapshot_files(&to_dir), files);
+        assert!(!from_dir.exists());
+        assert_eq!(
+            read_version_content(&to_dir, "v1", true).unwrap(),
+            "content of v1"
+        );
+    }
+
+    #[test]
+    fn migrating_without_history_is_an_error() {
+        let root = tempfile::tempdir().unwrap();
+        let locks = ManifestLocks::default();
+        let result = migrate_history(root.path(), &locks, "/docs/none.lml", "/docs/b.lml");
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+        assert!(!versions_dir(root.path(), "/docs/b.lml").exists());
+    }
+
+    #[test]
+    fn orphaned_snapshots_are_readded_from_their_content() {
+        let dir = tempfile::tempdir().unwrap();
+        write_versions(dir.path(), 2);
+        // A snapshot whose manifest write never happened
+        write_gz(&dir.path().join("orphan.lml.gz"), "three little words");
+        fs::write(dir.path().join("garbled.lml.gz"), b"not gzip").unwrap();
+
+        let report = repair_history(
+            dir.path(),
+            &ManifestLocks::default(),
+            "/docs/a.lml",
+            RepairMode::Readd,
+        )
+        .unwrap();
+        assert_eq!(report.readded, ["orphan"]);
+        assert_eq!(report.unreadable, ["garbled"]);
+        assert!(report.deleted.is_empty() && report.missing_files.is_empty());
+
+        let manifest = read_manifest(dir.path());
+        assert_eq!(manifest.versions.len(), 3);
+        let orphan = manifest.versions.iter().find(|v| v.id == "orphan").unwrap();
+        assert_eq!(orphan.word_count, 3);
+        assert_eq!(orphan.content_hash, content_hash("three little words"));
+        assert_eq!(orphan.document_path, "/docs/a.lml");
+        assert!(orphan.compressed);
+        // Unreadable files are left for the user to inspect
+        assert!(dir.path().join("garbled.lml.gz").exists());
+    }
+
+    #[test]
+    fn orphaned_snapshots_can_be_deleted() {
+        let dir = tempfile::tempdir().unwrap();
+        write_versions(dir.path(), 2);
+        fs::write(dir.path().join("orphan.lml"), "plain orphan").unwrap();
+
+        let report = repair_history(
+            dir.path(),
+            &ManifestLocks::default(),
+            "/docs/a.lml",
+            RepairMode::Delete,
+        )
+        .unwrap();
+        assert_eq!(report.deleted, ["orphan"]);
+        assert!(!dir.path().join("orphan.lml").exists());
+        assert_eq!(read_manifest(dir.path()).versions.len(), 2);
+    }
+
+    #[test]
+    fn manifest_entries_without_files_are_flagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let versions = write_versions(dir.path(), 3);
+        fs::remove_file(dir.path().join(versions[1].file_name())).unwrap();
+
+        let report = repair_history(
+            dir.path(),
+            &ManifestLocks::default(),
+            "/docs/a.lml",
+            RepairMode::Delete,
+        )
+        .unwrap();
+        assert_eq!(report.missing_files, [versions[1].id.clone()]);
+        assert!(report.deleted.is_empty());
+        // Flagged only; the manifest is not rewritten
+        assert_eq!(read_manifest(dir.path()).versions.len(), 3);
+    }
+
+    /// Store `content` as a new version of `/docs/a.lml` in `root/<name>`
+    fn store_in(
+        root: &Path,
+        name: &str,
+        content: &str,
+        settings: &crate::settings::Settings,
+    ) -> VersionEntry {
+        store_version(
+            root,
+            &root.join(name),
+            &ManifestLocks::default(),
+            "/docs/a.lml".to_string(),
+            content.to_string(),
+            None,
+            settings,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn snapshots_use_the_configured_compression_level() {
+        let root = tempfile::tempdir().unwrap();
+        let content: String = (0..2000)
+            .map(|i| format!("line {} of the draft\n", i))
+            .collect();
+        let level = |level: u32| crate::settings::Settings {
+            version_compression_level: level,
+            ..Default::default()
+        };
+
+        let stored = store_in(root.path(), "stored", &content, &level(0));
+        let smallest = store_in(root.path(), "smallest", &content, &level(9));
+        let clamped = store_in(root.path(), "clamped", &content, &level(42));
+        assert!(stored.compressed && smallest.compressed);
+        // Level 0 only wraps the bytes, so it can't be smaller than the input
+        assert!(stored.file_size_bytes >= content.len() as u64);
+        assert!(smallest.file_size_bytes < stored.file_size_bytes / 4);
+        assert_eq!(clamped.file_size_bytes, smallest.file_size_bytes);
+
+        for (name, entry) in [
+            ("stored", stored),
+            ("smallest", smallest),
+            ("clamped", clamped),
+        ] {
+            let restored = read_version_content(&root.path().join(name), &entry.id, true);
+            assert_eq!(restored.unwrap(), content, "{}", name);
+        }
+    }
+
+    #[test]
+    fn small_snapshots_are_stored_plain_and_large_ones_gzipped() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("doc");
+        let settings = Default::default();
+        let note = "A short note.";
+        let at_threshold = "x".repeat(UNCOMPRESSED_SNAPSHOT_BYTES);
+        let draft: String = (0..100).map(|i| format!("paragraph {}\n", i)).collect();
+
+        let small = store_in(root.path(), "doc", note, &settings);
+        let boundary = store_in(root.path(), "doc", &at_threshold, &settings);
+        let large = store_in(root.path(), "doc", &draft, &settings);
+
+        assert!(!small.compressed && !boundary.compressed && large.compressed);
+        assert_eq!(
+            fs::read_to_string(dir.join(format!("{}.lml", small.id))).unwrap(),
+            note
+        );
+        assert!(dir.join(format!("{}.lml.gz", large.id)).exists());
+        assert!(!dir.join(format!("{}.lml", large.id)).exists());
+
+        assert_eq!(restore_from(&dir, &small.id).unwrap(), note);
+        assert_eq!(restore_from(&dir, &boundary.id).unwrap(), at_threshold);
+        assert_eq!(restore_from(&dir, &large.id).unwrap(), draft);
+    }
+
+    #[test]
+    fn version_content_comes_with_its_entry() {
+        let root = tempfile::tempdir().unwrap();
+        let comment = Some("before rewrite".to_string());
+        let settings = Default::default();
+        let dir = root.path().join("doc");
+        let locks = ManifestLocks::default();
+        let content = "An older draft of the introduction.";
+        let stored = store_version(
+            root.path(),
+            &dir,
+            &locks,
+            "/docs/a.lml".to_string(),
+            content.to_string(),
+            comment.clone(),
+            &settings,
+        )
+        .unwrap();
+
+        let version = version_content(&dir, &stored.id).unwrap();
+        assert_eq!(version.content, content);
+        assert_eq!(version.entry.id, stored.id);
+        assert_eq!(version.entry.comment, comment);
+        assert_eq!(version.entry.word_count, 6);
+
+        let missing = version_content(&dir, "no-such-version");
+        assert!(matches!(missing, Err(AppError::NotFound(_))));
+    }
+
+    #[test]
+    fn snapshots_missing_from_the_manifest_restore_from_either_form() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("plain.lml"), "plain").unwrap();
+        write_gz(&dir.path().join("packed.lml.gz"), "packed");
+
+        assert_eq!(restore_from(dir.path(), "plain").unwrap(), "plain");
+        assert_eq!(restore_from(dir.path(), "packed").unwrap(), "packed");
+        assert!(matches!(
+            restore_from(dir.path(), "missing"),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    /// Counts the bytes read through it
+    struct CountingReader<'a> {
+        inner: &'a [u8],
+        bytes: usize,
+    }
+
+    impl Read for CountingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            self.bytes += read;
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn snapshots_are_hashed_while_compressing() {
+        // First 8 bytes of SHA-256("hello world")
+        let mut out = vec![];
+        let mut reader = CountingReader {
+            inner: b"hello world",
+            bytes: 0,
+        };
+        let hash = compress_and_hash(&mut reader, &mut out, Compression::default()).unwrap();
+        assert_eq!(hash, "b94d27b9934d3e08");
+        assert_eq!(reader.bytes, 11);
+
+        // Content spanning several chunks is read exactly once
+        let large = "lorem ipsum ".repeat(20_000);
+        let mut out = vec![];
+        let mut reader = CountingReader {
+            inner: large.as_bytes(),
+            bytes: 0,
+        };
+        let hash = compress_and_hash(&mut reader, &mut out, Compression::default()).unwrap();
+        assert_eq!(reader.bytes, large.len());
+        assert_eq!(hash, content_hash(&large));
+        let mut decompressed = String::new();
+        GzDecoder::new(out.as_slice())
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, large);
+    }
+
+    #[test]
+    fn version_hashes_are_the_truncated_sha256_of_the_content() {
+        let root = tempfile::tempdir().unwrap();
+        let settings = crate::settings::Settings::default();
+        let large = "hello world\n".repeat(1_000);
+        let cases = [
+            ("hello", "2cf24dba5fb0a30e", false),
+            ("hello world", "b94d27b9934d3e08", false),
+            (large.as_str(), "555812d3df91c539", true),
+        ];
+        for (content, digest, compressed) in cases {
+            let entry = store_in(root.path(), "doc", content, &settings);
+            assert_eq!(entry.content_hash, digest);
+            assert_eq!(entry.compressed, compressed);
+            let stored =
+                read_version_content(&root.path().join("doc"), &entry.id, entry.compressed);
+            assert_eq!(stored.unwrap(), content);
+        }
+    }
+
+    #[test]
+    fn unchanged_content_reuses_the_latest_version() {
+        let root = tempfile::tempdir().unwrap();
+        let settings = crate::settings::Settings::default();
+        let content = "draft ".repeat(1000);
+
+        let first = store_in(root.path(), "doc", &content, &settings);
+        let files = snapshot_files(&root.path().join("doc"));
+        let again = store_in(root.path(), "doc", &content, &settings);
+        assert_eq!(again.id, first.id);
+        assert_eq!(snapshot_files(&root.path().join("doc")), files);
+    }
+
+    #[test]
+    fn whitespace_only_changes_dedup_only_when_ignored() {
+        let root = tempfile::tempdir().unwrap();
+        let content = "# Title\n\nBody text.";
+        let with_newline = format!("{}\n", content);
+        let ignoring = crate::settings::Settings {
+            version_dedup_ignore_whitespace: true,
+            ..Default::default()
+        };
+
+        let first = store_in(root.path(), "on", content, &ignoring);
+        let again = store_in(root.path(), "on", &with_newline, &ignoring);
+        assert_eq!(again.id, first.id);
+        let spaced = store_in(
+            root.path(),
+            "on",
+            "# Title  \n\nBody text.\t\n\n",
+            &ignoring,
+        );
+        assert_eq!(spaced.id, first.id);
+        // Real edits still get a version, stored exactly as written
+        let edited = store_in(
+            root.path(),
+            "on",
+            &with_newline.replace("Body", "New"),
+            &ignoring,
+        );
+        assert_ne!(edited.id, first.id);
+        let stored = read_version_content(&root.path().join("on"), &edited.id, edited.compressed);
+        assert_eq!(stored.unwrap(), "# Title\n\nNew text.\n");
+
+        let off = crate::settings::Settings::default();
+        let first = store_in(root.path(), "off", content, &off);
+        let again = store_in(root.path(), "off", &with_newline, &off);
+        assert_ne!(again.id, first.id);
+    }
+
+    #[test]
+    fn line_ending_conversions_dedup_when_normalized() {
+        let root = tempfile::tempdir().unwrap();
+        let lf = "first line\nsecond line\n";
+        let crlf = "first line\r\nsecond line\r\n";
+        let normalizing = crate::settings::Settings {
+            version_dedup_normalize_line_endings: true,
+            ..Default::default()
+        };
+        assert_eq!(dedup_hash(lf, &normalizing), dedup_hash(crlf, &normalizing));
+        assert_eq!(
+            dedup_hash("a\rb", &normalizing),
+            dedup_hash("a\nb", &normalizing)
+        );
+        // Only line endings are normalized in this mode
+        assert_ne!(
+            dedup_hash("a \nb", &normalizing),
+            dedup_hash("a\nb", &normalizing)
+        );
+
+        let first = store_in(root.path(), "on", lf, &normalizing);
+        let converted = store_in(root.path(), "on", crlf, &normalizing);
+        assert_eq!(converted.id, first.id);
+        // The snapshot keeps the bytes it was saved with
+        let stored = read_version_content(&root.path().join("on"), &first.id, first.compressed);
+        assert_eq!(stored.unwrap(), lf);
+
+        let off = crate::settings::Settings::default();
+        let first = store_in(root.path(), "off", lf, &off);
+        let converted = store_in(root.path(), "off", crlf, &off);
+        assert_ne!(converted.id, first.id);
+        assert_ne!(content_hash(lf), content_hash(crlf));
+    }
+
+    #[test]
+    fn dedup_hashes_differ_between_modes() {
+        let settings =
+            |ignore_whitespace: bool, normalize_line_endings: bool| crate::settings::Settings {
+                version_dedup_ignore_whitespace: ignore_whitespace,
+                version_dedup_normalize_line_endings: normalize_line_endings,
+                ..Default::default()
+            };
+        assert_eq!(dedup_hash("a\n", &settings(false, false)), None);
+        let ws = dedup_hash("a\r\n", &settings(true, false)).unwrap();
+        let eol = dedup_hash("a\r\n", &settings(false, true)).unwrap();
+        assert_ne!(ws, eol);
+        assert_eq!(dedup_hash("a \n", &settings(true, false)).unwrap(), ws);
+        assert_eq!(dedup_hash("a\n", &settings(false, true)).unwrap(), eol);
+    }
+
+    #[test]
+    fn concurrent_versions_of_one_document_all_persist() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("doc");
+        let locks = ManifestLocks::default();
+        let settings = crate::settings::Settings::default();
+
+        let ids: Vec<String> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|i| {
+                    let (root, dir, locks, settings) = (root.path(), &dir, &locks, &settings);
+                    scope.spawn(move || {
+                        store_version(
+                            root,
+                            dir,
+                            locks,
+                            "/docs/a.lml".to_string(),
+                            format!("draft number {}", i),
+                            None,
+                            settings,
+                        )
+                        .unwrap()
+                        .id
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        let mut stored: Vec<String> = read_manifest(&dir)
+            .versions
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        let mut expected = ids;
+        stored.sort();
+        expected.sort();
+        assert_eq!(stored, expected);
+        assert_eq!(snapshot_files(&dir).len(), 8);
+    }
+
+    #[test]
+    fn the_configured_store_dir_overrides_the_default() {
+        let app_data = Path::new("/data/lilia");
+        assert_eq!(
+            resolve_version_store_root(None, app_data),
+            app_data.join("versions")
+        );
+        assert_eq!(
+            resolve_version_store_root(Some("  "), app_data),
+            app_data.join("versions")
+        );
+        assert_eq!(
+            resolve_version_store_root(Some("/mnt/big/versions"), app_data),
+            PathBuf::from("/mnt/big/versions")
+        );
+    }
+
+    #[test]
+    fn relocating_moves_the_store_and_saves_the_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = dir.path().join("versions");
+        let target = dir.path().join("elsewhere");
+        let version = store_in(&current, "doc", "hello", &Default::default());
+
+        let mut saved = false;
+        let warning = move_version_store(&current, &target, || {
+            saved = true;
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(saved);
+        assert_eq!(warning, None);
+        assert!(!current.exists());
+        let moved = target.join("doc");
+        assert_eq!(read_manifest(&moved).versions[0].id, version.id);
+        let content = read_version_content(&moved, &version.id, version.compressed).unwrap();
+        assert_eq!(content, "hello");
+    }
+
+    #[test]
+    fn a_settings_panel_save_keeps_the_relocated_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = dir.path().join("versions");
+        let target = dir.path().join("elsewhere");
+        store_in(&current, "doc", "hello", &Default::default());
+        let settings_path = dir.path().join("settings.json");
+        let settings = Mutex::new(SettingsManager::new(settings_path.clone()));
+
+        move_version_store(&current, &target, || save_store_dir(&settings, &target)).unwrap();
+        // Only the fields the settings panel edits
+        let ui_save = serde_json::json!({
+            "editorFontSize": 16,
+            "theme": "light",
+            "showOutline": true,
+        });
+        crate::commands::apply_settings_update(&mut settings.lock().unwrap(), ui_save).unwrap();
+
+        let saved = SettingsManager::new(settings_path).get_settings();
+        assert_eq!(saved.editor_font_size, 16);
+        assert_eq!(saved.theme, "light");
+        assert_eq!(
+            saved.version_store_dir,
+            Some(target.to_string_lossy().to_string())
+        );
+        assert_eq!(
+            resolve_version_store_root(saved.version_store_dir.as_deref(), dir.path()),
+            target
+        );
+    }
+
+    #[test]
+    fn a_failed_move_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = dir.path().join("versions");
+        store_in(&current, "doc", "hello", &Default::default());
+        let before = tree_bytes(&current);
+
+        // A missing target is removed again
+        let target = dir.path().join("elsewhere");
+        let err = move_version_store(&current, &target, || {
+            Err(AppError::Validation("settings are read-only".to_string()))
+        })
+        .unwrap_err();
+        assert!(matches!(err, AppError::Validation(_)));
+        assert!(!target.exists());
+        assert_eq!(tree_bytes(&current), before);
+
+        // An existing empty target is emptied but kept
+        fs::create_dir_all(&target).unwrap();
+        move_version_store(&current, &target, || {
+            Err(AppError::Validation("settings are read-only".to_string()))
+        })
+        .unwrap_err();
+        assert!(target.is_dir());
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+        assert_eq!(tree_bytes(&current), before);
+    }
+
+    #[test]
+    fn unsuitable_targets_are_rejected_before_copying() {
+        let dir = tempfile::tempdir().unwrap();
+        let current = dir.path().join("versions");
+        store_in(&current, "doc", "hello", &Default::default());
+        let never_saves = || -> Result<(), AppError> { panic!("setting saved") };
+
+        let busy = dir.path().join("busy");
+        fs::create_dir_all(&busy).unwrap();
+        fs::write(busy.join("notes.txt"), "keep me").unwrap();
+        let err = move_version_store(&current, &busy, never_saves).unwrap_err();
+        assert!(err.to_string().contains("not empty"));
+        assert_eq!(
+            fs::read_to_string(busy.join("notes.txt")).unwrap(),
+            "keep me"
+        );
+
+        let nested = current.join("inner");
+        let err = move_version_store(&current, &nested, never_saves).unwrap_err();
+        assert!(err.to_string().contains("inside itself"));
+        assert!(!nested.exists());
+    }
+
+    #[test]
+    fn stripping_latex_counts_only_prose() {
+        let content = "Let \\alpha = \\beta + \\gamma in $x$.\n\\section{Results} hold";
+        let plain = WordCountOptions::default();
+        let stripped = WordCountOptions {
+            strip_latex: true,
+            ..Default::default()
+        };
+        assert_eq!(count_words_ex(content, &plain), 10);
+        assert_eq!(count_words_ex(content, &stripped), 5);
+        // Escaped characters are dropped, not counted
+        assert_eq!(count_words_ex("50\\% off", &stripped), 2);
+    }
+
+    #[test]
+    fn default_word_count_matches_the_simple_counter() {
+        let content = "A state-of-the-art model,\n  with   two\tlines.";
+        assert_eq!(
+            count_words_ex(content, &WordCountOptions::default()),
+            count_words(content)
+        );
+
+        let split = WordCountOptions {
+            join_hyphenated: false,
+            ..Default::default()
+        };
+        assert_eq!(count_words_ex("a state-of-the-art model", &split), 6);
+        assert_eq!(count_words_ex("a -- b", &split), 2);
+    }
+
+    #[test]
+    fn code_blocks_can_be_excluded() {
+        let content = "intro words\n```rust\nlet x = 1;\n```\noutro";
+        let excluding = WordCountOptions {
+            exclude_code_blocks: true,
+            ..Default::default()
+        };
+        assert_eq!(count_words_ex(content, &excluding), 3);
+        assert_eq!(count_words_ex(content, &WordCountOptions::default()), 9);
+    }
+
+    #[test]
+    fn changelog_lists_newest_first_with_word_deltas() {
+        let versions = vec![
+            VersionEntry {
+                word_count: 90,
+                ..entry("v3", "2024-03-01T12:00:00+00:00")
+            },
+            VersionEntry {
+                word_count: 120,
+                comment: Some(" Submitted draft ".to_string()),
+                ..entry("v2", "2024-02-01T08:30:00+00:00")
+            },
+            VersionEntry {
+                word_count: 100,
+                ..entry("v1", "2024-01-01T00:00:00+00:00")
+            },
+        ];
+
+        assert_eq!(
+            render_changelog("/docs/thesis.lml", &versions),
+            "# Version History: thesis.lml\n\n\
+             ## 2024-03-01 12:00:00 UTC\n\n\
+             - Words: 90 (-30)\n\n\
+             ## 2024-02-01 08:30:00 UTC\n\n\
+             - Comment: Submitted draft\n\
+             - Words: 120 (+20)\n\n\
+             ## 2024-01-01 00:00:00 UTC\n\n\
+             - Words: 100 (initial)\n\n"
+        );
+    }
+
+    #[test]
+    fn changelog_without_versions_says_so() {
+        assert_eq!(
+            render_changelog("/docs/thesis.lml", &[]),
+            "# Version History: thesis.lml\n\n_No versions recorded._\n"
+        );
+    }
+
+    #[test]
+    fn change_stats_compare_against_the_latest_version() {
+        let dir = tempfile::tempdir().unwrap();
+        // The latest stored version is "content of v1"
+        write_versions(dir.path(), 2);
+
+        let stats = change_stats(dir.path(), "content of v1\nnew line\n").unwrap();
+        assert_eq!((stats.added, stats.removed, stats.unchanged), (1, 0, 1));
+
+        let stats = change_stats(dir.path(), "rewritten\n").unwrap();
+        assert_eq!((stats.added, stats.removed, stats.unchanged), (1, 1, 0));
+
+        let stats = change_stats(dir.path(), "content of v1").unwrap();
+        assert_eq!((stats.added, stats.removed, stats.unchanged), (0, 0, 1));
+    }
+
+    #[test]
+    fn change_stats_without_versions_count_everything_as_added() {
+        let dir = tempfile::tempdir().unwrap();
+        let stats = change_stats(dir.path(), "one\ntwo\nthree").unwrap();
+        assert_eq!((stats.added, stats.removed, stats.unchanged), (3, 0, 0));
+    }
+
+    #[test]
+    fn manifests_round_trip_through_the_compressed_form() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = VersionManifest {
+            versions: entries(200),
+        };
+        let ids = |manifest: &VersionManifest| -> Vec<String> {
+            manifest.versions.iter().map(|v| v.id.clone()).collect()
+        };
+
+        write_manifest_as(dir.path(), &manifest, true).unwrap();
+        assert!(dir.path().join(COMPRESSED_MANIFEST_FILE).exists());
+        assert!(!dir.path().join(MANIFEST_FILE).exists());
+        assert_eq!(ids(&read_manifest(dir.path())), ids(&manifest));
+
+        // Later writes keep the compressed form
+        let mut shorter = read_manifest(dir.path());
+        shorter.versions.truncate(3);
+        write_manifest(dir.path(), &shorter).unwrap();
+        assert!(!dir.path().join(MANIFEST_FILE).exists());
+        assert_eq!(ids(&read_manifest(dir.path())), ["v199", "v198", "v197"]);
+    }
+
+    #[test]
+    fn a_plain_manifest_is_migrated_when_compression_is_enabled() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("doc");
+        let plain = crate::settings::Settings::default();
+        let first = store_in(root.path(), "doc", "first draft", &plain);
+        assert!(dir.join(MANIFEST_FILE).exists());
+
+        let compressing = crate::settings::Settings {
+            compress_version_manifests: true,
+            ..Default::default()
+        };
+        let second = store_in(root.path(), "doc", "second draft", &compressing);
+        assert!(dir.join(COMPRESSED_MANIFEST_FILE).exists());
+        assert!(!dir.join(MANIFEST_FILE).exists());
+        let ids: Vec<String> = read_manifest(&dir)
+            .versions
+            .into_iter()
+            .map(|v| v.id)
+            .collect();
+        assert_eq!(ids, [second.id, first.id]);
+    }
+
+    #[test]
+    fn paging_returns_windows_and_total() {
+        let dir = tempfile::tempdir().unwrap();
+        let versions = VersionManifest {
+            versions: entries(30),
+        };
+        write_manifest_as(dir.path(), &versions, false).unwrap();
+
+        let ids = |page: &VersionPage| -> Vec<String> {
+            page.entries.iter().map(|e| e.id.clone()).collect()
+        };
+
+        let first = versions_page(dir.path(), 0, 10);
+        assert_eq!(first.total, 30);
+        assert_eq!(
+            ids(&first),
+            (20..30)
+                .rev()
+                .map(|i| format!("v{}", i))
+                .collect::<Vec<_>>()
+        );
+
+        let last = versions_page(dir.path(), 25, 10);
+        assert_eq!(last.total, 30);
+        assert_eq!(ids(&last), ["v4", "v3", "v2", "v1", "v0"]);
+
+        let past_end = versions_page(dir.path(), 30, 10);
+        assert_eq!(past_end.total, 30);
+        assert!(past_end.entries.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use crate::versions::{count_words, history_path, open_version_store, versions_since};
+use crate::AppState;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::State;
+
+/// Baseline captured when a writing session starts
+#[derive(Debug, Clone)]
+pub struct WritingSession {
+    pub baseline_words: u32,
+    pub started_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionStats {
+    /// Net words since the session began; negative after heavy cuts
+    pub words_this_session: i64,
+    pub versions_this_session: usize,
+    pub started_at: String,
+}
+
+impl WritingSession {
+    pub fn start(content: &str) -> Self {
+        Self {
+            baseline_words: count_words(content),
+            started_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+
+    /// Progress since the baseline, counting versions in the document's history
+    /// filed as `history_path` under `store_root`
+    pub fn stats(
+        &self,
+        current_content: &str,
+        store_root: &Path,
+        history_path: &str,
+    ) -> SessionStats {
+        SessionStats {
+            words_this_session: count_words(current_content) as i64 - self.baseline_words as i64,
+            versions_this_session: versions_since(store_root, history_path, &self.started_at),
+            started_at: self.started_at.clone(),
+        }
+    }
+}
+
+#[tauri::command]
+pub fn begin_writing_session(
+    document_path: String,
+    content: String,
+    state: State<AppState>,
+) -> Result<(), String> {
+    let mut sessions = state
+        .writing_sessions
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?;
+    sessions.insert(document_path, WritingSession::start(&content));
+    Ok(())
+}
+
+#[tauri::command]
+pub fn session_stats(
+    document_path: String,
+    current_content: String,
+    state: State<AppState>,
+) -> Result<SessionStats, String> {
+    let session = state
+        .writing_sessions
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .get(&document_path)
+        .cloned()
+        .ok_or_else(|| format!("No writing session in progress for {}", document_path))?;
+
+    let (_store, store_root) = open_version_store(&state)?;
+    let history_path = history_path(&state, &document_path);
+    Ok(session.stats(&current_content, &store_root, &history_path))
+}
+
+#[tauri::command]
+pub fn end_writing_session(document_path: String, state: State<AppState>) -> Result<(), String> {
+    state
+        .writing_sessions
+        .lock()
+        .map_err(|e| format!("Lock error: {}", e))?
+        .remove(&document_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_this_session_are_measured_from_the_baseline() {
+        let store = tempfile::tempdir().unwrap();
+        let session = WritingSession::start("one two three");
+        assert_eq!(session.baseline_words, 3);
+
+        let stats = session.stats("one two three four five", store.path(), "/docs/a.lml");
+        assert_eq!(stats.words_this_session, 2);
+        assert_eq!(stats.started_at, session.started_at);
+        // No history has been written for this document yet
+        assert_eq!(stats.versions_this_session, 0);
+    }
+
+    #[test]
+    fn cutting_text_gives_a_negative_count() {
+        let store = tempfile::tempdir().unwrap();
+        let session = WritingSession::start("a long first draft of the intro");
+        let stats = session.stats("a short intro", store.path(), "/docs/a.lml");
+        assert_eq!(stats.words_this_session, -4);
+    }
+}
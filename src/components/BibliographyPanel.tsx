@@ -1,7 +1,7 @@
 import { useState, useEffect, useCallback, useRef } from "react";
 import { Search, Plus, Trash2, Import, BookOpen } from "lucide-react";
 import { open } from "@tauri-apps/plugin-dialog";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "@/lib/ipc";
 import { useAppStore } from "@/store/app-store";
 import { useBibliographyStore } from "@/store/bibliography-store";
 import { getEditorInstance } from "./Editor";
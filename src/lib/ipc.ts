@@ -0,0 +1,20 @@
+import { invoke as tauriInvoke, type InvokeArgs } from "@tauri-apps/api/core";
+
+/**
+ * Call a backend command and report whether it succeeded to the opt-in
+ * debug log. The backend only writes the entry when `debug_logging` is on.
+ */
+export async function invoke<T>(cmd: string, args?: InvokeArgs): Promise<T> {
+  try {
+    const result = await tauriInvoke<T>(cmd, args);
+    reportOutcome(cmd, true);
+    return result;
+  } catch (error) {
+    reportOutcome(cmd, false);
+    throw error;
+  }
+}
+
+function reportOutcome(command: string, ok: boolean) {
+  tauriInvoke("record_command_outcome", { command, ok }).catch(() => {});
+}
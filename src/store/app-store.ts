@@ -1,5 +1,5 @@
 import { create } from "zustand";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "@/lib/ipc";
 import { open, save } from "@tauri-apps/plugin-dialog";
 import { useSettingsStore } from "./settings-store";
 
@@ -1,5 +1,5 @@
 import { create } from "zustand";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "@/lib/ipc";
 import { parseBibTeX } from "@/lib/lilia";
 import type { BibEntry } from "@/lib/lilia";
 
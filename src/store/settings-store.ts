@@ -1,5 +1,5 @@
 import { create } from "zustand";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "@/lib/ipc";
 
 export type SidePanel = "outline" | "bibliography" | "history" | null;
 
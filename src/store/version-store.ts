@@ -1,5 +1,5 @@
 import { create } from "zustand";
-import { invoke } from "@tauri-apps/api/core";
+import { invoke } from "@/lib/ipc";
 
 export interface VersionEntry {
   id: string;
@@ -9,6 +9,7 @@ export interface VersionEntry {
   word_count: number;
   file_size_bytes: number;
   content_hash: string;
+  tagged: boolean;
 }
 
 interface VersionState {
@@ -19,6 +20,7 @@ interface VersionState {
   createVersion: (path: string, content: string, comment?: string) => Promise<void>;
   restoreVersion: (id: string, path: string) => Promise<string>;
   deleteVersion: (id: string, path: string) => Promise<void>;
+  tagVersion: (id: string, path: string, tagged: boolean) => Promise<void>;
 }
 
 export const useVersionStore = create<VersionState>((set, get) => ({
@@ -67,4 +69,13 @@ export const useVersionStore = create<VersionState>((set, get) => ({
     });
     await get().loadVersions(path);
   },
+
+  tagVersion: async (id: string, path: string, tagged: boolean) => {
+    await invoke<VersionEntry>("tag_version", {
+      versionId: id,
+      documentPath: path,
+      tagged,
+    });
+    await get().loadVersions(path);
+  },
 }));